@@ -0,0 +1,69 @@
+//! Heap usage of large repositories, `cargo bench --bench memory`.
+//!
+//! Counts the bytes held by a repository of many crates from few authors, once after publishing
+//! and once after loading it back from its store.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use semver_repo::{CrateKind, Metadata, Repository, SemVer};
+
+struct Counting;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        ALLOCATED.fetch_sub(layout.size(), Ordering::Relaxed);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+const CRATES: usize = 100_000;
+const AUTHORS: usize = 500;
+
+fn allocated() -> usize {
+    ALLOCATED.load(Ordering::Relaxed)
+}
+
+fn report(what: &str, bytes: usize) {
+    println!(
+        "{what:<10} {:>8.1} MiB, {:>5} bytes per crate",
+        bytes as f64 / (1024.0 * 1024.0),
+        bytes / CRATES
+    );
+}
+
+fn main() {
+    let dir = tempfile::tempdir().unwrap();
+    let store = dir.path().join("store.json");
+
+    let before = allocated();
+    let mut repo = Repository::open(&store, None).unwrap();
+    for i in 0..CRATES {
+        let metadata = Metadata::new(
+            format!("crate_number_{i}"),
+            format!("Author Number {}", i % AUTHORS),
+            CrateKind::Library,
+        );
+        repo.add_crate(metadata, SemVer::new(1, 0, 0)).unwrap();
+        repo.add_release(format!("crate_number_{i}"), SemVer::new(1, 1, 0))
+            .unwrap();
+    }
+    report("published", allocated() - before);
+    repo.save().unwrap();
+    drop(repo);
+
+    let before = allocated();
+    let repo = Repository::open(&store, None).unwrap();
+    report("loaded", allocated() - before);
+    drop(repo);
+}
@@ -0,0 +1,123 @@
+//! Timings of the hot paths, `cargo bench --bench repository`, measured with criterion.
+//!
+//! Arguments filter benchmarks by name, e.g. `cargo bench --bench repository -- store/`. To
+//! check a branch for regressions, record a baseline with `-- --save-baseline main` on the main
+//! branch and compare against it with `-- --baseline main`.
+
+use std::hint::black_box;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use semver_repo::compression::Compression;
+use semver_repo::search::SearchOptions;
+use semver_repo::{CrateKind, Metadata, Repository, SemVer};
+
+fn metadata(i: usize) -> Metadata {
+    Metadata::new(
+        format!("crate_number_{i}"),
+        format!("Author Number {}", i % 500),
+        CrateKind::Library,
+    )
+    .with_description(format!("the {i}th crate of the benchmarks"))
+}
+
+fn populated(dir: &tempfile::TempDir, crates: usize) -> Repository {
+    let mut repo = Repository::with_capacity(dir.path().join(format!("{crates}.json")), crates);
+    for i in 0..crates {
+        repo.add_crate(metadata(i), SemVer::new(1, 0, 0)).unwrap();
+    }
+    repo
+}
+
+fn semver(c: &mut Criterion) {
+    c.bench_function("semver/parse", |b| {
+        b.iter(|| black_box("12.345.6789").parse::<SemVer>())
+    });
+    c.bench_function("semver/display", |b| {
+        b.iter(|| SemVer::new(12, 345, 6789).to_string())
+    });
+}
+
+fn lookups(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    for crates in [10_000, 100_000] {
+        let repo = populated(&dir, crates);
+        c.bench_with_input(
+            BenchmarkId::new("find_containing", crates),
+            &repo,
+            |b, repo| {
+                b.iter(|| {
+                    repo.find_containing("number_42", SearchOptions::default())
+                        .len()
+                })
+            },
+        );
+        c.bench_with_input(BenchmarkId::new("find_exact", crates), &repo, |b, repo| {
+            b.iter(|| repo.find_exact("crate_number_4242").is_some())
+        });
+        c.bench_with_input(BenchmarkId::new("search", crates), &repo, |b, repo| {
+            b.iter(|| repo.search("4242th").len())
+        });
+    }
+}
+
+/// the directories outlive the repositories saving themselves on drop, which isn't measured
+fn publishing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("publish");
+    group.sample_size(20);
+    group.bench_function("1000", |b| {
+        b.iter_with_large_drop(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut repo = Repository::open(dir.path().join("store.json"), None).unwrap();
+            for i in 0..1000 {
+                repo.add_crate(metadata(i), SemVer::new(1, 0, 0)).unwrap();
+                repo.add_release(format!("crate_number_{i}"), SemVer::new(1, 1, 0))
+                    .unwrap();
+            }
+            (repo, dir)
+        })
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("bulk_load");
+    group.sample_size(20);
+    group.bench_function("1000", |b| {
+        b.iter_with_large_drop(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let mut repo = Repository::open(dir.path().join("store.json"), None).unwrap();
+            let releases = vec![SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)];
+            repo.bulk_load((0..1000).map(|i| (metadata(i), releases.clone())))
+                .unwrap();
+            (repo, dir)
+        })
+    });
+    group.finish();
+}
+
+fn store(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let mut repo = populated(&dir, 10_000);
+    let mut group = c.benchmark_group("store");
+    group.sample_size(20);
+    for compression in [
+        Compression::None,
+        Compression::default(),
+        "gzip".parse().unwrap(),
+    ] {
+        let format = compression.to_string();
+        let format = format.split(':').next().unwrap().to_string();
+        repo.set_compression(compression);
+        group.bench_function(BenchmarkId::new("save/10000", &format), |b| {
+            b.iter(|| repo.save().unwrap())
+        });
+        repo.save().unwrap();
+        group.bench_function(BenchmarkId::new("load/10000", &format), |b| {
+            b.iter_with_large_drop(|| {
+                Repository::open(dir.path().join("10000.json"), None).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, semver, lookups, publishing, store);
+criterion_main!(benches);
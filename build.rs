@@ -0,0 +1,14 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // generates the gRPC server and client, see `src/grpc.rs`
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/registry.proto");
+        if std::env::var_os("PROTOC").is_none() {
+            let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc");
+            std::env::set_var("PROTOC", protoc);
+        }
+        tonic_prost_build::compile_protos("proto/registry.proto")
+            .expect("could not compile proto/registry.proto");
+    }
+}
@@ -2,17 +2,13 @@ use semver_repo::{CrateKind, Metadata, Repository, SemVer};
 
 fn main() -> anyhow::Result<()> {
     let store = option_env!("SEMVER_REPO").ok_or(anyhow::anyhow!("missing SEMVER_REPO env var"))?;
-    let mut repo = Repository::new(store);
+    let mut repo = Repository::open(store, None)?;
     println!("repo: {repo:?}");
 
     println!("find crate: {:?}", repo.find_exact("linux.exe"));
 
     repo.add_crate(
-        Metadata::new(
-            "linux.exe".to_string(),
-            "Linus Torvalds".to_string(),
-            CrateKind::Binary,
-        ),
+        Metadata::new("linux.exe", "Linus Torvalds", CrateKind::Binary),
         SemVer::new(1, 0, 0),
     )?;
 
@@ -24,14 +20,16 @@ fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[allow(dead_code, clippy::empty_loop)]
 fn loop_forever() -> ! {
     loop {}
 }
 
+#[allow(dead_code)]
 fn never() {
     let yes_please = true;
 
-    let nice_number = match yes_please {
+    let _nice_number = match yes_please {
         true => 13,
         false => return, // -> !
     };
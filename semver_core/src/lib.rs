@@ -0,0 +1,241 @@
+//! The version types of `semver_repo`, [`SemVer`] and [`VersionReq`], without the registry.
+//!
+//! `no_std`, needing just an allocator, e.g. for bootloaders. Serde support is behind the `serde`
+//! feature. Constructors and comparisons are `const fn`, so versions can be checked at compile
+//! time:
+//!
+//! ```
+//! use semver_core::SemVer;
+//!
+//! const MINIMUM: SemVer = SemVer::new(1, 2, 0);
+//! const _: () = assert!(SemVer::new(1, 4, 2).compare(&MINIMUM).is_gt());
+//! ```
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod req;
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Display;
+use core::num::ParseIntError;
+use core::str::FromStr;
+
+pub use req::{ParseVersionReqError, VersionReq};
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SemVer {
+    major: u64,
+    minor: u64,
+    patch: u64,
+}
+
+const fn compare_parts(a: u64, b: u64) -> Ordering {
+    if a < b {
+        Ordering::Less
+    } else if a > b {
+        Ordering::Greater
+    } else {
+        Ordering::Equal
+    }
+}
+
+impl SemVer {
+    pub const fn new(major: u64, minor: u64, patch: u64) -> SemVer {
+        SemVer {
+            major,
+            minor,
+            patch,
+        }
+    }
+
+    const fn new_short(major: u64) -> SemVer {
+        Self::new(major, 0, 0)
+    }
+
+    pub const fn major(&self) -> u64 {
+        self.major
+    }
+
+    pub const fn minor(&self) -> u64 {
+        self.minor
+    }
+
+    pub const fn patch(&self) -> u64 {
+        self.patch
+    }
+
+    /// like [`Ord::cmp`], but usable in constants
+    pub const fn compare(&self, other: &SemVer) -> Ordering {
+        match compare_parts(self.major, other.major) {
+            Ordering::Equal => match compare_parts(self.minor, other.minor) {
+                Ordering::Equal => compare_parts(self.patch, other.patch),
+                ordering => ordering,
+            },
+            ordering => ordering,
+        }
+    }
+
+    /// Whether moving between `self` and `other` is a non-breaking change by cargo's caret rules:
+    /// the same major version, the same minor version while the major is 0, and for `0.0.x` only
+    /// the very same version.
+    pub const fn is_compatible_with(&self, other: &SemVer) -> bool {
+        match (self.major, self.minor) {
+            (0, 0) => self.compare(other).is_eq(),
+            (0, minor) => other.major == 0 && other.minor == minor,
+            (major, _) => other.major == major,
+        }
+    }
+
+    /// Like [`SemVer::from_str`], but also accepts what tools emit besides proper versions: a
+    /// leading `v`, missing minor or patch parts, which are 0 then, and parts with leading zeros.
+    /// `v1.2` is `1.2.0`, `2024.01.03` is `2024.1.3`.
+    pub fn parse_lenient(s: &str) -> Result<Self, ParseError> {
+        parse(s, true)
+    }
+}
+
+impl Ord for SemVer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.compare(other)
+    }
+}
+
+impl PartialOrd for SemVer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Default for SemVer {
+    fn default() -> Self {
+        Self::new_short(1)
+    }
+}
+
+impl Display for SemVer {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ParseError {
+    #[error("wrong number of parts, {0} (expected: 3)")]
+    WrongNumberOfParts(usize),
+    #[error("could not parse integer")]
+    ParseInt(#[from] ParseIntError),
+    #[error("part '{0}' is not a number")]
+    NotANumber(String),
+    #[error("part '{0}' has a leading zero")]
+    LeadingZero(String),
+}
+
+// impl std::fmt::Display for ParseError {
+//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+//         f.write_str("parse error")
+//     }
+// }
+
+// impl std::error::Error for ParseError {}
+
+// impl From<ParseIntError> for ParseError {
+//     fn from(e: ParseIntError) -> Self {
+//         ParseError::ParseInt(e)
+//     }
+// }
+
+/// a part of a version, only digits and without leading zeros unless `lenient`
+fn parse_part(part: &str, lenient: bool) -> Result<u64, ParseError> {
+    let number = part.parse()?;
+    // the only thing besides digits `parse` accepts
+    if part.starts_with('+') {
+        return Err(ParseError::NotANumber(part.to_string()));
+    }
+    if !lenient && part.len() > 1 && part.starts_with('0') {
+        return Err(ParseError::LeadingZero(part.to_string()));
+    }
+    Ok(number)
+}
+
+fn parse(s: &str, lenient: bool) -> Result<SemVer, ParseError> {
+    let s = match lenient {
+        true => s.strip_prefix('v').unwrap_or(s),
+        false => s,
+    };
+    let parts: Vec<&str> = s.split('.').collect();
+    if parts.len() != 3 && !(lenient && parts.len() < 3) {
+        return Err(ParseError::WrongNumberOfParts(parts.len()));
+    }
+    let mut numbers = [0; 3];
+    for (number, part) in numbers.iter_mut().zip(parts) {
+        *number = parse_part(part, lenient)?;
+    }
+    Ok(numbers.into())
+}
+
+/// `major.minor.patch` as the semver spec has it, digits only and without leading zeros
+impl FromStr for SemVer {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse(s, false)
+    }
+}
+
+impl TryFrom<String> for SemVer {
+    type Error = ParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<[u64; 3]> for SemVer {
+    fn from(value: [u64; 3]) -> Self {
+        SemVer {
+            major: value[0],
+            minor: value[1],
+            patch: value[2],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ONE: SemVer = SemVer::new(1, 0, 0);
+
+    #[test]
+    fn const_comparisons() {
+        const {
+            assert!(SemVer::new(1, 0, 1).compare(&ONE).is_gt());
+            assert!(ONE.is_compatible_with(&SemVer::new(1, 9, 0)));
+        };
+
+        let versions = [
+            [0, 0, 1],
+            [0, 2, 0],
+            [1, 0, 0],
+            [1, 0, 1],
+            [1, 2, 0],
+            [2, 0, 0],
+        ];
+        for a in versions.map(SemVer::from) {
+            for b in versions.map(SemVer::from) {
+                assert_eq!(
+                    a.major
+                        .cmp(&b.major)
+                        .then(a.minor.cmp(&b.minor))
+                        .then(a.patch.cmp(&b.patch)),
+                    a.cmp(&b)
+                );
+            }
+        }
+    }
+}
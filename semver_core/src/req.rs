@@ -0,0 +1,253 @@
+//! Version requirements like `^1.2` or `>=1.0, <2.0`.
+//!
+//! Requirements follow cargo: a bare version means `^`, versions may be partial like `1.2`, and
+//! `*`, `1.*` or `1.2.*` match anything with that prefix.
+
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Display;
+use core::str::FromStr;
+
+use crate::SemVer;
+
+/// most comparators a requirement may have
+const MAX_COMPARATORS: usize = 16;
+
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid version requirement '{0}', expected e.g. ^1.2 or >=1.0, <2.0")]
+pub struct ParseVersionReqError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Exact,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
+    Tilde,
+    Caret,
+}
+
+/// a single `op version` part of a requirement, missing parts match anything
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Comparator {
+    op: Op,
+    major: u64,
+    minor: Option<u64>,
+    patch: Option<u64>,
+}
+
+impl Comparator {
+    fn matches(&self, version: SemVer) -> bool {
+        // the parts of `version` given in the comparator, so `<=1.2` includes `1.2.9`
+        let given = [Some(self.major), self.minor, self.patch];
+        let len = given.iter().take_while(|part| part.is_some()).count();
+        let bound: Vec<u64> = given.iter().flatten().copied().collect();
+        let prefix = &[version.major, version.minor, version.patch][..len];
+        let lower = SemVer::new(self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0));
+        match self.op {
+            Op::Exact => prefix == bound,
+            Op::Greater => prefix > &bound[..],
+            Op::GreaterEq => prefix >= &bound[..],
+            Op::Less => prefix < &bound[..],
+            Op::LessEq => prefix <= &bound[..],
+            Op::Tilde => version >= lower && prefix[..len.min(2)] == bound[..len.min(2)],
+            Op::Caret => {
+                version >= lower
+                    && match (self.major, self.minor, self.patch) {
+                        (0, Some(0), Some(_)) => version == lower,
+                        (0, Some(minor), _) => version.major == 0 && version.minor == minor,
+                        (major, _, _) => version.major == major,
+                    }
+            }
+        }
+    }
+}
+
+impl FromStr for Comparator {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (op, version) = [
+            (">=", Op::GreaterEq),
+            ("<=", Op::LessEq),
+            (">", Op::Greater),
+            ("<", Op::Less),
+            ("=", Op::Exact),
+            ("~", Op::Tilde),
+            ("^", Op::Caret),
+        ]
+        .into_iter()
+        .find_map(|(symbol, op)| s.strip_prefix(symbol).map(|rest| (op, rest.trim_start())))
+        .unwrap_or((Op::Caret, s));
+        let mut parts = version.split('.');
+        let mut wildcard = false;
+        let mut part = |required: bool| -> Result<Option<u64>, ()> {
+            match parts.next() {
+                None if !required => Ok(None),
+                Some("*" | "x" | "X") if !required => {
+                    wildcard = true;
+                    Ok(None)
+                }
+                Some(_) if wildcard => Err(()),
+                Some(part) if !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()) => {
+                    part.parse().map(Some).map_err(|_| ())
+                }
+                _ => Err(()),
+            }
+        };
+        let major = part(true)?.ok_or(())?;
+        let minor = part(false)?;
+        let patch = match minor {
+            Some(_) => part(false)?,
+            None => None,
+        };
+        if parts.next().is_some() {
+            return Err(());
+        }
+        // `1.*` is `=1` whatever the operator, like cargo
+        let op = if wildcard { Op::Exact } else { op };
+        Ok(Self {
+            op,
+            major,
+            minor,
+            patch,
+        })
+    }
+}
+
+impl Display for Comparator {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let op = match self.op {
+            Op::Exact => "=",
+            Op::Greater => ">",
+            Op::GreaterEq => ">=",
+            Op::Less => "<",
+            Op::LessEq => "<=",
+            Op::Tilde => "~",
+            Op::Caret => "^",
+        };
+        write!(f, "{op}{}", self.major)?;
+        for part in [self.minor, self.patch].into_iter().flatten() {
+            write!(f, ".{part}")?;
+        }
+        Ok(())
+    }
+}
+
+/// A version requirement like in a manifest, matching versions that satisfy all comparators
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "String", into = "String")
+)]
+pub struct VersionReq {
+    /// empty for `*`
+    comparators: Vec<Comparator>,
+}
+
+impl VersionReq {
+    /// any version, `*`
+    pub const STAR: VersionReq = VersionReq {
+        comparators: Vec::new(),
+    };
+
+    pub fn matches(&self, version: SemVer) -> bool {
+        self.comparators.iter().all(|c| c.matches(version))
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = ParseVersionReqError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseVersionReqError(s.to_string());
+        if s.trim() == "*" {
+            return Ok(Self::STAR);
+        }
+        let comparators = s
+            .split(',')
+            .map(|c| c.parse().map_err(|_| invalid()))
+            .collect::<Result<Vec<Comparator>, _>>()?;
+        if comparators.len() > MAX_COMPARATORS {
+            return Err(invalid());
+        }
+        Ok(Self { comparators })
+    }
+}
+
+impl TryFrom<String> for VersionReq {
+    type Error = ParseVersionReqError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<VersionReq> for String {
+    fn from(req: VersionReq) -> Self {
+        req.to_string()
+    }
+}
+
+impl Display for VersionReq {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        if self.comparators.is_empty() {
+            return f.write_str("*");
+        }
+        for (i, comparator) in self.comparators.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{comparator}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    fn v(s: &str) -> SemVer {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn requirements() {
+        for (req_str, matching, other) in [
+            ("1.2.3", &["1.2.3", "1.9.0"][..], &["1.2.2", "2.0.0"][..]),
+            ("^0.2.3", &["0.2.3", "0.2.9"], &["0.3.0", "0.2.2"]),
+            ("^0.0.3", &["0.0.3"], &["0.0.4"]),
+            ("^0", &["0.0.1", "0.9.0"], &["1.0.0"]),
+            ("~1.2.3", &["1.2.3", "1.2.9"], &["1.3.0", "1.2.2"]),
+            ("~1", &["1.0.0", "1.9.9"], &["2.0.0"]),
+            ("=1.2", &["1.2.0", "1.2.9"], &["1.3.0"]),
+            ("1.*", &["1.0.0", "1.9.0"], &["2.0.0", "0.9.0"]),
+            ("*", &["0.0.1", "9.0.0"], &[]),
+            (">1.2", &["1.3.0"], &["1.2.9"]),
+            ("<=1.2", &["1.2.9", "0.1.0"], &["1.3.0"]),
+            (">=1.0, <2.0", &["1.0.0", "1.9.9"], &["2.0.0", "0.9.0"]),
+        ] {
+            let parsed = req(req_str);
+            for version in matching {
+                assert!(parsed.matches(v(version)), "{req_str} {version}");
+            }
+            for version in other {
+                assert!(!parsed.matches(v(version)), "{req_str} {version}");
+            }
+        }
+        for invalid in ["", "1.2.3.4", "^", ">=1, ", "1.*.3", "a.b", "1.-2", "=>1"] {
+            assert!(invalid.parse::<VersionReq>().is_err(), "{}", invalid);
+        }
+        assert_eq!(">=1.0, <2", req(" >= 1.0,<2").to_string());
+        assert_eq!("^1.2", req("1.2").to_string());
+        assert_eq!("*", req("*").to_string());
+    }
+}
@@ -0,0 +1,211 @@
+//! One line per answered request, kept apart from the debug log so it can be shipped and parsed
+//! on its own. Lines come in the [`AccessLogFormat::Combined`] format of web servers,
+//!
+//! ```text
+//! 127.0.0.1:50312 - - [17/Oct/2026:09:30:00 +0000] "AddCrate hello_bin" 200 9 1.234ms
+//! ```
+//!
+//! or as [`AccessLogFormat::Json`] objects. Status codes follow HTTP, see [`status_code`].
+
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, LineWriter, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::api::{ApiError, ApiResult};
+use crate::RepoError;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    #[default]
+    Combined,
+    Json,
+}
+
+#[derive(Error, Debug)]
+#[error("unknown access log format '{0}', expected 'combined' or 'json'")]
+pub struct ParseAccessLogFormatError(String);
+
+impl FromStr for AccessLogFormat {
+    type Err = ParseAccessLogFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "combined" => Ok(Self::Combined),
+            "json" => Ok(Self::Json),
+            _ => Err(ParseAccessLogFormatError(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccessLogConfig {
+    /// appended to, `-` writes to stdout
+    pub path: PathBuf,
+    pub format: AccessLogFormat,
+}
+
+/// What the access log records about a request
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub at: DateTime<Utc>,
+    pub peer: String,
+    /// see [`crate::api::ApiRequest::kind`]
+    pub request: &'static str,
+    #[serde(rename = "crate")]
+    pub crate_name: Option<String>,
+    pub status: u16,
+    /// length of the response
+    pub bytes: usize,
+    #[serde(rename = "latency_us", serialize_with = "micros")]
+    pub latency: Duration,
+}
+
+fn micros<S: serde::Serializer>(latency: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(latency.as_micros())
+}
+
+impl Entry {
+    pub fn format(&self, format: AccessLogFormat) -> String {
+        match format {
+            AccessLogFormat::Combined => self.to_string(),
+            AccessLogFormat::Json => serde_json::to_string(self).unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} - - [{}] \"{} {}\" {} {} {:.3}ms",
+            self.peer,
+            self.at.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.request,
+            self.crate_name.as_deref().unwrap_or("-"),
+            self.status,
+            self.bytes,
+            self.latency.as_secs_f64() * 1000.0
+        )
+    }
+}
+
+/// the HTTP status closest to a JSON response of the server
+pub fn status_code(response: &str) -> u16 {
+    if response.starts_with(r#"{"Ok""#) {
+        return 200;
+    }
+    match serde_json::from_str::<ApiResult<serde::de::IgnoredAny>>(response) {
+        Ok(Ok(_)) => 200,
+        Ok(Err(e)) => match e {
+            ApiError::Internal => 500,
+            ApiError::Unauthorized => 401,
+            ApiError::Repo(RepoError::NotFound) | ApiError::UnknownRegistry(_) => 404,
+            ApiError::Repo(RepoError::Forbidden | RepoError::NotOwner) => 403,
+            ApiError::Repo(RepoError::AlreadyExists)
+            | ApiError::ReadOnly
+            | ApiError::Conflict { .. }
+            | ApiError::IdempotencyKeyReused => 409,
+            ApiError::QuotaExceeded(_) => 429,
+            ApiError::Upstream(_) => 502,
+            _ => 400,
+        },
+        // e.g. a batch, which answers with a list
+        Err(_) => 200,
+    }
+}
+
+/// An open access log, shared by all listener threads
+pub struct AccessLog {
+    format: AccessLogFormat,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl AccessLog {
+    pub fn open(config: &AccessLogConfig) -> io::Result<Self> {
+        let out: Box<dyn Write + Send> = if config.path.as_os_str() == "-" {
+            Box::new(io::stdout())
+        } else {
+            let file: File = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&config.path)?;
+            Box::new(LineWriter::new(file))
+        };
+        Ok(Self {
+            format: config.format,
+            out: Mutex::new(out),
+        })
+    }
+
+    pub fn record(&self, entry: &Entry) {
+        let line = entry.format(self.format);
+        if let Err(e) = writeln!(self.out.lock().unwrap(), "{}", line) {
+            log::warn!("could not write access log - {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            at: Utc.with_ymd_and_hms(2026, 10, 17, 9, 30, 0).unwrap(),
+            peer: "127.0.0.1:50312".to_string(),
+            request: "AddCrate",
+            crate_name: Some("hello_bin".to_string()),
+            status: 200,
+            bytes: 9,
+            latency: Duration::from_micros(1234),
+        }
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            r#"127.0.0.1:50312 - - [17/Oct/2026:09:30:00 +0000] "AddCrate hello_bin" 200 9 1.234ms"#,
+            entry().format(AccessLogFormat::Combined)
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&entry().format(AccessLogFormat::Json)).unwrap();
+        assert_eq!("hello_bin", json["crate"]);
+        assert_eq!(1234, json["latency_us"]);
+        assert_eq!(200, json["status"]);
+        assert_eq!(Ok(AccessLogFormat::Json), "json".parse().map_err(drop));
+        assert!("xml".parse::<AccessLogFormat>().is_err());
+    }
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(200, status_code(r#"{"Ok":null}"#));
+        assert_eq!(200, status_code(r#"[{"Ok":null}]"#));
+        assert_eq!(401, status_code(r#"{"Err":"Unauthorized"}"#));
+        assert_eq!(404, status_code(r#"{"Err":{"Repo":"NotFound"}}"#));
+        assert_eq!(409, status_code(r#"{"Err":"ReadOnly"}"#));
+    }
+
+    #[test]
+    fn appends_lines() -> io::Result<()> {
+        let file = NamedTempFile::new()?;
+        let config = AccessLogConfig {
+            path: file.path().to_path_buf(),
+            format: AccessLogFormat::Combined,
+        };
+        AccessLog::open(&config)?.record(&entry());
+        AccessLog::open(&config)?.record(&entry());
+        assert_eq!(2, std::fs::read_to_string(file.path())?.lines().count());
+        Ok(())
+    }
+}
@@ -0,0 +1,593 @@
+//! Privileged operations, only reachable through [`crate::api::ApiRequest::Admin`] with the
+//! server's admin token.
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::advisories::Advisory;
+use crate::auth::Scope;
+use crate::blocklist::BlockedVersion;
+use crate::events::Event;
+use crate::import::{self, Release};
+use crate::names::NameRules;
+use crate::scheduler::TaskInfo;
+use crate::{Crate, Metadata, RepoError, Repository, SemVer};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AdminRequest {
+    /// removes a crate including its whole release history, servers ask for confirmation first,
+    /// see [`crate::confirmation`]
+    DeleteCrate(String),
+    /// needs confirming like `DeleteCrate`
+    TransferOwnership {
+        name: String,
+        new_author: String,
+    },
+    /// replaces the metadata of the crate with the same name
+    EditMetadata(Metadata),
+    RenameCrate {
+        from: String,
+        to: String,
+    },
+    /// backfills historical releases in any order, see [`crate::import`]
+    ImportReleases(String, Vec<Release>),
+    /// like `ImportReleases`, with versions as other tools write them if `lenient`, see
+    /// [`crate::import::parse_releases`]
+    ImportVersions {
+        name: String,
+        versions: Vec<String>,
+        #[serde(default)]
+        lenient: bool,
+    },
+    /// creates a namespace for scoped crates like `@name/crate` or replaces its owners
+    RegisterNamespace {
+        name: String,
+        owners: Vec<String>,
+    },
+    /// keeps anyone from publishing crates matching a glob pattern like `acme-*`,
+    /// see [`crate::names::NameRules`]
+    ReserveName(String),
+    /// keeps anyone from publishing crates with `term` anywhere in their name
+    BlockTerm(String),
+    /// removes a reserved name or blocked term
+    RemoveNameRule(String),
+    NameRules,
+    /// A new token identifying `user` in `ApiRequest::Authenticated`, see [`crate::auth`].
+    /// Without scopes it can do anything the user can, without `expires` it never expires.
+    IssueToken {
+        user: String,
+        #[serde(default)]
+        scopes: Vec<Scope>,
+        #[serde(default)]
+        expires: Option<DateTime<Utc>>,
+    },
+    /// re-keys the crate index from the crates' metadata
+    RebuildIndices,
+    /// drops change feed entries of deleted crates and saves the store
+    Compact,
+    Stats,
+    /// the server's background tasks, see [`crate::scheduler`]
+    Tasks,
+    /// runs the background task with this name right away
+    RunTask(String),
+    /// rereads the server's settings file, see [`crate::settings`]
+    Reload,
+    /// files a security advisory against a crate, see [`crate::advisories`]
+    FileAdvisory {
+        name: String,
+        advisory: Advisory,
+    },
+    WithdrawAdvisory {
+        name: String,
+        id: String,
+    },
+    /// keeps `version` from being resolved for everyone, see [`crate::blocklist`]
+    BlockVersion {
+        name: String,
+        version: SemVer,
+        reason: String,
+    },
+    UnblockVersion {
+        name: String,
+        version: SemVer,
+    },
+    BlockedVersions,
+    /// executes the request a `ConfirmationRequired` response is about, see
+    /// [`crate::confirmation`]
+    Confirm(String),
+}
+
+impl AdminRequest {
+    /// Running a task isn't, read-only servers only schedule tasks that leave the crates alone,
+    /// like saving.
+    pub fn is_mutating(&self) -> bool {
+        !matches!(
+            self,
+            AdminRequest::Stats
+                | AdminRequest::NameRules
+                | AdminRequest::BlockedVersions
+                | AdminRequest::Tasks
+                | AdminRequest::RunTask(_)
+                | AdminRequest::Reload
+        )
+    }
+
+    /// Whether the request does more than change the repository in memory, e.g. saves it, so
+    /// a dry run can't undo it.
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            AdminRequest::Compact
+                | AdminRequest::RunTask(_)
+                | AdminRequest::Reload
+                | AdminRequest::Confirm(_)
+        )
+    }
+
+    /// the crate the request is about, if any
+    pub fn crate_name(&self) -> Option<&str> {
+        match self {
+            AdminRequest::DeleteCrate(name)
+            | AdminRequest::TransferOwnership { name, .. }
+            | AdminRequest::ImportReleases(name, _)
+            | AdminRequest::ImportVersions { name, .. }
+            | AdminRequest::FileAdvisory { name, .. }
+            | AdminRequest::WithdrawAdvisory { name, .. }
+            | AdminRequest::BlockVersion { name, .. }
+            | AdminRequest::UnblockVersion { name, .. } => Some(name),
+            AdminRequest::EditMetadata(metadata) => Some(metadata.name()),
+            AdminRequest::RenameCrate { from, .. } => Some(from),
+            AdminRequest::RegisterNamespace { .. }
+            | AdminRequest::ReserveName(_)
+            | AdminRequest::BlockTerm(_)
+            | AdminRequest::RemoveNameRule(_)
+            | AdminRequest::NameRules
+            | AdminRequest::BlockedVersions
+            | AdminRequest::IssueToken { .. }
+            | AdminRequest::Confirm(_)
+            | AdminRequest::RebuildIndices
+            | AdminRequest::Compact
+            | AdminRequest::Stats
+            | AdminRequest::Tasks
+            | AdminRequest::RunTask(_)
+            | AdminRequest::Reload => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdminResponse {
+    Done,
+    Reindexed {
+        fixed: usize,
+    },
+    Compacted {
+        removed_changes: usize,
+    },
+    Stats(RepoStats),
+    Token(String),
+    Imported {
+        releases: usize,
+    },
+    NameRules(NameRules),
+    Tasks(Vec<TaskInfo>),
+    /// crate names with their blocked versions
+    BlockedVersions(Vec<(String, BlockedVersion)>),
+    /// the request was held back until `token` is sent with `AdminRequest::Confirm` within
+    /// `within_secs` seconds, see [`crate::confirmation`]
+    ConfirmationRequired {
+        token: String,
+        within_secs: u64,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoStats {
+    pub crates: usize,
+    pub releases: usize,
+    pub yanked: usize,
+    pub changes: usize,
+    pub last_seq: u64,
+}
+
+/// compares without short-circuiting, so response times don't leak how much of a token matched
+pub fn token_matches(expected: &str, given: &str) -> bool {
+    let (expected, given) = (expected.as_bytes(), given.as_bytes());
+    if expected.len() != given.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(given)
+        .fold(0, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+impl Repository {
+    pub fn handle_admin(&mut self, request: AdminRequest) -> Result<AdminResponse, RepoError> {
+        match request {
+            AdminRequest::DeleteCrate(name) => {
+                self.delete_crate(name)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::TransferOwnership { name, new_author } => {
+                self.transfer_ownership(name, new_author)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::EditMetadata(metadata) => {
+                self.edit_metadata(metadata)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::RenameCrate { from, to } => {
+                self.rename_crate(from, to)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::ImportReleases(name, releases) => Ok(AdminResponse::Imported {
+                releases: self.import_releases(name, releases)?,
+            }),
+            AdminRequest::ImportVersions {
+                name,
+                versions,
+                lenient,
+            } => {
+                let releases = import::parse_releases(versions, lenient)
+                    .map_err(|_| RepoError::InvalidVersion)?;
+                Ok(AdminResponse::Imported {
+                    releases: self.import_releases(name, releases)?,
+                })
+            }
+            AdminRequest::RegisterNamespace { name, owners } => {
+                self.register_namespace(name, owners)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::ReserveName(pattern) => {
+                self.name_rules_mut().reserve(pattern);
+                Ok(AdminResponse::NameRules(self.name_rules.clone()))
+            }
+            AdminRequest::BlockTerm(term) => {
+                self.name_rules_mut().block(term);
+                Ok(AdminResponse::NameRules(self.name_rules.clone()))
+            }
+            AdminRequest::RemoveNameRule(rule) => match self.name_rules_mut().remove(rule) {
+                true => Ok(AdminResponse::NameRules(self.name_rules.clone())),
+                false => Err(RepoError::NotFound),
+            },
+            AdminRequest::NameRules => Ok(AdminResponse::NameRules(self.name_rules.clone())),
+            AdminRequest::IssueToken {
+                user,
+                scopes,
+                expires,
+            } => Ok(AdminResponse::Token(
+                self.issue_token(user, scopes, expires),
+            )),
+            AdminRequest::RebuildIndices => Ok(AdminResponse::Reindexed {
+                fixed: self.rebuild_indices(),
+            }),
+            AdminRequest::Compact => Ok(AdminResponse::Compacted {
+                removed_changes: self.compact(),
+            }),
+            AdminRequest::FileAdvisory { name, advisory } => {
+                self.file_advisory(name, advisory)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::WithdrawAdvisory { name, id } => {
+                self.withdraw_advisory(name, id)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::BlockVersion {
+                name,
+                version,
+                reason,
+            } => {
+                self.block_version(name, version, reason)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::UnblockVersion { name, version } => {
+                self.unblock_version(name, version)?;
+                Ok(AdminResponse::Done)
+            }
+            AdminRequest::BlockedVersions => {
+                Ok(AdminResponse::BlockedVersions(self.blocked_versions()))
+            }
+            AdminRequest::Stats => Ok(AdminResponse::Stats(self.stats())),
+            // background tasks belong to the server, a bare repository has none
+            AdminRequest::Tasks => Ok(AdminResponse::Tasks(vec![])),
+            // held back requests are the server's, nothing is pending here
+            AdminRequest::Confirm(_) => Err(RepoError::NotFound),
+            AdminRequest::RunTask(_) | AdminRequest::Reload => Err(RepoError::NotFound),
+        }
+    }
+
+    pub fn delete_crate(&mut self, name: impl AsRef<str>) -> Result<Crate, RepoError> {
+        let crt = self
+            .crates
+            .remove(name.as_ref())
+            .ok_or(RepoError::NotFound)?;
+        self.aliases.retain(|_, target| target != name.as_ref());
+        self.reindex(name.as_ref());
+        self.changes.record(Event::CrateDeleted {
+            name: crt.metadata.name.clone(),
+        });
+        Ok(Arc::unwrap_or_clone(crt))
+    }
+
+    pub fn transfer_ownership(
+        &mut self,
+        name: impl AsRef<str>,
+        new_author: impl AsRef<str>,
+    ) -> Result<(), RepoError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        let mut metadata = crt.metadata.clone();
+        metadata.author = new_author.as_ref().into();
+        self.edit_metadata(metadata)
+    }
+
+    /// replaces the metadata of the crate called `metadata.name()`, checked by
+    /// [`Metadata::validated`]
+    pub fn edit_metadata(&mut self, metadata: Metadata) -> Result<(), RepoError> {
+        let mut metadata = metadata.validated()?;
+        self.intern_author(&mut metadata);
+        let crt = self
+            .crates
+            .get_mut(metadata.name())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        metadata.name = crt.metadata.name.clone();
+        crt.metadata = metadata.clone();
+        crt.revision += 1;
+        self.reindex(metadata.name());
+        self.changes.record(Event::MetadataChanged { metadata });
+        Ok(())
+    }
+
+    /// Renames a crate, the old name stays an alias so lookups keep finding it.
+    /// Fails if a crate called `to` exists already.
+    pub fn rename_crate(
+        &mut self,
+        from: impl AsRef<str>,
+        to: impl AsRef<str>,
+    ) -> Result<(), RepoError> {
+        let (from, to) = (from.as_ref(), to.as_ref());
+        self.check_name(to)?;
+        self.move_crate(from, to)?;
+        self.changes.record(Event::CrateRenamed {
+            from: from.into(),
+            to: to.into(),
+        });
+        Ok(())
+    }
+
+    /// renames without recording a change, for replication
+    pub(crate) fn move_crate(&mut self, from: &str, to: &str) -> Result<(), RepoError> {
+        if self.crates.contains_key(to) {
+            return Err(RepoError::AlreadyExists);
+        }
+        let mut crt = self.crates.remove(from).ok_or(RepoError::NotFound)?;
+        let moved = Arc::make_mut(&mut crt);
+        moved.metadata.name = to.into();
+        moved.revision += 1;
+        self.crates.insert(crt.metadata.name.clone(), crt);
+
+        self.aliases.remove(to);
+        // keep aliases pointing at the current name, so lookups never chain
+        for target in self.aliases.values_mut() {
+            if target == from {
+                *target = to.to_string();
+            }
+        }
+        self.aliases.insert(from.to_string(), to.to_string());
+        self.reindex(from);
+        self.reindex(to);
+        Ok(())
+    }
+
+    /// Makes sure every crate is filed under its own name, e.g. after a store has been edited by
+    /// hand. Returns the number of entries that had to be moved.
+    pub fn rebuild_indices(&mut self) -> usize {
+        let misfiled: Vec<Arc<str>> = self
+            .crates
+            .iter()
+            .filter(|(k, v)| k.as_ref() != v.metadata.name())
+            .map(|(k, _)| k.clone())
+            .collect();
+        for key in &misfiled {
+            if let Some(crt) = self.crates.remove(key) {
+                self.crates.insert(crt.metadata.name.clone(), crt);
+                self.mark_dirty();
+            }
+        }
+        self.reindex_all();
+        misfiled.len()
+    }
+
+    /// Removes change feed entries about crates that no longer exist and saves the store.
+    /// Returns the number of removed entries.
+    pub fn compact(&mut self) -> usize {
+        let (crates, aliases) = (&self.crates, &self.aliases);
+        let removed = self.changes.retain(|change| {
+            let name = change.event.crate_name();
+            // deletions and renames stay, so followers of the feed learn about them, and so does
+            // what happened to a live crate under its old names, replaying needs it
+            matches!(
+                change.event,
+                Event::CrateDeleted { .. } | Event::CrateRenamed { .. }
+            ) || crates.contains_key(name)
+                || aliases
+                    .get(name)
+                    .is_some_and(|target| crates.contains_key(target.as_str()))
+        });
+        if let Err(e) = self.save() {
+            log::error!("could not save compacted repository: {}", e);
+        }
+        removed
+    }
+
+    pub fn stats(&self) -> RepoStats {
+        RepoStats {
+            crates: self.crates.len(),
+            releases: self.crates.values().map(|c| c.release_history.len()).sum(),
+            yanked: self.crates.values().map(|c| c.yanked.len()).sum(),
+            changes: self.changes.since(0).len(),
+            last_seq: self.changes.last_seq(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, SemVer};
+
+    #[test]
+    fn admin_operations() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["hello_bin", "hello_moon"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.add_release("hello_moon", SemVer::new(1, 1, 0))?;
+
+        repo.handle_admin(AdminRequest::TransferOwnership {
+            name: "hello_bin".to_string(),
+            new_author: "Idle Person".to_string(),
+        })?;
+        assert_eq!(
+            "Idle Person",
+            repo.find_exact("hello_bin").unwrap().metadata.author()
+        );
+
+        repo.handle_admin(AdminRequest::DeleteCrate("hello_moon".to_string()))?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.handle_admin(AdminRequest::DeleteCrate("hello_moon".to_string()))
+        );
+
+        // crate added, release added, deletion
+        assert_eq!(
+            Ok(AdminResponse::Compacted { removed_changes: 2 }),
+            repo.handle_admin(AdminRequest::Compact)
+        );
+        assert_eq!(
+            Ok(AdminResponse::Stats(RepoStats {
+                crates: 1,
+                releases: 1,
+                yanked: 0,
+                changes: 3,
+                last_seq: 5,
+            })),
+            repo.handle_admin(AdminRequest::Stats)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rebuild_indices() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        let crt = repo.crates.remove("hello_bin").unwrap();
+        repo.crates.insert("oops".into(), crt);
+
+        assert_eq!(1, repo.rebuild_indices());
+        assert!(repo.find_exact("hello_bin").is_some());
+        assert_eq!(0, repo.rebuild_indices());
+        Ok(())
+    }
+
+    #[test]
+    fn rename() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["hello_bin", "hello_moon"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        assert_eq!(
+            Err(RepoError::AlreadyExists),
+            repo.rename_crate("hello_bin", "hello_moon")
+        );
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.rename_crate("nope", "hello_world")
+        );
+
+        repo.rename_crate("hello_bin", "hello_world")?;
+        repo.handle_admin(AdminRequest::RenameCrate {
+            from: "hello_world".to_string(),
+            to: "hello_universe".to_string(),
+        })?;
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!("hello_universe", crt.metadata().name());
+        assert_eq!(Some("hello_universe"), repo.resolve_alias("hello_world"));
+        repo.record_download("hello_world");
+        assert_eq!(1, repo.find_exact("hello_universe").unwrap().downloads());
+        repo.add_release("hello_world", SemVer::new(1, 1, 0))?;
+        repo.yank("hello_world", SemVer::new(1, 0, 0))?;
+        let crt = repo.find_exact("hello_universe").unwrap();
+        assert_eq!(Some(SemVer::new(1, 1, 0)), crt.latest());
+        assert!(crt.is_yanked(SemVer::new(1, 0, 0)));
+        assert_eq!(
+            Event::Yanked {
+                name: "hello_universe".into(),
+                version: SemVer::new(1, 0, 0)
+            },
+            repo.changes().since(0).last().unwrap().event
+        );
+
+        // taking over an old name ends the redirect
+        repo.add_crate(
+            Metadata::new("hello_bin", "Someone Else", CrateKind::Library),
+            SemVer::new(0, 1, 0),
+        )?;
+        assert_eq!(
+            "Someone Else",
+            repo.find_exact("hello_bin").unwrap().metadata().author()
+        );
+        assert_eq!(None, repo.resolve_alias("hello_bin"));
+        Ok(())
+    }
+
+    #[test]
+    fn compact_keeps_renamed() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.rename_crate("hello_bin", "hello_world")?;
+        repo.add_release("hello_world", SemVer::new(1, 2, 0))?;
+        assert_eq!(0, repo.compact());
+
+        let follower_store = NamedTempFile::new().unwrap();
+        let mut follower = Repository::new(&follower_store);
+        for change in repo.changes().since(0).to_vec() {
+            follower.apply(change)?;
+        }
+        assert_eq!(
+            repo.find_exact("hello_world").unwrap().release_history,
+            follower.find_exact("hello_bin").unwrap().release_history
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tokens() {
+        assert!(token_matches("s3cret", "s3cret"));
+        assert!(!token_matches("s3cret", "s3cre"));
+        assert!(!token_matches("s3cret", "s3creT"));
+    }
+}
@@ -0,0 +1,233 @@
+//! Security advisories against ranges of a crate's releases, like those of the RustSec
+//! database. Admins file and withdraw them, see [`crate::admin::AdminRequest::FileAdvisory`],
+//! anyone can look them up, and resolutions and search results point out affected releases.
+
+use std::fmt::Display;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::Event;
+use crate::resolve::VersionReq;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// longest advisory id, like `RUSTSEC-2024-0001`
+const MAX_ID_LEN: usize = 64;
+/// longest description
+pub const MAX_DESCRIPTION_LEN: usize = 16 * 1024;
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Severity {
+    Low,
+    #[default]
+    Medium,
+    High,
+    Critical,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        };
+        f.write_str(severity)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Advisory {
+    /// unique per crate, filing an advisory with the same id replaces it
+    pub id: String,
+    #[serde(default)]
+    pub severity: Severity,
+    /// the releases the advisory is about
+    pub affected: VersionReq,
+    #[serde(default)]
+    pub description: String,
+}
+
+impl Advisory {
+    pub fn affects(&self, version: SemVer) -> bool {
+        self.affected.matches(version)
+    }
+
+    fn validate(&self) -> Result<(), RepoError> {
+        let valid_id = !self.id.is_empty()
+            && self.id.len() <= MAX_ID_LEN
+            && self
+                .id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        match valid_id && self.description.len() <= MAX_DESCRIPTION_LEN {
+            true => Ok(()),
+            false => Err(RepoError::InvalidAdvisory),
+        }
+    }
+}
+
+impl Crate {
+    pub fn advisories(&self) -> &[Advisory] {
+        &self.advisories
+    }
+
+    /// the advisories affecting `version`
+    pub fn advisories_for(&self, version: SemVer) -> impl Iterator<Item = &Advisory> {
+        self.advisories
+            .iter()
+            .filter(move |advisory| advisory.affects(version))
+    }
+
+    /// the highest severity of the advisories affecting `version`, if any
+    pub fn advisory_severity(&self, version: SemVer) -> Option<Severity> {
+        self.advisories_for(version)
+            .map(|advisory| advisory.severity)
+            .max()
+    }
+
+    pub(crate) fn file_advisory(&mut self, advisory: Advisory) -> Result<(), RepoError> {
+        advisory.validate()?;
+        self.advisories.retain(|a| a.id != advisory.id);
+        self.advisories.push(advisory);
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub(crate) fn withdraw_advisory(&mut self, id: &str) -> Result<(), RepoError> {
+        let before = self.advisories.len();
+        self.advisories.retain(|a| a.id != id);
+        if self.advisories.len() == before {
+            return Err(RepoError::NotFound);
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Files `advisory` against the crate called `name`, replacing one with the same id
+    pub fn file_advisory(
+        &mut self,
+        name: impl AsRef<str>,
+        advisory: Advisory,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.file_advisory(advisory.clone())?;
+        self.changes.record(Event::AdvisoryFiled {
+            name: crt.metadata.name.clone(),
+            advisory,
+        });
+        Ok(())
+    }
+
+    pub fn withdraw_advisory(
+        &mut self,
+        name: impl AsRef<str>,
+        id: impl AsRef<str>,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.withdraw_advisory(id.as_ref())?;
+        self.changes.record(Event::AdvisoryWithdrawn {
+            name: crt.metadata.name.clone(),
+            id: id.as_ref().to_string(),
+        });
+        Ok(())
+    }
+
+    /// the advisories against the crate called `name`, only those affecting `version` if given
+    pub fn advisories(
+        &self,
+        name: impl AsRef<str>,
+        version: Option<SemVer>,
+    ) -> Result<Vec<Advisory>, RepoError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        Ok(crt
+            .advisories()
+            .iter()
+            .filter(|advisory| version.is_none_or(|version| advisory.affects(version)))
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::api::CrateSummary;
+    use crate::search::SearchOptions;
+    use crate::{CrateKind, Metadata};
+
+    fn advisory(id: &str, severity: Severity, affected: &str) -> Advisory {
+        Advisory {
+            id: id.into(),
+            severity,
+            affected: affected.parse().unwrap(),
+            description: "use after free".into(),
+        }
+    }
+
+    #[test]
+    fn advisories() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_lib", SemVer::new(1, 1, 0))?;
+        repo.file_advisory(
+            "hello_lib",
+            advisory("RUSTSEC-2024-0001", Severity::Low, "<1.1"),
+        )?;
+        repo.file_advisory(
+            "hello_lib",
+            advisory("RUSTSEC-2024-0002", Severity::High, ">=1.0, <2"),
+        )?;
+        assert_eq!(
+            Err(RepoError::InvalidAdvisory),
+            repo.file_advisory("hello_lib", advisory("no spaces", Severity::Low, "*"))
+        );
+
+        let ids = |advisories: Vec<Advisory>| -> Vec<String> {
+            advisories.into_iter().map(|advisory| advisory.id).collect()
+        };
+        assert_eq!(
+            vec!["RUSTSEC-2024-0002"],
+            ids(repo.advisories("hello_lib", Some(SemVer::new(1, 1, 0)))?)
+        );
+        assert_eq!(2, repo.advisories("hello_lib", None)?.len());
+
+        let resolution = repo.resolve("hello_lib", &"1.0".parse().unwrap(), false, false)?;
+        assert_eq!(vec!["RUSTSEC-2024-0002"], resolution.advisories);
+        let found = repo.find_containing("hello", SearchOptions::default());
+        assert_eq!(Some(Severity::High), CrateSummary::from(found[0]).advisory);
+
+        repo.withdraw_advisory("hello_lib", "RUSTSEC-2024-0002")?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.withdraw_advisory("hello_lib", "RUSTSEC-2024-0002")
+        );
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert_eq!(None, crt.advisory_severity(SemVer::new(1, 1, 0)));
+        assert_eq!(
+            Some(Severity::Low),
+            crt.advisory_severity(SemVer::new(1, 0, 0))
+        );
+        assert_eq!(5, repo.changes().last_seq());
+        Ok(())
+    }
+}
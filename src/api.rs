@@ -1,25 +1,548 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 
+use crate::admin::{AdminRequest, AdminResponse};
+use crate::advisories::{Advisory, Severity};
+use crate::audit::AuditEntry;
+use crate::auth::TokenInfo;
+use crate::channels::Channel;
+use crate::dependencies::{Dependency, DependencyDiagnostic};
+use crate::deprecation::Deprecation;
+use crate::events::Change;
+use crate::features::Features;
+use crate::orgs::{OrgRequest, Organization};
+use crate::platform::Platform;
+use crate::policy::PolicyViolation;
+use crate::preflight::PublishWarning;
+use crate::quota::Quota;
+use crate::replication::{FeedStatus, Snapshot};
+use crate::resolve::{Resolution, VersionReq};
+use crate::search::{Cursor, Page, SearchOptions};
 use crate::{Crate, Metadata, RepoError, SemVer};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ApiRequest {
     FindExact(String),
-    FindAllContaining(String),
+    FindAllContaining(String, SearchOptions),
+    /// Like `FindAllContaining`, but a page at a time. Pass the `next` cursor of a page to get the
+    /// following one, the limit of `options` is the page size.
+    FindAllContainingPage {
+        query: String,
+        #[serde(default)]
+        options: SearchOptions,
+        #[serde(default)]
+        cursor: Option<Cursor>,
+    },
+    /// crates matching a glob pattern like `serde*json`, see [`crate::Repository::find_matching`]
+    FindMatching(String),
+    /// crates whose name matches a regular expression, see [`crate::search::compile_pattern`]
+    FindRegex(String),
+    /// all crates of a namespace like `@myorg/…`, given without the `@`
+    ListNamespace(String),
+    /// full-text search over names, descriptions and keywords, best match first
+    Search(String),
     AddCrate(Metadata, SemVer),
     AddRelease(String, SemVer),
+    Yank(String, SemVer),
+    /// publishes a release to a channel other than the default `Stable`, see [`crate::channels`]
+    AddReleaseTo {
+        name: String,
+        version: SemVer,
+        channel: Channel,
+    },
+    /// the newest release that isn't yanked, in `channel` or a more stable one
+    LatestVersion {
+        name: String,
+        #[serde(default)]
+        channel: Channel,
+    },
+    /// the newest stable release that isn't yanked and is a non-breaking upgrade from `base`, see
+    /// [`crate::SemVer::is_compatible_with`]
+    LatestCompatible {
+        name: String,
+        base: SemVer,
+    },
+    /// the newest release matching `req` and why newer matching ones were skipped, see
+    /// [`crate::resolve`]
+    Resolve {
+        name: String,
+        req: VersionReq,
+        #[serde(default)]
+        include_prerelease: bool,
+        #[serde(default)]
+        include_yanked: bool,
+    },
+    /// the advisories against a crate, only those affecting `version` if given
+    Advisories {
+        name: String,
+        #[serde(default)]
+        version: Option<SemVer>,
+    },
+    /// adds a crate with all of its releases, or nothing if any release is invalid
+    PublishAtomic {
+        metadata: Metadata,
+        releases: Vec<SemVer>,
+    },
+    /// like `PublishAtomic`, with versions as other tools write them if `lenient`, see
+    /// [`crate::import::parse_versions`]
+    PublishVersions {
+        metadata: Metadata,
+        versions: Vec<String>,
+        #[serde(default)]
+        lenient: bool,
+    },
+    /// Deprecates `version`, or the whole crate if `None`. A `deprecation` of `None` lifts it.
+    /// Requires an `Authenticated` request by an owner, see [`crate::Repository::check_owner`].
+    Deprecate {
+        name: String,
+        #[serde(default)]
+        version: Option<SemVer>,
+        deprecation: Option<Deprecation>,
+    },
+    /// Attaches release notes to `version`, replacing earlier ones. `None` removes them.
+    /// Requires an `Authenticated` request by an owner, like `Deprecate`.
+    SetReleaseNotes {
+        name: String,
+        version: SemVer,
+        notes: Option<String>,
+    },
+    /// the release notes of versions newer than `from` up to and including `to`, newest first,
+    /// concatenated as `## <version>` sections, see [`crate::changelog`]
+    Changelog(String, SemVer, SemVer),
+    /// Attaches a markdown README to `version`, replacing an earlier one. `None` removes it.
+    /// Capped at [`crate::readme::MAX_README_LEN`] bytes, requires an `Authenticated` request by
+    /// an owner, like `Deprecate`.
+    SetReadme {
+        name: String,
+        version: SemVer,
+        readme: Option<String>,
+    },
+    /// the README of a version, `None` if it has none
+    GetReadme(String, SemVer),
+    /// Records the minimum Rust version and targets of `version`, replacing what was known
+    /// before. Requires an `Authenticated` request by an owner, like `Deprecate`.
+    SetPlatform {
+        name: String,
+        version: SemVer,
+        platform: Platform,
+    },
+    /// Records the cargo features of `version`, replacing earlier ones. Requires an
+    /// `Authenticated` request by an owner, like `Deprecate`.
+    SetFeatures {
+        name: String,
+        version: SemVer,
+        features: Features,
+    },
+    /// the features of a version, empty if none are known, see [`crate::features`]
+    GetFeatures(String, SemVer),
+    /// Records the dependencies of `version`, replacing earlier ones, unless they are rejected
+    /// with [`ApiError::DependenciesRejected`]. Answers with warnings about them. Requires an
+    /// `Authenticated` request by an owner, like `Deprecate`.
+    SetDependencies {
+        name: String,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    },
+    /// replaces a crate's metadata, see [`crate::Repository::update_metadata`].
+    /// Requires an `Authenticated` request.
+    UpdateMetadata(Metadata),
+    /// manages organizations, see [`crate::orgs`]. Requires an `Authenticated` request.
+    Org(OrgRequest),
+    /// The tokens of the authenticated user, or of every user for tokens with the `admin` scope,
+    /// see [`crate::auth`]. Requires an `Authenticated` request.
+    Tokens,
+    /// revokes a token by the id [`ApiRequest::Tokens`] lists it with, like `Tokens` only one of
+    /// the user's own without the `admin` scope
+    RevokeToken(String),
+    /// a read-only GraphQL query, see [`crate::graphql`]
+    #[cfg(feature = "graphql")]
+    GraphQL {
+        query: String,
+        #[serde(default)]
+        variables: serde_json::Value,
+    },
+    /// audit entries of a crate with a sequence number greater than `since`, see [`crate::audit`]
+    AuditLog(String, u64),
+    /// long-poll for changes with a sequence number greater than `since`.
+    /// Answers immediately if there are any, otherwise waits for the next mutation or times out
+    /// with an empty list.
+    Subscribe {
+        since: u64,
+    },
+    FeedStatus,
+    /// answers right away with the [`ServerInfo`], to check connectivity and compatibility
+    Ping,
+    /// the complete repository, for followers that are too far behind to catch up via `Subscribe`
+    Snapshot,
+    /// Runs several requests in one round trip, answering with their results in order.
+    /// If `transactional`, the first failure undoes everything and aborts the batch.
+    Batch {
+        requests: Vec<ApiRequest>,
+        #[serde(default)]
+        transactional: bool,
+    },
+    /// Executes `request` once per `key` and user. Retrying with the same key replays the original
+    /// response, see [`crate::idempotency`]. Only honoured at the top level, not inside batches.
+    Idempotent {
+        key: String,
+        request: Box<ApiRequest>,
+    },
+    /// Executes `request` only if the crate it is about is still at `revision`, answering with
+    /// `ApiError::Conflict` otherwise. Use revision 0 to require that the crate doesn't exist.
+    IfRevision {
+        revision: u64,
+        request: Box<ApiRequest>,
+    },
+    /// executes `request` on behalf of the user `token` was issued to, see [`crate::auth`]
+    Authenticated {
+        token: String,
+        request: Box<ApiRequest>,
+    },
+    /// Executes `request` in the registry called `name` instead of the default one, see
+    /// [`crate::registries`]. Only honoured at the top level, not inside batches.
+    Registry {
+        name: String,
+        request: Box<ApiRequest>,
+    },
+    /// Executes `request` as part of the client's distributed trace, `traceparent` is a W3C
+    /// trace context like `00-<trace id>-<span id>-01`. Servers without the `otel` feature ignore
+    /// it, see [`crate::telemetry`]. Only honoured at the top level, not inside batches.
+    Traced {
+        traceparent: String,
+        request: Box<ApiRequest>,
+    },
+    /// Validates and executes `request` like it was sent on its own, then undoes whatever it
+    /// changed, e.g. for pre-checks in CI. Answers with a [`DryRunOutcome`], or the error the
+    /// request would have failed with. Changes nothing, so read-only servers answer it too.
+    DryRun {
+        request: Box<ApiRequest>,
+    },
+    /// What's wrong or suspicious about publishing `version` of the crate, as a new crate or a
+    /// release of an existing one, so CI can gate releases on policy. Answers with a list of
+    /// [`PublishWarning`]s, empty if there's nothing to point out. Publishes nothing.
+    CheckPublish(Metadata, SemVer),
+    /// privileged operations, rejected unless `token` matches the server's admin token
+    Admin {
+        token: String,
+        request: AdminRequest,
+    },
+}
+
+impl ApiRequest {
+    /// whether the request changes the repository, i.e. is rejected by read-only servers
+    pub fn is_mutating(&self) -> bool {
+        match self {
+            ApiRequest::FindExact(_)
+            | ApiRequest::FindAllContaining(..)
+            | ApiRequest::FindAllContainingPage { .. }
+            | ApiRequest::FindMatching(_)
+            | ApiRequest::FindRegex(_)
+            | ApiRequest::Search(_)
+            | ApiRequest::ListNamespace(_)
+            | ApiRequest::Subscribe { .. }
+            | ApiRequest::FeedStatus
+            | ApiRequest::Ping
+            | ApiRequest::Snapshot
+            | ApiRequest::LatestVersion { .. }
+            | ApiRequest::LatestCompatible { .. }
+            | ApiRequest::Resolve { .. }
+            | ApiRequest::Advisories { .. }
+            | ApiRequest::AuditLog(..)
+            | ApiRequest::Changelog(..)
+            | ApiRequest::GetReadme(..)
+            | ApiRequest::GetFeatures(..)
+            | ApiRequest::Tokens
+            | ApiRequest::DryRun { .. }
+            | ApiRequest::CheckPublish(..) => false,
+            #[cfg(feature = "graphql")]
+            ApiRequest::GraphQL { .. } => false,
+            ApiRequest::AddCrate(..)
+            | ApiRequest::AddRelease(..)
+            | ApiRequest::AddReleaseTo { .. }
+            | ApiRequest::Yank(..)
+            | ApiRequest::PublishAtomic { .. }
+            | ApiRequest::PublishVersions { .. }
+            | ApiRequest::UpdateMetadata(_)
+            | ApiRequest::Deprecate { .. }
+            | ApiRequest::SetReleaseNotes { .. }
+            | ApiRequest::SetReadme { .. }
+            | ApiRequest::SetPlatform { .. }
+            | ApiRequest::SetFeatures { .. }
+            | ApiRequest::SetDependencies { .. }
+            | ApiRequest::RevokeToken(_) => true,
+            ApiRequest::Org(request) => request.is_mutating(),
+            ApiRequest::Admin { request, .. } => request.is_mutating(),
+            ApiRequest::Batch { requests, .. } => requests.iter().any(ApiRequest::is_mutating),
+            ApiRequest::Idempotent { request, .. }
+            | ApiRequest::IfRevision { request, .. }
+            | ApiRequest::Authenticated { request, .. }
+            | ApiRequest::Registry { request, .. }
+            | ApiRequest::Traced { request, .. } => request.is_mutating(),
+        }
+    }
+
+    /// the name of the variant, that of the wrapped request for envelopes
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ApiRequest::FindExact(_) => "FindExact",
+            ApiRequest::FindAllContaining(..) => "FindAllContaining",
+            ApiRequest::FindAllContainingPage { .. } => "FindAllContainingPage",
+            ApiRequest::FindMatching(_) => "FindMatching",
+            ApiRequest::FindRegex(_) => "FindRegex",
+            ApiRequest::ListNamespace(_) => "ListNamespace",
+            ApiRequest::Search(_) => "Search",
+            ApiRequest::AddCrate(..) => "AddCrate",
+            ApiRequest::AddRelease(..) => "AddRelease",
+            ApiRequest::Yank(..) => "Yank",
+            ApiRequest::AddReleaseTo { .. } => "AddReleaseTo",
+            ApiRequest::LatestVersion { .. } => "LatestVersion",
+            ApiRequest::LatestCompatible { .. } => "LatestCompatible",
+            ApiRequest::Resolve { .. } => "Resolve",
+            ApiRequest::Advisories { .. } => "Advisories",
+            ApiRequest::PublishAtomic { .. } => "PublishAtomic",
+            ApiRequest::PublishVersions { .. } => "PublishVersions",
+            ApiRequest::Deprecate { .. } => "Deprecate",
+            ApiRequest::SetReleaseNotes { .. } => "SetReleaseNotes",
+            ApiRequest::Changelog(..) => "Changelog",
+            ApiRequest::SetReadme { .. } => "SetReadme",
+            ApiRequest::GetReadme(..) => "GetReadme",
+            ApiRequest::SetPlatform { .. } => "SetPlatform",
+            ApiRequest::SetFeatures { .. } => "SetFeatures",
+            ApiRequest::GetFeatures(..) => "GetFeatures",
+            ApiRequest::SetDependencies { .. } => "SetDependencies",
+            ApiRequest::UpdateMetadata(_) => "UpdateMetadata",
+            ApiRequest::Org(_) => "Org",
+            ApiRequest::Tokens => "Tokens",
+            ApiRequest::RevokeToken(_) => "RevokeToken",
+            #[cfg(feature = "graphql")]
+            ApiRequest::GraphQL { .. } => "GraphQL",
+            ApiRequest::AuditLog(..) => "AuditLog",
+            ApiRequest::Subscribe { .. } => "Subscribe",
+            ApiRequest::FeedStatus => "FeedStatus",
+            ApiRequest::Ping => "Ping",
+            ApiRequest::CheckPublish(..) => "CheckPublish",
+            ApiRequest::Snapshot => "Snapshot",
+            ApiRequest::Batch { .. } => "Batch",
+            ApiRequest::Admin { .. } => "Admin",
+            ApiRequest::Idempotent { request, .. }
+            | ApiRequest::IfRevision { request, .. }
+            | ApiRequest::Authenticated { request, .. }
+            | ApiRequest::Registry { request, .. }
+            | ApiRequest::Traced { request, .. }
+            | ApiRequest::DryRun { request } => request.kind(),
+        }
+    }
+
+    /// the crate the request is about, if it is about a single one
+    pub fn crate_name(&self) -> Option<&str> {
+        match self {
+            ApiRequest::FindExact(name)
+            | ApiRequest::AddRelease(name, _)
+            | ApiRequest::Yank(name, _)
+            | ApiRequest::AuditLog(name, _)
+            | ApiRequest::Changelog(name, ..)
+            | ApiRequest::SetReleaseNotes { name, .. }
+            | ApiRequest::GetReadme(name, _)
+            | ApiRequest::SetReadme { name, .. }
+            | ApiRequest::SetPlatform { name, .. }
+            | ApiRequest::SetFeatures { name, .. }
+            | ApiRequest::GetFeatures(name, _)
+            | ApiRequest::SetDependencies { name, .. }
+            | ApiRequest::AddReleaseTo { name, .. }
+            | ApiRequest::LatestVersion { name, .. }
+            | ApiRequest::LatestCompatible { name, .. }
+            | ApiRequest::Resolve { name, .. }
+            | ApiRequest::Advisories { name, .. }
+            | ApiRequest::Deprecate { name, .. } => Some(name),
+            ApiRequest::AddCrate(metadata, _)
+            | ApiRequest::PublishAtomic { metadata, .. }
+            | ApiRequest::PublishVersions { metadata, .. }
+            | ApiRequest::UpdateMetadata(metadata)
+            | ApiRequest::CheckPublish(metadata, _) => Some(metadata.name()),
+            ApiRequest::Idempotent { request, .. }
+            | ApiRequest::IfRevision { request, .. }
+            | ApiRequest::Authenticated { request, .. }
+            | ApiRequest::Registry { request, .. }
+            | ApiRequest::Traced { request, .. }
+            | ApiRequest::DryRun { request } => request.crate_name(),
+            ApiRequest::Admin { request, .. } => request.crate_name(),
+            ApiRequest::FindAllContaining(..)
+            | ApiRequest::FindAllContainingPage { .. }
+            | ApiRequest::FindMatching(_)
+            | ApiRequest::FindRegex(_)
+            | ApiRequest::Search(_)
+            | ApiRequest::ListNamespace(_)
+            | ApiRequest::Org(_)
+            | ApiRequest::Tokens
+            | ApiRequest::RevokeToken(_)
+            | ApiRequest::Subscribe { .. }
+            | ApiRequest::FeedStatus
+            | ApiRequest::Ping
+            | ApiRequest::Snapshot
+            | ApiRequest::Batch { .. } => None,
+            #[cfg(feature = "graphql")]
+            ApiRequest::GraphQL { .. } => None,
+        }
+    }
+}
+
+/// version of the request and response types, bumped on changes old clients can't handle
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What `Ping` answers with
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    /// of the server crate
+    pub version: String,
+    /// see [`PROTOCOL_VERSION`]
+    pub protocol: u32,
+}
+
+impl ServerInfo {
+    /// of this build
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            protocol: PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// What `DryRun` answers with
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunOutcome {
+    /// the serialized result the request would have had on its own
+    pub result: serde_json::Value,
+    /// the changes it would have recorded
+    pub changes: Vec<Change>,
+}
+
+/// What searches answer with. Serialized straight from the repository's crates, the whole crate
+/// with its releases is only available through `FindExact`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateSummary<'a> {
+    pub name: Cow<'a, str>,
+    pub author: Cow<'a, str>,
+    /// see [`Crate::latest`]
+    pub latest: Option<SemVer>,
+    /// the highest severity of the advisories affecting `latest`, see [`crate::advisories`]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub advisory: Option<Severity>,
+}
+
+impl<'a> From<&'a Crate> for CrateSummary<'a> {
+    fn from(crt: &'a Crate) -> Self {
+        Self {
+            name: Cow::Borrowed(crt.metadata().name()),
+            author: Cow::Borrowed(crt.metadata().author()),
+            latest: crt.latest(),
+            advisory: crt
+                .latest()
+                .and_then(|latest| crt.advisory_severity(latest)),
+        }
+    }
+}
+
+/// A request tagged with a client-chosen `id`. Sending one as the first line of a connection
+/// switches it to pipelining: any number of tagged requests may follow, each answered by a
+/// [`TaggedResponse`] with the same `id` as soon as it is done, in no particular order.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaggedRequest {
+    pub id: u64,
+    pub request: ApiRequest,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaggedResponse {
+    pub id: u64,
+    /// the `ApiResult` the request would have had on its own
+    pub response: Box<serde_json::value::RawValue>,
 }
 
 use thiserror::Error;
-#[derive(Error, Debug, Serialize, Deserialize)]
+#[derive(Error, Debug, Clone, Serialize, Deserialize)]
 pub enum ApiError {
     #[error("internal")]
     Internal,
     #[error("{0:?}")]
     Repo(#[from] RepoError),
+    #[error("unauthorized")]
+    Unauthorized,
+    /// the token is valid, but lacks the scope for a request of this kind, see [`crate::auth`]
+    #[error("token lacks the scope for {0}")]
+    OutOfScope(String),
+    #[error("server is read-only")]
+    ReadOnly,
+    /// too many connections or queued requests, nothing was executed, see
+    /// [`crate::backpressure`]
+    #[error("server is overloaded, try again later")]
+    Overloaded,
+    #[error("upstream registry failed: {0}")]
+    Upstream(String),
+    #[error("batch aborted at request {index}: {error}")]
+    BatchAborted { index: usize, error: Box<ApiError> },
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(String),
+    #[error("expected revision {expected}, found {actual}")]
+    Conflict { expected: u64, actual: u64 },
+    #[error("request is not about a single crate")]
+    NoCrate,
+    #[error("idempotency key was already used for a different request")]
+    IdempotencyKeyReused,
+    #[error("quota exceeded: {0:?}")]
+    QuotaExceeded(Quota),
+    #[error("rejected by policy {}: {}", .0.policy, .0.reason)]
+    PolicyViolation(PolicyViolation),
+    #[error("unknown registry '{0}'")]
+    UnknownRegistry(String),
+    /// see [`crate::confirmation`]
+    #[error("unknown or expired confirmation token")]
+    InvalidConfirmation,
+    /// destructive admin requests have to be sent on their own, to be confirmed
+    #[error("destructive admin requests can't be part of another request")]
+    ConfirmationRequired,
+    #[error("settings were not reloaded: {0}")]
+    InvalidSettings(String),
+    /// the admin request does more than change the repository, see
+    /// [`AdminRequest::has_side_effects`]
+    #[error("admin requests that save, reload or run tasks can't be dry run")]
+    NotDryRunnable,
+    /// every diagnostic, at least one of them an error, see [`crate::dependencies`]
+    #[error("dependencies rejected: {0:?}")]
+    DependenciesRejected(Vec<DependencyDiagnostic>),
 }
 
 pub type ApiResult<T> = Result<T, ApiError>;
 pub type AddResult = ApiResult<()>;
 pub type FindExactResult = ApiResult<Option<Crate>>;
-pub type FindAllContainingResult = ApiResult<Vec<Crate>>;
+pub type LatestVersionResult = ApiResult<Option<SemVer>>;
+pub type LatestCompatibleResult = ApiResult<Option<SemVer>>;
+pub type ResolveResult = ApiResult<Resolution>;
+pub type AdvisoriesResult = ApiResult<Vec<Advisory>>;
+pub type FindAllContainingResult = ApiResult<Vec<CrateSummary<'static>>>;
+pub type FindAllContainingPageResult = ApiResult<Page<CrateSummary<'static>>>;
+pub type FindMatchingResult = ApiResult<Vec<CrateSummary<'static>>>;
+pub type FindRegexResult = ApiResult<Vec<CrateSummary<'static>>>;
+pub type SearchResult = ApiResult<Vec<CrateSummary<'static>>>;
+pub type ListNamespaceResult = ApiResult<Vec<CrateSummary<'static>>>;
+pub type SubscribeResult = ApiResult<Vec<Change>>;
+pub type AdminResult = ApiResult<AdminResponse>;
+pub type FeedStatusResult = ApiResult<FeedStatus>;
+pub type SnapshotResult = ApiResult<Snapshot>;
+pub type PingResult = ApiResult<ServerInfo>;
+pub type DryRunResult = ApiResult<DryRunOutcome>;
+pub type CheckPublishResult = ApiResult<Vec<PublishWarning>>;
+pub type OrgResult = ApiResult<Organization>;
+pub type TokensResult = ApiResult<Vec<TokenInfo>>;
+pub type AuditLogResult = ApiResult<Vec<AuditEntry>>;
+pub type ChangelogResult = ApiResult<String>;
+pub type GetReadmeResult = ApiResult<Option<String>>;
+pub type GetFeaturesResult = ApiResult<Features>;
+/// the warnings about the dependencies, see [`crate::dependencies::DependencyDiagnostic`]
+pub type SetDependenciesResult = ApiResult<Vec<DependencyDiagnostic>>;
+/// a GraphQL response of `data` and `errors`
+#[cfg(feature = "graphql")]
+pub type GraphQLResult = ApiResult<serde_json::Value>;
+/// one result per request, each being the serialized result the request would have had on its own
+pub type BatchResult = ApiResult<Vec<ApiResult<serde_json::Value>>>;
@@ -0,0 +1,97 @@
+//! Append-only record of who changed which crate, and when.
+//!
+//! Unlike the change feed, the audit log is never compacted and keeps the history of deleted
+//! crates. It is persisted in the store together with the repository. Followers don't keep one,
+//! the audit log lives on the server that accepted the change.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{Change, Event};
+use crate::Repository;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// position of the change in the change feed
+    pub seq: u64,
+    pub at: DateTime<Utc>,
+    /// where the change came from, e.g. the client's address
+    pub actor: String,
+    pub event: Event,
+}
+
+/// audit entries by crate name
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AuditLog {
+    entries: HashMap<String, Vec<AuditEntry>>,
+}
+
+impl AuditLog {
+    pub fn record(&mut self, actor: &str, change: &Change) {
+        self.entries
+            .entry(change.event.crate_name().to_string())
+            .or_default()
+            .push(AuditEntry {
+                seq: change.seq,
+                at: change.at,
+                actor: actor.to_string(),
+                event: change.event.clone(),
+            });
+    }
+
+    /// entries about the crate called `name` with a sequence number greater than `since`
+    pub fn since(&self, name: &str, since: u64) -> &[AuditEntry] {
+        let entries = match self.entries.get(name) {
+            Some(entries) => entries,
+            None => return &[],
+        };
+        let start = entries.partition_point(|entry| entry.seq <= since);
+        &entries[start..]
+    }
+}
+
+impl Repository {
+    /// attributes all changes after `since` to `actor`
+    pub fn audit(&mut self, actor: &str, since: u64) {
+        for change in self.changes.since(since) {
+            self.audit.record(actor, change);
+            self.mutations += 1;
+        }
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer};
+
+    #[test]
+    fn survives_deletion() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.audit("127.0.0.1", 0);
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.delete_crate("hello_bin")?;
+        repo.audit("admin@127.0.0.1", 1);
+        repo.compact();
+
+        let log = repo.audit_log();
+        assert_eq!(3, log.since("hello_bin", 0).len());
+        let recent = log.since("hello_bin", 1);
+        assert_eq!(vec![2, 3], recent.iter().map(|e| e.seq).collect::<Vec<_>>());
+        assert_eq!("admin@127.0.0.1", recent[1].actor);
+        assert!(log.since("nope", 0).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+//! User tokens, issued by admins and sent with [`crate::api::ApiRequest::Authenticated`].
+//!
+//! Only hashes of the tokens are stored, a lost token can't be recovered, only replaced. Tokens
+//! may be limited to some [`Scope`]s, e.g. for CI, and expire. Tokens without scopes can do
+//! anything their user can, like those issued before scopes existed.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::api::ApiRequest;
+use crate::{glob, RepoError, Repository};
+
+/// length of the public id of a token, a prefix of its hash
+const ID_LEN: usize = 16;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid scope '{0}', expected publish:<crate name glob>, yank or admin")]
+pub struct ParseScopeError(String);
+
+/// What a token may be used for, written like `publish:acme-*`, `yank` or `admin`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Scope {
+    /// publishing crates whose name matches a glob pattern, and changing their releases
+    Publish(String),
+    /// yanking releases of any crate the user may yank
+    Yank,
+    /// anything, including listing and revoking every user's tokens
+    Admin,
+}
+
+impl FromStr for Scope {
+    type Err = ParseScopeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "yank" => Ok(Scope::Yank),
+            "admin" => Ok(Scope::Admin),
+            _ => match s.strip_prefix("publish:") {
+                Some(pattern) if !pattern.is_empty() => Ok(Scope::Publish(pattern.to_string())),
+                _ => Err(ParseScopeError(s.to_string())),
+            },
+        }
+    }
+}
+
+impl TryFrom<String> for Scope {
+    type Error = ParseScopeError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Scope> for String {
+    fn from(scope: Scope) -> Self {
+        scope.to_string()
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::Publish(pattern) => write!(f, "publish:{}", pattern),
+            Scope::Yank => f.write_str("yank"),
+            Scope::Admin => f.write_str("admin"),
+        }
+    }
+}
+
+/// A token, without the secret itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// identifies the token when listing or revoking it, not enough to authenticate
+    pub id: String,
+    pub user: String,
+    /// empty for tokens that can do anything their user can
+    #[serde(default)]
+    pub scopes: Vec<Scope>,
+    #[serde(default)]
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub last_used: Option<DateTime<Utc>>,
+}
+
+impl TokenInfo {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        self.scopes.contains(&Scope::Admin)
+    }
+
+    /// Whether the scopes allow `request`, including every request it wraps. Reads are always
+    /// allowed, whether the user may do the rest is checked separately.
+    pub fn permits(&self, request: &ApiRequest) -> bool {
+        if self.scopes.is_empty() || self.is_admin() {
+            return true;
+        }
+        let publishes = |name: &str| {
+            self.scopes.iter().any(|scope| match scope {
+                Scope::Publish(pattern) => glob::matches(pattern, name),
+                _ => false,
+            })
+        };
+        match request {
+            ApiRequest::Batch { requests, .. } => requests.iter().all(|r| self.permits(r)),
+            ApiRequest::Idempotent { request, .. }
+            | ApiRequest::IfRevision { request, .. }
+            | ApiRequest::Registry { request, .. }
+            | ApiRequest::Traced { request, .. }
+            | ApiRequest::DryRun { request } => self.permits(request),
+            // checked against the token they carry
+            ApiRequest::Authenticated { .. } | ApiRequest::Admin { .. } => true,
+            ApiRequest::Yank(..) => self.scopes.contains(&Scope::Yank),
+            // revoking its own token is always fine
+            ApiRequest::RevokeToken(_) => true,
+            request if !request.is_mutating() => true,
+            request => request.crate_name().is_some_and(publishes),
+        }
+    }
+}
+
+/// token hash → token
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "StoredTokens")]
+pub struct Tokens {
+    tokens: BTreeMap<String, TokenInfo>,
+}
+
+#[derive(Deserialize)]
+struct StoredTokens {
+    #[serde(default)]
+    tokens: BTreeMap<String, TokenInfo>,
+    /// token hash → user name, from before tokens had scopes
+    #[serde(default)]
+    users: BTreeMap<String, String>,
+}
+
+impl From<StoredTokens> for Tokens {
+    fn from(stored: StoredTokens) -> Self {
+        let mut tokens = stored.tokens;
+        for (hash, user) in stored.users {
+            let token = TokenInfo {
+                id: hash[..ID_LEN.min(hash.len())].to_string(),
+                user,
+                scopes: vec![],
+                expires: None,
+                last_used: None,
+            };
+            tokens.entry(hash).or_insert(token);
+        }
+        Self { tokens }
+    }
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl Tokens {
+    /// a new random token for `user`
+    pub fn issue(
+        &mut self,
+        user: impl AsRef<str>,
+        scopes: Vec<Scope>,
+        expires: Option<DateTime<Utc>>,
+    ) -> String {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("no randomness available");
+        let token = hex::encode(bytes);
+        let hash = hash(&token);
+        let info = TokenInfo {
+            id: hash[..ID_LEN].to_string(),
+            user: user.as_ref().to_string(),
+            scopes,
+            expires,
+            last_used: None,
+        };
+        self.tokens.insert(hash, info);
+        token
+    }
+
+    /// `token`, unless it is unknown or expired at `now`
+    pub fn get(&self, token: &str, now: DateTime<Utc>) -> Option<&TokenInfo> {
+        self.tokens
+            .get(&hash(token))
+            .filter(|info| !info.is_expired(now))
+    }
+
+    /// like [`Tokens::get`], recording that `token` was used at `now`
+    fn use_token(&mut self, token: &str, now: DateTime<Utc>) -> Option<&TokenInfo> {
+        let info = self
+            .tokens
+            .get_mut(&hash(token))
+            .filter(|info| !info.is_expired(now))?;
+        info.last_used = Some(now);
+        Some(info)
+    }
+
+    /// the tokens of `user`, or of everyone if `None`
+    pub fn list(&self, user: Option<&str>) -> Vec<TokenInfo> {
+        self.tokens
+            .values()
+            .filter(|info| user.is_none_or(|user| info.user == user))
+            .cloned()
+            .collect()
+    }
+
+    /// Revokes the token with the public `id`, only if it belongs to `user` unless that is `None`
+    pub fn revoke(&mut self, id: &str, user: Option<&str>) -> Result<TokenInfo, RepoError> {
+        let hash = self
+            .tokens
+            .iter()
+            .find(|(_, info)| info.id == id && user.is_none_or(|user| info.user == user))
+            .map(|(hash, _)| hash.clone())
+            .ok_or(RepoError::NotFound)?;
+        Ok(self.tokens.remove(&hash).expect("token was just found"))
+    }
+}
+
+impl Repository {
+    pub fn issue_token(
+        &mut self,
+        user: impl AsRef<str>,
+        scopes: Vec<Scope>,
+        expires: Option<DateTime<Utc>>,
+    ) -> String {
+        self.mark_dirty();
+        self.tokens.issue(user, scopes, expires)
+    }
+
+    /// `token` if it is known and hasn't expired, recording that it was used
+    pub fn authenticate(&mut self, token: &str) -> Option<&TokenInfo> {
+        let info = self.tokens.use_token(token, Utc::now())?;
+        self.mutations += 1;
+        Some(info)
+    }
+
+    /// the tokens of `user`, or of everyone if `None`
+    pub fn tokens(&self, user: Option<&str>) -> Vec<TokenInfo> {
+        self.tokens.list(user)
+    }
+
+    /// Revokes the token with the public `id`, only if it belongs to `user` unless that is `None`
+    pub fn revoke_token(&mut self, id: &str, user: Option<&str>) -> Result<(), RepoError> {
+        self.tokens.revoke(id, user)?;
+        self.mark_dirty();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn issue_and_authenticate() {
+        let mut tokens = Tokens::default();
+        let now = Utc::now();
+        let token = tokens.issue("Busy Person", vec![], None);
+        assert_eq!(64, token.len());
+        assert_eq!(
+            Some("Busy Person"),
+            tokens.get(&token, now).map(|info| info.user.as_str())
+        );
+        assert_eq!(None, tokens.get("guess", now));
+        assert_ne!(token, tokens.issue("Busy Person", vec![], None));
+        // tokens aren't stored in the clear
+        assert!(!serde_json::to_string(&tokens).unwrap().contains(&token));
+
+        let expiring = tokens.issue("Busy Person", vec![], Some(now + Duration::hours(1)));
+        assert!(tokens.get(&expiring, now).is_some());
+        assert_eq!(None, tokens.get(&expiring, now + Duration::hours(1)));
+    }
+
+    #[test]
+    fn tokens_without_scopes_still_load() {
+        let tokens: Tokens =
+            serde_json::from_str(r#"{"users": {"abcdef0123456789ff": "old"}}"#).unwrap();
+        let listed = tokens.list(None);
+        assert_eq!("old", listed[0].user);
+        assert_eq!("abcdef0123456789", listed[0].id);
+        assert!(listed[0].scopes.is_empty());
+    }
+
+    #[test]
+    fn scopes() {
+        assert_eq!(
+            Ok(Scope::Publish("acme-*".into())),
+            "publish:acme-*".parse()
+        );
+        assert_eq!("yank", Scope::Yank.to_string());
+        assert!("publish:".parse::<Scope>().is_err());
+        assert!("everything".parse::<Scope>().is_err());
+
+        let token = |scopes| TokenInfo {
+            id: "0".into(),
+            user: "ci".into(),
+            scopes,
+            expires: None,
+            last_used: None,
+        };
+        let metadata = |name| Metadata::new(name, "ci", CrateKind::Library);
+        let publish = token(vec![Scope::Publish("acme-*".into())]);
+        assert!(publish.permits(&ApiRequest::AddCrate(
+            metadata("acme-http"),
+            SemVer::new(1, 0, 0)
+        )));
+        assert!(!publish.permits(&ApiRequest::AddRelease(
+            "serde".into(),
+            SemVer::new(1, 0, 0)
+        )));
+        assert!(!publish.permits(&ApiRequest::Yank("acme-http".into(), SemVer::new(1, 0, 0))));
+        assert!(publish.permits(&ApiRequest::FindExact("serde".into())));
+        assert!(!publish.permits(&ApiRequest::Batch {
+            requests: vec![
+                ApiRequest::AddRelease("acme-http".into(), SemVer::new(1, 1, 0)),
+                ApiRequest::AddRelease("serde".into(), SemVer::new(1, 1, 0)),
+            ],
+            transactional: false,
+        }));
+        assert!(token(vec![Scope::Yank])
+            .permits(&ApiRequest::Yank("serde".into(), SemVer::new(1, 0, 0))));
+        assert!(token(vec![]).permits(&ApiRequest::Yank("serde".into(), SemVer::new(1, 0, 0))));
+    }
+
+    #[test]
+    fn list_and_revoke() {
+        let store = tempfile::NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let token = repo.issue_token("ci", vec![Scope::Yank], None);
+        repo.issue_token("someone", vec![], None);
+        let id = repo.authenticate(&token).unwrap().id.clone();
+        let listed = repo.tokens(Some("ci"));
+        assert_eq!(1, listed.len());
+        assert!(listed[0].last_used.is_some());
+        assert_eq!(2, repo.tokens(None).len());
+
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.revoke_token(&id, Some("someone"))
+        );
+        repo.revoke_token(&id, Some("ci")).unwrap();
+        assert_eq!(None, repo.authenticate(&token));
+        assert_eq!(1, repo.tokens(None).len());
+    }
+}
@@ -0,0 +1,75 @@
+//! Limits on concurrent connections and queued requests. Servers answer whatever exceeds them
+//! with [`crate::api::ApiError::Overloaded`] right away, instead of letting threads and waits for
+//! the repository pile up. Nothing was executed then, so clients may retry any request.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Counts what is active, up to an optional limit
+#[derive(Debug, Clone, Default)]
+pub struct Limiter {
+    limit: Option<usize>,
+    active: Arc<AtomicUsize>,
+}
+
+/// held while active, see [`Limiter::try_acquire`]
+#[derive(Debug)]
+pub struct Permit {
+    active: Arc<AtomicUsize>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.active.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl Limiter {
+    /// unlimited if `limit` is `None`
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            active: Arc::default(),
+        }
+    }
+
+    /// a permit if the limit isn't reached yet, it counts as active until dropped
+    pub fn try_acquire(&self) -> Option<Permit> {
+        self.active
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |active| {
+                match self.limit {
+                    Some(limit) if active >= limit => None,
+                    _ => Some(active + 1),
+                }
+            })
+            .ok()?;
+        Some(Permit {
+            active: self.active.clone(),
+        })
+    }
+
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits() {
+        let limiter = Limiter::new(Some(2));
+        let first = limiter.try_acquire().unwrap();
+        let _second = limiter.try_acquire().unwrap();
+        assert!(limiter.try_acquire().is_none());
+        assert_eq!(2, limiter.active());
+        drop(first);
+        let _third = limiter.try_acquire().unwrap();
+        assert_eq!(2, limiter.active());
+
+        let unlimited = Limiter::new(None);
+        let permits: Vec<_> = (0..100).filter_map(|_| unlimited.try_acquire()).collect();
+        assert_eq!(100, permits.len());
+    }
+}
@@ -0,0 +1,280 @@
+//! Synthetic traffic for sizing deployments, see `client bench`.
+//!
+//! Requests are sent at a fixed rate no matter how fast the server answers, so a slow server
+//! shows up as latency rather than as less traffic. Reads look up a crate created for the run,
+//! writes add new crates named `bench-<run>-<n>`, which stay in the repository.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use thiserror::Error;
+
+use crate::api::ApiRequest;
+use crate::client::{Client, ClientError};
+use crate::latency::Latencies;
+use crate::{CrateKind, Metadata, SemVer};
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum BenchError {
+    #[error("invalid mix '{0}', expected e.g. read:90,write:10")]
+    Mix(String),
+    #[error("invalid duration '{0}', expected e.g. 30s, 500ms or 2m")]
+    Duration(String),
+}
+
+/// Relative shares of reads and writes, e.g. `read:90,write:10`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mix {
+    pub read: u32,
+    pub write: u32,
+}
+
+impl Default for Mix {
+    fn default() -> Self {
+        Self {
+            read: 90,
+            write: 10,
+        }
+    }
+}
+
+impl Mix {
+    /// whether the `n`th request is a write, spreading writes evenly
+    fn is_write(&self, n: usize) -> bool {
+        let total = (self.read + self.write) as usize;
+        (n % total) * self.write as usize / total != ((n % total) + 1) * self.write as usize / total
+    }
+}
+
+impl FromStr for Mix {
+    type Err = BenchError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || BenchError::Mix(s.to_string());
+        let mut mix = Mix { read: 0, write: 0 };
+        for part in s.split(',') {
+            let (kind, share) = part.split_once(':').ok_or_else(invalid)?;
+            let share = share.trim().parse().map_err(|_| invalid())?;
+            match kind.trim() {
+                "read" => mix.read = share,
+                "write" => mix.write = share,
+                _ => return Err(invalid()),
+            }
+        }
+        match mix.read + mix.write {
+            0 => Err(invalid()),
+            _ => Ok(mix),
+        }
+    }
+}
+
+/// `30s`, `500ms` or `2m`
+pub fn parse_duration(s: &str) -> Result<Duration, BenchError> {
+    let invalid = || BenchError::Duration(s.to_string());
+    let split = s.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let value: u64 = s[..split].parse().map_err(|_| invalid())?;
+    match &s[split..] {
+        "ms" => Ok(Duration::from_millis(value)),
+        "s" => Ok(Duration::from_secs(value)),
+        "m" => Ok(Duration::from_secs(value * 60)),
+        _ => Err(invalid()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchConfig {
+    /// requests per second
+    pub rps: u32,
+    pub duration: Duration,
+    pub mix: Mix,
+    /// most requests in flight at a time, a server slower than `rps` allows for falls behind
+    pub concurrency: usize,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            rps: 100,
+            duration: Duration::from_secs(30),
+            mix: Mix::default(),
+            concurrency: 32,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub elapsed: Duration,
+    pub reads: Latencies,
+    pub writes: Latencies,
+    /// requests answered with an error or not at all
+    pub failed: usize,
+}
+
+impl Report {
+    pub fn sent(&self) -> usize {
+        self.reads.len() + self.writes.len() + self.failed
+    }
+
+    /// successful requests per second
+    pub fn throughput(&self) -> f64 {
+        (self.reads.len() + self.writes.len()) as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} requests in {:.1}s, {:.1} per second succeeded, {} failed",
+            self.sent(),
+            self.elapsed.as_secs_f64(),
+            self.throughput(),
+            self.failed
+        )?;
+        for (kind, latencies) in [("reads", &self.reads), ("writes", &self.writes)] {
+            if latencies.is_empty() {
+                continue;
+            }
+            writeln!(f, "\n{} ({}): {}", kind, latencies.len(), latencies)?;
+            let histogram = latencies.histogram();
+            let most = histogram.iter().map(|(_, count)| *count).max().unwrap_or(1);
+            for (bound, count) in histogram {
+                writeln!(
+                    f,
+                    "  <= {:>9.3}ms {:>7} {}",
+                    bound.as_secs_f64() * 1000.0,
+                    count,
+                    "#".repeat(count * 40 / most.max(1))
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// sends `config.rps` requests per second for `config.duration`
+pub fn run(client: &Client, config: &BenchConfig) -> Result<Report, ClientError> {
+    let run = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis();
+    let seed = format!("bench-{}", run);
+    client.add_crate(
+        Metadata::new(&seed, "bench", CrateKind::Library),
+        SemVer::new(1, 0, 0),
+    )?;
+
+    let interval = Duration::from_secs(1) / config.rps.max(1);
+    let total = (config.duration.as_secs_f64() * config.rps as f64) as usize;
+    let next = AtomicUsize::new(0);
+    let report = Mutex::new(Report::default());
+    let started = Instant::now();
+    let work = || loop {
+        let n = next.fetch_add(1, Ordering::Relaxed);
+        if n >= total {
+            break;
+        }
+        let due = started + interval * n as u32;
+        if let Some(wait) = due.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        let write = config.mix.is_write(n);
+        let request = match write {
+            true => ApiRequest::AddCrate(
+                Metadata::new(format!("{}-{}", seed, n), "bench", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            ),
+            false => ApiRequest::FindExact(seed.clone()),
+        };
+        let sent = Instant::now();
+        let res = client.request::<serde::de::IgnoredAny>(&request);
+        let latency = sent.elapsed();
+        let mut report = report.lock().unwrap();
+        match (res, write) {
+            (Ok(_), true) => report.writes.record(latency),
+            (Ok(_), false) => report.reads.record(latency),
+            (Err(e), _) => {
+                log::debug!("bench request failed: {}", e);
+                report.failed += 1;
+            }
+        }
+    };
+    thread::scope(|scope| {
+        for _ in 0..config.concurrency.clamp(1, total.max(1)) {
+            scope.spawn(work);
+        }
+    });
+    let mut report = report.into_inner().unwrap();
+    report.elapsed = started.elapsed();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::server::{Server, ServerConfig};
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Ok(Mix {
+                read: 90,
+                write: 10
+            }),
+            "read:90,write:10".parse()
+        );
+        assert_eq!(Ok(Mix { read: 0, write: 1 }), "write:1".parse());
+        assert!("read:0".parse::<Mix>().is_err());
+        assert!("delete:5".parse::<Mix>().is_err());
+        assert_eq!(Ok(Duration::from_secs(30)), parse_duration("30s"));
+        assert_eq!(Ok(Duration::from_millis(500)), parse_duration("500ms"));
+        assert_eq!(Ok(Duration::from_secs(120)), parse_duration("2m"));
+        assert!(parse_duration("30").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+
+    #[test]
+    fn mix_spreads_writes() {
+        let mix = Mix { read: 3, write: 1 };
+        let writes: Vec<_> = (0..8).map(|n| mix.is_write(n)).collect();
+        assert_eq!(2, writes.iter().filter(|w| **w).count());
+        assert!(!Mix { read: 1, write: 0 }.is_write(0));
+        assert!(Mix { read: 0, write: 1 }.is_write(0));
+    }
+
+    #[test]
+    fn runs() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let config = ServerConfig::new(store.path()).with_listen(vec!["127.0.0.1:0".parse()?]);
+        let server = Server::bind(config)?;
+        let client = Client::new(server.local_addrs()[0].to_string());
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.serve());
+
+        let report = run(
+            &client,
+            &BenchConfig {
+                rps: 200,
+                duration: Duration::from_millis(100),
+                mix: Mix { read: 1, write: 1 },
+                concurrency: 4,
+            },
+        )?;
+        assert_eq!(20, report.sent());
+        assert_eq!(0, report.failed);
+        assert_eq!(10, report.writes.len());
+        assert!(report.elapsed >= Duration::from_millis(95));
+        assert!(report.to_string().starts_with("20 requests in "));
+
+        shutdown.shutdown();
+        running.join().unwrap()?;
+        Ok(())
+    }
+}
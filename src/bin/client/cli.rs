@@ -0,0 +1,127 @@
+//! The client's command line. Usage, shell completions and the man page are all generated from
+//! [`Cli`] by clap, so none of them can drift from what is parsed.
+
+use std::io;
+use std::time::Duration;
+
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use semver_repo::bench::{self, Mix};
+
+use crate::output::OutputFormat;
+
+const ENVIRONMENT: &str = "\
+Environment:
+  REPO_ADDR    address of the server, e.g. [::1]:7878 or tcp://registry.local
+  REPO_PORT    port of the server on localhost without REPO_ADDR or a profile's address
+  REPO_TOKEN   token sent with requests of import-lock and run
+  REPO_CONFIG  client config with the profiles, ~/.config/semver_repo/config.toml by default";
+
+/// talks to a semver repository server, without a subcommand it runs a demo
+#[derive(Parser, Debug)]
+#[command(name = "client", after_help = ENVIRONMENT)]
+pub struct Cli {
+    /// aligned columns, the responses as JSON, or nothing but the exit code
+    #[arg(long, global = true, value_name = "FORMAT")]
+    pub output: Option<OutputFormat>,
+    /// settings from the client config, its default profile otherwise
+    #[arg(long, global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+    /// requests in flight at a time, 1 by default, 32 for bench
+    #[arg(long, global = true, value_name = "N")]
+    pub concurrency: Option<usize>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// registers every dependency locked in a Cargo.lock
+    ImportLock {
+        #[arg(value_name = "Cargo.lock", value_hint = clap::ValueHint::FilePath)]
+        lockfile: String,
+        #[arg(default_value = "unknown")]
+        author: String,
+        /// validates the requests without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// sends the requests in a file, one JSON request per line
+    Run {
+        #[arg(value_name = "script.jsonl", value_hint = clap::ValueHint::FilePath)]
+        script: String,
+        /// validates the requests without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// prints releases of matching crates as they are published
+    Watch {
+        /// name filter, every crate if empty
+        #[arg(default_value = "")]
+        filter: String,
+        /// run for every release, with REPO_CRATE, REPO_VERSION and REPO_CHANNEL set, e.g.
+        /// 'notify-send "$REPO_CRATE $REPO_VERSION"'
+        #[arg(long, value_name = "COMMAND", value_hint = clap::ValueHint::CommandString)]
+        exec: Option<String>,
+    },
+    /// measures round trips to the server and checks it speaks the same protocol
+    Ping {
+        #[arg(default_value_t = 10)]
+        attempts: usize,
+    },
+    /// sends requests at a fixed rate and reports throughput and latencies, the crates it adds
+    /// stay
+    Bench {
+        /// requests per second, 100 by default
+        #[arg(long, value_name = "N")]
+        rps: Option<u32>,
+        /// how long it runs, e.g. 500ms, 30s or 2m, 30s by default
+        #[arg(long, value_name = "TIME", value_parser = bench::parse_duration)]
+        duration: Option<Duration>,
+        /// shares of reads and writes, read:90,write:10 by default
+        #[arg(long, value_name = "MIX")]
+        mix: Option<Mix>,
+    },
+    /// prints shell completions
+    Completions { shell: Shell },
+    /// prints the man page
+    Man,
+}
+
+pub fn completions(shell: Shell) {
+    clap_complete::generate(shell, &mut Cli::command(), "client", &mut io::stdout());
+}
+
+pub fn man_page() -> io::Result<()> {
+    clap_mangen::Man::new(Cli::command()).render(&mut io::stdout())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definition() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn parse() {
+        let cli = Cli::parse_from(["client", "run", "--output", "json", "demo.jsonl"]);
+        assert_eq!(Some(OutputFormat::Json), cli.output);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Run { script, dry_run: false }) if script == "demo.jsonl"
+        ));
+        let cli = Cli::parse_from(["client", "bench", "--duration", "2m", "--concurrency", "4"]);
+        assert_eq!(Some(4), cli.concurrency);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Bench { duration: Some(d), .. }) if d == Duration::from_secs(120)
+        ));
+
+        assert!(Cli::try_parse_from(["client", "--output", "yaml"]).is_err());
+        assert!(Cli::try_parse_from(["client", "walk"]).is_err());
+        assert!(Cli::try_parse_from(["client", "run"]).is_err());
+    }
+}
@@ -0,0 +1,883 @@
+mod cli;
+mod output;
+mod profiles;
+mod script;
+
+use std::{
+    error::Error,
+    fmt::Debug,
+    path::PathBuf,
+    process::{Command, ExitCode},
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::Parser;
+use log::{debug, error, info};
+use semver_repo::{
+    api::{
+        AddResult, AdminResult, AdvisoriesResult, ApiError, ApiRequest, ApiResult, AuditLogResult,
+        BatchResult, ChangelogResult, CheckPublishResult, DryRunResult, FeedStatusResult,
+        FindAllContainingPageResult, FindAllContainingResult, FindExactResult, FindMatchingResult,
+        FindRegexResult, GetFeaturesResult, GetReadmeResult, LatestCompatibleResult,
+        LatestVersionResult, ListNamespaceResult, OrgResult, PingResult, ResolveResult,
+        SearchResult, SetDependenciesResult, SnapshotResult, SubscribeResult, TokensResult,
+        PROTOCOL_VERSION,
+    },
+    bench::{self, BenchConfig},
+    channels::Channel,
+    client::{Client, ClientError},
+    events::Event,
+    latency::Latencies,
+    lockfile, net,
+    search::{SearchOptions, SearchSort},
+    CrateKind,
+};
+use semver_repo::{Metadata, RepoError, SemVer};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::json;
+
+use crate::cli::Cli;
+use crate::output::OutputFormat;
+use crate::profiles::Profiles;
+
+trait ResponseHandler {
+    fn handle(&self, serialized: &str, output: OutputFormat) -> Result<bool, ClientError>;
+}
+
+impl ResponseHandler for ApiRequest {
+    fn handle(&self, serialized: &str, output: OutputFormat) -> Result<bool, ClientError> {
+        /// shows `res` in `output`, returns whether the request succeeded
+        fn respond<T: Serialize + Debug>(
+            output: OutputFormat,
+            serialized: &str,
+            context: String,
+            res: ApiResult<T>,
+        ) -> bool {
+            info!("← {}: {:?}", context, res);
+            match (output, &res) {
+                (OutputFormat::Table, Ok(value)) => match serde_json::to_value(value) {
+                    Ok(value) => print!("{}", output::table(&value)),
+                    Err(e) => error!("could not render response: {}", e),
+                },
+                (OutputFormat::Table, Err(e)) => eprintln!("error: {}", e),
+                (OutputFormat::Json, _) => println!("{}", serialized.trim_end()),
+                (OutputFormat::Quiet, _) => {}
+            }
+            res.is_ok()
+        }
+
+        /// malformed responses are `ClientError::Protocol`, errors of the server stay in the result
+        fn deserialize<T: DeserializeOwned>(serialized: &str) -> Result<T, ClientError> {
+            Ok(serde_json::from_str(serialized)?)
+        }
+
+        let succeeded = match self {
+            ApiRequest::FindExact(query) => {
+                let res: FindExactResult = deserialize(serialized)?;
+                respond(output, serialized, format!("find '{}'", query), res)
+            }
+            ApiRequest::FindAllContaining(query, _options) => {
+                let res: FindAllContainingResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("find all containing '{}'", query),
+                    res,
+                )
+            }
+            ApiRequest::FindAllContainingPage { query, .. } => {
+                let res: FindAllContainingPageResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("page of all containing '{}'", query),
+                    res,
+                )
+            }
+            ApiRequest::FindMatching(pattern) => {
+                let res: FindMatchingResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("find matching '{}'", pattern),
+                    res,
+                )
+            }
+            ApiRequest::FindRegex(pattern) => {
+                let res: FindRegexResult = deserialize(serialized)?;
+                respond(output, serialized, format!("find regex '{}'", pattern), res)
+            }
+            ApiRequest::ListNamespace(namespace) => {
+                let res: ListNamespaceResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("crates in namespace '{}'", namespace),
+                    res,
+                )
+            }
+            ApiRequest::Search(query) => {
+                let res: SearchResult = deserialize(serialized)?;
+                respond(output, serialized, format!("search '{}'", query), res)
+            }
+            ApiRequest::AddCrate(m, _version) => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Add new crate '{}'", m.name()),
+                    res,
+                )
+            }
+            ApiRequest::AddRelease(name, version) => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Add version {} to crate '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::Yank(name, version) => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Yank version {} of crate '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::AddReleaseTo {
+                name,
+                version,
+                channel,
+            } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Add {:?} version {} to crate '{}'", channel, version, name),
+                    res,
+                )
+            }
+            ApiRequest::LatestVersion { name, channel } => {
+                let res: LatestVersionResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("latest {:?} version of '{}'", channel, name),
+                    res,
+                )
+            }
+            ApiRequest::LatestCompatible { name, base } => {
+                let res: LatestCompatibleResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("latest version of '{}' compatible with {}", name, base),
+                    res,
+                )
+            }
+            ApiRequest::Resolve { name, req, .. } => {
+                let res: ResolveResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("resolve '{}' {}", name, req),
+                    res,
+                )
+            }
+            ApiRequest::Advisories { name, version } => {
+                let res: AdvisoriesResult = deserialize(serialized)?;
+                let version = version.map(|v| format!(" {v}")).unwrap_or_default();
+                respond(
+                    output,
+                    serialized,
+                    format!("advisories against '{}'{}", name, version),
+                    res,
+                )
+            }
+            ApiRequest::PublishAtomic { metadata, releases } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!(
+                        "Publish {} releases of '{}'",
+                        releases.len(),
+                        metadata.name()
+                    ),
+                    res,
+                )
+            }
+            ApiRequest::PublishVersions {
+                metadata, versions, ..
+            } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!(
+                        "Publish {} releases of '{}'",
+                        versions.len(),
+                        metadata.name()
+                    ),
+                    res,
+                )
+            }
+            ApiRequest::Deprecate {
+                name,
+                version,
+                deprecation,
+            } => {
+                let res: AddResult = deserialize(serialized)?;
+                let what = match version {
+                    Some(version) => format!("version {} of crate '{}'", version, name),
+                    None => format!("crate '{}'", name),
+                };
+                match deprecation {
+                    Some(_) => respond(output, serialized, format!("Deprecate {}", what), res),
+                    None => respond(output, serialized, format!("Undeprecate {}", what), res),
+                }
+            }
+            ApiRequest::SetReleaseNotes { name, version, .. } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Set release notes of version {} of '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::Changelog(name, from, to) => {
+                let res: ChangelogResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("changelog of '{}' from {} to {}", name, from, to),
+                    res,
+                )
+            }
+            ApiRequest::SetReadme { name, version, .. } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Set README of version {} of '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::SetPlatform {
+                name,
+                version,
+                platform,
+            } => {
+                let res: AddResult = deserialize(serialized)?;
+                let msrv = platform
+                    .rust_version
+                    .map_or("any".to_string(), |v| v.to_string());
+                respond(
+                    output,
+                    serialized,
+                    format!(
+                        "Set platform of version {} of '{}' to Rust {}, {} targets",
+                        version,
+                        name,
+                        msrv,
+                        platform.targets.len()
+                    ),
+                    res,
+                )
+            }
+            ApiRequest::SetFeatures {
+                name,
+                version,
+                features,
+            } => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!(
+                        "Set {} features of version {} of '{}'",
+                        features.len(),
+                        version,
+                        name
+                    ),
+                    res,
+                )
+            }
+            ApiRequest::SetDependencies {
+                name,
+                version,
+                dependencies,
+            } => {
+                let res: SetDependenciesResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!(
+                        "Set {} dependencies of version {} of '{}'",
+                        dependencies.len(),
+                        version,
+                        name
+                    ),
+                    res,
+                )
+            }
+            ApiRequest::GetFeatures(name, version) => {
+                let res: GetFeaturesResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("features of version {} of '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::GetReadme(name, version) => {
+                let res: GetReadmeResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("README of version {} of '{}'", version, name),
+                    res,
+                )
+            }
+            ApiRequest::UpdateMetadata(metadata) => {
+                let res: AddResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("Update metadata of '{}'", metadata.name()),
+                    res,
+                )
+            }
+            ApiRequest::Org(request) => {
+                let res: OrgResult = deserialize(serialized)?;
+                respond(output, serialized, format!("org {:?}", request), res)
+            }
+            ApiRequest::Tokens => {
+                let res: TokensResult = deserialize(serialized)?;
+                respond(output, serialized, "tokens".to_string(), res)
+            }
+            ApiRequest::RevokeToken(id) => {
+                let res: ApiResult<()> = deserialize(serialized)?;
+                respond(output, serialized, format!("Revoke token {}", id), res)
+            }
+            ApiRequest::AuditLog(name, since) => {
+                let res: AuditLogResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("audit log of '{}' since #{}", name, since),
+                    res,
+                )
+            }
+            ApiRequest::Subscribe { since } => {
+                let res: SubscribeResult = deserialize(serialized)?;
+                respond(output, serialized, format!("changes since #{}", since), res)
+            }
+            ApiRequest::FeedStatus => {
+                let res: FeedStatusResult = deserialize(serialized)?;
+                respond(output, serialized, "feed status".to_string(), res)
+            }
+            ApiRequest::Ping => {
+                let res: PingResult = deserialize(serialized)?;
+                respond(output, serialized, "ping".to_string(), res)
+            }
+            ApiRequest::Snapshot => {
+                let res: SnapshotResult = deserialize(serialized)?;
+                respond(output, serialized, "snapshot".to_string(), res)
+            }
+            ApiRequest::Batch { requests, .. } => {
+                let res: BatchResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("batch of {}", requests.len()),
+                    res,
+                )
+            }
+            ApiRequest::Idempotent { key, request } => {
+                debug!("idempotency key '{}'", key);
+                request.handle(serialized, output)?
+            }
+            ApiRequest::IfRevision { revision, request } => {
+                debug!("if at revision {}", revision);
+                request.handle(serialized, output)?
+            }
+            ApiRequest::Authenticated { request, .. } => request.handle(serialized, output)?,
+            ApiRequest::Registry { name, request } => {
+                debug!("in registry '{}'", name);
+                request.handle(serialized, output)?
+            }
+            ApiRequest::Traced {
+                traceparent,
+                request,
+            } => {
+                debug!("traced as {}", traceparent);
+                request.handle(serialized, output)?
+            }
+            #[cfg(feature = "graphql")]
+            ApiRequest::GraphQL { query, .. } => {
+                let res: semver_repo::api::GraphQLResult = deserialize(serialized)?;
+                respond(output, serialized, format!("graphql {:?}", query), res)
+            }
+            ApiRequest::DryRun { request } => {
+                let res: DryRunResult = deserialize(serialized)?;
+                respond(output, serialized, format!("dry run of {:?}", request), res)
+            }
+            ApiRequest::CheckPublish(metadata, version) => {
+                let res: CheckPublishResult = deserialize(serialized)?;
+                respond(
+                    output,
+                    serialized,
+                    format!("check publish {} {}", metadata.name(), version),
+                    res,
+                )
+            }
+            ApiRequest::Admin { request, .. } => {
+                let res: AdminResult = deserialize(serialized)?;
+                respond(output, serialized, format!("admin {:?}", request), res)
+            }
+        };
+        Ok(succeeded)
+    }
+}
+
+fn crate_data(name: impl AsRef<str>, major: u64) -> (Metadata, SemVer) {
+    (
+        Metadata::new(name, "Busy Person", CrateKind::Binary),
+        SemVer::new(major, 0, 0),
+    )
+}
+/// exit code when the server answered any request with an error, e.g. for shell scripts
+const API_ERROR: u8 = 2;
+
+fn main() -> Result<ExitCode, Box<dyn Error>> {
+    pretty_env_logger::init();
+    // clap's own exit code for usage errors would be API_ERROR's
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            let _ = e.print();
+            return Ok(match e.use_stderr() {
+                true => ExitCode::FAILURE,
+                false => ExitCode::SUCCESS,
+            });
+        }
+    };
+    // e.g. REPO_CONFIG=./staging.toml, see profiles.rs
+    let profiles = match std::env::var_os("REPO_CONFIG").map(PathBuf::from) {
+        Some(path) => Profiles::load(path)?,
+        None => Profiles::default_path()
+            .map(Profiles::load)
+            .transpose()?
+            .unwrap_or_default(),
+    };
+    let profile = profiles.select(cli.profile.as_deref())?;
+
+    // e.g. REPO_ADDR=[::1]:7878 or REPO_ADDR=tcp://registry.local
+    let target = match std::env::var("REPO_ADDR").ok().or(profile.addr) {
+        Some(addr) => net::parse_target(&addr, net::DEFAULT_PORT)?,
+        None => {
+            let port = std::env::var("REPO_PORT").unwrap_or(net::DEFAULT_PORT.to_string());
+            format!("{}:{}", net::DEFAULT_HOST, port)
+        }
+    };
+    let mut client = Client::new(&target);
+    if let Some(token) = std::env::var("REPO_TOKEN").ok().or(profile.token) {
+        client = client.with_token(token);
+    }
+    if let Some(registry) = profile.registry {
+        client = client.with_registry(registry);
+    }
+
+    let output = cli.output.or(profile.output).unwrap_or_default();
+    let concurrency = cli.concurrency;
+    match cli.command {
+        Some(cli::Command::ImportLock {
+            lockfile,
+            author,
+            dry_run,
+        }) => return import_lock(&client, &lockfile, &author, dry_run),
+        Some(cli::Command::Run { script, dry_run }) => {
+            return run_script(&client, &script, concurrency.unwrap_or(1), dry_run, output);
+        }
+        Some(cli::Command::Watch { filter, exec }) => {
+            return watch(&client, &filter, exec.as_deref(), output);
+        }
+        Some(cli::Command::Ping { attempts }) => return ping(&client, attempts, output),
+        Some(cli::Command::Bench { rps, duration, mix }) => {
+            let defaults = BenchConfig::default();
+            let config = BenchConfig {
+                rps: rps.unwrap_or(defaults.rps),
+                duration: duration.unwrap_or(defaults.duration),
+                mix: mix.unwrap_or(defaults.mix),
+                concurrency: concurrency.unwrap_or(defaults.concurrency),
+            };
+            return run_bench(&client, &config, output);
+        }
+        Some(cli::Command::Completions { shell }) => {
+            cli::completions(shell);
+            return Ok(ExitCode::SUCCESS);
+        }
+        Some(cli::Command::Man) => {
+            cli::man_page()?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    let binary_name = "hello_bin".to_string();
+    let (md, sv) = crate_data(&binary_name, 1);
+    let (md2, sv2) = crate_data("hello_moon", 2);
+
+    let requests = vec![
+        ApiRequest::AddCrate(md, sv),
+        ApiRequest::AddCrate(md2, sv2),
+        ApiRequest::AddRelease("who?".to_string(), SemVer::new(1, 0, 0)),
+        ApiRequest::AddRelease(binary_name.clone(), SemVer::new(1, 0, 4)),
+        ApiRequest::AddRelease(binary_name.clone(), SemVer::new(1, 0, 1)),
+        ApiRequest::AddRelease(binary_name.clone(), SemVer::new(1, 0, 5)),
+        ApiRequest::FindExact(binary_name.clone()),
+        ApiRequest::FindExact("stuxnet".to_string()),
+        ApiRequest::FindAllContaining(
+            "moon".to_string(),
+            SearchOptions::sorted_by(SearchSort::Relevance),
+        ),
+        ApiRequest::FindMatching("hello_*".to_string()),
+        ApiRequest::Yank(binary_name.clone(), SemVer::new(1, 0, 4)),
+        ApiRequest::AuditLog(binary_name.clone(), 0),
+        ApiRequest::Subscribe { since: 0 },
+    ];
+
+    let responses = script::bounded(&requests, concurrency.unwrap_or(1), |request| {
+        client.send(request)
+    });
+    let (mut succeeded, mut failed, mut unanswered) = (0, 0, 0);
+    for (request, response) in requests.iter().zip(responses) {
+        match response.and_then(|response| request.handle(&response, output)) {
+            Ok(true) => succeeded += 1,
+            Ok(false) => failed += 1,
+            Err(e) => {
+                error!("{} failed: {}", request.kind(), e);
+                unanswered += 1
+            }
+        }
+    }
+    if output != OutputFormat::Quiet {
+        eprintln!(
+            "{} requests succeeded, {} failed, {} unanswered",
+            succeeded, failed, unanswered
+        );
+    }
+
+    Ok(if unanswered > 0 {
+        ExitCode::FAILURE
+    } else if failed > 0 {
+        ExitCode::from(API_ERROR)
+    } else {
+        ExitCode::SUCCESS
+    })
+}
+
+/// registers every dependency locked in the `Cargo.lock` at `path`
+fn import_lock(
+    client: &Client,
+    path: &str,
+    author: &str,
+    dry_run: bool,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let dependencies = lockfile::dependencies(&std::fs::read_to_string(path)?)?;
+    let requests = lockfile::register_requests(&dependencies, author);
+    let results = match dry_run {
+        true => dry_run_batch(client, requests.clone())?,
+        false => client.batch(requests.clone(), false)?,
+    };
+    let (mut registered, mut skipped, mut failed) = (0, 0, 0);
+    for (request, result) in requests.iter().zip(results) {
+        match result {
+            Ok(_) => registered += 1,
+            Err(ApiError::Repo(
+                RepoError::AlreadyExists | RepoError::DuplicateVersion | RepoError::NotMonotonic,
+            )) => skipped += 1,
+            Err(e) => {
+                error!("{:?} failed: {}", request, e);
+                failed += 1
+            }
+        }
+    }
+    info!(
+        "{} crates from {}: {} releases registered, {} skipped as existing or out of order",
+        dependencies.len(),
+        path,
+        registered,
+        skipped
+    );
+    Ok(match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(API_ERROR),
+    })
+}
+
+/// The results `requests` would have in a batch, from a dry run of all of them at once, so each
+/// one sees what the ones before it would have changed
+fn dry_run_batch(
+    client: &Client,
+    requests: Vec<ApiRequest>,
+) -> Result<Vec<ApiResult<serde_json::Value>>, ClientError> {
+    let outcome = client.dry_run(ApiRequest::Batch {
+        requests,
+        transactional: false,
+    })?;
+    info!(
+        "the requests would record {} changes",
+        outcome.changes.len()
+    );
+    Ok(serde_json::from_value(outcome.result)?)
+}
+
+/// sends the requests in the script at `path`, printing a line per request
+fn run_script(
+    client: &Client,
+    path: &str,
+    concurrency: usize,
+    dry_run: bool,
+    output: OutputFormat,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let steps = script::parse(&std::fs::read_to_string(path)?)?;
+    let results = match dry_run {
+        true => {
+            let requests = steps.iter().map(|step| step.request.clone()).collect();
+            dry_run_batch(client, requests)?
+                .into_iter()
+                .map(|result| result.map_err(ClientError::from))
+                .collect()
+        }
+        false => script::run(client, &steps, concurrency),
+    };
+    let mut failed = 0;
+    for (step, result) in steps.iter().zip(&results) {
+        let what = format!(
+            "{}:{} {} {}",
+            path,
+            step.line,
+            step.request.kind(),
+            step.request.crate_name().unwrap_or("-")
+        );
+        match (output, result) {
+            (OutputFormat::Table, Ok(_)) => println!("{}  ok", what),
+            (OutputFormat::Table, Err(e)) => println!("{}  failed: {}", what, e),
+            (OutputFormat::Json, Ok(value)) => {
+                println!("{}", json!({ "line": step.line, "Ok": value }))
+            }
+            (OutputFormat::Json, Err(ClientError::Api(e))) => {
+                println!("{}", json!({ "line": step.line, "Err": e }))
+            }
+            (OutputFormat::Json, Err(e)) => {
+                println!("{}", json!({ "line": step.line, "Err": e.to_string() }))
+            }
+            (OutputFormat::Quiet, _) => {}
+        }
+        failed += result.is_err() as usize;
+    }
+    if output != OutputFormat::Quiet {
+        let would = if dry_run { " would have" } else { "" };
+        eprintln!(
+            "{} of {} requests in {}{} failed",
+            failed,
+            steps.len(),
+            path,
+            would
+        );
+    }
+    Ok(match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::from(API_ERROR),
+    })
+}
+
+/// prints releases of crates with `filter` in their name as they are published, until interrupted
+fn watch(
+    client: &Client,
+    filter: &str,
+    exec: Option<&str>,
+    output: OutputFormat,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let since = client.feed_status()?.last_seq;
+    for change in client.watch(since) {
+        let change = match change {
+            Ok(change) => change,
+            Err(e) => {
+                error!("watching failed, trying again: {}", e);
+                thread::sleep(Duration::from_secs(1));
+                continue;
+            }
+        };
+        let (name, version, channel) = match &change.event {
+            Event::CrateAdded {
+                metadata, version, ..
+            } => (metadata.name(), *version, Channel::default()),
+            Event::ReleaseAdded {
+                name,
+                version,
+                channel,
+            } => (&**name, *version, *channel),
+            _ => continue,
+        };
+        if !name.contains(filter) {
+            continue;
+        }
+        match output {
+            OutputFormat::Table => println!("{}  {}  {}  {:?}", change.at, name, version, channel),
+            OutputFormat::Json => println!("{}", serde_json::to_string(&change)?),
+            OutputFormat::Quiet => {}
+        }
+        if let Some(command) = exec {
+            let status = Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("REPO_CRATE", name)
+                .env("REPO_VERSION", version.to_string())
+                .env("REPO_CHANNEL", format!("{:?}", channel))
+                .status();
+            match status {
+                Ok(status) if status.success() => {}
+                Ok(status) => error!("'{}' failed for {} {}: {}", command, name, version, status),
+                Err(e) => error!("could not run '{}': {}", command, e),
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn run_bench(
+    client: &Client,
+    config: &BenchConfig,
+    output: OutputFormat,
+) -> Result<ExitCode, Box<dyn Error>> {
+    if output != OutputFormat::Quiet {
+        eprintln!(
+            "sending {} requests per second for {:?} to {}",
+            config.rps,
+            config.duration,
+            client.target()
+        );
+    }
+    let report = bench::run(client, config)?;
+    match output {
+        OutputFormat::Table => print!("{}", report),
+        OutputFormat::Json => {
+            let summary = |latencies: &Latencies| {
+                let ms = |p| latencies.percentile(p).map(|l| l.as_secs_f64() * 1000.0);
+                let histogram: Vec<_> = latencies
+                    .histogram()
+                    .into_iter()
+                    .map(|(bound, count)| {
+                        json!({"le_ms": bound.as_secs_f64() * 1000.0, "count": count})
+                    })
+                    .collect();
+                json!({
+                    "count": latencies.len(),
+                    "latency_ms": {
+                        "min": ms(0.0),
+                        "p50": ms(50.0),
+                        "p90": ms(90.0),
+                        "p99": ms(99.0),
+                        "max": ms(100.0),
+                    },
+                    "histogram": histogram,
+                })
+            };
+            let report = json!({
+                "sent": report.sent(),
+                "failed": report.failed,
+                "elapsed_s": report.elapsed.as_secs_f64(),
+                "throughput": report.throughput(),
+                "reads": summary(&report.reads),
+                "writes": summary(&report.writes),
+            });
+            println!("{}", report);
+        }
+        OutputFormat::Quiet => {}
+    }
+    Ok(match report.failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+/// pings the server `attempts` times, one after the other
+fn ping(
+    client: &Client,
+    attempts: usize,
+    output: OutputFormat,
+) -> Result<ExitCode, Box<dyn Error>> {
+    let mut latencies = Latencies::default();
+    let mut server = None;
+    for _ in 0..attempts {
+        let started = Instant::now();
+        match client.ping() {
+            Ok(info) => {
+                latencies.record(started.elapsed());
+                server = Some(info);
+            }
+            Err(ClientError::Api(ApiError::Internal)) => {
+                eprintln!(
+                    "the server predates ping, and protocol version {}",
+                    PROTOCOL_VERSION
+                );
+                return Ok(ExitCode::from(API_ERROR));
+            }
+            Err(e) => error!("ping failed: {}", e),
+        }
+    }
+    let Some(server) = server else {
+        eprintln!("no answer from {}", client.target());
+        return Ok(ExitCode::FAILURE);
+    };
+    let compatible = server.protocol == PROTOCOL_VERSION;
+    match output {
+        OutputFormat::Table => {
+            println!(
+                "{} answered {} of {} pings, version {}, protocol {}",
+                client.target(),
+                latencies.len(),
+                attempts,
+                server.version,
+                server.protocol
+            );
+            println!("{}", latencies);
+        }
+        OutputFormat::Json => {
+            let ms = |p| latencies.percentile(p).map(|l| l.as_secs_f64() * 1000.0);
+            let report = json!({
+                "server": server,
+                "sent": attempts,
+                "answered": latencies.len(),
+                "latency_ms": {
+                    "min": ms(0.0),
+                    "p50": ms(50.0),
+                    "p90": ms(90.0),
+                    "p99": ms(99.0),
+                    "max": ms(100.0),
+                },
+            });
+            println!("{}", report);
+        }
+        OutputFormat::Quiet => {}
+    }
+    if !compatible {
+        eprintln!(
+            "the server speaks protocol {}, this client {}",
+            server.protocol, PROTOCOL_VERSION
+        );
+        return Ok(ExitCode::from(API_ERROR));
+    }
+    Ok(match latencies.len() == attempts {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,197 @@
+//! Rendering responses of the server for people and scripts, see the `--output` flag of the
+//! client binary.
+
+use std::fmt::Write as _;
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// aligned columns
+    #[default]
+    Table,
+    /// the response as sent by the server
+    Json,
+    /// nothing, only the exit code tells how it went
+    Quiet,
+}
+
+/// `value` as text: lists of objects as a table with a column per key, objects as a column of
+/// keys next to their values, nested objects flattened to `outer.inner` keys
+pub fn table(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::Array(items) if items.is_empty() => String::new(),
+        Value::Array(items) if items.iter().all(Value::is_object) => {
+            let rows: Vec<Vec<(String, String)>> = items
+                .iter()
+                .filter_map(Value::as_object)
+                .map(flatten)
+                .collect();
+            let mut columns: Vec<&str> = vec![];
+            for (key, _) in rows.iter().flatten() {
+                if !columns.contains(&key.as_str()) {
+                    columns.push(key);
+                }
+            }
+            let header = columns.iter().map(|c| c.to_uppercase()).collect();
+            let cells = rows.iter().map(|row| {
+                columns
+                    .iter()
+                    .map(|column| {
+                        row.iter()
+                            .find(|(key, _)| key == column)
+                            .map(|(_, cell)| cell.clone())
+                            .unwrap_or_else(|| "-".to_string())
+                    })
+                    .collect()
+            });
+            aligned(std::iter::once(header).chain(cells).collect())
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(|item| format!("{}\n", cell(item)))
+            .collect(),
+        Value::Object(object) if version(value).is_none() => aligned(
+            flatten(object)
+                .into_iter()
+                .map(|(key, cell)| vec![key, cell])
+                .collect(),
+        ),
+        value => format!("{}\n", cell(value)),
+    }
+}
+
+/// the leaves of `object` with their dotted paths
+fn flatten(object: &Map<String, Value>) -> Vec<(String, String)> {
+    let mut leaves = vec![];
+    for (key, value) in object {
+        match value.as_object().filter(|_| version(value).is_none()) {
+            Some(inner) => match variant(inner) {
+                Some(cell) => leaves.push((key.clone(), cell)),
+                None => {
+                    for (inner_key, cell) in flatten(inner) {
+                        leaves.push((format!("{}.{}", key, inner_key), cell));
+                    }
+                }
+            },
+            None => leaves.push((key.clone(), cell(value))),
+        }
+    }
+    leaves
+}
+
+fn cell(value: &Value) -> String {
+    if let Some(version) = version(value) {
+        return version;
+    }
+    match value {
+        Value::Null => "-".to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(cell).collect::<Vec<_>>().join(", "),
+        value => value.to_string(),
+    }
+}
+
+/// enum variants with fields, e.g. [`semver_repo::events::Event`], as the variant followed by its
+/// values on one line instead of a column per field of every variant
+fn variant(object: &Map<String, Value>) -> Option<String> {
+    let (name, fields) = object.iter().next().filter(|_| object.len() == 1)?;
+    let fields = fields.as_object().filter(|_| version(fields).is_none())?;
+    let mut cell = name.clone();
+    for (_, value) in flatten(fields) {
+        if !value.is_empty() && value != "-" {
+            cell.push(' ');
+            cell.push_str(&value);
+        }
+    }
+    Some(cell)
+}
+
+/// serialized [`semver_repo::SemVer`]s as `major.minor.patch`
+fn version(value: &Value) -> Option<String> {
+    let object = value.as_object().filter(|object| object.len() == 3)?;
+    let part = |name| object.get(name).and_then(Value::as_u64);
+    Some(format!(
+        "{}.{}.{}",
+        part("major")?,
+        part("minor")?,
+        part("patch")?
+    ))
+}
+
+fn aligned(rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = vec![];
+    for row in &rows {
+        for (column, cell) in row.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(column) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    let mut out = String::new();
+    for row in &rows {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            let _ = write!(line, "{:width$}  ", cell, width = width);
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn tables() {
+        let summaries = json!([
+            {"name": "hello_bin", "author": "someone", "latest": {"major": 1, "minor": 0, "patch": 5}},
+            {"name": "hello_moon", "author": "Busy Person", "latest": null},
+        ]);
+        assert_eq!(
+            "NAME        AUTHOR       LATEST\n\
+             hello_bin   someone      1.0.5\n\
+             hello_moon  Busy Person  -\n",
+            table(&summaries)
+        );
+        let crt = json!({"metadata": {"name": "hello_bin"}, "yanked": [{"major": 1, "minor": 0, "patch": 4}, {"major": 1, "minor": 0, "patch": 5}]});
+        assert_eq!(
+            "metadata.name  hello_bin\nyanked         1.0.4, 1.0.5\n",
+            table(&crt)
+        );
+        let changes = json!([
+            {"seq": 1, "event": {"Yanked": {"name": "hello_bin", "version": {"major": 1, "minor": 0, "patch": 4}}}},
+            {"seq": 12, "event": {"Deprecated": {"name": "hello_moon", "note": ""}}},
+        ]);
+        assert_eq!(
+            "SEQ  EVENT\n\
+             1    Yanked hello_bin 1.0.4\n\
+             12   Deprecated hello_moon\n",
+            table(&changes)
+        );
+        assert_eq!("", table(&Value::Null));
+        assert_eq!(
+            "2.0.0\n",
+            table(&json!({"major": 2, "minor": 0, "patch": 0}))
+        );
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(
+            Ok(OutputFormat::Quiet),
+            OutputFormat::from_str("quiet", false)
+        );
+        assert!(OutputFormat::from_str("yaml", false).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+//! Named client settings, e.g. for a staging and a production registry, read from
+//! `~/.config/semver_repo/config.toml`:
+//!
+//! ```toml
+//! default = "staging"
+//!
+//! [profiles.staging]
+//! addr = "staging.registry.local"
+//! token = "s3cret"
+//!
+//! [profiles.prod]
+//! addr = "tcp://registry.local:7878"
+//! registry = "internal"
+//! output = "json"
+//! ```
+//!
+//! Command line flags and environment variables take precedence over the profile. The server
+//! doesn't speak TLS, so there are no TLS settings.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::output::OutputFormat;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// see [`semver_repo::net::parse_target`]
+    pub addr: Option<String>,
+    /// see [`semver_repo::client::Client::with_token`]
+    pub token: Option<String>,
+    /// see [`semver_repo::client::Client::with_registry`]
+    pub registry: Option<String>,
+    pub output: Option<OutputFormat>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profiles {
+    /// used unless another profile is asked for
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+#[derive(Error, Debug)]
+pub enum ProfileError {
+    #[error("could not read client config: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid client config: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("unknown profile '{0}'")]
+    Unknown(String),
+}
+
+impl Profiles {
+    /// `$XDG_CONFIG_HOME/semver_repo/config.toml`, or in `~/.config` without `XDG_CONFIG_HOME`
+    pub fn default_path() -> Option<PathBuf> {
+        let config = match std::env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config.join("semver_repo").join("config.toml"))
+    }
+
+    /// the profiles at `path`, none if there is no such file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ProfileError> {
+        match fs::read_to_string(path) {
+            Ok(toml) => Ok(toml::from_str(&toml)?),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// the profile called `name`, or the default one, which may be unset
+    pub fn select(&self, name: Option<&str>) -> Result<Profile, ProfileError> {
+        match name.or(self.default.as_deref()) {
+            Some(name) => self
+                .profiles
+                .get(name)
+                .cloned()
+                .ok_or_else(|| ProfileError::Unknown(name.to_string())),
+            None => Ok(Profile::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    #[test]
+    fn select() -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(
+            br#"
+            default = "staging"
+            [profiles.staging]
+            addr = "staging.registry.local"
+            [profiles.prod]
+            addr = "registry.local"
+            token = "s3cret"
+            output = "json"
+            "#,
+        )?;
+        let profiles = Profiles::load(file.path())?;
+        assert_eq!(
+            Some("staging.registry.local"),
+            profiles.select(None)?.addr.as_deref()
+        );
+        let prod = profiles.select(Some("prod"))?;
+        assert_eq!(Some("s3cret"), prod.token.as_deref());
+        assert_eq!(Some(OutputFormat::Json), prod.output);
+        assert!(matches!(
+            profiles.select(Some("dev")),
+            Err(ProfileError::Unknown(_))
+        ));
+
+        let missing = Profiles::load(file.path().with_extension("missing"))?;
+        assert_eq!(Profile::default(), missing.select(None)?);
+        Ok(())
+    }
+}
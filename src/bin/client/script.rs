@@ -0,0 +1,172 @@
+//! Running a file of requests, e.g. to seed demo data or migrate between servers. Scripts hold
+//! one JSON [`ApiRequest`] per line, blank lines and lines starting with `#` are skipped:
+//!
+//! ```text
+//! # two crates
+//! {"AddCrate":[{"name":"hello_bin","author":"someone","kind":"Binary"},{"major":1,"minor":0,"patch":0}]}
+//! {"AddRelease":["hello_bin",{"major":1,"minor":0,"patch":1}]}
+//! ```
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use semver_repo::api::ApiRequest;
+use semver_repo::client::{Client, ClientError};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+#[error("line {line}: {source}")]
+pub struct ScriptError {
+    pub line: usize,
+    pub source: serde_json::Error,
+}
+
+/// A request of a script with the line it is on, counting from 1
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub line: usize,
+    pub request: ApiRequest,
+}
+
+/// the requests of `script`, failing on the first line that isn't one
+pub fn parse(script: &str) -> Result<Vec<Step>, ScriptError> {
+    script
+        .lines()
+        .enumerate()
+        .map(|(idx, text)| (idx + 1, text.trim()))
+        .filter(|(_, text)| !text.is_empty() && !text.starts_with('#'))
+        .map(|(line, text)| {
+            serde_json::from_str(text)
+                .map(|request| Step { line, request })
+                .map_err(|source| ScriptError { line, source })
+        })
+        .collect()
+}
+
+/// Sends the `steps` with at most `concurrency` of them in flight, one after the other for
+/// `concurrency <= 1`, and returns their results in the order of `steps`. Failed steps don't stop
+/// the others.
+pub fn run(
+    client: &Client,
+    steps: &[Step],
+    concurrency: usize,
+) -> Vec<Result<serde_json::Value, ClientError>> {
+    bounded(steps, concurrency, |step| client.request(&step.request))
+}
+
+/// `f` of each of the `items`, in their order, computed by at most `concurrency` threads at a
+/// time including the calling one
+pub fn bounded<T: Sync, R: Send>(
+    items: &[T],
+    concurrency: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let results: Vec<_> = items.iter().map(|_| Mutex::new(None)).collect();
+    let next = AtomicUsize::new(0);
+    let work = || loop {
+        let idx = next.fetch_add(1, Ordering::Relaxed);
+        let Some(item) = items.get(idx) else {
+            break;
+        };
+        *results[idx].lock().unwrap() = Some(f(item));
+    };
+    thread::scope(|scope| {
+        for _ in 1..concurrency.min(items.len()) {
+            scope.spawn(work);
+        }
+        work();
+    });
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .into_inner()
+                .unwrap()
+                .expect("every item is done before the scope ends")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use semver_repo::api::ApiError;
+    use semver_repo::server::{Server, ServerConfig};
+    use semver_repo::{RepoError, SemVer};
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    const SCRIPT: &str = r#"
+        # hello_bin and some releases
+        {"AddCrate":[{"name":"hello_bin","author":"someone","kind":"Binary"},{"major":1,"minor":0,"patch":0}]}
+        {"AddRelease":["hello_bin",{"major":1,"minor":0,"patch":1}]}
+
+        {"AddRelease":["missing",{"major":1,"minor":0,"patch":0}]}
+    "#;
+
+    #[test]
+    fn parses_lines() {
+        let steps = parse(SCRIPT).unwrap();
+        assert_eq!(
+            vec![3, 4, 6],
+            steps.iter().map(|s| s.line).collect::<Vec<_>>()
+        );
+        assert!(matches!(
+            steps[1].request,
+            ApiRequest::AddRelease(ref name, _) if name == "hello_bin"
+        ));
+        assert_eq!(2, parse("\n{\"FindExact\":1}").unwrap_err().line);
+    }
+
+    #[test]
+    fn bounded_keeps_order() {
+        let running = AtomicUsize::new(0);
+        let most = AtomicUsize::new(0);
+        let squares = bounded(&(0..50).collect::<Vec<u64>>(), 4, |n| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            most.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(std::time::Duration::from_millis(1));
+            running.fetch_sub(1, Ordering::SeqCst);
+            n * n
+        });
+        assert_eq!((0..50).map(|n| n * n).collect::<Vec<_>>(), squares);
+        assert!(most.into_inner() <= 4);
+    }
+
+    #[test]
+    fn runs_steps() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let config = ServerConfig::new(store.path()).with_listen(vec!["127.0.0.1:0".parse()?]);
+        let server = Server::bind(config)?;
+        let client = Client::new(server.local_addrs()[0].to_string());
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.serve());
+
+        let results = run(&client, &parse(SCRIPT)?, 1);
+        assert!(results[0].is_ok() && results[1].is_ok());
+        assert!(matches!(
+            results[2],
+            Err(ClientError::Api(ApiError::Repo(RepoError::NotFound)))
+        ));
+
+        let steps: Vec<_> = (2..10)
+            .map(|major| Step {
+                line: major as usize,
+                request: ApiRequest::AddRelease("hello_bin".into(), SemVer::new(major, 0, 0)),
+            })
+            .collect();
+        // in any order, but releases only go up
+        let results = run(&client, &steps, 4);
+        assert_eq!(8, results.len());
+        assert!(results.iter().any(Result::is_ok));
+        let crt = client.find_exact("hello_bin")?.unwrap();
+        assert!(crt.latest() >= Some(SemVer::new(2, 0, 0)));
+
+        shutdown.shutdown();
+        running.join().unwrap()?;
+        Ok(())
+    }
+}
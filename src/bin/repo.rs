@@ -1,29 +1,381 @@
+use std::env;
+use std::path::Path;
+
+use semver_repo::channels::Channel;
+use semver_repo::compression::Compression;
+use semver_repo::convert::Backend;
+use semver_repo::dump;
+use semver_repo::encryption::StoreKey;
+use semver_repo::export::ExportFormat;
+use semver_repo::retention::RetentionPolicy;
+use semver_repo::search::SearchOptions;
+use semver_repo::shards::Sharding;
 use semver_repo::{CrateKind, Repository};
 use semver_repo::{Metadata, SemVer};
 
-fn main() -> anyhow::Result<()> {
-    let store = option_env!("SEMVER_REPO").ok_or(anyhow::anyhow!(
-        "missing SEMVER_REPO environment variable. Re-run with e.g.\n \
-        SEMVER_REPO=/tmp/store.json cargo run"
-    ))?;
-    let mut repo = Repository::new(store);
-    println!("repo: {repo:?}");
-
-    println!("find crate: {:?}", repo.find_exact("linux.exe"));
-
-    repo.add_crate(
-        Metadata::new(
-            "linux.exe".to_string(),
-            "Linus Torvalds".to_string(),
-            CrateKind::Binary,
-        ),
-        SemVer::new(1, 0, 0),
-    )?;
+/// `repo list`, one line per crate with its latest version
+fn list(repo: &Repository) {
+    for crt in repo {
+        let latest = crt.latest().map(|v| v.to_string()).unwrap_or("-".into());
+        let metadata = crt.metadata();
+        println!(
+            "{:<32} {:>10}  {:<8} {}",
+            metadata.name(),
+            latest,
+            metadata.kind().to_string(),
+            metadata.author()
+        );
+    }
+}
+
+/// `repo search <name part> [--msrv <rust version>] [--target <triple>]`, crates with a release
+/// building with that Rust version for that target and the newest such release
+fn search(repo: &Repository, name_part: &str, filters: &[&str]) -> anyhow::Result<()> {
+    let mut options = SearchOptions::default();
+    for filter in filters.chunks(2) {
+        match filter {
+            ["--msrv", msrv] => options.rust_version = Some(msrv.parse()?),
+            ["--target", target] => options.target = Some(target.to_string()),
+            _ => anyhow::bail!("{USAGE}"),
+        }
+    }
+    let (rust_version, target) = (options.rust_version, options.target.clone());
+    for crt in repo.find_containing(name_part, options) {
+        let compatible = crt
+            .latest_compatible(rust_version, target.as_deref())
+            .map(|v| v.to_string())
+            .unwrap_or("-".into());
+        println!("{:<32} {:>10}", crt.metadata().name(), compatible);
+    }
+    Ok(())
+}
+
+/// `repo show <name>`, the metadata and every release of a crate
+fn show(repo: &Repository, name: &str) -> anyhow::Result<()> {
+    let crt = repo
+        .find_exact(name)
+        .ok_or_else(|| anyhow::anyhow!("no crate named '{name}'"))?;
+    let metadata = crt.metadata();
+    println!("name:      {}", metadata.name());
+    println!("author:    {}", metadata.author());
+    println!("kind:      {}", metadata.kind());
+    if !metadata.description().is_empty() {
+        println!("about:     {}", metadata.description());
+    }
+    if !metadata.keywords().is_empty() {
+        println!("keywords:  {}", metadata.keywords().join(", "));
+    }
+    if let Some(license) = metadata.license() {
+        println!("license:   {}", license);
+    }
+    if let Some(url) = metadata.repository() {
+        println!("repo:      {}", url);
+    }
+    if let Some(url) = metadata.homepage() {
+        println!("homepage:  {}", url);
+    }
+    if let Some(org) = metadata.org() {
+        println!("org:       {org}");
+    }
+    println!("downloads: {}", crt.downloads());
+    println!("releases:");
+    for &version in crt.releases().iter().rev() {
+        let published = crt
+            .published_at(version)
+            .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let mut notes = vec![];
+        if crt.channel(version) != Channel::Stable {
+            notes.push(format!("{:?}", crt.channel(version)).to_lowercase());
+        }
+        if crt.is_yanked(version) {
+            notes.push("yanked".into());
+        }
+        if let Some(blocked) = crt.blocked().iter().find(|b| b.version == version) {
+            notes.push(format!("blocked: {}", blocked.reason));
+        }
+        if let Some(platform) = crt.platform(version) {
+            if let Some(msrv) = platform.rust_version {
+                notes.push(format!("rust {msrv}"));
+            }
+            if !platform.targets.is_empty() {
+                notes.push(platform.targets.join(" "));
+            }
+        }
+        if let Some(features) = crt.features(version) {
+            let names: Vec<_> = features.keys().map(String::as_str).collect();
+            notes.push(format!("features {}", names.join(" ")));
+        }
+        let line = format!(
+            "  {:<12} {published}  {}",
+            version.to_string(),
+            notes.join(", ")
+        );
+        println!("{}", line.trim_end());
+    }
+    Ok(())
+}
+
+/// `repo add <name> <version> [author]`, a release of an existing crate or a new library crate
+fn add(
+    mut repo: Repository,
+    name: &str,
+    version: &str,
+    author: Option<&str>,
+) -> anyhow::Result<()> {
+    let version: SemVer = version.parse()?;
+    match repo.find_exact(name) {
+        Some(_) => {
+            repo.add_release(name, version)?;
+            println!("published {name} {version}");
+        }
+        None => {
+            let author = author.unwrap_or("unknown");
+            repo.add_crate(Metadata::new(name, author, CrateKind::Library), version)?;
+            println!("added {name} {version} by {author}");
+        }
+    }
+    repo.save()?;
+    Ok(())
+}
+
+/// `repo yank <name> <version>`
+fn yank(mut repo: Repository, name: &str, version: &str) -> anyhow::Result<()> {
+    let version: SemVer = version.parse()?;
+    repo.yank(name, version)?;
+    repo.save()?;
+    println!("yanked {name} {version}");
+    Ok(())
+}
+
+/// `repo migrate [compression]`, see [`Repository::migrate`]
+fn migrate(mut repo: Repository, compression: Option<&str>) -> anyhow::Result<()> {
+    if let Some(compression) = compression {
+        repo.set_compression(compression.parse::<Compression>()?);
+    }
+    repo.migrate()?;
+    println!(
+        "rewrote {} crates, compressed with {}",
+        repo.iter().count(),
+        repo.compression()
+    );
+    Ok(())
+}
+
+/// `repo convert [--from <backend>] --to <backend> <path>`, see [`Repository::convert`]. The
+/// store stays as it is, point `SEMVER_REPO` at `path` to switch to the new one.
+fn convert(repo: &Repository, from: Option<&str>, to: &str, path: &str) -> anyhow::Result<()> {
+    let to: Backend = to.parse()?;
+    if let Some(from) = from {
+        let from: Backend = from.parse()?;
+        if std::mem::discriminant(&from) != std::mem::discriminant(&repo.backend()) {
+            anyhow::bail!("the store is {}, not {from}", repo.backend());
+        }
+    }
+    let converted = repo.convert(to, path)?;
+    println!(
+        "converted {} crates from {} to {to} in {path}, verified",
+        converted.iter().count(),
+        repo.backend()
+    );
+    Ok(())
+}
 
+/// `repo import-cratesio <dump-dir>`, see [`dump::import_cratesio`]
+fn import_cratesio(mut repo: Repository, dump_dir: &str) -> anyhow::Result<()> {
+    let status = dump::import_cratesio(&mut repo, dump_dir, |status| {
+        if status.crates % 1000 == 0 || status.crates == status.total_crates {
+            eprintln!(
+                "{}/{} crates, {} releases imported, {} skipped",
+                status.crates, status.total_crates, status.releases, status.skipped
+            );
+        }
+    })?;
     println!(
-        "find crate, second attempt: {:?}",
-        repo.find_exact("linux.exe")
+        "imported {} releases of {} crates, skipped {}",
+        status.releases, status.crates, status.skipped
     );
+    Ok(())
+}
+
+/// `repo export <csv|jsonl> [file]`, see [`Repository::export`], writes to stdout without a file
+fn export(repo: &Repository, format: &str, out: Option<&str>) -> anyhow::Result<()> {
+    let format: ExportFormat = format.parse().map_err(anyhow::Error::msg)?;
+    let rows = match out {
+        Some(path) => repo.export(
+            format,
+            std::io::BufWriter::new(std::fs::File::create(path)?),
+        )?,
+        None => repo.export(format, std::io::stdout().lock())?,
+    };
+    eprintln!("exported {rows} releases");
+    Ok(())
+}
+
+/// `repo render --out <dir>`, see [`Repository::render`]
+fn render(repo: &Repository, out: &str) -> anyhow::Result<()> {
+    let pages = repo.render(out)?;
+    println!("rendered {pages} crate pages into {out}");
+    Ok(())
+}
+
+/// `repo export-index <dir>`, see [`semver_repo::sparse_index`]
+fn export_index(repo: &Repository, out: &str) -> anyhow::Result<()> {
+    let files = repo.export_index(out)?;
+    println!("wrote {files} index files into {out}");
+    Ok(())
+}
+
+/// `repo graph [--json] [root...]`, see [`semver_repo::graph`]. DOT unless `--json`.
+fn graph(repo: &Repository, args: &[&str]) -> anyhow::Result<()> {
+    let (json, roots) = match args {
+        ["--json", roots @ ..] => (true, roots),
+        roots => (false, roots),
+    };
+    let graph = repo.dependency_graph(roots)?;
+    match json {
+        true => println!("{}", serde_json::to_string_pretty(&graph.to_json())?),
+        false => print!("{}", graph.to_dot()),
+    }
+    Ok(())
+}
+
+/// `repo rekey <keyfile|--none>`, see [`Repository::rekey`]. A missing key file is created with
+/// a new key, `--none` stores the repository unencrypted.
+fn rekey(mut repo: Repository, keyfile: &str) -> anyhow::Result<()> {
+    let key = if keyfile == "--none" {
+        None
+    } else if Path::new(keyfile).exists() {
+        Some(StoreKey::read(keyfile)?)
+    } else {
+        let key = StoreKey::generate();
+        key.write(keyfile)?;
+        eprintln!("generated a new key in {keyfile}");
+        Some(key)
+    };
+    repo.rekey(key)?;
+    match keyfile {
+        "--none" => println!("store is no longer encrypted"),
+        _ => println!("store is encrypted with the key in {keyfile}"),
+    }
+    Ok(())
+}
+
+/// `repo shard <prefix-len|--none>`, see [`semver_repo::shards`]. Converts the store into a
+/// directory sharded by that many leading characters of the names, or back into a single file.
+fn shard(mut repo: Repository, prefix_len: &str) -> anyhow::Result<()> {
+    let sharding: Option<Sharding> = match prefix_len {
+        "--none" => None,
+        prefix_len => Some(prefix_len.parse()?),
+    };
+    repo.set_sharding(sharding);
+    repo.save()?;
+    match sharding {
+        Some(sharding) => println!("store is sharded by the first {sharding} characters"),
+        None => println!("store is a single file"),
+    }
+    Ok(())
+}
+
+/// `repo fsck [--repair]`, or `repo verify` without repairs, see [`Repository::verify`]. Fails
+/// if problems remain.
+fn fsck(mut repo: Repository, repair: bool) -> anyhow::Result<()> {
+    if repair {
+        for problem in repo.repair() {
+            println!("repaired: {problem}");
+        }
+    }
+    let problems = repo.verify();
+    for problem in &problems {
+        println!("{problem}");
+    }
+    match problems.len() {
+        0 => Ok(()),
+        n => anyhow::bail!("{n} problems found"),
+    }
+}
+
+/// `repo compact [--dry-run] [policy]` with a policy like `prereleases=5,yanked_days=365`, see
+/// [`Repository::prune`]. Also drops change feed entries of deleted crates, see
+/// [`Repository::compact`], which is all it does without a policy.
+fn compact(mut repo: Repository, policy: Option<&str>, dry_run: bool) -> anyhow::Result<()> {
+    let policy: RetentionPolicy = match policy {
+        Some(policy) => policy.parse()?,
+        None => RetentionPolicy::default(),
+    };
+    if dry_run {
+        print!("{}", repo.prune_report(&policy));
+        return Ok(());
+    }
+    let report = repo.prune(&policy);
+    print!("{report}");
+    let removed = repo.compact();
+    println!(
+        "pruned {} releases, {} tombstones and {removed} changes of deleted crates",
+        report.releases.values().map(Vec::len).sum::<usize>(),
+        report.tombstones.len()
+    );
+    Ok(())
+}
+
+const USAGE: &str = "usage: repo <command>, working on the store without a running server
+       repo list
+       repo show <name>
+       repo search <name part> [--msrv <rust version>] [--target <triple>]
+       repo add <name> <version> [author]
+       repo yank <name> <version>
+       repo verify
+       repo fsck [--repair]
+       repo compact [--dry-run] [policy]
+       repo migrate [none|zstd[:level]|gzip[:level]]
+       repo convert [--from <backend>] --to <json|sharded[:prefix-len]|sqlite> <path>
+       repo import-cratesio <dump-dir>
+       repo export <csv|jsonl> [file]
+       repo render --out <dir>
+       repo export-index <dir>
+       repo graph [--json] [root...]
+       repo rekey <keyfile|--none>
+       repo shard <prefix-len|--none>";
+
+fn main() -> anyhow::Result<()> {
+    let store = env::var("SEMVER_REPO")
+        .ok()
+        .or(option_env!("SEMVER_REPO").map(String::from))
+        .ok_or(anyhow::anyhow!(
+            "missing SEMVER_REPO environment variable. Re-run with e.g.\n \
+            SEMVER_REPO=/tmp/store.json repo list"
+        ))?;
+    // e.g. SEMVER_REPO_KEY_FILE=/etc/semver/store.key for encrypted stores
+    let repo = Repository::open(store, StoreKey::from_env("SEMVER_REPO_KEY")?)?;
 
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        ["list"] => list(&repo),
+        ["show", name] => show(&repo, name)?,
+        ["search", name_part, filters @ ..] => search(&repo, name_part, filters)?,
+        ["add", name, version] => add(repo, name, version, None)?,
+        ["add", name, version, author] => add(repo, name, version, Some(author))?,
+        ["yank", name, version] => yank(repo, name, version)?,
+        ["verify"] | ["fsck"] => fsck(repo, false)?,
+        ["fsck", "--repair"] => fsck(repo, true)?,
+        ["compact"] => compact(repo, None, false)?,
+        ["compact", "--dry-run"] => compact(repo, None, true)?,
+        ["compact", "--dry-run", policy] => compact(repo, Some(policy), true)?,
+        ["compact", policy] => compact(repo, Some(policy), false)?,
+        ["migrate"] => migrate(repo, None)?,
+        ["migrate", compression] => migrate(repo, Some(compression))?,
+        ["convert", "--from", from, "--to", to, path] => convert(&repo, Some(from), to, path)?,
+        ["convert", "--to", to, path] => convert(&repo, None, to, path)?,
+        ["import-cratesio", dump_dir] => import_cratesio(repo, dump_dir)?,
+        ["export", format] => export(&repo, format, None)?,
+        ["export", format, out] => export(&repo, format, Some(out))?,
+        ["render", "--out", out] => render(&repo, out)?,
+        ["export-index", out] => export_index(&repo, out)?,
+        ["graph", args @ ..] => graph(&repo, args)?,
+        ["rekey", keyfile] => rekey(repo, keyfile)?,
+        ["shard", prefix_len] => shard(repo, prefix_len)?,
+        _ => anyhow::bail!("{USAGE}"),
+    }
     Ok(())
 }
@@ -1,129 +1,230 @@
-use std::io::BufReader;
-use std::net::{TcpListener, TcpStream};
-use std::{env, io::prelude::*};
-
-use log::{debug, error};
-use semver_repo::api::{ApiError, ApiResult, FindAllContainingResult};
-use semver_repo::{
-    api::{ApiRequest, FindExactResult},
-    RepoError, Repository,
-};
-use serde::Serialize;
-use thiserror::Error;
-
-trait JsonResponse: Serialize {
-    fn to_json(&self) -> String;
-}
-
-impl<T: Serialize> JsonResponse for Result<T, RepoError> {
-    fn to_json(&self) -> String {
-        // to avoid moving out of self, first convert to Result<&T, &E> using `as_ref()`
-        // then we need to dereference the repo error again so the generated `From` impl
-        // is available.
-        self.as_ref().map_err(|e| ApiError::from(*e)).to_json()
-    }
+use std::env;
+use std::time::Duration;
+
+use semver_repo::access_log::{AccessLogConfig, AccessLogFormat};
+use semver_repo::client::Client;
+use semver_repo::encryption::StoreKey;
+use semver_repo::replication::Follower;
+use semver_repo::server::{Autosave, Registries, ScheduledRetention, Server, ServerConfig};
+use semver_repo::upstream::{RegistryUpstream, UpstreamConfig};
+use semver_repo::webhooks::Webhook;
+use semver_repo::VersionPolicy;
+use semver_repo::{kinds, net};
+
+#[cfg(feature = "crates-io")]
+fn crates_io_upstream() -> anyhow::Result<UpstreamConfig> {
+    Ok(UpstreamConfig::new(
+        semver_repo::upstream::CratesIo::default(),
+    ))
 }
 
-fn internal_error() -> String {
-    let err: ApiResult<()> = Err(ApiError::Internal);
-    serde_json::to_string(&err).unwrap()
+#[cfg(not(feature = "crates-io"))]
+fn crates_io_upstream() -> anyhow::Result<UpstreamConfig> {
+    anyhow::bail!("crates.io upstream requires building with `--features crates-io`")
 }
 
-impl<T: Serialize> JsonResponse for Result<T, ApiError> {
-    fn to_json(&self) -> String {
-        match serde_json::to_string(&self) {
-            Ok(s) => s,
-
-            // safe fallback
-            Err(_) => internal_error(),
+/// rereads the settings file on SIGHUP, invalid files leave the active settings in place
+#[cfg(unix)]
+fn reload_on_sighup(reload: semver_repo::server::ReloadHandle) -> std::io::Result<()> {
+    let mut signals = signal_hook::iterator::Signals::new([signal_hook::consts::SIGHUP])?;
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if let Err(e) = reload.reload() {
+                log::error!("{}", e);
+            }
         }
-    }
-}
-
-#[test]
-fn ensure_safe_json() {
-    todo!();
-    // use std::collections::HashMap;
-    // type DangerMap = HashMap<u32, u32>;
-    // let mut danger: DangerMap = HashMap::default();
-    // danger.insert(1, 2);
-    // let fails: Result<_, ApiError> = Ok(danger);
-    // let cmp: Result<DangerMap, ApiError> = Err(ApiError::Internal);
-    // assert_eq!(fails.to_json(), cmp.to_json())
+    });
+    Ok(())
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     pretty_env_logger::init();
-    let store = option_env!("REPO_STORE").ok_or(anyhow::anyhow!("missing REPO_STORE env var"))?;
-    let mut repository = Repository::new(store);
-
-    let port = env::var("REPO_PORT").unwrap_or("7878".to_string());
-    let addr = format!("127.0.0.1:{}", port);
-    log::info!("serving at {}", addr);
-    let listener = TcpListener::bind(addr)?;
+    let store =
+        env::var("REPO_STORE").map_err(|_| anyhow::anyhow!("missing REPO_STORE env var"))?;
 
-    for connection in listener.incoming() {
-        let mut stream = match connection {
-            Ok(stream) => stream,
-            Err(e) => {
-                error!("Connection error: {:?}", e);
-                continue;
-            }
-        };
-
-        let response = handle(&mut stream, &mut repository);
-        debug!("sending response: {response}");
-        match write!(stream, "{}", response) {
-            Ok(_) => {}
-            Err(e) => error!("error writing to stream: {:?}", e),
+    let port = match env::var("REPO_PORT") {
+        Ok(port) => port.parse()?,
+        Err(_) => net::DEFAULT_PORT,
+    };
+    // e.g. REPO_BIND="127.0.0.1,[::1]:7979" or REPO_BIND=0.0.0.0
+    let bind = env::var("REPO_BIND").unwrap_or_else(|_| net::DEFAULT_HOST.to_string());
+    let mut config = ServerConfig::new(store).with_listen(net::parse_listen_addrs(&bind, port)?);
+
+    // e.g. REPO_WEBHOOKS="http://ci.local/hook|s3cret,http://chat.local:8080/semver|other"
+    if let Ok(hooks) = env::var("REPO_WEBHOOKS") {
+        for hook in hooks.split(',').filter(|h| !h.is_empty()) {
+            let (url, secret) = hook
+                .split_once('|')
+                .ok_or(anyhow::anyhow!("webhook '{}' lacks a '|secret'", hook))?;
+            config.webhooks.hooks.push(Webhook::new(url, secret));
         }
     }
+    config.webhooks.dead_letter = env::var_os("REPO_WEBHOOK_DEAD_LETTER").map(Into::into);
+    config.feed_dir = env::var_os("REPO_FEED_DIR").map(Into::into);
+    // e.g. REPO_ACCESS_LOG=/var/log/semver/access.log or REPO_ACCESS_LOG=- for stdout,
+    // REPO_ACCESS_LOG_FORMAT=json for one JSON object per line instead of the combined format
+    if let Some(path) = env::var_os("REPO_ACCESS_LOG") {
+        config.access_log = Some(AccessLogConfig {
+            path: path.into(),
+            format: match env::var("REPO_ACCESS_LOG_FORMAT") {
+                Ok(format) => format.parse()?,
+                Err(_) => AccessLogFormat::default(),
+            },
+        });
+    }
 
-    Ok(())
-}
-
-#[derive(Error, Debug)]
-enum ParseError {
-    #[error("unreadable")]
-    Unreadable,
-    #[error("garbage: {0}")]
-    Garbage(String),
-}
+    config.admin_token = env::var("REPO_ADMIN_TOKEN").ok();
+    // e.g. REPO_ADMIN_BIND=127.0.0.1:7879 to keep admin requests off the public listeners
+    if let Ok(admin_bind) = env::var("REPO_ADMIN_BIND") {
+        config.admin_listen = net::parse_listen_addrs(&admin_bind, port.saturating_add(1))?;
+    }
 
-fn parse_request(stream: &mut TcpStream) -> Result<ApiRequest, ParseError> {
-    let mut buf = String::new();
-    stream
-        .read_to_string(&mut buf)
-        .map_err(|_| ParseError::Unreadable)?;
-    serde_json::from_str(&buf).map_err(|_| ParseError::Garbage(buf))
-}
+    // e.g. REPO_CONFIRMATION_SECS=300 to allow more time to confirm deletions, 0 disables
+    // confirming
+    if let Ok(secs) = env::var("REPO_CONFIRMATION_SECS") {
+        config.confirmation_window = match secs.parse()? {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        };
+    }
 
-fn handle(stream: &mut TcpStream, repository: &mut Repository) -> String {
-    let request = match parse_request(stream) {
-        Ok(request) => request,
-        Err(e) => {
-            log::warn!("could not parse request - {}", e);
-            return internal_error();
-        }
+    config.case_insensitive_lookup = env::var_os("REPO_CASE_INSENSITIVE").is_some();
+    // e.g. REPO_RESERVED_NAMES="std,core,acme-*", more can be added at runtime by admins
+    let list = |var| -> Vec<String> {
+        env::var(var)
+            .unwrap_or_default()
+            .split(',')
+            .filter(|item| !item.is_empty())
+            .map(str::to_string)
+            .collect()
     };
-
-    match request {
-        ApiRequest::FindExact(crate_name) => {
-            let res: FindExactResult =
-                Ok(repository.find_exact(&crate_name).map(|crt| crt.to_owned()));
-            res.to_json()
+    config.reserved_names = list("REPO_RESERVED_NAMES");
+    config.blocked_terms = list("REPO_BLOCKED_TERMS");
+    let limit = |var| -> anyhow::Result<Option<usize>> {
+        env::var(var)
+            .ok()
+            .map(|limit| limit.parse())
+            .transpose()
+            .map_err(Into::into)
+    };
+    config.quotas.max_crates_per_author = limit("REPO_MAX_CRATES_PER_AUTHOR")?;
+    config.quotas.max_releases_per_day = limit("REPO_MAX_RELEASES_PER_DAY")?;
+    config.quotas.max_request_size = limit("REPO_MAX_REQUEST_SIZE")?;
+    // beyond these, requests are answered with ApiError::Overloaded right away
+    config.max_connections = limit("REPO_MAX_CONNECTIONS")?;
+    config.max_queued_requests = limit("REPO_MAX_QUEUED_REQUESTS")?;
+    // REPO_VERSION_POLICY=unique allows backports like 1.4.9 after 2.0.0
+    config.version_policy = match env::var("REPO_VERSION_POLICY").as_deref() {
+        Ok("strict") => Some(VersionPolicy::StrictlyIncreasing),
+        Ok("unique") => Some(VersionPolicy::Unique),
+        Ok(other) => return Err(anyhow::anyhow!("unknown REPO_VERSION_POLICY '{}'", other).into()),
+        Err(_) => None,
+    };
+    // e.g. REPO_KIND_POLICIES="binary:unique,republish;library:description"
+    if let Ok(policies) = env::var("REPO_KIND_POLICIES") {
+        config.kind_policies = kinds::parse_kind_policies(&policies)?;
+    }
+    // e.g. REPO_COMPRESSION=zstd:19, gzip:6 or none. Stores in any format are read.
+    if let Ok(compression) = env::var("REPO_COMPRESSION") {
+        config.compression = compression.parse()?;
+    }
+    // REPO_STORE_KEY=<64 hex digits> or REPO_STORE_KEY_FILE=/etc/semver/store.key, see `repo rekey`
+    config.store_key = StoreKey::from_env("REPO_STORE_KEY")?;
+    // e.g. REPO_AUTOSAVE_SECS=30 REPO_AUTOSAVE_CHANGES=100, either enables autosaving
+    let autosave_secs = env::var("REPO_AUTOSAVE_SECS").ok();
+    let autosave_changes = env::var("REPO_AUTOSAVE_CHANGES").ok();
+    if autosave_secs.is_some() || autosave_changes.is_some() {
+        let mut autosave = Autosave::default();
+        if let Some(secs) = autosave_secs {
+            autosave.interval = Duration::from_secs(secs.parse()?);
         }
-        ApiRequest::AddCrate(metadata, version) => {
-            repository.add_crate(metadata, version).to_json()
+        if let Some(changes) = autosave_changes {
+            autosave.after_mutations = changes.parse()?;
         }
-        ApiRequest::AddRelease(name, version) => repository.add_release(name, version).to_json(),
-        ApiRequest::FindAllContaining(name) => {
-            let res: FindAllContainingResult = Ok(repository
-                .find_containing(name)
-                .into_iter()
-                .cloned()
-                .collect());
-            res.to_json()
+        config.autosave = Some(autosave);
+    }
+    // e.g. REPO_RETENTION="prereleases=5,yanked_days=365,tombstone_days=90", daily by default
+    if let Ok(policy) = env::var("REPO_RETENTION") {
+        let interval = match env::var("REPO_RETENTION_INTERVAL_SECS") {
+            Ok(secs) => Duration::from_secs(secs.parse()?),
+            Err(_) => Duration::from_secs(24 * 60 * 60),
+        };
+        config.retention = Some(ScheduledRetention {
+            policy: policy.parse()?,
+            interval,
+        });
+    }
+    // e.g. REPO_REGISTRIES_DIR=/var/lib/semver/registries REPO_REGISTRIES=team-a,staging
+    if let Ok(dir) = env::var("REPO_REGISTRIES_DIR") {
+        let names = env::var("REPO_REGISTRIES").unwrap_or_default();
+        config.registries = Some(Registries {
+            dir: dir.into(),
+            names: names
+                .split(',')
+                .filter(|name| !name.is_empty())
+                .map(String::from)
+                .collect(),
+        });
+    }
+    // e.g. REPO_GRPC_ADDR=127.0.0.1:50051
+    #[cfg(feature = "grpc")]
+    if let Ok(addr) = env::var("REPO_GRPC_ADDR") {
+        config.grpc_listen = Some(addr.parse()?);
+    }
+    // e.g. REPO_WEBSOCKET_ADDR=127.0.0.1:7879
+    #[cfg(feature = "websocket")]
+    if let Ok(addr) = env::var("REPO_WEBSOCKET_ADDR") {
+        config.websocket_listen = Some(addr.parse()?);
+    }
+    // e.g. REPO_OTLP_ENDPOINT=http://localhost:4318, with REPO_OTLP_SERVICE_NAME=semver-staging
+    #[cfg(feature = "otel")]
+    if let Ok(endpoint) = env::var("REPO_OTLP_ENDPOINT") {
+        let mut telemetry = semver_repo::telemetry::TelemetryConfig::new(endpoint);
+        if let Ok(name) = env::var("REPO_OTLP_SERVICE_NAME") {
+            telemetry.service_name = name;
         }
+        config.telemetry = Some(telemetry);
+    }
+    config.read_only = env::args().skip(1).any(|arg| arg == "--read-only");
+    // replaces a corrupt store with an empty one instead of refusing to start
+    config.force_new = env::args().skip(1).any(|arg| arg == "--force-new");
+    // e.g. REPO_SETTINGS=/etc/semver/settings.toml, reread on SIGHUP, see semver_repo::settings
+    config.settings_file = env::var_os("REPO_SETTINGS").map(Into::into);
+    // e.g. REPO_FOLLOW=primary.local:7878 to run as a read-only mirror
+    if let Ok(primary) = env::var("REPO_FOLLOW") {
+        let target = net::parse_target(&primary, net::DEFAULT_PORT)?;
+        log::info!("following {}", target);
+        config.follow = Some(Follower::new(Client::new(target)));
+    }
+    if config.read_only {
+        log::info!("read-only mode, rejecting all changes");
     }
+
+    // e.g. REPO_UPSTREAM=other-registry.local:7878 or REPO_UPSTREAM=crates.io
+    if let Ok(upstream) = env::var("REPO_UPSTREAM") {
+        let mut upstream_config = if upstream == "crates.io" {
+            crates_io_upstream()?
+        } else {
+            let target = net::parse_target(&upstream, net::DEFAULT_PORT)?;
+            UpstreamConfig::new(RegistryUpstream(Client::new(target)))
+        };
+        if let Ok(ttl) = env::var("REPO_UPSTREAM_TTL") {
+            upstream_config.ttl = Duration::from_secs(ttl.parse()?);
+        }
+        if let Ok(capacity) = env::var("REPO_UPSTREAM_CACHE") {
+            upstream_config.cache_capacity = capacity.parse()?;
+        }
+        upstream_config.offline = env::var_os("REPO_UPSTREAM_OFFLINE").is_some();
+        config.upstream = Some(upstream_config);
+    }
+
+    #[cfg(unix)]
+    let reloadable = config.settings_file.is_some();
+    let server = Server::bind(config)?;
+    #[cfg(unix)]
+    if reloadable {
+        reload_on_sighup(server.reload_handle())?;
+    }
+    server.serve()?;
+    Ok(())
 }
@@ -0,0 +1,133 @@
+//! Content-addressed storage for large per-version data like READMEs, kept out of the store.
+//!
+//! Blobs live in a directory next to the store, `<store>.blobs`, one file per blob named by the
+//! SHA-256 of its content. Storing the same content twice keeps one copy. Blobs are only removed
+//! when a rolled back change wrote them, see [`crate::Repository::rollback`].
+
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::tmp_path;
+
+/// hex SHA-256 of a blob's content
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlobId(String);
+
+impl BlobId {
+    pub fn of(content: &[u8]) -> Self {
+        BlobId(hex::encode(Sha256::digest(content)))
+    }
+
+    /// ids read from stores or the network name files, so must not contain anything but hex
+    fn is_valid(&self) -> bool {
+        self.0.len() == 64 && self.0.bytes().all(|b| b.is_ascii_hexdigit())
+    }
+}
+
+impl Display for BlobId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobStore {
+    dir: PathBuf,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// the blobs of the store at `store`
+    pub fn beside(store: &Path) -> Self {
+        let mut dir = store.as_os_str().to_owned();
+        dir.push(".blobs");
+        Self::new(dir)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Stores `content` unless it already is. Written to a temporary file first, so readers
+    /// never see partial blobs.
+    pub fn put(&self, content: &[u8]) -> io::Result<BlobId> {
+        let id = BlobId::of(content);
+        let path = self.dir.join(&id.0);
+        if path.is_file() {
+            return Ok(id);
+        }
+        fs::create_dir_all(&self.dir)?;
+        let tmp = tmp_path(&path);
+        let mut file = fs::File::create(&tmp)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        fs::rename(&tmp, &path)?;
+        Ok(id)
+    }
+
+    /// the content of `id`, `None` if there is no such blob
+    pub fn get(&self, id: &BlobId) -> io::Result<Option<Vec<u8>>> {
+        if !id.is_valid() {
+            return Ok(None);
+        }
+        match fs::read(self.dir.join(&id.0)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn contains(&self, id: &BlobId) -> bool {
+        id.is_valid() && self.dir.join(&id.0).is_file()
+    }
+
+    /// Removes `id`, a missing blob is fine.
+    pub fn remove(&self, id: &BlobId) -> io::Result<()> {
+        if !id.is_valid() {
+            return Ok(());
+        }
+        match fs::remove_file(self.dir.join(&id.0)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn put_and_get() -> io::Result<()> {
+        let dir = tempdir()?;
+        let blobs = BlobStore::beside(&dir.path().join("store.json"));
+        assert_eq!(dir.path().join("store.json.blobs"), blobs.dir());
+
+        let id = blobs.put(b"# hello")?;
+        assert_eq!(id, blobs.put(b"# hello")?);
+        assert!(blobs.contains(&id));
+        assert_eq!(Some(b"# hello".to_vec()), blobs.get(&id)?);
+        assert_eq!(1, fs::read_dir(blobs.dir())?.count());
+
+        assert_eq!(None, blobs.get(&BlobId::of(b"missing"))?);
+        let escaping = BlobId("../store.json".into());
+        assert!(!blobs.contains(&escaping));
+        assert_eq!(None, blobs.get(&escaping)?);
+
+        blobs.remove(&id)?;
+        assert!(!blobs.contains(&id));
+        blobs.remove(&id)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! Versions admins block for everyone, e.g. known-malicious uploads, see
+//! [`crate::admin::AdminRequest::BlockVersion`].
+//!
+//! Unlike yanked releases, blocked ones are never resolved or the latest version, not even on
+//! request, and their owners can't undo it. They stay in the history, marked as blocked.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockedVersion {
+    pub version: SemVer,
+    /// why, shown to anyone looking at the release
+    pub reason: String,
+}
+
+impl Crate {
+    pub fn blocked(&self) -> &[BlockedVersion] {
+        &self.blocked
+    }
+
+    pub fn is_blocked(&self, version: SemVer) -> bool {
+        self.blocked
+            .iter()
+            .any(|blocked| blocked.version == version)
+    }
+
+    /// Blocks `version`, replacing the reason if it is blocked already
+    pub(crate) fn block(&mut self, version: SemVer, reason: String) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        self.blocked.retain(|blocked| blocked.version != version);
+        self.blocked.push(BlockedVersion { version, reason });
+        self.revision += 1;
+        Ok(())
+    }
+
+    pub(crate) fn unblock(&mut self, version: SemVer) -> Result<(), RepoError> {
+        if !self.is_blocked(version) {
+            return Err(RepoError::NotFound);
+        }
+        self.blocked.retain(|blocked| blocked.version != version);
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    pub fn block_version(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        reason: impl Into<String>,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        let reason = reason.into();
+        crt.block(version, reason.clone())?;
+        self.changes.record(Event::VersionBlocked {
+            name: crt.metadata.name.clone(),
+            version,
+            reason,
+        });
+        Ok(())
+    }
+
+    pub fn unblock_version(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.unblock(version)?;
+        self.changes.record(Event::VersionUnblocked {
+            name: crt.metadata.name.clone(),
+            version,
+        });
+        Ok(())
+    }
+
+    /// every blocked version with the name of its crate, ordered by name
+    pub fn blocked_versions(&self) -> Vec<(String, BlockedVersion)> {
+        self.iter()
+            .flat_map(|crt| {
+                let name = crt.metadata().name();
+                crt.blocked()
+                    .iter()
+                    .map(move |blocked| (name.to_string(), blocked.clone()))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::channels::Channel;
+    use crate::resolve::{SkipReason, Skipped, VersionReq};
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn block() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_lib", SemVer::new(1, 1, 0))?;
+        repo.block_version("hello_lib", SemVer::new(1, 1, 0), "steals tokens")?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.block_version("hello_lib", SemVer::new(2, 0, 0), "nope")
+        );
+
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert_eq!(Some(SemVer::new(1, 0, 0)), crt.latest());
+        assert_eq!(Some(SemVer::new(1, 0, 0)), crt.latest_in(Channel::Nightly));
+        assert_eq!(2, crt.releases().len());
+        let resolution = repo.resolve("hello_lib", &VersionReq::STAR, true, true)?;
+        assert_eq!(Some(SemVer::new(1, 0, 0)), resolution.version);
+        assert_eq!(
+            vec![Skipped {
+                version: SemVer::new(1, 1, 0),
+                reason: SkipReason::Blocked
+            }],
+            resolution.skipped
+        );
+        assert_eq!(
+            vec![(
+                "hello_lib".to_string(),
+                BlockedVersion {
+                    version: SemVer::new(1, 1, 0),
+                    reason: "steals tokens".into()
+                }
+            )],
+            repo.blocked_versions()
+        );
+
+        repo.unblock_version("hello_lib", SemVer::new(1, 1, 0))?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.unblock_version("hello_lib", SemVer::new(1, 1, 0))
+        );
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert_eq!(Some(SemVer::new(1, 1, 0)), crt.latest());
+        assert_eq!(4, repo.changes().last_seq());
+        Ok(())
+    }
+}
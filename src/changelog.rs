@@ -0,0 +1,154 @@
+//! Release notes attached to single versions, and changelogs concatenating them over a range of
+//! versions, e.g. everything since the version a user is on.
+
+use std::sync::Arc;
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// longest release notes of a single version, in bytes
+pub const MAX_RELEASE_NOTES_LEN: usize = 64 * 1024;
+
+impl Crate {
+    pub fn release_notes(&self, version: SemVer) -> Option<&str> {
+        self.release_notes
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, notes)| notes.as_str())
+    }
+
+    /// notes of the releases newer than `from` up to and including `to`, newest first
+    pub fn changelog(&self, from: SemVer, to: SemVer) -> Vec<(SemVer, &str)> {
+        let mut notes: Vec<_> = self
+            .release_notes
+            .iter()
+            .filter(|(version, _)| from < *version && *version <= to)
+            .map(|(version, notes)| (*version, notes.as_str()))
+            .collect();
+        notes.sort_by(|(a, _), (b, _)| b.cmp(a));
+        notes
+    }
+
+    /// Replaces the notes of `version`, `None` or empty notes remove them
+    pub(crate) fn set_release_notes(
+        &mut self,
+        version: SemVer,
+        notes: Option<String>,
+    ) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        let notes = notes.filter(|notes| !notes.trim().is_empty());
+        if notes.as_ref().map(String::len) > Some(MAX_RELEASE_NOTES_LEN) {
+            return Err(RepoError::ReleaseNotesTooLong);
+        }
+        self.release_notes.retain(|(v, _)| *v != version);
+        if let Some(notes) = notes {
+            self.release_notes.push((version, notes));
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+/// `## <version>` sections with the notes of each release, as returned by [`Crate::changelog`]
+pub fn render(changelog: &[(SemVer, &str)]) -> String {
+    changelog
+        .iter()
+        .map(|(version, notes)| format!("## {}\n\n{}\n", version, notes.trim()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Repository {
+    /// Attaches release notes to `version` of the crate called `name`, replacing earlier ones.
+    /// `None` removes them.
+    pub fn set_release_notes(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        notes: Option<String>,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_release_notes(version, notes)?;
+        self.changes.record(Event::ReleaseNotesChanged {
+            name: crt.metadata.name.clone(),
+            version,
+            notes: crt.release_notes(version).map(String::from),
+        });
+        Ok(())
+    }
+
+    /// the rendered notes of the releases of `name` newer than `from` up to and including `to`,
+    /// see [`Crate::changelog`]
+    pub fn changelog(
+        &self,
+        name: impl AsRef<str>,
+        from: SemVer,
+        to: SemVer,
+    ) -> Result<String, RepoError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        Ok(render(&crt.changelog(from, to)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn changelog() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.add_release("hello_bin", SemVer::new(1, 2, 0))?;
+        repo.add_release("hello_bin", SemVer::new(2, 0, 0))?;
+
+        repo.set_release_notes("hello_bin", SemVer::new(1, 0, 0), Some("first".into()))?;
+        repo.set_release_notes("hello_bin", SemVer::new(1, 1, 0), Some("faster\n".into()))?;
+        repo.set_release_notes("hello_bin", SemVer::new(2, 0, 0), Some("breaking".into()))?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.set_release_notes("hello_bin", SemVer::new(3, 0, 0), Some("soon".into()))
+        );
+        assert_eq!(
+            Err(RepoError::ReleaseNotesTooLong),
+            repo.set_release_notes(
+                "hello_bin",
+                SemVer::new(1, 2, 0),
+                Some("x".repeat(MAX_RELEASE_NOTES_LEN + 1))
+            )
+        );
+
+        assert_eq!(
+            "## 2.0.0\n\nbreaking\n\n## 1.1.0\n\nfaster\n",
+            repo.changelog("hello_bin", SemVer::new(1, 0, 0), SemVer::new(2, 0, 0))?
+        );
+        assert_eq!(
+            "",
+            repo.changelog("hello_bin", SemVer::new(1, 1, 0), SemVer::new(1, 2, 0))?
+        );
+
+        repo.set_release_notes("hello_bin", SemVer::new(2, 0, 0), None)?;
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!(None, crt.release_notes(SemVer::new(2, 0, 0)));
+        assert_eq!(Some("first"), crt.release_notes(SemVer::new(1, 0, 0)));
+        assert_eq!(
+            vec![(SemVer::new(1, 1, 0), "faster\n")],
+            crt.changelog(SemVer::new(1, 0, 0), SemVer::new(2, 0, 0))
+        );
+        assert_eq!(8, repo.changes().last_seq());
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Release channels, so pre-releases can be published without becoming the latest version.
+//!
+//! Lookups of the latest version default to [`Channel::Stable`]. Opting into a channel includes
+//! the more stable ones, e.g. the latest `Beta` version may also be a stable one.
+
+use std::sync::Arc;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// ordered from most to least stable
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum Channel {
+    #[default]
+    Stable,
+    Beta,
+    Nightly,
+}
+
+impl Crate {
+    /// the channel `version` was published to
+    pub fn channel(&self, version: SemVer) -> Channel {
+        self.channels
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, channel)| *channel)
+            .unwrap_or_default()
+    }
+
+    /// the newest release that hasn't been yanked, in `channel` or a more stable one
+    #[must_use]
+    pub fn latest_in(&self, channel: Channel) -> Option<SemVer> {
+        // not necessarily the last one, see `VersionPolicy::Unique`
+        self.release_history
+            .iter()
+            .filter(|v| !self.is_yanked(**v) && !self.is_blocked(**v))
+            .filter(|v| self.channel(**v) <= channel)
+            .max()
+            .copied()
+    }
+
+    /// the newest stable release that hasn't been yanked and is a non-breaking upgrade from
+    /// `base`, see [`SemVer::is_compatible_with`]
+    #[must_use]
+    pub fn latest_upgrade(&self, base: SemVer) -> Option<SemVer> {
+        self.release_history
+            .iter()
+            .filter(|v| !self.is_yanked(**v) && !self.is_blocked(**v))
+            .filter(|v| self.channel(**v) == Channel::Stable)
+            .filter(|v| **v >= base && v.is_compatible_with(&base))
+            .max()
+            .copied()
+    }
+
+    pub(crate) fn set_channel(&mut self, version: SemVer, channel: Channel) {
+        self.channels.retain(|(v, _)| *v != version);
+        // only pre-releases are listed, everything else is stable
+        if channel != Channel::Stable {
+            self.channels.push((version, channel));
+        }
+    }
+}
+
+impl Repository {
+    /// like [`Repository::add_release`], publishing to `channel`
+    pub fn add_release_to(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        channel: Channel,
+    ) -> Result<(), RepoError> {
+        let name = name.as_ref();
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        let metadata = self
+            .crates
+            .get(name)
+            .map(|crt| crt.metadata())
+            .ok_or(RepoError::NotFound)?;
+        self.check_kind_policy(metadata)?;
+        let policy = self.version_policy_for(metadata.kind());
+        let republish = self.kind_policy(metadata.kind()).allow_republish;
+        let crt = self
+            .crates
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+
+        match republish && crt.releases().contains(&version) {
+            true => crt.republish(version, Utc::now()),
+            false => crt.add_release_at(version, Utc::now(), policy)?,
+        }
+        crt.set_channel(version, channel);
+        self.changes.record(Event::ReleaseAdded {
+            name: crt.metadata.name.clone(),
+            version,
+            channel,
+        });
+        Ok(())
+    }
+
+    /// the latest version of the crate called `name` in `channel`, see [`Crate::latest_in`]
+    pub fn latest_version(&self, name: impl AsRef<str>, channel: Channel) -> Option<SemVer> {
+        self.find_exact(name)?.latest_in(channel)
+    }
+
+    /// the newest non-breaking upgrade of the crate called `name` from `base`, see
+    /// [`Crate::latest_upgrade`]
+    pub fn latest_compatible(&self, name: impl AsRef<str>, base: SemVer) -> Option<SemVer> {
+        self.find_exact(name)?.latest_upgrade(base)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn channels() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release_to("hello_bin", SemVer::new(1, 1, 0), Channel::Beta)?;
+        repo.add_release_to("hello_bin", SemVer::new(1, 2, 0), Channel::Nightly)?;
+
+        let latest = |repo: &Repository, channel| repo.latest_version("hello_bin", channel);
+        assert_eq!(Some(SemVer::new(1, 0, 0)), latest(&repo, Channel::Stable));
+        assert_eq!(Some(SemVer::new(1, 1, 0)), latest(&repo, Channel::Beta));
+        assert_eq!(Some(SemVer::new(1, 2, 0)), latest(&repo, Channel::Nightly));
+        assert_eq!(
+            Some(SemVer::new(1, 0, 0)),
+            repo.find_exact("hello_bin").unwrap().latest()
+        );
+
+        repo.add_release("hello_bin", SemVer::new(1, 3, 0))?;
+        assert_eq!(Some(SemVer::new(1, 3, 0)), latest(&repo, Channel::Beta));
+        assert_eq!(
+            Channel::Nightly,
+            repo.find_exact("hello_bin")
+                .unwrap()
+                .channel(SemVer::new(1, 2, 0))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn latest_compatible() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Binary),
+            SemVer::new(0, 9, 0),
+        )?;
+        for version in [[1, 4, 2], [1, 5, 0], [1, 6, 0], [1, 7, 0]] {
+            repo.add_release("hello_lib", version.into())?;
+        }
+        repo.add_release_to("hello_lib", SemVer::new(1, 8, 0), Channel::Beta)?;
+        repo.add_release("hello_lib", SemVer::new(2, 0, 0))?;
+        repo.yank("hello_lib", SemVer::new(1, 7, 0))?;
+
+        let latest = |base| repo.latest_compatible("hello_lib", base);
+        assert_eq!(Some(SemVer::new(1, 6, 0)), latest(SemVer::new(1, 4, 2)));
+        assert_eq!(Some(SemVer::new(2, 0, 0)), latest(SemVer::new(2, 0, 0)));
+        assert_eq!(Some(SemVer::new(0, 9, 0)), latest(SemVer::new(0, 9, 0)));
+        // nothing newer than the base
+        assert_eq!(None, latest(SemVer::new(1, 6, 1)));
+        assert_eq!(None, latest(SemVer::new(3, 0, 0)));
+        assert_eq!(
+            None,
+            repo.latest_compatible("missing", SemVer::new(1, 0, 0))
+        );
+        Ok(())
+    }
+}
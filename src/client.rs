@@ -0,0 +1,1414 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use log::debug;
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::admin::{AdminRequest, AdminResponse};
+use crate::advisories::Advisory;
+use crate::api::{
+    ApiError, ApiRequest, ApiResult, CrateSummary, DryRunOutcome, ServerInfo, TaggedRequest,
+    TaggedResponse,
+};
+use crate::audit::AuditEntry;
+use crate::auth::TokenInfo;
+use crate::channels::Channel;
+use crate::dependencies::{Dependency, DependencyDiagnostic};
+use crate::deprecation::Deprecation;
+use crate::events::Change;
+use crate::features::Features;
+use crate::orgs::{OrgRequest, Organization};
+use crate::platform::Platform;
+use crate::preflight::PublishWarning;
+use crate::replication::{FeedStatus, Snapshot};
+use crate::resolve::{Resolution, VersionReq};
+use crate::search::{Cursor, Page, SearchOptions};
+use crate::{Crate, Metadata, SemVer};
+
+#[cfg(feature = "async-client")]
+pub mod r#async;
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid response: {0}")]
+    Protocol(#[from] serde_json::Error),
+    #[error("server error: {0}")]
+    Api(#[from] ApiError),
+    #[error("timed out")]
+    TimedOut,
+}
+
+/// io errors of timed out connections are reported as `ClientError::TimedOut`
+fn io_error(e: io::Error) -> ClientError {
+    match e.kind() {
+        io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ClientError::TimedOut,
+        _ => ClientError::Io(e),
+    }
+}
+
+/// How long a [`Client`] waits for the server, see [`Client::with_timeouts`]. `None` waits
+/// forever, which is the default. Leave `Subscribe` requests enough time to wait for changes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    /// longest wait for the next bytes of a response
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+    /// longest time a request may take altogether, including retries
+    pub deadline: Option<Duration>,
+}
+
+/// the time left for the next operation, failing if the deadline has passed
+fn remaining(timeout: Option<Duration>, deadline: Option<Instant>) -> io::Result<Option<Duration>> {
+    let left = match deadline {
+        Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+            Some(left) if !left.is_zero() => Some(left),
+            _ => return Err(io::ErrorKind::TimedOut.into()),
+        },
+        None => None,
+    };
+    Ok(match (timeout, left) {
+        (Some(timeout), Some(left)) => Some(timeout.min(left)),
+        (timeout, left) => timeout.or(left),
+    })
+}
+
+fn connect(
+    target: &str,
+    timeouts: &Timeouts,
+    deadline: Option<Instant>,
+) -> io::Result<TimedStream> {
+    let stream = match remaining(timeouts.connect, deadline)? {
+        None => TcpStream::connect(target)?,
+        Some(timeout) => {
+            let mut last_error = None;
+            let mut connected = None;
+            for addr in target.to_socket_addrs()? {
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(stream) => {
+                        connected = Some(stream);
+                        break;
+                    }
+                    Err(e) => last_error = Some(e),
+                }
+            }
+            match (connected, last_error) {
+                (Some(stream), _) => stream,
+                (None, Some(e)) => return Err(e),
+                (None, None) => return Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+    };
+    Ok(TimedStream {
+        stream,
+        timeouts: *timeouts,
+        deadline,
+    })
+}
+
+/// A connection that gives up once the server stalls for longer than allowed
+#[derive(Debug)]
+struct TimedStream {
+    stream: TcpStream,
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+}
+
+impl Read for TimedStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timeout = remaining(self.timeouts.read, self.deadline)?;
+        self.stream.set_read_timeout(timeout)?;
+        self.stream.read(buf)
+    }
+}
+
+impl Write for TimedStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let timeout = remaining(self.timeouts.write, self.deadline)?;
+        self.stream.set_write_timeout(timeout)?;
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// How a [`Client`] retries requests that didn't get an answer, e.g. because the connection was
+/// refused or reset. Only requests that are safe to repeat are retried: reads, and mutations
+/// carrying an idempotency key, see [`Client::request_idempotent`]. Requests rejected with
+/// [`ApiError::Overloaded`] weren't executed, those are retried whatever they are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// attempts per request, including the first one
+    pub max_attempts: u32,
+    /// wait before the first retry, doubled after every failure
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// the wait before retry number `retry`, starting at 1. Somewhere between half and all of the
+    /// backoff, so clients that failed together don't retry together.
+    fn backoff(&self, retry: u32) -> Duration {
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(retry - 1))
+            .min(self.max_backoff);
+        let mut random = [0; 4];
+        getrandom::getrandom(&mut random).expect("no randomness available");
+        let jitter = f64::from(u32::from_le_bytes(random)) / f64::from(u32::MAX);
+        backoff / 2 + (backoff / 2).mul_f64(jitter)
+    }
+}
+
+/// How many connections a [`Client`] keeps open for later requests, see [`Client::with_pool`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// idle connections kept at most, more are closed after their request
+    pub max_idle: usize,
+    /// idle connections are closed after this long
+    pub idle_timeout: Duration,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self {
+            max_idle: 4,
+            idle_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// A connection in pipelining mode carrying one request at a time, see [`TaggedRequest`]
+#[derive(Debug)]
+struct PooledConnection {
+    reader: BufReader<TimedStream>,
+    next_id: u64,
+}
+
+impl PooledConnection {
+    fn send(
+        &mut self,
+        request: &ApiRequest,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        self.reader.get_mut().deadline = deadline;
+        self.next_id += 1;
+        let line = serde_json::to_string(&TaggedRequest {
+            id: self.next_id,
+            request: request.clone(),
+        })?;
+        debug!("→ {}", line);
+        writeln!(self.reader.get_mut(), "{}", line).map_err(io_error)?;
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(io_error)? == 0 {
+            return Err(closed().into());
+        }
+        let tagged: TaggedResponse = serde_json::from_str(&line)?;
+        Ok(tagged.response.get().to_string())
+    }
+}
+
+/// idle connections with the time they were last used, shared by clones of a [`Client`]
+#[derive(Debug)]
+struct ConnectionPool {
+    config: PoolConfig,
+    idle: Mutex<Vec<(PooledConnection, Instant)>>,
+}
+
+impl ConnectionPool {
+    /// the most recently used connection that hasn't timed out
+    fn take(&self) -> Option<PooledConnection> {
+        let mut idle = self.idle.lock().unwrap();
+        idle.retain(|(_, used)| used.elapsed() < self.config.idle_timeout);
+        idle.pop().map(|(connection, _)| connection)
+    }
+
+    fn put(&self, connection: PooledConnection) {
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() < self.config.max_idle {
+            idle.push((connection, Instant::now()));
+        }
+    }
+}
+
+/// How long a [`Client`] remembers answers to lookups, see [`Client::with_cache`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheConfig {
+    /// answers older than this are asked for again
+    pub ttl: Duration,
+    /// the oldest answer is dropped to make room for more
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            ttl: Duration::from_secs(60),
+            max_entries: 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CachedResponse {
+    crate_name: String,
+    at: Instant,
+    response: String,
+}
+
+/// successful answers to `FindExact` and `LatestVersion`, keyed by request and shared by clones of
+/// a [`Client`]
+#[derive(Debug)]
+struct ResponseCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    /// the crate `request` looks up if its answer can be cached
+    fn cacheable(request: &ApiRequest) -> Option<&str> {
+        match request {
+            ApiRequest::FindExact(name) | ApiRequest::LatestVersion { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    fn get(&self, request: &ApiRequest) -> Option<String> {
+        Self::cacheable(request)?;
+        let key = serde_json::to_string(request).ok()?;
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(&key) {
+            Some(entry) if entry.at.elapsed() < self.config.ttl => Some(entry.response.clone()),
+            Some(_) => {
+                entries.remove(&key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// remembers the answer to a lookup, forgets what a mutation may have changed
+    fn update(&self, request: &ApiRequest, response: Option<&str>) {
+        if request.is_mutating() {
+            let mut entries = self.entries.lock().unwrap();
+            match request.crate_name() {
+                Some(name) => entries.retain(|_, entry| entry.crate_name != name),
+                // e.g. batches, which may touch any crate
+                None => entries.clear(),
+            }
+            return;
+        }
+        let (name, response) = match (Self::cacheable(request), response) {
+            (Some(name), Some(response)) if response.starts_with(r#"{"Ok""#) => (name, response),
+            _ => return,
+        };
+        let key = match serde_json::to_string(request) {
+            Ok(key) => key,
+            Err(_) => return,
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.at.elapsed() < self.config.ttl);
+        while entries.len() >= self.config.max_entries {
+            let oldest = match entries.iter().min_by_key(|(_, entry)| entry.at) {
+                Some((key, _)) => key.clone(),
+                None => return,
+            };
+            entries.remove(&oldest);
+        }
+        entries.insert(
+            key,
+            CachedResponse {
+                crate_name: name.to_string(),
+                at: Instant::now(),
+                response: response.to_string(),
+            },
+        );
+    }
+}
+
+/// whether sending `request` twice does no harm
+fn is_safe_to_retry(request: &ApiRequest) -> bool {
+    match request {
+        // the server only honours keys in the outer envelopes
+        ApiRequest::Idempotent { .. } => true,
+        ApiRequest::Authenticated { request, .. }
+        | ApiRequest::Registry { request, .. }
+        | ApiRequest::Traced { request, .. } => is_safe_to_retry(request),
+        request => !request.is_mutating(),
+    }
+}
+
+/// whether the server rejected the request with `ApiError::Overloaded` without executing it
+fn is_overloaded(response: &str) -> bool {
+    matches!(
+        serde_json::from_str::<ApiResult<serde::de::IgnoredAny>>(response),
+        Ok(Err(ApiError::Overloaded))
+    )
+}
+
+/// An endless stream of changes, see [`Client::watch`]
+#[derive(Debug)]
+pub struct Watch<'a> {
+    client: &'a Client,
+    since: u64,
+    pending: VecDeque<Change>,
+}
+
+impl Iterator for Watch<'_> {
+    type Item = Result<Change, ClientError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(change) = self.pending.pop_front() {
+                return Some(Ok(change));
+            }
+            match self.client.subscribe(self.since) {
+                Ok(changes) => {
+                    if let Some(last) = changes.last() {
+                        self.since = last.seq;
+                    }
+                    self.pending.extend(changes);
+                }
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Talks to a server over the line based JSON protocol, one connection per request.
+#[derive(Debug, Clone)]
+pub struct Client {
+    target: String,
+    /// sent with every request, see [`Client::with_token`]
+    token: Option<String>,
+    /// see [`Client::with_registry`]
+    registry: Option<String>,
+    /// no retries if `None`, see [`Client::with_retry`]
+    retry: Option<RetryPolicy>,
+    /// see [`Client::with_pool`]
+    pool: Option<Arc<ConnectionPool>>,
+    timeouts: Timeouts,
+    /// see [`Client::with_cache`]
+    cache: Option<Arc<ResponseCache>>,
+}
+
+impl Client {
+    /// `target` is anything `TcpStream::connect` accepts, see [`crate::net::parse_target`]
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            token: None,
+            registry: None,
+            retry: None,
+            pool: None,
+            timeouts: Timeouts::default(),
+            cache: None,
+        }
+    }
+
+    /// sends every request on behalf of the user `token` was issued to, see
+    /// [`ApiRequest::Authenticated`]
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// sends every request to the registry called `name` instead of the default one, see
+    /// [`ApiRequest::Registry`]
+    pub fn with_registry(mut self, name: impl Into<String>) -> Self {
+        self.registry = Some(name.into());
+        self
+    }
+
+    /// retries requests that are safe to repeat when the server can't be reached
+    pub fn with_retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Reuses connections for later requests instead of connecting for each one, which pays off
+    /// for many requests in a row, e.g. imports. Clones of the client share the pool.
+    pub fn with_pool(mut self, config: PoolConfig) -> Self {
+        self.pool = Some(Arc::new(ConnectionPool {
+            config,
+            idle: Mutex::new(vec![]),
+        }));
+        self
+    }
+
+    /// gives up with `ClientError::TimedOut` instead of waiting for a stalled server forever
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Answers repeated `FindExact` and `LatestVersion` lookups from memory until `config.ttl`
+    /// passes, e.g. in build scripts. Mutations sent through the client, or its clones, drop what
+    /// they may have changed. Changes by others show up once the cached answers expire.
+    pub fn with_cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(Arc::new(ResponseCache {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }));
+        self
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// sends `request` and returns the raw response line
+    pub fn send(&self, request: &ApiRequest) -> Result<String, ClientError> {
+        self.send_until(request, self.deadline())
+    }
+
+    /// when a request sent now has to be answered
+    fn deadline(&self) -> Option<Instant> {
+        self.timeouts
+            .deadline
+            .map(|deadline| Instant::now() + deadline)
+    }
+
+    fn send_until(
+        &self,
+        request: &ApiRequest,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        if let Some(pool) = &self.pool {
+            return self.send_pooled(pool, request, deadline);
+        }
+        let request_json = serde_json::to_string(request)?;
+        debug!("→ {}", request_json);
+        let mut buffer = String::new();
+        connect(&self.target, &self.timeouts, deadline)
+            .and_then(|mut connection| {
+                writeln!(connection, "{}", request_json)?;
+                connection.stream.shutdown(Shutdown::Write)?;
+                connection.read_to_string(&mut buffer)
+            })
+            .map_err(io_error)?;
+        if buffer.is_empty() {
+            return Err(closed().into());
+        }
+        Ok(buffer)
+    }
+
+    fn send_pooled(
+        &self,
+        pool: &ConnectionPool,
+        request: &ApiRequest,
+        deadline: Option<Instant>,
+    ) -> Result<String, ClientError> {
+        let reused = pool.take();
+        let is_reused = reused.is_some();
+        let mut connection = match reused {
+            Some(connection) => connection,
+            None => self.connect_pooled(deadline)?,
+        };
+        let response = match connection.send(request, deadline) {
+            // the server might have closed it while it was idle
+            Err(ClientError::Io(e)) if is_reused && is_safe_to_retry(request) => {
+                debug!("reconnecting after stale connection failed: {}", e);
+                connection = self.connect_pooled(deadline)?;
+                connection.send(request, deadline)?
+            }
+            res => res?,
+        };
+        pool.put(connection);
+        Ok(response)
+    }
+
+    fn connect_pooled(&self, deadline: Option<Instant>) -> Result<PooledConnection, ClientError> {
+        Ok(PooledConnection {
+            reader: BufReader::new(
+                connect(&self.target, &self.timeouts, deadline).map_err(io_error)?,
+            ),
+            next_id: 0,
+        })
+    }
+
+    /// [`Client::send`], retried as configured within the deadline
+    fn send_with_retries(&self, request: &ApiRequest) -> Result<String, ClientError> {
+        let deadline = self.deadline();
+        let policy = match self.retry {
+            Some(policy) => policy,
+            None => return self.send_until(request, deadline),
+        };
+        let safe = is_safe_to_retry(request);
+        let mut retry = 0;
+        loop {
+            let reason = match self.send_until(request, deadline) {
+                Err(e @ (ClientError::Io(_) | ClientError::TimedOut))
+                    if safe && retry + 1 < policy.max_attempts =>
+                {
+                    e.to_string()
+                }
+                Ok(response) if is_overloaded(&response) && retry + 1 < policy.max_attempts => {
+                    ApiError::Overloaded.to_string()
+                }
+                res => return res,
+            };
+            retry += 1;
+            let backoff = policy.backoff(retry);
+            if deadline.is_some_and(|deadline| Instant::now() + backoff >= deadline) {
+                return Err(ClientError::TimedOut);
+            }
+            debug!("retrying in {:?}: {}", backoff, reason);
+            thread::sleep(backoff);
+        }
+    }
+
+    /// opens a connection for sending several requests at once, see [`Pipeline`]
+    pub fn pipeline(&self) -> Result<Pipeline, ClientError> {
+        Pipeline::connect(
+            &self.target,
+            self.token.clone(),
+            self.registry.clone(),
+            self.timeouts,
+        )
+    }
+
+    /// sends `request` and unpacks the server's `ApiResult<T>`
+    pub fn request<T: DeserializeOwned>(&self, request: &ApiRequest) -> Result<T, ClientError> {
+        let cached = self.cache.as_ref().and_then(|cache| cache.get(request));
+        let response = match cached {
+            Some(response) => {
+                debug!("answered from cache: {}", request.kind());
+                response
+            }
+            None => {
+                let response = match envelope(request, &self.token, &self.registry) {
+                    Some(request) => self.send_with_retries(&request),
+                    None => self.send_with_retries(request),
+                };
+                if let Some(cache) = &self.cache {
+                    cache.update(request, response.as_deref().ok());
+                }
+                response?
+            }
+        };
+        let res: ApiResult<T> = serde_json::from_str(&response)?;
+        Ok(res?)
+    }
+
+    /// Like [`Client::request`], but safe to retry: the server executes `request` only once per
+    /// `key` and replays its response otherwise, see [`ApiRequest::Idempotent`].
+    pub fn request_idempotent<T: DeserializeOwned>(
+        &self,
+        key: impl Into<String>,
+        request: ApiRequest,
+    ) -> Result<T, ClientError> {
+        self.request(&ApiRequest::Idempotent {
+            key: key.into(),
+            request: Box::new(request),
+        })
+    }
+
+    /// Sends `request` only to be executed if its crate is still at `revision`, see
+    /// [`ApiRequest::IfRevision`]. Fails with `ApiError::Conflict` otherwise.
+    pub fn request_if_revision<T: DeserializeOwned>(
+        &self,
+        revision: u64,
+        request: ApiRequest,
+    ) -> Result<T, ClientError> {
+        self.request(&ApiRequest::IfRevision {
+            revision,
+            request: Box::new(request),
+        })
+    }
+
+    pub fn find_exact(&self, name: impl Into<String>) -> Result<Option<Crate>, ClientError> {
+        self.request(&ApiRequest::FindExact(name.into()))
+    }
+
+    pub fn find_containing(
+        &self,
+        name_part: impl Into<String>,
+        options: SearchOptions,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::FindAllContaining(name_part.into(), options))
+    }
+
+    /// crates matching a glob pattern, see [`crate::Repository::find_matching`]
+    pub fn find_matching(
+        &self,
+        pattern: impl Into<String>,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::FindMatching(pattern.into()))
+    }
+
+    /// crates whose name matches a regular expression, see [`ApiRequest::FindRegex`]
+    pub fn find_regex(
+        &self,
+        pattern: impl Into<String>,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::FindRegex(pattern.into()))
+    }
+
+    /// full-text search, see [`ApiRequest::Search`]
+    pub fn search(
+        &self,
+        query: impl Into<String>,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::Search(query.into()))
+    }
+
+    /// all crates of `namespace`, see [`crate::names`]
+    pub fn list_namespace(
+        &self,
+        namespace: impl Into<String>,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::ListNamespace(namespace.into()))
+    }
+
+    pub fn add_crate(&self, metadata: Metadata, version: SemVer) -> Result<(), ClientError> {
+        self.request(&ApiRequest::AddCrate(metadata, version))
+    }
+
+    pub fn add_release(&self, name: impl Into<String>, version: SemVer) -> Result<(), ClientError> {
+        self.request(&ApiRequest::AddRelease(name.into(), version))
+    }
+
+    /// publishes to a channel other than `Stable`, see [`crate::channels`]
+    pub fn add_release_to(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        channel: Channel,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::AddReleaseTo {
+            name: name.into(),
+            version,
+            channel,
+        })
+    }
+
+    /// the newest release in `channel` or a more stable one, see [`ApiRequest::LatestVersion`]
+    pub fn latest_version(
+        &self,
+        name: impl Into<String>,
+        channel: Channel,
+    ) -> Result<Option<SemVer>, ClientError> {
+        self.request(&ApiRequest::LatestVersion {
+            name: name.into(),
+            channel,
+        })
+    }
+
+    /// the newest non-breaking upgrade from `base`, see [`ApiRequest::LatestCompatible`]
+    pub fn latest_compatible(
+        &self,
+        name: impl Into<String>,
+        base: SemVer,
+    ) -> Result<Option<SemVer>, ClientError> {
+        self.request(&ApiRequest::LatestCompatible {
+            name: name.into(),
+            base,
+        })
+    }
+
+    /// the newest release matching `req`, see [`ApiRequest::Resolve`]
+    pub fn resolve(
+        &self,
+        name: impl Into<String>,
+        req: VersionReq,
+        include_prerelease: bool,
+        include_yanked: bool,
+    ) -> Result<Resolution, ClientError> {
+        self.request(&ApiRequest::Resolve {
+            name: name.into(),
+            req,
+            include_prerelease,
+            include_yanked,
+        })
+    }
+
+    /// the advisories against a crate, see [`ApiRequest::Advisories`]
+    pub fn advisories(
+        &self,
+        name: impl Into<String>,
+        version: Option<SemVer>,
+    ) -> Result<Vec<Advisory>, ClientError> {
+        self.request(&ApiRequest::Advisories {
+            name: name.into(),
+            version,
+        })
+    }
+
+    pub fn yank(&self, name: impl Into<String>, version: SemVer) -> Result<(), ClientError> {
+        self.request(&ApiRequest::Yank(name.into(), version))
+    }
+
+    /// replaces the release notes of `version`, see [`ApiRequest::SetReleaseNotes`]
+    pub fn set_release_notes(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        notes: Option<String>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetReleaseNotes {
+            name: name.into(),
+            version,
+            notes,
+        })
+    }
+
+    /// the release notes of versions newer than `from` up to `to`, see [`ApiRequest::Changelog`]
+    pub fn changelog(
+        &self,
+        name: impl Into<String>,
+        from: SemVer,
+        to: SemVer,
+    ) -> Result<String, ClientError> {
+        self.request(&ApiRequest::Changelog(name.into(), from, to))
+    }
+
+    /// replaces the README of `version`, see [`ApiRequest::SetReadme`]
+    pub fn set_readme(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        readme: Option<String>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetReadme {
+            name: name.into(),
+            version,
+            readme,
+        })
+    }
+
+    pub fn readme(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+    ) -> Result<Option<String>, ClientError> {
+        self.request(&ApiRequest::GetReadme(name.into(), version))
+    }
+
+    /// records what `version` builds with, see [`ApiRequest::SetPlatform`]
+    pub fn set_platform(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        platform: Platform,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetPlatform {
+            name: name.into(),
+            version,
+            platform,
+        })
+    }
+
+    /// records the features of `version`, see [`ApiRequest::SetFeatures`]
+    pub fn set_features(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        features: Features,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetFeatures {
+            name: name.into(),
+            version,
+            features,
+        })
+    }
+
+    /// records the dependencies of `version` and returns warnings about them, see
+    /// [`ApiRequest::SetDependencies`]
+    pub fn set_dependencies(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    ) -> Result<Vec<DependencyDiagnostic>, ClientError> {
+        self.request(&ApiRequest::SetDependencies {
+            name: name.into(),
+            version,
+            dependencies,
+        })
+    }
+
+    /// the features of `version`, empty if none are known
+    pub fn features(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+    ) -> Result<Features, ClientError> {
+        self.request(&ApiRequest::GetFeatures(name.into(), version))
+    }
+
+    /// adds a crate with all of its releases, or nothing, see [`ApiRequest::PublishAtomic`]
+    pub fn publish_atomic(
+        &self,
+        metadata: Metadata,
+        releases: Vec<SemVer>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::PublishAtomic { metadata, releases })
+    }
+
+    /// like [`Client::publish_atomic`], with versions as other tools write them if `lenient`,
+    /// see [`ApiRequest::PublishVersions`]
+    pub fn publish_versions(
+        &self,
+        metadata: Metadata,
+        versions: Vec<String>,
+        lenient: bool,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::PublishVersions {
+            metadata,
+            versions,
+            lenient,
+        })
+    }
+
+    /// waits for changes newer than `since`, see [`ApiRequest::Subscribe`].
+    /// Returns an empty list if the server timed out waiting.
+    pub fn subscribe(&self, since: u64) -> Result<Vec<Change>, ClientError> {
+        self.request(&ApiRequest::Subscribe { since })
+    }
+
+    /// Changes newer than `since` as they happen, waiting for more when there are none. Errors are
+    /// passed on, iterating further tries again from the last change seen.
+    pub fn watch(&self, since: u64) -> Watch<'_> {
+        Watch {
+            client: self,
+            since,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// deprecates a crate or one of its versions, requires a token, see [`ApiRequest::Deprecate`]
+    pub fn deprecate(
+        &self,
+        name: impl Into<String>,
+        version: Option<SemVer>,
+        deprecation: Option<Deprecation>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::Deprecate {
+            name: name.into(),
+            version,
+            deprecation,
+        })
+    }
+
+    /// replaces a crate's metadata, requires a token, see [`ApiRequest::UpdateMetadata`]
+    pub fn update_metadata(&self, metadata: Metadata) -> Result<(), ClientError> {
+        self.request(&ApiRequest::UpdateMetadata(metadata))
+    }
+
+    /// manages organizations, requires a token, see [`crate::orgs`]
+    pub fn org(&self, request: OrgRequest) -> Result<Organization, ClientError> {
+        self.request(&ApiRequest::Org(request))
+    }
+
+    /// the user's tokens, everyone's with the `admin` scope, see [`ApiRequest::Tokens`]
+    pub fn tokens(&self) -> Result<Vec<TokenInfo>, ClientError> {
+        self.request(&ApiRequest::Tokens)
+    }
+
+    /// revokes the token with this id, see [`ApiRequest::RevokeToken`]
+    pub fn revoke_token(&self, id: impl Into<String>) -> Result<(), ClientError> {
+        self.request(&ApiRequest::RevokeToken(id.into()))
+    }
+
+    pub fn audit_log(
+        &self,
+        name: impl Into<String>,
+        since: u64,
+    ) -> Result<Vec<AuditEntry>, ClientError> {
+        self.request(&ApiRequest::AuditLog(name.into(), since))
+    }
+
+    pub fn feed_status(&self) -> Result<FeedStatus, ClientError> {
+        self.request(&ApiRequest::FeedStatus)
+    }
+
+    /// the server's version, see [`ApiRequest::Ping`]. Servers predating it answer with
+    /// `ApiError::Internal`.
+    pub fn ping(&self) -> Result<ServerInfo, ClientError> {
+        self.request(&ApiRequest::Ping)
+    }
+
+    pub fn snapshot(&self) -> Result<Snapshot, ClientError> {
+        self.request(&ApiRequest::Snapshot)
+    }
+
+    /// a GraphQL response of `data` and `errors`, see [`crate::graphql`]
+    #[cfg(feature = "graphql")]
+    pub fn graphql(
+        &self,
+        query: impl Into<String>,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, ClientError> {
+        self.request(&ApiRequest::GraphQL {
+            query: query.into(),
+            variables,
+        })
+    }
+
+    /// a page of [`Client::find_containing`], see [`ApiRequest::FindAllContainingPage`]
+    pub fn find_containing_page(
+        &self,
+        query: impl Into<String>,
+        options: SearchOptions,
+        cursor: Option<Cursor>,
+    ) -> Result<Page<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::FindAllContainingPage {
+            query: query.into(),
+            options,
+            cursor,
+        })
+    }
+
+    /// what `request` would do, without doing it, see [`ApiRequest::DryRun`]
+    pub fn dry_run(&self, request: ApiRequest) -> Result<DryRunOutcome, ClientError> {
+        self.request(&ApiRequest::DryRun {
+            request: Box::new(request),
+        })
+    }
+
+    /// what's wrong or suspicious about publishing `version`, see [`ApiRequest::CheckPublish`]
+    pub fn check_publish(
+        &self,
+        metadata: Metadata,
+        version: SemVer,
+    ) -> Result<Vec<PublishWarning>, ClientError> {
+        self.request(&ApiRequest::CheckPublish(metadata, version))
+    }
+
+    /// runs `requests` in one round trip, see [`ApiRequest::Batch`]
+    pub fn batch(
+        &self,
+        requests: Vec<ApiRequest>,
+        transactional: bool,
+    ) -> Result<Vec<ApiResult<serde_json::Value>>, ClientError> {
+        self.request(&ApiRequest::Batch {
+            requests,
+            transactional,
+        })
+    }
+
+    pub fn admin(
+        &self,
+        token: impl Into<String>,
+        request: AdminRequest,
+    ) -> Result<AdminResponse, ClientError> {
+        self.request(&ApiRequest::Admin {
+            token: token.into(),
+            request,
+        })
+    }
+}
+
+/// responses still awaited, by request id. `None` once the connection is gone.
+type Pending = Arc<Mutex<Option<HashMap<u64, mpsc::Sender<String>>>>>;
+
+/// A single connection carrying any number of requests at the same time, e.g. from several
+/// threads. Responses are matched to their requests by id, see [`TaggedRequest`].
+#[derive(Debug)]
+pub struct Pipeline {
+    writer: Mutex<TcpStream>,
+    pending: Pending,
+    next_id: AtomicU64,
+    token: Option<String>,
+    registry: Option<String>,
+    /// only `write` and `deadline` apply, responses may take turns
+    timeouts: Timeouts,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Pipeline {
+    fn connect(
+        target: &str,
+        token: Option<String>,
+        registry: Option<String>,
+        timeouts: Timeouts,
+    ) -> Result<Self, ClientError> {
+        let deadline = timeouts.deadline.map(|deadline| Instant::now() + deadline);
+        let stream = connect(target, &timeouts, deadline)
+            .map_err(io_error)?
+            .stream;
+        stream.set_write_timeout(timeouts.write)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let pending: Pending = Arc::new(Mutex::new(Some(HashMap::new())));
+        let reader = {
+            let pending = pending.clone();
+            thread::spawn(move || Self::receive(reader, &pending))
+        };
+        Ok(Self {
+            writer: Mutex::new(stream),
+            pending,
+            next_id: AtomicU64::new(1),
+            token,
+            registry,
+            timeouts,
+            reader: Some(reader),
+        })
+    }
+
+    /// hands every response to whoever is waiting for it, until the connection is closed
+    fn receive(mut reader: BufReader<TcpStream>, pending: &Pending) {
+        let mut line = String::new();
+        while matches!(reader.read_line(&mut line), Ok(n) if n > 0) {
+            match serde_json::from_str::<TaggedResponse>(&line) {
+                Ok(tagged) => {
+                    let waiting = pending
+                        .lock()
+                        .unwrap()
+                        .as_mut()
+                        .and_then(|pending| pending.remove(&tagged.id));
+                    if let Some(waiting) = waiting {
+                        let _ = waiting.send(tagged.response.get().to_string());
+                    }
+                }
+                Err(e) => debug!("ignoring unexpected response {:?}: {}", line, e),
+            }
+            line.clear();
+        }
+        // fails everyone still waiting
+        pending.lock().unwrap().take();
+    }
+
+    /// sends `request` and returns the raw response, waiting only for this one
+    pub fn send(&self, request: &ApiRequest) -> Result<String, ClientError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::channel();
+        match self.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(id, tx),
+            None => return Err(closed().into()),
+        };
+        let line = serde_json::to_string(&TaggedRequest {
+            id,
+            request: request.clone(),
+        })?;
+        debug!("→ {}", line);
+        writeln!(self.writer.lock().unwrap(), "{}", line).map_err(io_error)?;
+        let response = match self.timeouts.deadline {
+            Some(deadline) => rx.recv_timeout(deadline).map_err(|e| match e {
+                mpsc::RecvTimeoutError::Timeout => ClientError::TimedOut,
+                mpsc::RecvTimeoutError::Disconnected => closed().into(),
+            }),
+            None => rx.recv().map_err(|_| closed().into()),
+        };
+        if response.is_err() {
+            if let Some(pending) = self.pending.lock().unwrap().as_mut() {
+                pending.remove(&id);
+            }
+        }
+        response
+    }
+
+    /// like [`Client::request`]
+    pub fn request<T: DeserializeOwned>(&self, request: &ApiRequest) -> Result<T, ClientError> {
+        let response = match envelope(request, &self.token, &self.registry) {
+            Some(request) => self.send(&request)?,
+            None => self.send(request)?,
+        };
+        let res: ApiResult<T> = serde_json::from_str(&response)?;
+        Ok(res?)
+    }
+}
+
+/// `request` wrapped as configured, `None` if it is sent as it is
+fn envelope(
+    request: &ApiRequest,
+    token: &Option<String>,
+    registry: &Option<String>,
+) -> Option<ApiRequest> {
+    if token.is_none() && registry.is_none() {
+        return None;
+    }
+    let mut request = request.clone();
+    if let Some(token) = token {
+        request = ApiRequest::Authenticated {
+            token: token.clone(),
+            request: Box::new(request),
+        };
+    }
+    if let Some(name) = registry {
+        request = ApiRequest::Registry {
+            name: name.clone(),
+            request: Box::new(request),
+        };
+    }
+    Some(request)
+}
+
+fn closed() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::ConnectionAborted, "connection closed")
+}
+
+impl Drop for Pipeline {
+    fn drop(&mut self) {
+        if let Ok(writer) = self.writer.lock() {
+            let _ = writer.shutdown(Shutdown::Both);
+        }
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    /// a server that hangs up on the first `drops` connections and answers the rest
+    fn flaky_server(drops: usize) -> (String, JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut connections = 0;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                connections += 1;
+                let mut line = String::new();
+                BufReader::new(&stream).read_line(&mut line).unwrap();
+                if line.contains("Stop") {
+                    return connections;
+                }
+                if connections > drops {
+                    stream.write_all(b"{\"Ok\":null}\n").unwrap();
+                }
+            }
+            connections
+        });
+        (target, server)
+    }
+
+    fn stop(target: &str) {
+        let _ = Client::new(target).send(&ApiRequest::FindExact("Stop".to_string()));
+    }
+
+    #[test]
+    fn retries() {
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(10),
+        };
+        let (target, server) = flaky_server(2);
+        let client = Client::new(&target).with_retry(policy);
+        assert_eq!(None, client.find_exact("hello_bin").unwrap());
+        stop(&target);
+        assert_eq!(4, server.join().unwrap());
+
+        // mutations without a key are sent once
+        let (target, server) = flaky_server(2);
+        let client = Client::new(&target).with_retry(policy);
+        let metadata = Metadata::new("hello_bin", "someone", crate::CrateKind::Binary);
+        assert!(matches!(
+            client.add_crate(metadata.clone(), SemVer::new(1, 0, 0)),
+            Err(ClientError::Io(_))
+        ));
+        client
+            .request_idempotent::<()>("key", ApiRequest::AddCrate(metadata, SemVer::new(1, 0, 0)))
+            .unwrap();
+        stop(&target);
+        assert_eq!(4, server.join().unwrap());
+    }
+
+    /// a pipelining server answering `requests_per_connection` requests on each connection, returns
+    /// the number of connections once `stop` ends it
+    fn pipelining_server(requests_per_connection: usize) -> (String, JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            let mut connections = 0;
+            for stream in listener.incoming() {
+                let mut reader = BufReader::new(stream.unwrap());
+                connections += 1;
+                for _ in 0..requests_per_connection {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 {
+                        break;
+                    }
+                    if line.contains("Stop") {
+                        return connections;
+                    }
+                    let TaggedRequest { id, .. } = serde_json::from_str(&line).unwrap();
+                    writeln!(
+                        reader.get_mut(),
+                        r#"{{"id":{},"response":{{"Ok":null}}}}"#,
+                        id
+                    )
+                    .unwrap();
+                }
+            }
+            connections
+        });
+        (target, server)
+    }
+
+    #[test]
+    fn pool() {
+        let (target, server) = pipelining_server(usize::MAX);
+        let client = Client::new(&target).with_pool(PoolConfig::default());
+        for _ in 0..10 {
+            assert_eq!(None, client.clone().find_exact("hello_bin").unwrap());
+        }
+        // the server answers one connection at a time
+        drop(client);
+        stop(&target);
+        // one for all requests, one for stopping
+        assert_eq!(2, server.join().unwrap());
+
+        let (target, server) = pipelining_server(usize::MAX);
+        let client = Client::new(&target).with_pool(PoolConfig {
+            max_idle: 4,
+            idle_timeout: Duration::ZERO,
+        });
+        for _ in 0..3 {
+            client.find_exact("hello_bin").unwrap();
+        }
+        drop(client);
+        stop(&target);
+        assert_eq!(4, server.join().unwrap());
+
+        // stale connections are replaced for reads
+        let (target, server) = pipelining_server(1);
+        let client = Client::new(&target).with_pool(PoolConfig::default());
+        for _ in 0..3 {
+            client.find_exact("hello_bin").unwrap();
+        }
+        stop(&target);
+        assert_eq!(4, server.join().unwrap());
+    }
+
+    #[test]
+    fn timeouts() {
+        // accepts connections, but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let timeouts = Timeouts {
+            read: Some(Duration::from_millis(20)),
+            ..Timeouts::default()
+        };
+        let client = Client::new(&target).with_timeouts(timeouts);
+        assert!(matches!(
+            client.find_exact("hello_bin"),
+            Err(ClientError::TimedOut)
+        ));
+        let pipeline = Client::new(&target)
+            .with_timeouts(Timeouts {
+                deadline: Some(Duration::from_millis(20)),
+                ..Timeouts::default()
+            })
+            .pipeline()
+            .unwrap();
+        assert!(matches!(
+            pipeline.send(&ApiRequest::FeedStatus),
+            Err(ClientError::TimedOut)
+        ));
+
+        // retries stop at the deadline
+        let started = Instant::now();
+        let client = Client::new(&target)
+            .with_timeouts(Timeouts {
+                deadline: Some(Duration::from_millis(200)),
+                ..timeouts
+            })
+            .with_retry(RetryPolicy {
+                max_attempts: u32::MAX,
+                initial_backoff: Duration::from_millis(1),
+                max_backoff: Duration::from_millis(10),
+            })
+            .with_pool(PoolConfig::default());
+        assert!(matches!(
+            client.find_exact("hello_bin"),
+            Err(ClientError::TimedOut)
+        ));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        drop(listener);
+    }
+
+    #[test]
+    fn cache() {
+        let (target, server) = flaky_server(0);
+        let client = Client::new(&target).with_cache(CacheConfig::default());
+        for _ in 0..3 {
+            assert_eq!(None, client.clone().find_exact("hello_bin").unwrap());
+        }
+        // invalidates `hello_bin`, but not `hello_moon`
+        client.find_exact("hello_moon").unwrap();
+        client
+            .add_release("hello_bin", SemVer::new(1, 0, 0))
+            .unwrap();
+        client.find_exact("hello_bin").unwrap();
+        client.find_exact("hello_moon").unwrap();
+        stop(&target);
+        assert_eq!(5, server.join().unwrap());
+
+        let (target, server) = flaky_server(0);
+        let client = Client::new(&target).with_cache(CacheConfig {
+            ttl: Duration::ZERO,
+            ..CacheConfig::default()
+        });
+        for _ in 0..3 {
+            client.find_exact("hello_bin").unwrap();
+        }
+        stop(&target);
+        assert_eq!(4, server.join().unwrap());
+    }
+
+    #[test]
+    fn typed_errors() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let target = listener.local_addr().unwrap().to_string();
+        let server = thread::spawn(move || {
+            for response in ["not json\n", "{\"Err\":{\"Repo\":\"NotFound\"}}\n"] {
+                let (mut stream, _) = listener.accept().unwrap();
+                BufReader::new(&stream)
+                    .read_line(&mut String::new())
+                    .unwrap();
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+        let client = Client::new(&target);
+        assert!(matches!(
+            client.find_exact("hello_bin"),
+            Err(ClientError::Protocol(_))
+        ));
+        assert!(matches!(
+            client.add_release("hello_bin", SemVer::new(1, 0, 0)),
+            Err(ClientError::Api(ApiError::Repo(crate::RepoError::NotFound)))
+        ));
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(300),
+        };
+        for (retry, max) in [(1, 100), (2, 200), (3, 300), (9, 300)] {
+            let backoff = policy.backoff(retry);
+            let max = Duration::from_millis(max);
+            assert!(max / 2 <= backoff && backoff <= max, "{:?}", backoff);
+        }
+    }
+}
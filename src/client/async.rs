@@ -0,0 +1,451 @@
+//! A [`Client`] for async applications on tokio, talking the same protocol as the blocking
+//! [`super::Client`] with the same requests, responses and [`ClientError`]s. Every request runs
+//! on the caller's task, no threads are spawned.
+
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::{closed, envelope, ClientError, Timeouts};
+use crate::admin::{AdminRequest, AdminResponse};
+use crate::advisories::Advisory;
+use crate::api::{ApiRequest, ApiResult, CrateSummary, DryRunOutcome, ServerInfo};
+use crate::auth::TokenInfo;
+use crate::channels::Channel;
+use crate::dependencies::{Dependency, DependencyDiagnostic};
+use crate::events::Change;
+use crate::features::Features;
+use crate::platform::Platform;
+use crate::preflight::PublishWarning;
+use crate::replication::FeedStatus;
+use crate::resolve::{Resolution, VersionReq};
+use crate::search::SearchOptions;
+use crate::{Crate, Metadata, SemVer};
+
+#[derive(Debug, Clone)]
+pub struct Client {
+    target: String,
+    token: Option<String>,
+    registry: Option<String>,
+    timeouts: Timeouts,
+}
+
+impl Client {
+    /// `target` is anything `TcpStream::connect` accepts, see [`crate::net::parse_target`]
+    pub fn new(target: impl Into<String>) -> Self {
+        Self {
+            target: target.into(),
+            token: None,
+            registry: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+
+    /// see [`super::Client::with_token`]
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// see [`super::Client::with_registry`]
+    pub fn with_registry(mut self, name: impl Into<String>) -> Self {
+        self.registry = Some(name.into());
+        self
+    }
+
+    /// Only `connect` and `deadline` apply, futures can be given up on by dropping them anyway.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    pub fn target(&self) -> &str {
+        &self.target
+    }
+
+    /// sends `request` and returns the raw response line
+    pub async fn send(&self, request: &ApiRequest) -> Result<String, ClientError> {
+        within(self.timeouts.deadline, self.exchange(request)).await?
+    }
+
+    async fn exchange(&self, request: &ApiRequest) -> Result<String, ClientError> {
+        let mut line = serde_json::to_string(request)?;
+        log::debug!("→ {}", line);
+        line.push('\n');
+        let mut connection =
+            within(self.timeouts.connect, TcpStream::connect(&self.target)).await??;
+        connection.write_all(line.as_bytes()).await?;
+        connection.shutdown().await?;
+        let mut buffer = String::new();
+        connection.read_to_string(&mut buffer).await?;
+        if buffer.is_empty() {
+            return Err(closed().into());
+        }
+        Ok(buffer)
+    }
+
+    /// sends `request` and unpacks the server's `ApiResult<T>`
+    pub async fn request<T: DeserializeOwned>(
+        &self,
+        request: &ApiRequest,
+    ) -> Result<T, ClientError> {
+        let response = match envelope(request, &self.token, &self.registry) {
+            Some(request) => self.send(&request).await?,
+            None => self.send(request).await?,
+        };
+        let res: ApiResult<T> = serde_json::from_str(&response)?;
+        Ok(res?)
+    }
+
+    /// see [`super::Client::request_idempotent`]
+    pub async fn request_idempotent<T: DeserializeOwned>(
+        &self,
+        key: impl Into<String>,
+        request: ApiRequest,
+    ) -> Result<T, ClientError> {
+        self.request(&ApiRequest::Idempotent {
+            key: key.into(),
+            request: Box::new(request),
+        })
+        .await
+    }
+
+    pub async fn find_exact(&self, name: impl Into<String>) -> Result<Option<Crate>, ClientError> {
+        self.request(&ApiRequest::FindExact(name.into())).await
+    }
+
+    pub async fn find_containing(
+        &self,
+        name_part: impl Into<String>,
+        options: SearchOptions,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::FindAllContaining(name_part.into(), options))
+            .await
+    }
+
+    pub async fn search(
+        &self,
+        query: impl Into<String>,
+    ) -> Result<Vec<CrateSummary<'static>>, ClientError> {
+        self.request(&ApiRequest::Search(query.into())).await
+    }
+
+    pub async fn add_crate(&self, metadata: Metadata, version: SemVer) -> Result<(), ClientError> {
+        self.request(&ApiRequest::AddCrate(metadata, version)).await
+    }
+
+    pub async fn add_release(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::AddRelease(name.into(), version))
+            .await
+    }
+
+    pub async fn yank(&self, name: impl Into<String>, version: SemVer) -> Result<(), ClientError> {
+        self.request(&ApiRequest::Yank(name.into(), version)).await
+    }
+
+    pub async fn latest_version(
+        &self,
+        name: impl Into<String>,
+        channel: Channel,
+    ) -> Result<Option<SemVer>, ClientError> {
+        self.request(&ApiRequest::LatestVersion {
+            name: name.into(),
+            channel,
+        })
+        .await
+    }
+
+    pub async fn latest_compatible(
+        &self,
+        name: impl Into<String>,
+        base: SemVer,
+    ) -> Result<Option<SemVer>, ClientError> {
+        self.request(&ApiRequest::LatestCompatible {
+            name: name.into(),
+            base,
+        })
+        .await
+    }
+
+    pub async fn resolve(
+        &self,
+        name: impl Into<String>,
+        req: VersionReq,
+        include_prerelease: bool,
+        include_yanked: bool,
+    ) -> Result<Resolution, ClientError> {
+        self.request(&ApiRequest::Resolve {
+            name: name.into(),
+            req,
+            include_prerelease,
+            include_yanked,
+        })
+        .await
+    }
+
+    pub async fn advisories(
+        &self,
+        name: impl Into<String>,
+        version: Option<SemVer>,
+    ) -> Result<Vec<Advisory>, ClientError> {
+        self.request(&ApiRequest::Advisories {
+            name: name.into(),
+            version,
+        })
+        .await
+    }
+
+    pub async fn set_release_notes(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        notes: Option<String>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetReleaseNotes {
+            name: name.into(),
+            version,
+            notes,
+        })
+        .await
+    }
+
+    pub async fn changelog(
+        &self,
+        name: impl Into<String>,
+        from: SemVer,
+        to: SemVer,
+    ) -> Result<String, ClientError> {
+        self.request(&ApiRequest::Changelog(name.into(), from, to))
+            .await
+    }
+
+    pub async fn set_readme(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        readme: Option<String>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetReadme {
+            name: name.into(),
+            version,
+            readme,
+        })
+        .await
+    }
+
+    pub async fn readme(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+    ) -> Result<Option<String>, ClientError> {
+        self.request(&ApiRequest::GetReadme(name.into(), version))
+            .await
+    }
+
+    pub async fn set_platform(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        platform: Platform,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetPlatform {
+            name: name.into(),
+            version,
+            platform,
+        })
+        .await
+    }
+
+    pub async fn set_features(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        features: Features,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::SetFeatures {
+            name: name.into(),
+            version,
+            features,
+        })
+        .await
+    }
+
+    pub async fn set_dependencies(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    ) -> Result<Vec<DependencyDiagnostic>, ClientError> {
+        self.request(&ApiRequest::SetDependencies {
+            name: name.into(),
+            version,
+            dependencies,
+        })
+        .await
+    }
+
+    pub async fn features(
+        &self,
+        name: impl Into<String>,
+        version: SemVer,
+    ) -> Result<Features, ClientError> {
+        self.request(&ApiRequest::GetFeatures(name.into(), version))
+            .await
+    }
+
+    pub async fn publish_atomic(
+        &self,
+        metadata: Metadata,
+        releases: Vec<SemVer>,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::PublishAtomic { metadata, releases })
+            .await
+    }
+
+    pub async fn publish_versions(
+        &self,
+        metadata: Metadata,
+        versions: Vec<String>,
+        lenient: bool,
+    ) -> Result<(), ClientError> {
+        self.request(&ApiRequest::PublishVersions {
+            metadata,
+            versions,
+            lenient,
+        })
+        .await
+    }
+
+    /// see [`super::Client::subscribe`]
+    pub async fn subscribe(&self, since: u64) -> Result<Vec<Change>, ClientError> {
+        self.request(&ApiRequest::Subscribe { since }).await
+    }
+
+    pub async fn feed_status(&self) -> Result<FeedStatus, ClientError> {
+        self.request(&ApiRequest::FeedStatus).await
+    }
+
+    /// see [`super::Client::ping`]
+    pub async fn tokens(&self) -> Result<Vec<TokenInfo>, ClientError> {
+        self.request(&ApiRequest::Tokens).await
+    }
+
+    pub async fn revoke_token(&self, id: impl Into<String>) -> Result<(), ClientError> {
+        self.request(&ApiRequest::RevokeToken(id.into())).await
+    }
+
+    pub async fn ping(&self) -> Result<ServerInfo, ClientError> {
+        self.request(&ApiRequest::Ping).await
+    }
+
+    /// see [`super::Client::dry_run`]
+    pub async fn dry_run(&self, request: ApiRequest) -> Result<DryRunOutcome, ClientError> {
+        self.request(&ApiRequest::DryRun {
+            request: Box::new(request),
+        })
+        .await
+    }
+
+    /// see [`super::Client::check_publish`]
+    pub async fn check_publish(
+        &self,
+        metadata: Metadata,
+        version: SemVer,
+    ) -> Result<Vec<PublishWarning>, ClientError> {
+        self.request(&ApiRequest::CheckPublish(metadata, version))
+            .await
+    }
+
+    pub async fn batch(
+        &self,
+        requests: Vec<ApiRequest>,
+        transactional: bool,
+    ) -> Result<Vec<ApiResult<serde_json::Value>>, ClientError> {
+        self.request(&ApiRequest::Batch {
+            requests,
+            transactional,
+        })
+        .await
+    }
+
+    pub async fn admin(
+        &self,
+        token: impl Into<String>,
+        request: AdminRequest,
+    ) -> Result<AdminResponse, ClientError> {
+        self.request(&ApiRequest::Admin {
+            token: token.into(),
+            request,
+        })
+        .await
+    }
+}
+
+/// `future`, failing with `ClientError::TimedOut` if it takes longer than `timeout`
+async fn within<T>(
+    timeout: Option<Duration>,
+    future: impl std::future::Future<Output = T>,
+) -> Result<T, ClientError> {
+    match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, future)
+            .await
+            .map_err(|_| ClientError::TimedOut),
+        None => Ok(future.await),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::server::{Server, ServerConfig};
+    use crate::CrateKind;
+
+    #[test]
+    fn requests() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let config = ServerConfig::new(store.path()).with_listen(vec!["127.0.0.1:0".parse()?]);
+        let server = Server::bind(config)?;
+        let client = Client::new(server.local_addrs()[0].to_string());
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.serve());
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            // one task each, all at the same time
+            let added: Vec<_> = (1..=5)
+                .map(|major| {
+                    let client = client.clone();
+                    tokio::spawn(async move {
+                        let name = format!("async-{}", major);
+                        let metadata = Metadata::new(name, "someone", CrateKind::Library);
+                        client.add_crate(metadata, SemVer::new(major, 0, 0)).await
+                    })
+                })
+                .collect();
+            for task in added {
+                task.await.unwrap()?;
+            }
+            let crt = client.find_exact("async-3").await?.unwrap();
+            assert_eq!(Some(SemVer::new(3, 0, 0)), crt.latest());
+            assert!(matches!(
+                client.add_release("missing", SemVer::new(1, 0, 0)).await,
+                Err(ClientError::Api(_))
+            ));
+            Ok::<_, ClientError>(())
+        })?;
+
+        shutdown.shutdown();
+        running.join().unwrap()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! Compression of the on-disk store. Stores are written with the repository's [`Compression`],
+//! zstd unless configured otherwise. Loading tells the format by the magic bytes at the start of
+//! the file, so uncompressed stores written by older versions keep loading.
+
+use std::fmt::Display;
+use std::io::{self, BufWriter, Read, Write};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Compression {
+    /// plain JSON
+    None,
+    /// levels 1 to 22, higher is smaller but slower
+    Zstd { level: i32 },
+    /// levels 0 to 9
+    Gzip { level: u32 },
+}
+
+impl Compression {
+    pub const DEFAULT_ZSTD_LEVEL: i32 = 3;
+    pub const DEFAULT_GZIP_LEVEL: u32 = 6;
+
+    /// the format of a store with these `contents`, with the default level
+    pub fn detect(contents: &[u8]) -> Self {
+        if contents.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd {
+                level: Self::DEFAULT_ZSTD_LEVEL,
+            }
+        } else if contents.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip {
+                level: Self::DEFAULT_GZIP_LEVEL,
+            }
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Runs `write` with a buffered writer compressing into `writer`, returns `writer` once the
+    /// compressed data is complete.
+    pub(crate) fn encode<W: Write>(
+        self,
+        writer: W,
+        write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+    ) -> io::Result<W> {
+        match self {
+            Compression::None => buffered(writer, write),
+            Compression::Zstd { level } => {
+                buffered(zstd::Encoder::new(writer, level)?, write)?.finish()
+            }
+            Compression::Gzip { level } => {
+                let encoder =
+                    flate2::write::GzEncoder::new(writer, flate2::Compression::new(level));
+                buffered(encoder, write)?.finish()
+            }
+        }
+    }
+}
+
+/// encoders are slow with the many tiny writes of serde_json
+fn buffered<W: Write>(
+    writer: W,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<W> {
+    let mut buffered = BufWriter::new(writer);
+    write(&mut buffered)?;
+    buffered
+        .into_inner()
+        .map_err(io::IntoInnerError::into_error)
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd {
+            level: Self::DEFAULT_ZSTD_LEVEL,
+        }
+    }
+}
+
+/// `contents` of a store in any format, uncompressed
+pub fn decompress(contents: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut plain = vec![];
+    match Compression::detect(&contents) {
+        Compression::None => return Ok(contents),
+        Compression::Zstd { .. } => zstd::Decoder::new(&contents[..])?.read_to_end(&mut plain)?,
+        Compression::Gzip { .. } => {
+            flate2::read::GzDecoder::new(&contents[..]).read_to_end(&mut plain)?
+        }
+    };
+    Ok(plain)
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid compression '{0}', expected none, zstd[:1-22] or gzip[:0-9]")]
+pub struct ParseCompressionError(String);
+
+/// `none`, `zstd`, `gzip`, or one of the latter with a level like `zstd:19`
+impl FromStr for Compression {
+    type Err = ParseCompressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseCompressionError(s.to_string());
+        let (format, level) = match s.split_once(':') {
+            Some((format, level)) => (format, Some(level)),
+            None => (s, None),
+        };
+        match (format, level) {
+            ("none", None) => Ok(Compression::None),
+            ("zstd", level) => {
+                let level = level.map_or(Ok(Self::DEFAULT_ZSTD_LEVEL), str::parse);
+                match level {
+                    Ok(level @ 1..=22) => Ok(Compression::Zstd { level }),
+                    _ => Err(err()),
+                }
+            }
+            ("gzip", level) => {
+                let level = level.map_or(Ok(Self::DEFAULT_GZIP_LEVEL), str::parse);
+                match level {
+                    Ok(level @ 0..=9) => Ok(Compression::Gzip { level }),
+                    _ => Err(err()),
+                }
+            }
+            _ => Err(err()),
+        }
+    }
+}
+
+impl Display for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Compression::None => write!(f, "none"),
+            Compression::Zstd { level } => write!(f, "zstd:{}", level),
+            Compression::Gzip { level } => write!(f, "gzip:{}", level),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Ok(Compression::None), "none".parse());
+        assert_eq!(Ok(Compression::default()), "zstd".parse());
+        assert_eq!(Ok(Compression::Gzip { level: 9 }), "gzip:9".parse());
+        for invalid in ["zstd:0", "gzip:10", "none:1", "lz4", ""] {
+            assert!(invalid.parse::<Compression>().is_err(), "{}", invalid);
+        }
+        let zstd = Compression::Zstd { level: 19 };
+        assert_eq!(Ok(zstd), zstd.to_string().parse());
+    }
+
+    #[test]
+    fn every_format_loads() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        for compression in ["none", "zstd:19", "gzip:1", "zstd"] {
+            let compression: Compression = compression.parse().unwrap();
+            let mut repo = Repository::open(&store, None).unwrap();
+            repo.set_compression(compression);
+            let name = format!("compressed-{}", compression);
+            repo.add_crate(
+                Metadata::new(&name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+            drop(repo);
+
+            let contents = std::fs::read(store.path()).unwrap();
+            assert_eq!(
+                std::mem::discriminant(&compression),
+                std::mem::discriminant(&Compression::detect(&contents))
+            );
+            // the previous formats were read back as well
+            let repo = Repository::open(&store, None).unwrap();
+            assert!(repo.find_exact(&name).is_some());
+            assert!(repo.find_exact("compressed-none").is_some());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_rewrites_unchanged_store() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let mut repo = Repository::open(&store, None).unwrap();
+        repo.set_compression(Compression::None);
+        repo.add_crate(
+            Metadata::new("plain", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        drop(repo);
+
+        let mut repo = Repository::open(&store, None).unwrap();
+        assert!(!repo.is_dirty());
+        repo.migrate()?;
+        let contents = std::fs::read(store.path())?;
+        assert_eq!(Compression::default(), Compression::detect(&contents));
+        assert!(Repository::open(&store, None)
+            .unwrap()
+            .find_exact("plain")
+            .is_some());
+        Ok(())
+    }
+}
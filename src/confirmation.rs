@@ -0,0 +1,137 @@
+//! Two-step confirmation of destructive admin requests, deleting a crate or transferring its
+//! ownership.
+//!
+//! Servers answer such a request with [`AdminResponse::ConfirmationRequired`] instead of
+//! executing it, and only execute it once the token in there comes back in
+//! [`AdminRequest::Confirm`] within the confirmation window, so a script can't delete a crate by
+//! accident. Each token confirms once.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::admin::{AdminRequest, AdminResponse};
+use crate::api::ApiRequest;
+
+#[derive(Debug)]
+struct Pending {
+    at: Instant,
+    request: AdminRequest,
+}
+
+/// Destructive admin requests held back until they are confirmed, by confirmation token
+#[derive(Debug)]
+pub struct Confirmations {
+    window: Duration,
+    pending: HashMap<String, Pending>,
+}
+
+/// whether `request` has to be confirmed before it is executed
+pub fn is_destructive(request: &AdminRequest) -> bool {
+    matches!(
+        request,
+        AdminRequest::DeleteCrate(_) | AdminRequest::TransferOwnership { .. }
+    )
+}
+
+/// Whether `request` is or wraps a destructive admin request. Dry runs change nothing, so they
+/// don't count.
+pub fn contains_destructive(request: &ApiRequest) -> bool {
+    match request {
+        ApiRequest::Admin { request, .. } => is_destructive(request),
+        ApiRequest::Batch { requests, .. } => requests.iter().any(contains_destructive),
+        ApiRequest::Idempotent { request, .. }
+        | ApiRequest::IfRevision { request, .. }
+        | ApiRequest::Authenticated { request, .. }
+        | ApiRequest::Registry { request, .. }
+        | ApiRequest::Traced { request, .. } => contains_destructive(request),
+        _ => false,
+    }
+}
+
+impl Confirmations {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// holds back `request` until it is confirmed, answering with the token to confirm it with
+    pub fn hold(&mut self, request: AdminRequest) -> AdminResponse {
+        let window = self.window;
+        self.pending
+            .retain(|_, pending| pending.at.elapsed() < window);
+        let mut bytes = [0u8; 16];
+        getrandom::getrandom(&mut bytes).expect("no randomness available");
+        let token = hex::encode(bytes);
+        self.pending.insert(
+            token.clone(),
+            Pending {
+                at: Instant::now(),
+                request,
+            },
+        );
+        AdminResponse::ConfirmationRequired {
+            token,
+            within_secs: window.as_secs(),
+        }
+    }
+
+    /// the request `token` confirms, if it was held back within the window
+    pub fn confirm(&mut self, token: &str) -> Option<AdminRequest> {
+        self.pending
+            .remove(token)
+            .filter(|pending| pending.at.elapsed() < self.window)
+            .map(|pending| pending.request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn confirms_once_within_window() {
+        let mut confirmations = Confirmations::new(Duration::from_secs(60));
+        let token = match confirmations.hold(AdminRequest::DeleteCrate("crab".into())) {
+            AdminResponse::ConfirmationRequired { token, within_secs } => {
+                assert_eq!(60, within_secs);
+                token
+            }
+            other => panic!("unexpected response {:?}", other),
+        };
+        assert!(confirmations.confirm("guess").is_none());
+        assert!(matches!(
+            confirmations.confirm(&token),
+            Some(AdminRequest::DeleteCrate(name)) if name == "crab"
+        ));
+        assert!(confirmations.confirm(&token).is_none());
+
+        let mut expired = Confirmations::new(Duration::ZERO);
+        let AdminResponse::ConfirmationRequired { token, .. } =
+            expired.hold(AdminRequest::DeleteCrate("crab".into()))
+        else {
+            unreachable!()
+        };
+        assert!(expired.confirm(&token).is_none());
+    }
+
+    #[test]
+    fn destructive() {
+        let delete = ApiRequest::Admin {
+            token: "s3cret".into(),
+            request: AdminRequest::DeleteCrate("crab".into()),
+        };
+        assert!(!contains_destructive(&ApiRequest::Admin {
+            token: "s3cret".into(),
+            request: AdminRequest::Stats,
+        }));
+        assert!(contains_destructive(&ApiRequest::Batch {
+            requests: vec![ApiRequest::Ping, delete.clone()],
+            transactional: true,
+        }));
+        assert!(!contains_destructive(&ApiRequest::DryRun {
+            request: Box::new(delete),
+        }));
+    }
+}
@@ -0,0 +1,202 @@
+//! Moving a repository to another storage backend, e.g. from a single JSON file to SQLite once it
+//! grew too big to rewrite after every publish, see `repo convert`. The converted store is read
+//! back and compared with the original before it is handed out, so nothing is lost on the way.
+
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::fsck::Problem;
+use crate::shards::Sharding;
+use crate::{Repository, StoreError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Backend {
+    /// a single file, see [`crate::compression`]
+    Json,
+    /// a directory of files, see [`crate::shards`]
+    Sharded(Sharding),
+    /// an SQLite database, needs the `sqlite` feature
+    Sqlite,
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid backend '{0}', expected json, sharded[:prefix-len] or sqlite")]
+pub struct ParseBackendError(String);
+
+/// `json`, `sqlite`, or `sharded` with an optional prefix length like `sharded:3`
+impl FromStr for Backend {
+    type Err = ParseBackendError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseBackendError(s.to_string());
+        match s.split_once(':') {
+            Some(("sharded", prefix_len)) => {
+                Ok(Backend::Sharded(prefix_len.parse().map_err(|_| err())?))
+            }
+            Some(_) => Err(err()),
+            None => match s {
+                "json" => Ok(Backend::Json),
+                "sharded" => Ok(Backend::Sharded(Sharding::default())),
+                "sqlite" => Ok(Backend::Sqlite),
+                _ => Err(err()),
+            },
+        }
+    }
+}
+
+impl Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::Json => write!(f, "json"),
+            Backend::Sharded(sharding) => write!(f, "sharded:{}", sharding),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ConvertError {
+    #[error("{0} already exists")]
+    Exists(String),
+    #[error("this build lacks the sqlite feature")]
+    SqliteUnsupported,
+    #[error(transparent)]
+    Store(#[from] StoreError),
+    #[error("the converted store differs from the original")]
+    Mismatch,
+    #[error("the converted store has {} problems, repair the original first", .0.len())]
+    Problems(Vec<Problem>),
+}
+
+impl From<std::io::Error> for ConvertError {
+    fn from(e: std::io::Error) -> Self {
+        ConvertError::Store(e.into())
+    }
+}
+
+impl From<serde_json::Error> for ConvertError {
+    fn from(e: serde_json::Error) -> Self {
+        ConvertError::Store(e.into())
+    }
+}
+
+impl Repository {
+    pub fn backend(&self) -> Backend {
+        match (self.sqlite, self.sharding) {
+            (true, _) => Backend::Sqlite,
+            (false, Some(sharding)) => Backend::Sharded(sharding),
+            (false, None) => Backend::Json,
+        }
+    }
+
+    /// Writes a copy of the repository to a new store at `path` in `backend`, with the same
+    /// compression and key, and returns it as read back. Fails if the copy differs from the
+    /// repository or [`Repository::verify`] finds problems with it. The repository itself is left
+    /// alone.
+    pub fn convert(
+        &self,
+        backend: Backend,
+        path: impl AsRef<Path>,
+    ) -> Result<Repository, ConvertError> {
+        let path = path.as_ref();
+        if path.exists() {
+            return Err(ConvertError::Exists(path.display().to_string()));
+        }
+        if backend == Backend::Sqlite && cfg!(not(feature = "sqlite")) {
+            return Err(ConvertError::SqliteUnsupported);
+        }
+        let mut copy: Repository = serde_json::from_value(serde_json::to_value(self)?)?;
+        copy.store = path.into();
+        copy.compression = self.compression;
+        copy.key = self.key.clone();
+        copy.sharding = match backend {
+            Backend::Sharded(sharding) => Some(sharding),
+            Backend::Json | Backend::Sqlite => None,
+        };
+        copy.sqlite = backend == Backend::Sqlite;
+        copy.save()?;
+        drop(copy);
+
+        let converted = Repository::open(path, self.key.clone())?;
+        if contents(&converted)? != contents(self)? {
+            return Err(ConvertError::Mismatch);
+        }
+        match converted.verify() {
+            problems if problems.is_empty() => Ok(converted),
+            problems => Err(ConvertError::Problems(problems)),
+        }
+    }
+}
+
+/// everything saved of `repo` but where and how
+fn contents(repo: &Repository) -> Result<serde_json::Value, serde_json::Error> {
+    let mut contents = serde_json::to_value(repo)?;
+    if let Some(contents) = contents.as_object_mut() {
+        contents.remove("store");
+        contents.remove("sharding");
+    }
+    Ok(contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Ok(Backend::Json), "json".parse());
+        assert_eq!(
+            Ok(Backend::Sharded(Sharding { prefix_len: 3 })),
+            "sharded:3".parse()
+        );
+        assert_eq!(Ok(Backend::Sqlite), "sqlite".parse());
+        for invalid in ["sharded:0", "json:1", "csv", ""] {
+            assert!(invalid.parse::<Backend>().is_err(), "{}", invalid);
+        }
+        let sharded = Backend::Sharded(Sharding::default());
+        assert_eq!(Ok(sharded), sharded.to_string().parse());
+    }
+
+    #[test]
+    fn converts_back_and_forth() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let mut repo = Repository::new(dir.path().join("store.json"));
+        for name in ["hello", "world", "Hello-Again"] {
+            repo.add_crate(
+                Metadata::new(name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.yank("hello", SemVer::new(1, 0, 0))?;
+        repo.issue_token("someone", vec![], None);
+
+        let mut backends = vec![Backend::Sharded(Sharding { prefix_len: 1 })];
+        if cfg!(feature = "sqlite") {
+            backends.push(Backend::Sqlite);
+        }
+        for backend in backends {
+            let path = dir.path().join(backend.to_string().replace(':', "-"));
+            let converted = repo.convert(backend, &path)?;
+            assert_eq!(backend, converted.backend());
+            assert!(converted
+                .find_exact("hello")
+                .unwrap()
+                .is_yanked(SemVer::new(1, 0, 0)));
+
+            let back =
+                converted.convert(Backend::Json, dir.path().join(format!("{}.json", backend)))?;
+            assert_eq!(Backend::Json, back.backend());
+            assert!(matches!(
+                converted.convert(Backend::Json, &path),
+                Err(ConvertError::Exists(_))
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,382 @@
+//! The dependencies single releases declare, each a crate name with a version requirement, see
+//! [`crate::graph`] for following them through the repository.
+//!
+//! Dependencies may name crates the repository doesn't know, e.g. ones from crates.io, which
+//! is only pointed out. Declaring dependencies no release satisfies, or that lead back to the
+//! release, is rejected, see [`DependencyDiagnostic`].
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::api::ApiError;
+use crate::events::Event;
+use crate::names::CrateName;
+use crate::resolve::VersionReq;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// most dependencies a single release may declare
+pub const MAX_DEPENDENCIES: usize = 512;
+/// longest dependency name, namespace included
+const MAX_NAME_LEN: usize = 128;
+
+/// like the dependency tables of a manifest
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+pub enum DependencyKind {
+    #[default]
+    Normal,
+    Dev,
+    Build,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dependency {
+    pub name: String,
+    pub req: VersionReq,
+    #[serde(default)]
+    pub kind: DependencyKind,
+    /// only used if a feature enables it
+    #[serde(default)]
+    pub optional: bool,
+}
+
+impl Dependency {
+    pub fn new(name: impl Into<String>, req: VersionReq) -> Self {
+        Self {
+            name: name.into(),
+            req,
+            kind: DependencyKind::Normal,
+            optional: false,
+        }
+    }
+
+    pub fn kind(mut self, kind: DependencyKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    pub fn optional(mut self, optional: bool) -> Self {
+        self.optional = optional;
+        self
+    }
+}
+
+/// What's wrong with declared dependencies, given the crates in the repository
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DependencyDiagnostic {
+    /// not in the repository, fine for crates from elsewhere
+    UnknownCrate { name: String },
+    /// no release that isn't yanked matches, pre-releases included
+    Unsatisfiable { name: String, req: VersionReq },
+    /// The release would depend on itself through the crates in `path`, which starts and ends
+    /// with its own crate. Dev-dependencies don't count, like for cargo.
+    Cycle { path: Vec<String> },
+}
+
+impl DependencyDiagnostic {
+    /// whether declaring the dependencies is rejected, rather than just pointed out
+    pub fn is_error(&self) -> bool {
+        !matches!(self, DependencyDiagnostic::UnknownCrate { .. })
+    }
+}
+
+impl Display for DependencyDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DependencyDiagnostic::UnknownCrate { name } => {
+                write!(f, "{} is not in the repository", name)
+            }
+            DependencyDiagnostic::Unsatisfiable { name, req } => {
+                write!(f, "no release of {} matches {}", name, req)
+            }
+            DependencyDiagnostic::Cycle { path } => {
+                write!(f, "dependency cycle {}", path.join(" -> "))
+            }
+        }
+    }
+}
+
+/// why [`Repository::set_dependencies`] failed
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum DependencyError {
+    #[error(transparent)]
+    Repo(#[from] RepoError),
+    /// every diagnostic, at least one of them an error
+    #[error("dependencies rejected: {0:?}")]
+    Rejected(Vec<DependencyDiagnostic>),
+}
+
+impl From<DependencyError> for ApiError {
+    fn from(e: DependencyError) -> Self {
+        match e {
+            DependencyError::Repo(e) => ApiError::Repo(e),
+            DependencyError::Rejected(diagnostics) => ApiError::DependenciesRejected(diagnostics),
+        }
+    }
+}
+
+fn validate(dependencies: &[Dependency]) -> Result<(), RepoError> {
+    if dependencies.len() > MAX_DEPENDENCIES {
+        return Err(RepoError::InvalidDependency);
+    }
+    for dependency in dependencies {
+        if dependency.name.len() > MAX_NAME_LEN {
+            return Err(RepoError::InvalidName);
+        }
+        CrateName::parse(&dependency.name)?;
+    }
+    Ok(())
+}
+
+impl Crate {
+    /// the dependencies `version` declares, empty if nothing is known
+    pub fn dependencies(&self, version: SemVer) -> &[Dependency] {
+        self.dependencies
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, dependencies)| &dependencies[..])
+            .unwrap_or_default()
+    }
+
+    /// Replaces the dependencies of `version`, no dependencies remove them
+    pub(crate) fn set_dependencies(
+        &mut self,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    ) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        validate(&dependencies)?;
+        self.dependencies.retain(|(v, _)| *v != version);
+        if !dependencies.is_empty() {
+            self.dependencies.push((version, dependencies));
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Records the dependencies of `version` of the crate called `name`, replacing earlier ones,
+    /// unless [`Repository::check_dependencies`] finds errors. Returns the warnings.
+    pub fn set_dependencies(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    ) -> Result<Vec<DependencyDiagnostic>, DependencyError> {
+        let name = name.as_ref();
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        if !crt.releases().contains(&version) {
+            return Err(RepoError::NotFound.into());
+        }
+        let diagnostics = self.check_dependencies(name, version, &dependencies);
+        if diagnostics.iter().any(DependencyDiagnostic::is_error) {
+            return Err(DependencyError::Rejected(diagnostics));
+        }
+        let crt = self
+            .crates
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_dependencies(version, dependencies.clone())?;
+        self.changes.record(Event::DependenciesChanged {
+            name: crt.metadata.name.clone(),
+            version,
+            dependencies,
+        });
+        Ok(diagnostics)
+    }
+
+    /// What's wrong with `version` of the crate called `name` declaring `dependencies`, see
+    /// [`DependencyDiagnostic`]
+    pub fn check_dependencies(
+        &self,
+        name: &str,
+        version: SemVer,
+        dependencies: &[Dependency],
+    ) -> Vec<DependencyDiagnostic> {
+        let mut diagnostics = vec![];
+        for dependency in dependencies {
+            match self.find_exact(&dependency.name) {
+                None => diagnostics.push(DependencyDiagnostic::UnknownCrate {
+                    name: dependency.name.clone(),
+                }),
+                Some(crt) if crt.resolve(&dependency.req, true, false).version.is_none() => {
+                    diagnostics.push(DependencyDiagnostic::Unsatisfiable {
+                        name: dependency.name.clone(),
+                        req: dependency.req.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(path) = self.find_cycle(name, version, dependencies) {
+            diagnostics.push(DependencyDiagnostic::Cycle { path });
+        }
+        diagnostics
+    }
+
+    /// A path through what `dependencies` resolve to, back to `version` of `name`. Searched
+    /// depth-first without recursion, every release visited at most once.
+    fn find_cycle(
+        &self,
+        name: &str,
+        version: SemVer,
+        dependencies: &[Dependency],
+    ) -> Option<Vec<String>> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![(vec![name.to_string()], dependencies)];
+        while let Some((path, dependencies)) = stack.pop() {
+            for dependency in dependencies {
+                if dependency.kind == DependencyKind::Dev {
+                    continue;
+                }
+                let Some(crt) = self.find_exact(&dependency.name) else {
+                    continue;
+                };
+                let Some(resolved) = crt.resolve(&dependency.req, true, false).version else {
+                    continue;
+                };
+                let mut path = path.clone();
+                path.push(dependency.name.clone());
+                if dependency.name == name && resolved == version {
+                    return Some(path);
+                }
+                if visited.insert((crt.metadata().name(), resolved)) {
+                    stack.push((path, crt.dependencies(resolved)));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn set_dependencies() -> Result<(), DependencyError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        let dependencies = vec![
+            Dependency::new("serde", "^1.0".parse().unwrap()).optional(true),
+            Dependency::new("@acme/http", VersionReq::STAR),
+            Dependency::new("tempfile", "3".parse().unwrap()).kind(DependencyKind::Dev),
+        ];
+        let warnings =
+            repo.set_dependencies("hello_lib", SemVer::new(1, 0, 0), dependencies.clone())?;
+        assert_eq!(3, warnings.len());
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert_eq!(&dependencies[..], crt.dependencies(SemVer::new(1, 0, 0)));
+
+        assert_eq!(
+            Err(RepoError::InvalidName.into()),
+            repo.set_dependencies(
+                "hello_lib",
+                SemVer::new(1, 0, 0),
+                vec![Dependency::new("@acme/", VersionReq::STAR)]
+            )
+        );
+        assert_eq!(
+            Err(RepoError::NotFound.into()),
+            repo.set_dependencies("hello_lib", SemVer::new(2, 0, 0), vec![])
+        );
+
+        repo.set_dependencies("hello_lib", SemVer::new(1, 0, 0), vec![])?;
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert!(crt.dependencies(SemVer::new(1, 0, 0)).is_empty());
+        assert_eq!(3, repo.changes().last_seq());
+        Ok(())
+    }
+
+    #[test]
+    fn diagnostics() -> Result<(), DependencyError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["hello_bin", "hello_lib", "hello_macros"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        let req = |s: &str| s.parse::<VersionReq>().unwrap();
+        repo.set_dependencies(
+            "hello_bin",
+            SemVer::new(1, 0, 0),
+            vec![Dependency::new("hello_lib", req("1"))],
+        )?;
+        repo.set_dependencies(
+            "hello_lib",
+            SemVer::new(1, 0, 0),
+            vec![Dependency::new("hello_macros", req("1"))],
+        )?;
+        // dev-dependencies may go in circles
+        repo.set_dependencies(
+            "hello_macros",
+            SemVer::new(1, 0, 0),
+            vec![Dependency::new("hello_bin", req("1")).kind(DependencyKind::Dev)],
+        )?;
+
+        assert_eq!(
+            Err(DependencyError::Rejected(vec![
+                DependencyDiagnostic::Unsatisfiable {
+                    name: "hello_lib".into(),
+                    req: req("^2")
+                },
+                DependencyDiagnostic::Cycle {
+                    path: vec![
+                        "hello_macros".into(),
+                        "hello_bin".into(),
+                        "hello_lib".into(),
+                        "hello_macros".into()
+                    ]
+                },
+            ])),
+            repo.set_dependencies(
+                "hello_macros",
+                SemVer::new(1, 0, 0),
+                vec![
+                    Dependency::new("hello_lib", req("2")),
+                    Dependency::new("hello_bin", req("1")).kind(DependencyKind::Build),
+                ],
+            )
+        );
+        assert_eq!(
+            Some(DependencyDiagnostic::Cycle {
+                path: vec!["hello_lib".into(), "hello_lib".into()]
+            }),
+            repo.check_dependencies(
+                "hello_lib",
+                SemVer::new(1, 0, 0),
+                &[Dependency::new("hello_lib", VersionReq::STAR)],
+            )
+            .pop()
+        );
+        // a release depending on an older release of its own crate is no cycle
+        repo.add_release("hello_lib", SemVer::new(2, 0, 0))?;
+        assert_eq!(
+            Ok(vec![]),
+            repo.set_dependencies(
+                "hello_lib",
+                SemVer::new(2, 0, 0),
+                vec![Dependency::new("hello_lib", req("1"))],
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,137 @@
+//! Deprecating whole crates or single versions, optionally pointing users to a replacement.
+
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Deprecation {
+    /// why, or what to do instead
+    #[serde(default)]
+    pub message: Option<String>,
+    /// name of a crate to use instead
+    #[serde(default)]
+    pub replacement: Option<String>,
+}
+
+impl Crate {
+    /// whether the crate as a whole is deprecated
+    pub fn deprecation(&self) -> Option<&Deprecation> {
+        self.deprecation.as_ref()
+    }
+
+    /// why `version` is deprecated, falling back to the crate's deprecation
+    pub fn version_deprecation(&self, version: SemVer) -> Option<&Deprecation> {
+        self.deprecated_versions
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, deprecation)| deprecation)
+            .or(self.deprecation.as_ref())
+    }
+
+    /// Deprecates `version`, or the whole crate if `None`. A `deprecation` of `None` lifts it.
+    pub(crate) fn set_deprecation(
+        &mut self,
+        version: Option<SemVer>,
+        deprecation: Option<Deprecation>,
+    ) -> Result<(), RepoError> {
+        match version {
+            Some(version) => {
+                if !self.release_history.contains(&version) {
+                    return Err(RepoError::NotFound);
+                }
+                self.deprecated_versions.retain(|(v, _)| *v != version);
+                if let Some(deprecation) = deprecation {
+                    self.deprecated_versions.push((version, deprecation));
+                }
+            }
+            None => self.deprecation = deprecation,
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Deprecates the crate called `name`, or just `version` of it. A `deprecation` of `None`
+    /// lifts a previous deprecation. Check permissions with [`Repository::check_owner`] first.
+    pub fn deprecate(
+        &mut self,
+        name: impl AsRef<str>,
+        version: Option<SemVer>,
+        deprecation: Option<Deprecation>,
+    ) -> Result<(), RepoError> {
+        let name = name.as_ref();
+        let crt = self
+            .crates
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_deprecation(version, deprecation.clone())?;
+        self.changes.record(Event::Deprecated {
+            name: crt.metadata.name.clone(),
+            version,
+            deprecation,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn deprecate() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+
+        let insecure = Deprecation {
+            message: Some("insecure".to_string()),
+            replacement: None,
+        };
+        repo.deprecate(
+            "hello_bin",
+            Some(SemVer::new(1, 0, 0)),
+            Some(insecure.clone()),
+        )?;
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.deprecate("hello_bin", Some(SemVer::new(2, 0, 0)), None)
+        );
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!(None, crt.deprecation());
+        assert_eq!(
+            Some(&insecure),
+            crt.version_deprecation(SemVer::new(1, 0, 0))
+        );
+        assert_eq!(None, crt.version_deprecation(SemVer::new(1, 1, 0)));
+
+        let moved = Deprecation {
+            message: None,
+            replacement: Some("hello_world".to_string()),
+        };
+        repo.deprecate("hello_bin", None, Some(moved.clone()))?;
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!(Some(&moved), crt.version_deprecation(SemVer::new(1, 1, 0)));
+        assert_eq!(
+            Some(&insecure),
+            crt.version_deprecation(SemVer::new(1, 0, 0))
+        );
+
+        repo.deprecate("hello_bin", None, None)?;
+        assert_eq!(None, repo.find_exact("hello_bin").unwrap().deprecation());
+        Ok(())
+    }
+}
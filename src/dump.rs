@@ -0,0 +1,341 @@
+//! Seeding a repository from a [crates.io database dump](https://crates.io/data-access).
+//!
+//! Reads `crates.csv` and `versions.csv`, and if present `users.csv` and `crate_owners.csv` to
+//! credit the first owner as author. Versions with pre-release or build tags don't fit
+//! [`SemVer`] and are skipped, as are names the repository rejects.
+//!
+//! `versions.csv`, by far the largest file, is streamed: its releases are imported and the
+//! repository saved every [`SAVE_EVERY`] of them. Imports can be resumed, crates that exist
+//! already only get their missing releases.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use csv::StringRecord;
+use thiserror::Error;
+
+use crate::import::Release;
+use crate::{CrateKind, Metadata, RepoError, Repository, SemVer};
+
+/// number of releases read from the dump between imports, each followed by a save
+pub const SAVE_EVERY: usize = 100_000;
+/// author of crates without a known owner
+pub const UNKNOWN_AUTHOR: &str = "crates.io";
+
+#[derive(Error, Debug)]
+pub enum DumpError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid csv: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("{0:?} not found, expected an extracted crates.io dump")]
+    MissingFile(PathBuf),
+    #[error("{file} lacks a '{column}' column")]
+    MissingColumn { file: String, column: &'static str },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    /// crates handled so far, including skipped ones
+    pub crates: usize,
+    pub total_crates: usize,
+    /// releases added to the repository
+    pub releases: usize,
+    /// crates and versions that couldn't be imported
+    pub skipped: usize,
+}
+
+/// a CSV file of the dump, with columns looked up by name
+struct Table {
+    name: String,
+    reader: csv::Reader<File>,
+    headers: StringRecord,
+}
+
+impl Table {
+    fn open(data: &Path, name: &str) -> Result<Self, DumpError> {
+        let path = data.join(name);
+        if !path.is_file() {
+            return Err(DumpError::MissingFile(path));
+        }
+        let mut reader = csv::Reader::from_path(&path)?;
+        let headers = reader.headers()?.clone();
+        Ok(Self {
+            name: name.to_string(),
+            reader,
+            headers,
+        })
+    }
+
+    fn column(&self, column: &'static str) -> Result<usize, DumpError> {
+        self.headers
+            .iter()
+            .position(|header| header == column)
+            .ok_or_else(|| DumpError::MissingColumn {
+                file: self.name.clone(),
+                column,
+            })
+    }
+}
+
+/// the directory containing the CSV files, the dump puts them in `data/`
+fn data_dir(dump: &Path) -> PathBuf {
+    let data = dump.join("data");
+    if data.is_dir() {
+        data
+    } else {
+        dump.to_path_buf()
+    }
+}
+
+fn parse_time(s: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+        .map(|t| t.and_utc())
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(s).ok().map(|t| t.into()))
+}
+
+/// crate id → login of its first user owner
+fn owners(data: &Path) -> Result<HashMap<String, String>, DumpError> {
+    if !data.join("users.csv").is_file() || !data.join("crate_owners.csv").is_file() {
+        return Ok(HashMap::new());
+    }
+    let mut users = Table::open(data, "users.csv")?;
+    let (id, login) = (users.column("id")?, users.column("gh_login")?);
+    let mut logins = HashMap::new();
+    for record in users.reader.records() {
+        let record = record?;
+        logins.insert(record[id].to_string(), record[login].to_string());
+    }
+
+    let mut crate_owners = Table::open(data, "crate_owners.csv")?;
+    let crate_id = crate_owners.column("crate_id")?;
+    let owner_id = crate_owners.column("owner_id")?;
+    // teams have kind 1
+    let kind = crate_owners.column("owner_kind").ok();
+    let mut owners = HashMap::new();
+    for record in crate_owners.reader.records() {
+        let record = record?;
+        if kind.is_some_and(|kind| &record[kind] != "0") {
+            continue;
+        }
+        if let Some(login) = logins.get(&record[owner_id]) {
+            owners
+                .entry(record[crate_id].to_string())
+                .or_insert_with(|| login.clone());
+        }
+    }
+    Ok(owners)
+}
+
+/// Imports the extracted crates.io dump at `dump` into `repo`, calling `progress` after every
+/// crate.
+pub fn import_cratesio(
+    repo: &mut Repository,
+    dump: impl AsRef<Path>,
+    mut progress: impl FnMut(&Progress),
+) -> Result<Progress, DumpError> {
+    let data = data_dir(dump.as_ref());
+    let mut status = Progress::default();
+
+    let owners = owners(&data)?;
+    let mut crates = Table::open(&data, "crates.csv")?;
+    let (id, name) = (crates.column("id")?, crates.column("name")?);
+    let description = crates.column("description").ok();
+    // crate id → metadata, the releases are streamed from `versions.csv`
+    let mut metadata = HashMap::new();
+    for record in crates.reader.records() {
+        let record = record?;
+        let author = owners
+            .get(&record[id])
+            .map(String::as_str)
+            .unwrap_or(UNKNOWN_AUTHOR);
+        let mut crate_metadata = Metadata::new(&record[name], author, CrateKind::Library);
+        if let Some(description) = description {
+            crate_metadata = crate_metadata.with_description(&record[description]);
+        }
+        metadata.insert(record[id].to_string(), crate_metadata);
+    }
+    status.total_crates = metadata.len();
+
+    let mut versions = Table::open(&data, "versions.csv")?;
+    let (crate_id, num) = (versions.column("crate_id")?, versions.column("num")?);
+    let created_at = versions.column("created_at")?;
+    let yanked = versions.column("yanked")?;
+    let mut batch = Batch::default();
+    for record in versions.reader.records() {
+        let record = record?;
+        match record[num].parse::<SemVer>() {
+            Ok(version) => batch.releases.push((
+                record[crate_id].to_string(),
+                Release {
+                    version,
+                    published_at: parse_time(&record[created_at]),
+                },
+                &record[yanked] == "t",
+            )),
+            Err(_) => status.skipped += 1,
+        }
+        if batch.releases.len() == SAVE_EVERY {
+            batch.import(repo, &metadata, &mut status, &mut progress);
+            repo.save()?;
+        }
+    }
+    batch.import(repo, &metadata, &mut status, &mut progress);
+
+    // crates without a single release that fits
+    for (id, crate_metadata) in &metadata {
+        if !batch.handled.contains(id) {
+            log::debug!("skipping crate '{}': no releases", crate_metadata.name());
+            status.skipped += 1;
+            status.crates += 1;
+            progress(&status);
+        }
+    }
+    repo.save()?;
+    Ok(status)
+}
+
+/// releases read from `versions.csv` but not imported yet
+#[derive(Default)]
+struct Batch {
+    /// crate id, release and whether it is yanked
+    releases: Vec<(String, Release, bool)>,
+    /// ids of the crates imported or skipped so far
+    handled: HashSet<String>,
+}
+
+impl Batch {
+    /// imports the releases read so far, grouped by crate
+    fn import(
+        &mut self,
+        repo: &mut Repository,
+        metadata: &HashMap<String, Metadata>,
+        status: &mut Progress,
+        progress: &mut impl FnMut(&Progress),
+    ) {
+        let mut by_crate: HashMap<String, Vec<(Release, bool)>> = HashMap::new();
+        for (id, release, yanked) in self.releases.drain(..) {
+            by_crate.entry(id).or_default().push((release, yanked));
+        }
+        for (id, releases) in by_crate {
+            // versions of crates missing from `crates.csv`
+            let Some(crate_metadata) = metadata.get(&id) else {
+                continue;
+            };
+            // crates may span several batches, but are only counted once
+            let first_time = self.handled.insert(id);
+            if first_time {
+                status.crates += 1;
+            }
+            match import_crate(repo, crate_metadata, releases) {
+                Ok(added) => status.releases += added,
+                Err(e) => {
+                    log::debug!("skipping crate '{}': {}", crate_metadata.name(), e);
+                    if first_time {
+                        status.skipped += 1;
+                    }
+                }
+            }
+            progress(status);
+        }
+    }
+}
+
+/// adds the crate unless it exists already, followed by all releases it lacks
+fn import_crate(
+    repo: &mut Repository,
+    metadata: &Metadata,
+    releases: Vec<(Release, bool)>,
+) -> Result<usize, RepoError> {
+    let name = metadata.name();
+    let yanked: Vec<SemVer> = releases
+        .iter()
+        .filter(|(_, yanked)| *yanked)
+        .map(|(release, _)| release.version)
+        .collect();
+    let releases = releases.into_iter().map(|(release, _)| release).collect();
+    let added = match repo.find_exact(name) {
+        Some(_) => repo.import_releases(name, releases)?,
+        None => repo.import_crate(metadata.clone(), releases)?,
+    };
+    for version in yanked {
+        repo.yank(name, version)?;
+    }
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use tempfile::{tempdir, NamedTempFile};
+
+    use super::*;
+
+    #[test]
+    fn import_dump() -> Result<(), Box<dyn std::error::Error>> {
+        let dump = tempdir()?;
+        let data = dump.path().join("data");
+        fs::create_dir(&data)?;
+        fs::write(
+            data.join("crates.csv"),
+            "id,name,description,created_at\n\
+             1,serde,\"A generic serialization/deserialization framework\",2014-12-05 20:20:39.487502\n\
+             2,nothing,no versions,2014-12-05 20:20:39.487502\n",
+        )?;
+        fs::write(
+            data.join("versions.csv"),
+            "id,crate_id,num,created_at,yanked\n\
+             10,1,1.0.0,2017-04-20 18:25:37.121818,f\n\
+             11,1,0.9.0,2017-01-20 18:25:37.121818,f\n\
+             12,1,1.0.1-rc.1,2017-04-21 18:25:37.121818,f\n\
+             13,1,1.0.1,2017-04-22 18:25:37.121818,t\n",
+        )?;
+        fs::write(data.join("users.csv"), "gh_login,id\ndtolnay,100\n")?;
+        fs::write(
+            data.join("crate_owners.csv"),
+            "crate_id,owner_id,owner_kind\n1,100,0\n",
+        )?;
+
+        let store = NamedTempFile::new()?;
+        let mut repo = Repository::new(&store);
+        let mut updates = 0;
+        let status = import_cratesio(&mut repo, dump.path(), |_| updates += 1)?;
+        assert_eq!(
+            Progress {
+                crates: 2,
+                total_crates: 2,
+                releases: 3,
+                // the pre-release and the crate without versions
+                skipped: 2,
+            },
+            status
+        );
+        assert_eq!(2, updates);
+
+        let serde = repo.find_exact("serde").unwrap();
+        assert_eq!("dtolnay", serde.metadata().author());
+        assert_eq!(
+            &[
+                SemVer::new(0, 9, 0),
+                SemVer::new(1, 0, 0),
+                SemVer::new(1, 0, 1)
+            ],
+            serde.releases()
+        );
+        assert!(serde.is_yanked(SemVer::new(1, 0, 1)));
+        assert_eq!(Some(SemVer::new(1, 0, 0)), serde.latest());
+        assert_eq!(
+            parse_time("2017-01-20 18:25:37.121818"),
+            serde.published_at(SemVer::new(0, 9, 0))
+        );
+
+        // resuming doesn't duplicate anything
+        let status = import_cratesio(&mut repo, dump.path(), |_| {})?;
+        assert_eq!(0, status.releases);
+        Ok(())
+    }
+}
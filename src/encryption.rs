@@ -0,0 +1,183 @@
+//! Encryption of the store at rest with AES-256-GCM, for registries whose crate names are secret.
+//!
+//! Encrypted stores start with a magic header and a random nonce, followed by the encrypted,
+//! possibly compressed, JSON. They can only be opened with [`crate::Repository::open`] and the
+//! key they were saved with. [`crate::Repository::rekey`] rotates the key, or removes it.
+
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use aes_gcm::aead::{Aead, KeyInit, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use thiserror::Error;
+
+/// also serves as associated data, so the format version can't be swapped
+const MAGIC: &[u8; 8] = b"SVREPO\x00\x01";
+const NONCE_LEN: usize = 12;
+
+/// A 256 bit key, written as 64 hex digits in key files and environment variables
+#[derive(Clone, PartialEq, Eq)]
+pub struct StoreKey([u8; 32]);
+
+#[derive(Error, Debug)]
+pub enum KeyError {
+    #[error("a key must be 64 hex digits")]
+    Invalid,
+    #[error("could not read key file: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl StoreKey {
+    /// a new random key
+    pub fn generate() -> Self {
+        let mut bytes = [0u8; 32];
+        getrandom::getrandom(&mut bytes).expect("no randomness available");
+        Self(bytes)
+    }
+
+    pub fn from_hex(hex: &str) -> Result<Self, KeyError> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(hex.trim(), &mut bytes).map_err(|_| KeyError::Invalid)?;
+        Ok(Self(bytes))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    /// reads a key file holding the hex digits, surrounding whitespace is ignored
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, KeyError> {
+        Self::from_hex(&fs::read_to_string(path)?)
+    }
+
+    /// the key in the env var `var`, or in the key file named by `{var}_FILE`
+    pub fn from_env(var: &str) -> Result<Option<Self>, KeyError> {
+        if let Ok(hex) = std::env::var(var) {
+            return Self::from_hex(&hex).map(Some);
+        }
+        std::env::var_os(format!("{}_FILE", var))
+            .map(Self::read)
+            .transpose()
+    }
+
+    /// writes a key file only its owner can read
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        io::Write::write_all(&mut options.open(path)?, self.to_hex().as_bytes())
+    }
+
+    fn cipher(&self) -> Aes256Gcm {
+        Aes256Gcm::new(&self.0.into())
+    }
+}
+
+impl Debug for StoreKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("StoreKey(..)")
+    }
+}
+
+/// whether `contents` are an encrypted store
+pub fn is_encrypted(contents: &[u8]) -> bool {
+    contents.starts_with(MAGIC)
+}
+
+pub fn encrypt(key: &StoreKey, plain: &[u8]) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LEN];
+    getrandom::getrandom(&mut nonce).expect("no randomness available");
+    let payload = Payload {
+        msg: plain,
+        aad: MAGIC,
+    };
+    let encrypted = key
+        .cipher()
+        .encrypt(Nonce::from_slice(&nonce), payload)
+        .expect("stores fit into AES-GCM messages");
+    [&MAGIC[..], &nonce, &encrypted].concat()
+}
+
+/// `None` if `contents` aren't an encrypted store or `key` doesn't fit
+pub fn decrypt(key: &StoreKey, contents: &[u8]) -> Option<Vec<u8>> {
+    let rest = contents.strip_prefix(MAGIC)?;
+    if rest.len() < NONCE_LEN {
+        return None;
+    }
+    let (nonce, encrypted) = rest.split_at(NONCE_LEN);
+    let payload = Payload {
+        msg: encrypted,
+        aad: MAGIC,
+    };
+    key.cipher().decrypt(Nonce::from_slice(nonce), payload).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, NamedTempFile};
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer, StoreError};
+
+    #[test]
+    fn keys() -> Result<(), KeyError> {
+        let key = StoreKey::generate();
+        assert_eq!(key, StoreKey::from_hex(&format!("{}\n", key.to_hex()))?);
+        assert!(StoreKey::from_hex("abc").is_err());
+        assert!(!format!("{:?}", key).contains(&key.to_hex()));
+
+        let dir = tempdir()?;
+        let path = dir.path().join("store.key");
+        key.write(&path)?;
+        assert_eq!(key, StoreKey::read(&path)?);
+        // never replaces a key, that would lock out its store
+        assert!(StoreKey::generate().write(&path).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_store() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let key = StoreKey::generate();
+        let mut repo = Repository::open(&store, Some(key.clone())).unwrap();
+        repo.add_crate(
+            Metadata::new("project-nightingale", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        drop(repo);
+
+        let contents = fs::read(store.path()).unwrap();
+        assert!(is_encrypted(&contents));
+        assert!(!String::from_utf8_lossy(&contents).contains("nightingale"));
+        assert!(matches!(
+            Repository::open(&store, None),
+            Err(StoreError::Encrypted)
+        ));
+        assert!(matches!(
+            Repository::open(&store, Some(StoreKey::generate())),
+            Err(StoreError::WrongKey)
+        ));
+
+        // rotate the key, the old one no longer works
+        let mut repo = Repository::open(&store, Some(key.clone())).unwrap();
+        let new_key = StoreKey::generate();
+        repo.rekey(Some(new_key.clone())).unwrap();
+        drop(repo);
+        assert!(matches!(
+            Repository::open(&store, Some(key)),
+            Err(StoreError::WrongKey)
+        ));
+        let mut repo = Repository::open(&store, Some(new_key)).unwrap();
+        assert!(repo.find_exact("project-nightingale").is_some());
+
+        // and back to plain
+        repo.rekey(None).unwrap();
+        drop(repo);
+        let repo = Repository::open(&store, None).unwrap();
+        assert!(repo.find_exact("project-nightingale").is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::advisories::Advisory;
+use crate::blobs::BlobId;
+use crate::channels::Channel;
+use crate::dependencies::Dependency;
+use crate::deprecation::Deprecation;
+use crate::features::Features;
+use crate::import::Release;
+use crate::platform::Platform;
+use crate::{Crate, Metadata, SemVer};
+
+/// A single mutation of the repository. Crate names are shared with the repository where possible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    CrateAdded {
+        metadata: Metadata,
+        version: SemVer,
+        /// when `version` was originally published, for crates imported with their history, see
+        /// [`Repository::import_crate`](crate::Repository::import_crate)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        published_at: Option<DateTime<Utc>>,
+    },
+    ReleaseAdded {
+        name: Arc<str>,
+        version: SemVer,
+        #[serde(default)]
+        channel: Channel,
+    },
+    Yanked {
+        name: Arc<str>,
+        version: SemVer,
+    },
+    CrateDeleted {
+        name: Arc<str>,
+    },
+    /// the crate is now called `to`, `from` stays an alias for it
+    CrateRenamed {
+        from: Arc<str>,
+        to: Arc<str>,
+    },
+    /// the crate's metadata was replaced, e.g. after an ownership transfer
+    MetadataChanged {
+        metadata: Metadata,
+    },
+    /// historical releases were backfilled, see [`crate::import`]
+    ReleasesImported {
+        name: Arc<str>,
+        releases: Vec<Release>,
+    },
+    /// `version` or with `None` the whole crate was deprecated, or undeprecated if
+    /// `deprecation` is `None`
+    Deprecated {
+        name: Arc<str>,
+        version: Option<SemVer>,
+        deprecation: Option<Deprecation>,
+    },
+    /// the release notes of `version` were replaced, or removed if `notes` is `None`
+    ReleaseNotesChanged {
+        name: Arc<str>,
+        version: SemVer,
+        notes: Option<String>,
+    },
+    /// the README of `version` was replaced by the one stored as `blob`, or removed if `None`
+    ReadmeChanged {
+        name: Arc<str>,
+        version: SemVer,
+        blob: Option<BlobId>,
+    },
+    /// the minimum Rust version and targets of `version` were replaced
+    PlatformChanged {
+        name: Arc<str>,
+        version: SemVer,
+        platform: Platform,
+    },
+    /// the features of `version` were replaced
+    FeaturesChanged {
+        name: Arc<str>,
+        version: SemVer,
+        features: Features,
+    },
+    /// the dependencies of `version` were replaced
+    DependenciesChanged {
+        name: Arc<str>,
+        version: SemVer,
+        dependencies: Vec<Dependency>,
+    },
+    /// an advisory was filed against the crate, replacing one with the same id
+    AdvisoryFiled {
+        name: Arc<str>,
+        advisory: Advisory,
+    },
+    AdvisoryWithdrawn {
+        name: Arc<str>,
+        id: String,
+    },
+    /// an admin blocked `version`, see [`crate::blocklist`]
+    VersionBlocked {
+        name: Arc<str>,
+        version: SemVer,
+        reason: String,
+    },
+    VersionUnblocked {
+        name: Arc<str>,
+        version: SemVer,
+    },
+    /// releases were removed by a retention policy, see [`crate::retention`]
+    ReleasesPruned {
+        name: Arc<str>,
+        versions: Vec<SemVer>,
+    },
+}
+
+impl Event {
+    /// name of the crate the event is about
+    pub fn crate_name(&self) -> &str {
+        match self {
+            Event::CrateAdded { metadata, .. } | Event::MetadataChanged { metadata } => {
+                metadata.name()
+            }
+            Event::ReleaseAdded { name, .. }
+            | Event::Yanked { name, .. }
+            | Event::Deprecated { name, .. }
+            | Event::ReleaseNotesChanged { name, .. }
+            | Event::ReadmeChanged { name, .. }
+            | Event::PlatformChanged { name, .. }
+            | Event::FeaturesChanged { name, .. }
+            | Event::DependenciesChanged { name, .. }
+            | Event::AdvisoryFiled { name, .. }
+            | Event::AdvisoryWithdrawn { name, .. }
+            | Event::VersionBlocked { name, .. }
+            | Event::VersionUnblocked { name, .. }
+            | Event::ReleasesImported { name, .. }
+            | Event::ReleasesPruned { name, .. }
+            | Event::CrateDeleted { name } => name,
+            Event::CrateRenamed { to, .. } => to,
+        }
+    }
+
+    /// shares names with the keys of `crates` and authors with `authors`, see
+    /// [`crate::Repository::intern_author`]
+    pub(crate) fn intern(
+        &mut self,
+        crates: &BTreeMap<Arc<str>, Arc<Crate>>,
+        authors: &mut HashSet<Arc<str>>,
+    ) {
+        let share = |name: &mut Arc<str>| {
+            if let Some((key, _)) = crates.get_key_value(name.as_ref()) {
+                *name = key.clone();
+            }
+        };
+        match self {
+            Event::CrateAdded { metadata, .. } | Event::MetadataChanged { metadata } => {
+                share(&mut metadata.name);
+                crate::intern(authors, &mut metadata.author);
+            }
+            Event::ReleaseAdded { name, .. }
+            | Event::Yanked { name, .. }
+            | Event::Deprecated { name, .. }
+            | Event::ReleaseNotesChanged { name, .. }
+            | Event::ReadmeChanged { name, .. }
+            | Event::PlatformChanged { name, .. }
+            | Event::FeaturesChanged { name, .. }
+            | Event::DependenciesChanged { name, .. }
+            | Event::AdvisoryFiled { name, .. }
+            | Event::AdvisoryWithdrawn { name, .. }
+            | Event::VersionBlocked { name, .. }
+            | Event::VersionUnblocked { name, .. }
+            | Event::ReleasesImported { name, .. }
+            | Event::ReleasesPruned { name, .. }
+            | Event::CrateDeleted { name } => share(name),
+            Event::CrateRenamed { to, .. } => share(to),
+        }
+    }
+}
+
+/// An [`Event`] tagged with its position in the change feed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub seq: u64,
+    /// when the change was recorded. Stores predating timestamps load as the unix epoch.
+    #[serde(default)]
+    pub at: DateTime<Utc>,
+    pub event: Event,
+}
+
+/// Append-only list of changes with monotonically increasing sequence numbers, starting at 1.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ChangeLog {
+    last_seq: u64,
+    changes: Vec<Change>,
+    /// how often the log was modified since it was loaded, see [`crate::Repository::is_dirty`]
+    #[serde(skip)]
+    mutations: u64,
+}
+
+impl ChangeLog {
+    pub fn record(&mut self, event: Event) -> u64 {
+        self.mutations += 1;
+        self.last_seq += 1;
+        self.changes.push(Change {
+            seq: self.last_seq,
+            at: Utc::now(),
+            event,
+        });
+        self.last_seq
+    }
+
+    /// Appends a change recorded by another repository, keeping its sequence number.
+    /// Changes that are not newer than the last one are ignored, returns whether it was added.
+    pub fn replicate(&mut self, change: Change) -> bool {
+        if change.seq <= self.last_seq {
+            return false;
+        }
+        self.mutations += 1;
+        self.last_seq = change.seq;
+        self.changes.push(change);
+        true
+    }
+
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        self.changes.reserve(additional);
+    }
+
+    pub(crate) fn events_mut(&mut self) -> impl Iterator<Item = &mut Event> {
+        self.changes.iter_mut().map(|change| &mut change.event)
+    }
+
+    /// forgets all changes, the next one recorded gets `last_seq + 1`
+    pub fn reset(&mut self, last_seq: u64) {
+        self.mutations += 1;
+        self.last_seq = last_seq;
+        self.changes.clear();
+    }
+
+    /// sequence number of the oldest change still around, `last_seq + 1` if there are none
+    pub fn oldest_seq(&self) -> u64 {
+        self.changes
+            .first()
+            .map(|c| c.seq)
+            .unwrap_or(self.last_seq + 1)
+    }
+
+    /// sequence number of the most recent change, 0 if nothing happened yet
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// keeps only the changes matching `keep`, returns the number of removed changes.
+    /// Sequence numbers stay untouched.
+    pub fn retain(&mut self, keep: impl FnMut(&Change) -> bool) -> usize {
+        let before = self.changes.len();
+        self.changes.retain(keep);
+        let removed = before - self.changes.len();
+        if removed > 0 {
+            self.mutations += 1;
+        }
+        removed
+    }
+
+    pub(crate) fn mutations(&self) -> u64 {
+        self.mutations
+    }
+
+    /// all changes with a sequence number greater than `seq`
+    pub fn since(&self, seq: u64) -> &[Change] {
+        let start = self.changes.partition_point(|c| c.seq <= seq);
+        &self.changes[start..]
+    }
+}
@@ -0,0 +1,152 @@
+//! Flat reports of all releases, for feeding reporting pipelines.
+
+use std::io::Write;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::view::View;
+use crate::{Crate, CrateKind, Repository};
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("csv error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("serialization error: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// with a header row
+    Csv,
+    /// one JSON object per line
+    JsonLines,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "jsonl" | "json-lines" => Ok(Self::JsonLines),
+            other => Err(format!(
+                "unknown export format '{other}', expected csv or jsonl"
+            )),
+        }
+    }
+}
+
+/// one release of a crate
+#[derive(Debug, Serialize)]
+struct Row<'a> {
+    #[serde(rename = "crate")]
+    name: &'a str,
+    author: &'a str,
+    kind: &'a CrateKind,
+    version: String,
+    published_at: Option<DateTime<Utc>>,
+}
+
+impl Repository {
+    /// Writes a row per release to `writer`, ordered by crate name and version history.
+    /// Returns the number of rows.
+    pub fn export(&self, format: ExportFormat, writer: impl Write) -> Result<usize, ExportError> {
+        export(self.iter(), format, writer)
+    }
+}
+
+impl View {
+    /// like [`Repository::export`], for exports that shouldn't hold on to the repository
+    pub fn export(&self, format: ExportFormat, writer: impl Write) -> Result<usize, ExportError> {
+        export(self.iter(), format, writer)
+    }
+}
+
+fn export<'a>(
+    crates: impl Iterator<Item = &'a Crate>,
+    format: ExportFormat,
+    writer: impl Write,
+) -> Result<usize, ExportError> {
+    let rows = crates.flat_map(|crt| {
+        let metadata = crt.metadata();
+        crt.releases().iter().map(move |version| Row {
+            name: metadata.name(),
+            author: metadata.author(),
+            kind: metadata.kind(),
+            version: version.to_string(),
+            published_at: crt.published_at(*version),
+        })
+    });
+    let mut count = 0;
+    match format {
+        ExportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(writer);
+            for row in rows {
+                csv.serialize(row)?;
+                count += 1;
+            }
+            csv.flush()?;
+        }
+        ExportFormat::JsonLines => {
+            let mut writer = writer;
+            for row in rows {
+                serde_json::to_writer(&mut writer, &row)?;
+                writeln!(writer)?;
+                count += 1;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{Metadata, SemVer};
+
+    #[test]
+    fn export() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.add_crate(
+            Metadata::new("a_lib", "Lazy Person", CrateKind::Library),
+            SemVer::new(0, 1, 0),
+        )?;
+
+        let mut csv = vec![];
+        assert_eq!(3, repo.export(ExportFormat::Csv, &mut csv)?);
+        let csv = String::from_utf8(csv)?;
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!("crate,author,kind,version,published_at", lines[0]);
+        assert!(lines[1].starts_with("a_lib,Lazy Person,Library,0.1.0,"));
+        assert!(lines[3].starts_with("hello_bin,Busy Person,Binary,1.1.0,"));
+
+        let mut jsonl = vec![];
+        assert_eq!(3, repo.export(ExportFormat::JsonLines, &mut jsonl)?);
+        let rows: Vec<serde_json::Value> = String::from_utf8(jsonl)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!("hello_bin", rows[1]["crate"]);
+        assert_eq!("1.0.0", rows[1]["version"]);
+        assert!(rows[1]["published_at"].is_string());
+
+        assert_eq!(Ok(ExportFormat::JsonLines), "jsonl".parse());
+        assert!("xml".parse::<ExportFormat>().is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,175 @@
+//! The cargo features of single releases, each feature with what it enables: other features,
+//! optional dependencies like `dep:serde`, or features of dependencies like `serde/std`.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// feature name → what it enables, like the `[features]` table of a manifest
+pub type Features = BTreeMap<String, Vec<String>>;
+
+/// most features a single release may have, like crates.io
+pub const MAX_FEATURES: usize = 300;
+/// longest feature name or entry
+const MAX_FEATURE_LEN: usize = 128;
+
+/// letters, digits, `_`, `-`, `+` and `.`, starting with a letter, digit or `_`
+fn is_feature_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphanumeric() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.'))
+        && name.len() <= MAX_FEATURE_LEN
+}
+
+/// `feature`, `dep:name`, `name/feature` or `name?/feature`
+fn is_feature_entry(entry: &str) -> bool {
+    if let Some(dep) = entry.strip_prefix("dep:") {
+        return is_feature_name(dep);
+    }
+    match entry.split_once('/') {
+        Some((dep, feature)) => {
+            is_feature_name(dep.strip_suffix('?').unwrap_or(dep)) && is_feature_name(feature)
+        }
+        None => is_feature_name(entry),
+    }
+}
+
+fn validate(features: &Features) -> Result<(), RepoError> {
+    let valid = features.len() <= MAX_FEATURES
+        && features.iter().all(|(name, enables)| {
+            is_feature_name(name) && enables.iter().all(|entry| is_feature_entry(entry))
+        });
+    match valid {
+        true => Ok(()),
+        false => Err(RepoError::InvalidFeature),
+    }
+}
+
+impl Crate {
+    /// the features of `version`, `None` if nothing is known
+    pub fn features(&self, version: SemVer) -> Option<&Features> {
+        self.features
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, features)| features)
+    }
+
+    /// Replaces the features of `version`, no features remove them
+    pub(crate) fn set_features(
+        &mut self,
+        version: SemVer,
+        features: Features,
+    ) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        validate(&features)?;
+        self.features.retain(|(v, _)| *v != version);
+        if !features.is_empty() {
+            self.features.push((version, features));
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Records the features of `version` of the crate called `name`, replacing earlier ones
+    pub fn set_features(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        features: Features,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_features(version, features.clone())?;
+        self.changes.record(Event::FeaturesChanged {
+            name: crt.metadata.name.clone(),
+            version,
+            features,
+        });
+        Ok(())
+    }
+
+    /// the features of `version` of the crate called `name`, empty if none are known
+    pub fn features(&self, name: impl AsRef<str>, version: SemVer) -> Result<Features, RepoError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        if !crt.releases().contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        Ok(crt.features(version).cloned().unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    fn features(table: &[(&str, &[&str])]) -> Features {
+        table
+            .iter()
+            .map(|(name, enables)| {
+                let enables = enables.iter().map(|entry| entry.to_string()).collect();
+                (name.to_string(), enables)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn set_and_list() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_lib", SemVer::new(1, 1, 0))?;
+
+        let table = features(&[
+            ("default", &["std"]),
+            ("std", &["serde?/std", "dep:libc"]),
+            ("derive", &["serde/derive"]),
+            ("c++20", &[]),
+        ]);
+        repo.set_features("hello_lib", SemVer::new(1, 1, 0), table.clone())?;
+        assert_eq!(table, repo.features("hello_lib", SemVer::new(1, 1, 0))?);
+        assert_eq!(
+            Features::new(),
+            repo.features("hello_lib", SemVer::new(1, 0, 0))?
+        );
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.features("hello_lib", SemVer::new(2, 0, 0))
+        );
+
+        for invalid in [
+            features(&[("", &[])]),
+            features(&[("-std", &[])]),
+            features(&[("std", &["dep:"])]),
+            features(&[("std", &["serde/"])]),
+            features(&[("std", &["a b"])]),
+        ] {
+            assert_eq!(
+                Err(RepoError::InvalidFeature),
+                repo.set_features("hello_lib", SemVer::new(1, 0, 0), invalid)
+            );
+        }
+
+        repo.set_features("hello_lib", SemVer::new(1, 1, 0), Features::new())?;
+        let crt = repo.find_exact("hello_lib").unwrap();
+        assert_eq!(None, crt.features(SemVer::new(1, 1, 0)));
+        assert_eq!(4, repo.changes().last_seq());
+        Ok(())
+    }
+}
@@ -0,0 +1,220 @@
+//! Atom feeds of recent releases, built from the change feed.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+use crate::events::Event;
+use crate::{Repository, SemVer};
+
+/// file name of the registry wide feed written by [`write_feeds`]
+pub const ALL_RELEASES: &str = "releases.atom";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeedEntry<'a> {
+    pub name: &'a str,
+    pub author: &'a str,
+    pub version: SemVer,
+    pub published: DateTime<Utc>,
+}
+
+/// the `limit` most recent releases, newest first, optionally restricted to one crate
+pub fn recent_releases<'a>(
+    repo: &'a Repository,
+    crate_name: Option<&str>,
+    limit: usize,
+) -> Vec<FeedEntry<'a>> {
+    repo.changes()
+        .since(0)
+        .iter()
+        .rev()
+        .filter_map(|change| {
+            let (name, version) = match &change.event {
+                Event::CrateAdded {
+                    metadata, version, ..
+                } => (metadata.name(), *version),
+                Event::ReleaseAdded { name, version, .. } => (name.as_ref(), *version),
+                Event::Yanked { .. }
+                | Event::CrateDeleted { .. }
+                | Event::CrateRenamed { .. }
+                | Event::MetadataChanged { .. }
+                | Event::Deprecated { .. }
+                | Event::ReleaseNotesChanged { .. }
+                | Event::ReadmeChanged { .. }
+                | Event::PlatformChanged { .. }
+                | Event::FeaturesChanged { .. }
+                | Event::DependenciesChanged { .. }
+                | Event::AdvisoryFiled { .. }
+                | Event::AdvisoryWithdrawn { .. }
+                | Event::VersionBlocked { .. }
+                | Event::VersionUnblocked { .. }
+                | Event::ReleasesImported { .. }
+                | Event::ReleasesPruned { .. } => return None,
+            };
+            if crate_name.map(|n| n != name).unwrap_or(false) {
+                return None;
+            }
+            // skips releases of crates that no longer exist
+            let crt = repo.find_exact(name)?;
+            Some(FeedEntry {
+                name: crt.metadata.name(),
+                author: crt.metadata.author(),
+                version,
+                published: change.at,
+            })
+        })
+        .take(limit)
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => res.push_str("&amp;"),
+            '<' => res.push_str("&lt;"),
+            '>' => res.push_str("&gt;"),
+            '"' => res.push_str("&quot;"),
+            '\'' => res.push_str("&apos;"),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+fn timestamp(t: DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Renders an Atom feed of the `limit` most recent releases, either registry wide or for a
+/// single crate.
+pub fn atom(repo: &Repository, crate_name: Option<&str>, limit: usize) -> String {
+    let entries = recent_releases(repo, crate_name, limit);
+    let (id, title) = match crate_name {
+        Some(name) => (
+            format!("urn:semver-repo:crate:{}", escape(name)),
+            format!("Releases of {}", escape(name)),
+        ),
+        None => (
+            "urn:semver-repo:releases".to_string(),
+            "Recent releases".to_string(),
+        ),
+    };
+    // an empty feed still needs an update time, the epoch is as good as any
+    let updated = entries.first().map(|e| e.published).unwrap_or_default();
+
+    let mut xml = String::new();
+    // writing into a String can't fail
+    let _ = write!(
+        xml,
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         \x20 <id>{id}</id>\n\
+         \x20 <title>{title}</title>\n\
+         \x20 <updated>{}</updated>\n",
+        timestamp(updated)
+    );
+    for entry in entries {
+        let name = escape(entry.name);
+        let _ = write!(
+            xml,
+            "  <entry>\n\
+             \x20   <id>urn:semver-repo:release:{name}:{version}</id>\n\
+             \x20   <title>{name} {version}</title>\n\
+             \x20   <updated>{published}</updated>\n\
+             \x20   <author><name>{author}</name></author>\n\
+             \x20   <summary>{name} {version} has been published</summary>\n\
+             \x20 </entry>\n",
+            version = entry.version,
+            published = timestamp(entry.published),
+            author = escape(entry.author),
+        );
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+/// file name of a crate's feed, with anything but `[A-Za-z0-9._-]` replaced
+pub fn crate_feed_file(name: &str) -> String {
+    let safe: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "._-".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("{}.atom", safe)
+}
+
+/// Writes the registry wide feed plus the feeds of `crate_names` into `dir`.
+/// Returns the paths that were written.
+pub fn write_feeds<'a>(
+    repo: &Repository,
+    dir: impl AsRef<Path>,
+    crate_names: impl IntoIterator<Item = &'a str>,
+    limit: usize,
+) -> Result<Vec<PathBuf>, std::io::Error> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+
+    let mut written = vec![];
+    let path = dir.join(ALL_RELEASES);
+    fs::write(&path, atom(repo, None, limit))?;
+    written.push(path);
+
+    for name in crate_names {
+        let path = dir.join(crate_feed_file(name));
+        fs::write(&path, atom(repo, Some(name), limit))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError};
+
+    #[test]
+    fn feeds() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("ham&eggs", "Sam <sam@example.com>", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("ham&eggs", SemVer::new(1, 1, 0))?;
+        repo.yank("ham&eggs", SemVer::new(1, 0, 0))?;
+
+        let recent: Vec<_> = recent_releases(&repo, None, 2)
+            .into_iter()
+            .map(|e| (e.name, e.version))
+            .collect();
+        assert_eq!(
+            vec![
+                ("ham&eggs", SemVer::new(1, 1, 0)),
+                ("hello_bin", SemVer::new(1, 0, 0))
+            ],
+            recent
+        );
+        assert_eq!(2, recent_releases(&repo, Some("ham&eggs"), 10).len());
+
+        let xml = atom(&repo, Some("ham&eggs"), 10);
+        assert!(xml.contains("<title>ham&amp;eggs 1.1.0</title>"));
+        assert!(xml.contains("<name>Sam &lt;sam@example.com&gt;</name>"));
+        assert!(!xml.contains("hello_bin"));
+        assert_eq!("ham_eggs.atom", crate_feed_file("ham&eggs"));
+        Ok(())
+    }
+}
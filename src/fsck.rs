@@ -0,0 +1,389 @@
+//! Consistency checks of a repository, e.g. after its store was edited by hand or written by a
+//! buggy version. See [`Repository::verify`] and `repo fsck`.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::fulltext::SearchIndex;
+use crate::{Crate, Repository, SemVer, VersionPolicy};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Problem {
+    /// the crate is filed under another name than the one in its metadata
+    Misfiled { key: String, name: String },
+    /// the release was published more than once
+    DuplicateRelease { name: String, version: SemVer },
+    /// the release isn't newer than the ones before it, despite
+    /// [`VersionPolicy::StrictlyIncreasing`]
+    Unordered { name: String, version: SemVer },
+    /// the release is yanked, on a channel or deprecated without having been published
+    UnknownVersion { name: String, version: SemVer },
+    /// an old name of a renamed crate refers to a crate that doesn't exist
+    DanglingAlias { alias: String, target: String },
+    /// the full-text index doesn't match the crates
+    StaleSearchIndex,
+    /// the case insensitive name lookup doesn't match the crates
+    StaleCaseIndex,
+    /// the change feed isn't ordered by sequence number, or goes beyond its last one
+    FeedOutOfOrder { seq: u64 },
+}
+
+impl Problem {
+    /// whether [`Repository::repair`] fixes it
+    pub fn is_repairable(&self) -> bool {
+        !matches!(self, Problem::FeedOutOfOrder { .. })
+    }
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Misfiled { key, name } => write!(f, "{} is filed as {}", name, key),
+            Problem::DuplicateRelease { name, version } => {
+                write!(f, "{} {} was published more than once", name, version)
+            }
+            Problem::Unordered { name, version } => {
+                write!(f, "{} {} is older than a previous release", name, version)
+            }
+            Problem::UnknownVersion { name, version } => {
+                write!(
+                    f,
+                    "{} {} is referenced but was never published",
+                    name, version
+                )
+            }
+            Problem::DanglingAlias { alias, target } => {
+                write!(f, "alias {} refers to missing crate {}", alias, target)
+            }
+            Problem::StaleSearchIndex => write!(f, "full-text index is out of date"),
+            Problem::StaleCaseIndex => write!(f, "case insensitive lookup is out of date"),
+            Problem::FeedOutOfOrder { seq } => write!(f, "change #{} is out of order", seq),
+        }
+    }
+}
+
+/// the problems of a single crate's releases
+fn verify_crate(crt: &Crate, policy: VersionPolicy, problems: &mut Vec<Problem>) {
+    let name = || crt.metadata.name().to_string();
+    let mut seen = HashSet::new();
+    let mut newest: Option<SemVer> = None;
+    for &version in &crt.release_history {
+        if !seen.insert(version) {
+            problems.push(Problem::DuplicateRelease {
+                name: name(),
+                version,
+            });
+        } else if policy == VersionPolicy::StrictlyIncreasing && newest > Some(version) {
+            problems.push(Problem::Unordered {
+                name: name(),
+                version,
+            });
+        }
+        newest = newest.max(Some(version));
+    }
+    let referenced = crt
+        .yanked
+        .iter()
+        .copied()
+        .chain(crt.channels.iter().map(|(version, _)| *version))
+        .chain(crt.deprecated_versions.iter().map(|(version, _)| *version))
+        .chain(crt.release_notes.iter().map(|(version, _)| *version))
+        .chain(crt.readmes.iter().map(|(version, _)| *version))
+        .chain(crt.platforms.iter().map(|(version, _)| *version))
+        .chain(crt.features.iter().map(|(version, _)| *version))
+        .chain(crt.dependencies.iter().map(|(version, _)| *version))
+        .chain(crt.blocked.iter().map(|blocked| blocked.version));
+    let mut reported = HashSet::new();
+    for version in referenced {
+        if !seen.contains(&version) && reported.insert(version) {
+            problems.push(Problem::UnknownVersion {
+                name: name(),
+                version,
+            });
+        }
+    }
+}
+
+/// Removes duplicate releases and references to unpublished ones, and sorts the history if
+/// `policy` requires it. Publication times move along with their releases.
+fn repair_crate(crt: &mut Crate, policy: VersionPolicy) {
+    let mut published_at = std::mem::take(&mut crt.published_at);
+    published_at.resize(crt.release_history.len(), DateTime::<Utc>::default());
+    let mut seen = HashSet::new();
+    let mut releases: Vec<(SemVer, DateTime<Utc>)> = crt
+        .release_history
+        .iter()
+        .copied()
+        .zip(published_at)
+        .filter(|(version, _)| seen.insert(*version))
+        .collect();
+    if policy == VersionPolicy::StrictlyIncreasing {
+        releases.sort_by_key(|(version, _)| *version);
+    }
+    (crt.release_history, crt.published_at) = releases.into_iter().unzip();
+    crt.yanked.retain(|version| seen.contains(version));
+    crt.channels.retain(|(version, _)| seen.contains(version));
+    crt.deprecated_versions
+        .retain(|(version, _)| seen.contains(version));
+    crt.release_notes
+        .retain(|(version, _)| seen.contains(version));
+    crt.readmes.retain(|(version, _)| seen.contains(version));
+    crt.platforms.retain(|(version, _)| seen.contains(version));
+    crt.features.retain(|(version, _)| seen.contains(version));
+    crt.dependencies
+        .retain(|(version, _)| seen.contains(version));
+    crt.blocked
+        .retain(|blocked| seen.contains(&blocked.version));
+    crt.revision += 1;
+}
+
+impl Repository {
+    /// Checks that every crate is filed under its name, release histories fit the version
+    /// policy without duplicates, aliases lead somewhere and the derived indices match the
+    /// crates. An empty list means the repository is consistent.
+    pub fn verify(&self) -> Vec<Problem> {
+        let mut problems = vec![];
+        for (key, crt) in &self.crates {
+            if key.as_ref() != crt.metadata.name() {
+                problems.push(Problem::Misfiled {
+                    key: key.to_string(),
+                    name: crt.metadata.name().to_string(),
+                });
+            }
+            verify_crate(
+                crt,
+                self.version_policy_for(&crt.metadata.kind),
+                &mut problems,
+            );
+        }
+        for (alias, target) in &self.aliases {
+            if !self.crates.contains_key(target.as_str()) {
+                problems.push(Problem::DanglingAlias {
+                    alias: alias.clone(),
+                    target: target.clone(),
+                });
+            }
+        }
+        if self.index != SearchIndex::build(self.iter()) {
+            problems.push(Problem::StaleSearchIndex);
+        }
+        if self.names_ignoring_case != self.expected_case_index() {
+            problems.push(Problem::StaleCaseIndex);
+        }
+        let mut last = 0;
+        for change in self.changes.since(0) {
+            if change.seq <= last || change.seq > self.changes.last_seq() {
+                problems.push(Problem::FeedOutOfOrder { seq: change.seq });
+            }
+            last = change.seq;
+        }
+        problems
+    }
+
+    /// Fixes what [`Repository::verify`] finds, returns the problems that were fixed. Duplicate
+    /// releases are dropped, keeping the first, and references to unpublished releases removed.
+    pub fn repair(&mut self) -> Vec<Problem> {
+        let problems = self.verify();
+        let repairable: Vec<Problem> = problems
+            .into_iter()
+            .filter(Problem::is_repairable)
+            .collect();
+        if repairable.is_empty() {
+            return repairable;
+        }
+        self.rebuild_indices();
+        let broken: HashSet<&str> = repairable
+            .iter()
+            .filter_map(|problem| match problem {
+                Problem::DuplicateRelease { name, .. }
+                | Problem::Unordered { name, .. }
+                | Problem::UnknownVersion { name, .. } => Some(name.as_str()),
+                _ => None,
+            })
+            .collect();
+        for name in broken {
+            let Some(policy) = self
+                .find_exact(name)
+                .map(|crt| self.version_policy_for(&crt.metadata.kind))
+            else {
+                continue;
+            };
+            if let Some(crt) = self.crates.get_mut(name).map(Arc::make_mut) {
+                repair_crate(crt, policy);
+            }
+        }
+        let crates = &self.crates;
+        self.aliases
+            .retain(|_, target| crates.contains_key(target.as_str()));
+        self.reindex_all();
+        self.mark_dirty();
+        repairable
+    }
+
+    /// Repairs release histories that don't fit their version policy or have duplicates, e.g. in
+    /// a store that was edited by hand or merged, logging what was fixed. Run on load, so the
+    /// validation of new releases can rely on them.
+    pub(crate) fn normalize_histories(&mut self) {
+        let broken: Vec<(Arc<str>, VersionPolicy, Vec<Problem>)> = self
+            .crates
+            .iter()
+            .filter_map(|(name, crt)| {
+                let policy = self.version_policy_for(&crt.metadata.kind);
+                let mut problems = vec![];
+                verify_crate(crt, policy, &mut problems);
+                (!problems.is_empty()).then(|| (name.clone(), policy, problems))
+            })
+            .collect();
+        if broken.is_empty() {
+            return;
+        }
+        for (name, policy, problems) in broken {
+            for problem in problems {
+                log::warn!("repairing store: {}", problem);
+            }
+            if let Some(crt) = self.crates.get_mut(&name).map(Arc::make_mut) {
+                repair_crate(crt, policy);
+            }
+        }
+        self.mark_dirty();
+    }
+
+    fn expected_case_index(&self) -> HashMap<String, Arc<str>> {
+        let mut expected = HashMap::new();
+        for name in self.crates.keys() {
+            expected
+                .entry(name.to_lowercase())
+                .or_insert_with(|| name.clone());
+        }
+        expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError};
+
+    #[test]
+    fn verify_and_repair() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("fine", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_crate(
+            Metadata::new("broken", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("broken", SemVer::new(2, 0, 0))?;
+        assert_eq!(Vec::<Problem>::new(), repo.verify());
+
+        // as if the store had been edited by hand
+        let crt = Arc::make_mut(repo.crates.get_mut("broken").unwrap());
+        crt.release_history.push(SemVer::new(1, 5, 0));
+        crt.release_history.push(SemVer::new(2, 0, 0));
+        crt.yanked.push(SemVer::new(3, 0, 0));
+        repo.aliases.insert("old".to_string(), "gone".to_string());
+        let problems = repo.verify();
+        assert_eq!(
+            vec![
+                Problem::Unordered {
+                    name: "broken".to_string(),
+                    version: SemVer::new(1, 5, 0)
+                },
+                Problem::DuplicateRelease {
+                    name: "broken".to_string(),
+                    version: SemVer::new(2, 0, 0)
+                },
+                Problem::UnknownVersion {
+                    name: "broken".to_string(),
+                    version: SemVer::new(3, 0, 0)
+                },
+                Problem::DanglingAlias {
+                    alias: "old".to_string(),
+                    target: "gone".to_string()
+                },
+            ],
+            problems
+        );
+
+        assert_eq!(problems, repo.repair());
+        assert_eq!(Vec::<Problem>::new(), repo.verify());
+        let crt = repo.find_exact("broken").unwrap();
+        assert_eq!(
+            &[
+                SemVer::new(1, 0, 0),
+                SemVer::new(1, 5, 0),
+                SemVer::new(2, 0, 0)
+            ],
+            crt.releases()
+        );
+        assert!(crt.published_at(SemVer::new(2, 0, 0)).unwrap() > DateTime::<Utc>::default());
+        assert!(repo.is_dirty());
+        Ok(())
+    }
+
+    #[test]
+    fn normalized_on_load() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("merged", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("merged", SemVer::new(2, 0, 0))?;
+        let crt = Arc::make_mut(repo.crates.get_mut("merged").unwrap());
+        crt.release_history.push(SemVer::new(1, 5, 0));
+        crt.release_history.push(SemVer::new(1, 0, 0));
+        repo.save().unwrap();
+        drop(repo);
+
+        let mut repo = Repository::open(&store, None).unwrap();
+        assert!(repo.verify().is_empty());
+        assert!(repo.is_dirty());
+        assert_eq!(
+            &[
+                SemVer::new(1, 0, 0),
+                SemVer::new(1, 5, 0),
+                SemVer::new(2, 0, 0)
+            ],
+            repo.find_exact("merged").unwrap().releases()
+        );
+        assert_eq!(
+            Err(RepoError::DuplicateVersion),
+            repo.add_release("merged", SemVer::new(1, 5, 0))
+        );
+        assert_eq!(
+            Err(RepoError::NotMonotonic),
+            repo.add_release("merged", SemVer::new(1, 6, 0))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stale_indices() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("Indexed", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.index = SearchIndex::default();
+        repo.names_ignoring_case.clear();
+        assert_eq!(
+            vec![Problem::StaleSearchIndex, Problem::StaleCaseIndex],
+            repo.verify()
+        );
+        repo.repair();
+        assert!(repo.find_ignoring_case("indexed").is_some());
+        assert_eq!(1, repo.search("indexed").len());
+        Ok(())
+    }
+}
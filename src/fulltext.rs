@@ -0,0 +1,180 @@
+//! Full-text search over crate names, descriptions and keywords.
+//!
+//! The repository keeps an inverted index of the words in each crate's metadata, it is not
+//! persisted but rebuilt when the store is loaded. Results are ranked with BM25.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{Crate, Repository};
+
+/// BM25 term frequency saturation
+const K1: f64 = 1.2;
+/// BM25 document length normalization
+const B: f64 = 0.75;
+
+/// lowercase words of letters and digits, so `serde_json` is `serde` and `json`
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+fn document(crt: &Crate) -> Vec<String> {
+    let metadata = crt.metadata();
+    let mut words: Vec<String> = tokenize(metadata.name()).collect();
+    words.extend(tokenize(metadata.description()));
+    for keyword in metadata.keywords() {
+        words.extend(tokenize(keyword));
+    }
+    words
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchIndex {
+    /// word → crate name → number of occurrences. Names are shared with the repository.
+    postings: HashMap<String, HashMap<Arc<str>, u32>>,
+    /// crate name → number of words
+    lengths: HashMap<Arc<str>, usize>,
+}
+
+impl SearchIndex {
+    pub fn with_capacity(crates: usize) -> Self {
+        Self {
+            postings: HashMap::new(),
+            lengths: HashMap::with_capacity(crates),
+        }
+    }
+
+    pub fn build<'a>(crates: impl IntoIterator<Item = &'a Crate>) -> Self {
+        let mut index = Self::default();
+        for crt in crates {
+            index.insert(crt);
+        }
+        index
+    }
+
+    pub fn insert(&mut self, crt: &Crate) {
+        let name = &crt.metadata.name;
+        self.remove(name);
+        let words = document(crt);
+        self.lengths.insert(name.clone(), words.len());
+        for word in words {
+            *self
+                .postings
+                .entry(word)
+                .or_default()
+                .entry(name.clone())
+                .or_default() += 1;
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        if self.lengths.remove(name).is_none() {
+            return;
+        }
+        self.postings.retain(|_, docs| {
+            docs.remove(name);
+            !docs.is_empty()
+        });
+    }
+
+    /// names of crates containing any word of `query`, best match first, ties broken by name
+    pub fn search(&self, query: &str) -> Vec<(String, f64)> {
+        let documents = self.lengths.len() as f64;
+        let average_len = self.lengths.values().sum::<usize>() as f64 / documents.max(1.0);
+        let mut scores: HashMap<&Arc<str>, f64> = HashMap::new();
+        for word in tokenize(query) {
+            let docs = match self.postings.get(&word) {
+                Some(docs) => docs,
+                None => continue,
+            };
+            let found_in = docs.len() as f64;
+            let idf = (1.0 + (documents - found_in + 0.5) / (found_in + 0.5)).ln();
+            for (name, &count) in docs {
+                let tf = f64::from(count);
+                let len = self.lengths[name] as f64;
+                let norm = K1 * (1.0 - B + B * len / average_len.max(1.0));
+                *scores.entry(name).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+        let mut ranked: Vec<(String, f64)> = scores
+            .into_iter()
+            .map(|(name, score)| (name.to_string(), score))
+            .collect();
+        ranked.sort_by(|(a, a_score), (b, b_score)| {
+            b_score.total_cmp(a_score).then_with(|| a.cmp(b))
+        });
+        ranked
+    }
+}
+
+impl Repository {
+    /// Full-text search, see the [module docs](self). Best match first.
+    pub fn search(&self, query: impl AsRef<str>) -> Vec<&Crate> {
+        self.index
+            .search(query.as_ref())
+            .into_iter()
+            .filter_map(|(name, _)| self.crates.get(name.as_str()).map(Arc::as_ref))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer};
+
+    #[test]
+    fn ranks_by_relevance() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for (name, description, keywords) in [
+            (
+                "serde",
+                "A generic serialization framework",
+                vec!["serde", "no_std"],
+            ),
+            (
+                "serde_json",
+                "A JSON serialization file format",
+                vec!["json"],
+            ),
+            (
+                "tokio",
+                "An event-driven, non-blocking I/O platform",
+                vec!["async", "io"],
+            ),
+        ] {
+            let metadata = Metadata::new(name, "Busy Person", CrateKind::Library)
+                .with_description(description)
+                .with_keywords(keywords);
+            repo.add_crate(metadata, SemVer::new(1, 0, 0))?;
+        }
+
+        let names = |repo: &Repository, query| -> Vec<String> {
+            repo.search(query)
+                .iter()
+                .map(|c| c.metadata().name().to_string())
+                .collect()
+        };
+        assert_eq!(
+            vec!["serde_json", "serde"],
+            names(&repo, "JSON serialization")
+        );
+        assert_eq!(vec!["tokio"], names(&repo, "async I/O"));
+        assert!(names(&repo, "database").is_empty());
+
+        repo.edit_metadata(Metadata::new("tokio", "Busy Person", CrateKind::Library))?;
+        assert!(names(&repo, "async").is_empty());
+        repo.delete_crate("serde_json")?;
+        assert_eq!(vec!["serde"], names(&repo, "JSON serialization"));
+
+        // the index isn't stored, but rebuilt on load
+        repo.save().unwrap();
+        let loaded = Repository::open(&store, None).unwrap();
+        assert_eq!(vec!["serde"], names(&loaded, "framework"));
+        Ok(())
+    }
+}
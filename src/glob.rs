@@ -0,0 +1,49 @@
+//! Minimal glob matching for crate names: `*` matches any run of characters, `?` a single one.
+
+pub(crate) fn matches(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // position of the last `*` and the name position it currently stands for
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                // let the last `*` swallow one more character
+                Some((star, swallowed)) => {
+                    backtrack = Some((star, swallowed + 1));
+                    p = star + 1;
+                    n = swallowed + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+
+    #[test]
+    fn globs() {
+        assert!(matches("serde*json", "serde_json"));
+        assert!(matches("serde*json", "serdejson"));
+        assert!(matches("serde*", "serde"));
+        assert!(matches("*-sys", "openssl-sys"));
+        assert!(matches("tokio-?", "tokio-1"));
+        assert!(matches("*a*b*", "xaxxbx"));
+        assert!(!matches("serde*json", "serde_json5"));
+        assert!(!matches("tokio-?", "tokio-"));
+        assert!(!matches("serde", "Serde"));
+    }
+}
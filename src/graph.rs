@@ -0,0 +1,239 @@
+//! The dependency graph of crates in the repository, for visualizing which internal crates
+//! depend on which, as Graphviz DOT or JSON adjacency lists.
+//!
+//! Every crate is taken at its latest release, and every dependency at the release its
+//! requirement resolves to, see [`Crate::resolve`]. Dependencies the repository can't resolve,
+//! e.g. crates from crates.io, end up as nodes without a version and aren't followed further.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Write as _};
+
+use serde::Serialize;
+
+use crate::dependencies::DependencyKind;
+use crate::resolve::VersionReq;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// a crate at the release the graph uses
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Node {
+    pub name: String,
+    /// `None` if the crate isn't in the repository or no release matches
+    pub version: Option<SemVer>,
+}
+
+/// `name version`, or just `name` without a version
+impl Display for Node {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.version {
+            Some(version) => write!(f, "{} {}", self.name, version),
+            None => f.write_str(&self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Edge {
+    pub to: Node,
+    pub req: VersionReq,
+    pub kind: DependencyKind,
+}
+
+/// see the [module docs](self)
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    /// every node with its outgoing edges
+    nodes: BTreeMap<Node, Vec<Edge>>,
+}
+
+impl DependencyGraph {
+    pub fn nodes(&self) -> impl Iterator<Item = &Node> {
+        self.nodes.keys()
+    }
+
+    /// the dependencies of `node`, empty for nodes not in the graph
+    pub fn edges(&self, node: &Node) -> &[Edge] {
+        self.nodes.get(node).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Graphviz DOT, dev-dependencies dashed, build-dependencies dotted and unresolved
+    /// dependencies gray
+    pub fn to_dot(&self) -> String {
+        let quote = |node: &Node| format!("\"{}\"", node.to_string().replace(['"', '\\'], "_"));
+        let mut dot = String::from("digraph dependencies {\n");
+        for (node, edges) in &self.nodes {
+            match node.version {
+                Some(_) => {
+                    let _ = writeln!(dot, "    {};", quote(node));
+                }
+                None => {
+                    let _ = writeln!(dot, "    {} [color=gray];", quote(node));
+                }
+            }
+            for edge in edges {
+                let style = match edge.kind {
+                    DependencyKind::Normal => "",
+                    DependencyKind::Dev => ", style=dashed",
+                    DependencyKind::Build => ", style=dotted",
+                };
+                let _ = writeln!(
+                    dot,
+                    "    {} -> {} [label=\"{}\"{style}];",
+                    quote(node),
+                    quote(&edge.to),
+                    edge.req
+                );
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// `{"node": [{"to": "node", "req": "^1", "kind": "Normal"}]}`, nodes written like
+    /// [`Node`]'s `Display`
+    pub fn to_json(&self) -> serde_json::Value {
+        let adjacency: BTreeMap<String, Vec<serde_json::Value>> = self
+            .nodes
+            .iter()
+            .map(|(node, edges)| {
+                let edges = edges
+                    .iter()
+                    .map(|edge| {
+                        serde_json::json!({
+                            "to": edge.to.to_string(),
+                            "req": edge.req,
+                            "kind": edge.kind,
+                        })
+                    })
+                    .collect();
+                (node.to_string(), edges)
+            })
+            .collect();
+        serde_json::json!(adjacency)
+    }
+}
+
+impl Repository {
+    /// The graph of everything reachable from the crates called `roots`, or from all crates if
+    /// there are none. Fails if a root doesn't exist.
+    pub fn dependency_graph<S: AsRef<str>>(
+        &self,
+        roots: impl IntoIterator<Item = S>,
+    ) -> Result<DependencyGraph, RepoError> {
+        let node = |crt: &Crate| Node {
+            name: crt.metadata().name().to_string(),
+            version: crt.latest(),
+        };
+        let mut queue = roots
+            .into_iter()
+            .map(|root| self.find_exact(root).map(node).ok_or(RepoError::NotFound))
+            .collect::<Result<VecDeque<_>, _>>()?;
+        if queue.is_empty() {
+            queue = self.iter().map(node).collect();
+        }
+
+        let mut graph = DependencyGraph::default();
+        while let Some(from) = queue.pop_front() {
+            if graph.nodes.contains_key(&from) {
+                continue;
+            }
+            let mut edges = vec![];
+            if let (Some(version), Some(crt)) = (from.version, self.find_exact(&from.name)) {
+                for dependency in crt.dependencies(version) {
+                    let to = Node {
+                        name: dependency.name.clone(),
+                        version: self
+                            .find_exact(&dependency.name)
+                            .and_then(|dep| dep.resolve(&dependency.req, false, false).version),
+                    };
+                    queue.push_back(to.clone());
+                    edges.push(Edge {
+                        to,
+                        req: dependency.req.clone(),
+                        kind: dependency.kind,
+                    });
+                }
+            }
+            graph.nodes.insert(from, edges);
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::dependencies::Dependency;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn graph() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["hello_bin", "hello_lib", "hello_macros", "unrelated"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.add_release("hello_lib", SemVer::new(1, 1, 0))?;
+        let req = |s: &str| s.parse::<VersionReq>().unwrap();
+        repo.set_dependencies(
+            "hello_bin",
+            SemVer::new(1, 0, 0),
+            vec![
+                Dependency::new("hello_lib", req("^1")),
+                Dependency::new("serde", req("^1.0")),
+            ],
+        )?;
+        repo.set_dependencies(
+            "hello_lib",
+            SemVer::new(1, 1, 0),
+            vec![
+                Dependency::new("hello_macros", req("1")),
+                Dependency::new("hello_bin", req("*")).kind(DependencyKind::Dev),
+            ],
+        )?;
+
+        let graph = repo.dependency_graph(["hello_bin"])?;
+        let names: Vec<_> = graph.nodes().map(Node::to_string).collect();
+        assert_eq!(
+            vec![
+                "hello_bin 1.0.0",
+                "hello_lib 1.1.0",
+                "hello_macros 1.0.0",
+                "serde"
+            ],
+            names
+        );
+        assert_eq!(
+            json!({
+                "hello_bin 1.0.0": [
+                    { "to": "hello_lib 1.1.0", "req": "^1", "kind": "Normal" },
+                    { "to": "serde", "req": "^1.0", "kind": "Normal" },
+                ],
+                "hello_lib 1.1.0": [
+                    { "to": "hello_macros 1.0.0", "req": "^1", "kind": "Normal" },
+                    { "to": "hello_bin 1.0.0", "req": "*", "kind": "Dev" },
+                ],
+                "hello_macros 1.0.0": [],
+                "serde": [],
+            }),
+            graph.to_json()
+        );
+        let dot = graph.to_dot();
+        assert!(dot.contains("    \"hello_bin 1.0.0\" -> \"hello_lib 1.1.0\" [label=\"^1\"];\n"));
+        assert!(dot.contains("\"hello_bin 1.0.0\" [label=\"*\", style=dashed];"));
+        assert!(dot.contains("    \"serde\" [color=gray];\n"));
+
+        assert_eq!(
+            5,
+            repo.dependency_graph(Vec::<String>::new())?.nodes().count()
+        );
+        assert_eq!(Err(RepoError::NotFound), repo.dependency_graph(["nope"]));
+        Ok(())
+    }
+}
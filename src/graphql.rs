@@ -0,0 +1,486 @@
+//! Read-only GraphQL queries over the repository, behind the `graphql` feature.
+//!
+//! Lets UIs fetch crates together with their releases, owners and organizations in a single
+//! request. Queries resolve against a [`View`] taken up front, so they see a consistent state.
+//! Releases list the dependencies they declare, see [`crate::graph`] for following them.
+
+use std::sync::{Arc, OnceLock};
+
+use async_graphql::{
+    Context, EmptyMutation, EmptySubscription, Enum, Object, Schema, SimpleObject, Variables,
+};
+
+use crate::deprecation::Deprecation;
+use crate::license::License;
+use crate::orgs::Organization;
+use crate::source_url::SourceUrl;
+use crate::view::View;
+use crate::Repository;
+
+pub type RepoSchema = Schema<Query, EmptyMutation, EmptySubscription>;
+
+/// default and maximum number of crates per page
+const PAGE_SIZE: usize = 100;
+/// Crates link to their organization and organizations to their crates, so queries can nest
+/// without end. Deeper or more complex ones are rejected, lists of crates count once per crate.
+const MAX_DEPTH: usize = 10;
+const MAX_COMPLEXITY: usize = 5_000;
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "crate::channels::Channel")]
+enum Channel {
+    Stable,
+    Beta,
+    Nightly,
+}
+
+/// see `kindName` for the name of `OTHER` kinds
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+enum CrateKind {
+    Binary,
+    Library,
+    ProcMacro,
+    Cdylib,
+    Workspace,
+    Other,
+}
+
+impl From<&crate::CrateKind> for CrateKind {
+    fn from(kind: &crate::CrateKind) -> Self {
+        match kind {
+            crate::CrateKind::Binary => CrateKind::Binary,
+            crate::CrateKind::Library => CrateKind::Library,
+            crate::CrateKind::ProcMacro => CrateKind::ProcMacro,
+            crate::CrateKind::Cdylib => CrateKind::Cdylib,
+            crate::CrateKind::Workspace => CrateKind::Workspace,
+            crate::CrateKind::Other(_) => CrateKind::Other,
+        }
+    }
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "crate::orgs::Role")]
+enum Role {
+    Reader,
+    Publisher,
+    Admin,
+}
+
+#[derive(Enum, Debug, Clone, Copy, PartialEq, Eq)]
+#[graphql(remote = "crate::dependencies::DependencyKind")]
+enum DependencyKind {
+    Normal,
+    Dev,
+    Build,
+}
+
+#[derive(SimpleObject)]
+struct Dependency {
+    name: String,
+    /// version requirement, e.g. `^1.2`
+    req: String,
+    kind: DependencyKind,
+    optional: bool,
+}
+
+impl From<&crate::dependencies::Dependency> for Dependency {
+    fn from(dependency: &crate::dependencies::Dependency) -> Self {
+        Self {
+            name: dependency.name.clone(),
+            req: dependency.req.to_string(),
+            kind: dependency.kind.into(),
+            optional: dependency.optional,
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct DeprecationInfo {
+    message: Option<String>,
+    replacement: Option<String>,
+}
+
+impl From<&Deprecation> for DeprecationInfo {
+    fn from(deprecation: &Deprecation) -> Self {
+        Self {
+            message: deprecation.message.clone(),
+            replacement: deprecation.replacement.clone(),
+        }
+    }
+}
+
+#[derive(SimpleObject)]
+struct Release {
+    version: String,
+    /// RFC 3339
+    published_at: Option<String>,
+    yanked: bool,
+    /// by an admin, see [`crate::blocklist`]
+    blocked: bool,
+    channel: Channel,
+    /// of this version, or the whole crate
+    deprecation: Option<DeprecationInfo>,
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(SimpleObject)]
+struct Owner {
+    user: String,
+    role: Role,
+}
+
+fn members(org: &Organization) -> Vec<Owner> {
+    org.members
+        .iter()
+        .map(|(user, role)| Owner {
+            user: user.clone(),
+            role: (*role).into(),
+        })
+        .collect()
+}
+
+struct Crate {
+    catalog: Arc<View>,
+    name: String,
+}
+
+impl Crate {
+    fn get(&self) -> &crate::Crate {
+        &self.catalog.crates[self.name.as_str()]
+    }
+}
+
+#[Object]
+impl Crate {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn author(&self) -> &str {
+        self.get().metadata().author()
+    }
+
+    async fn description(&self) -> &str {
+        self.get().metadata().description()
+    }
+
+    async fn keywords(&self) -> &[String] {
+        self.get().metadata().keywords()
+    }
+
+    /// SPDX license expression
+    async fn license(&self) -> Option<&str> {
+        self.get().metadata().license().map(License::as_str)
+    }
+
+    async fn repository(&self) -> Option<&str> {
+        self.get().metadata().repository().map(SourceUrl::as_str)
+    }
+
+    async fn homepage(&self) -> Option<&str> {
+        self.get().metadata().homepage().map(SourceUrl::as_str)
+    }
+
+    async fn kind(&self) -> CrateKind {
+        self.get().metadata().kind().into()
+    }
+
+    /// e.g. `proc-macro`, also for kinds the `kind` enum lacks
+    async fn kind_name(&self) -> String {
+        self.get().metadata().kind().to_string()
+    }
+
+    async fn downloads(&self) -> u64 {
+        self.get().downloads()
+    }
+
+    /// the newest release that isn't yanked, in `channel` or a more stable one
+    async fn latest(
+        &self,
+        #[graphql(default_with = "Channel::Stable")] channel: Channel,
+    ) -> Option<String> {
+        self.get().latest_in(channel.into()).map(|v| v.to_string())
+    }
+
+    /// oldest first
+    async fn releases(&self) -> Vec<Release> {
+        let crt = self.get();
+        crt.releases()
+            .iter()
+            .map(|version| Release {
+                version: version.to_string(),
+                published_at: crt.published_at(*version).map(|at| at.to_rfc3339()),
+                yanked: crt.is_yanked(*version),
+                blocked: crt.is_blocked(*version),
+                channel: crt.channel(*version).into(),
+                deprecation: crt.version_deprecation(*version).map(Into::into),
+                dependencies: crt.dependencies(*version).iter().map(Into::into).collect(),
+            })
+            .collect()
+    }
+
+    async fn deprecation(&self) -> Option<DeprecationInfo> {
+        self.get().deprecation().map(Into::into)
+    }
+
+    /// members of the crate's organization, or its author
+    async fn owners(&self) -> Vec<Owner> {
+        let metadata = self.get().metadata();
+        match metadata.org().and_then(|org| self.catalog.orgs.get(org)) {
+            Some(org) => members(org),
+            None => vec![Owner {
+                user: metadata.author().to_string(),
+                role: Role::Admin,
+            }],
+        }
+    }
+
+    async fn organization(&self) -> Option<Org> {
+        let name = self.get().metadata().org()?;
+        self.catalog.orgs.contains_key(name).then(|| Org {
+            catalog: self.catalog.clone(),
+            name: name.to_string(),
+        })
+    }
+}
+
+struct Org {
+    catalog: Arc<View>,
+    name: String,
+}
+
+#[Object(name = "Organization")]
+impl Org {
+    async fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn members(&self) -> Vec<Owner> {
+        members(&self.catalog.orgs[&self.name])
+    }
+
+    /// the organization's crates, paged like `Query.crates`
+    #[graphql(complexity = "page_size(first) * child_complexity")]
+    async fn crates(&self, after: Option<String>, first: Option<usize>) -> Vec<Crate> {
+        let org = Some(self.name.as_str());
+        page(&self.catalog, after, first, |crt| {
+            crt.metadata().org() == org
+        })
+    }
+}
+
+/// the number of crates a page holds at most, `first` if it's smaller than [`PAGE_SIZE`]
+fn page_size(first: Option<usize>) -> usize {
+    first.unwrap_or(PAGE_SIZE).min(PAGE_SIZE)
+}
+
+/// crates sorted by name after the crate called `after`, as many as fit in a page
+fn page(
+    catalog: &Arc<View>,
+    after: Option<String>,
+    first: Option<usize>,
+    filter: impl Fn(&crate::Crate) -> bool,
+) -> Vec<Crate> {
+    let start = match &after {
+        Some(after) => std::ops::Bound::Excluded(after.as_str()),
+        None => std::ops::Bound::Unbounded,
+    };
+    catalog
+        .crates
+        .range::<str, _>((start, std::ops::Bound::Unbounded))
+        .filter(|(_, crt)| filter(crt))
+        .take(page_size(first))
+        .map(|(name, _)| Crate {
+            catalog: catalog.clone(),
+            name: name.to_string(),
+        })
+        .collect()
+}
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    #[graphql(name = "crate")]
+    async fn krate(&self, ctx: &Context<'_>, name: String) -> Option<Crate> {
+        let catalog = ctx.data_unchecked::<Arc<View>>();
+        catalog.crates.contains_key(name.as_str()).then(|| Crate {
+            catalog: catalog.clone(),
+            name,
+        })
+    }
+
+    /// Crates sorted by name, optionally only those whose name contains `contains`.
+    /// Pages start after the crate called `after`, and hold at most 100 crates.
+    #[graphql(complexity = "page_size(first) * child_complexity")]
+    async fn crates(
+        &self,
+        ctx: &Context<'_>,
+        contains: Option<String>,
+        after: Option<String>,
+        first: Option<usize>,
+    ) -> Vec<Crate> {
+        let catalog = ctx.data_unchecked::<Arc<View>>();
+        page(catalog, after, first, |crt| {
+            contains
+                .as_ref()
+                .is_none_or(|part| crt.metadata().name().contains(part.as_str()))
+        })
+    }
+
+    async fn organization(&self, ctx: &Context<'_>, name: String) -> Option<Org> {
+        let catalog = ctx.data_unchecked::<Arc<View>>();
+        catalog.orgs.contains_key(&name).then(|| Org {
+            catalog: catalog.clone(),
+            name,
+        })
+    }
+}
+
+/// the schema, built on first use
+pub fn schema() -> &'static RepoSchema {
+    static SCHEMA: OnceLock<RepoSchema> = OnceLock::new();
+    SCHEMA.get_or_init(|| {
+        Schema::build(Query, EmptyMutation, EmptySubscription)
+            .limit_depth(MAX_DEPTH)
+            .limit_complexity(MAX_COMPLEXITY)
+            .finish()
+    })
+}
+
+impl Repository {
+    /// Executes a GraphQL `query` with JSON `variables`, answering with a standard GraphQL
+    /// response of `data` and `errors`. See [`schema`] for what can be queried.
+    pub fn graphql(&self, query: &str, variables: serde_json::Value) -> serde_json::Value {
+        self.view().graphql(query, variables)
+    }
+}
+
+impl View {
+    /// like [`Repository::graphql`], for queries that shouldn't hold on to the repository
+    pub fn graphql(&self, query: &str, variables: serde_json::Value) -> serde_json::Value {
+        let catalog = Arc::new(self.clone());
+        let request = async_graphql::Request::new(query)
+            .variables(Variables::from_json(variables))
+            .data(catalog);
+        let response = pollster::block_on(schema().execute(request));
+        serde_json::to_value(response).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::dependencies::{Dependency, DependencyKind};
+    use crate::{Metadata, RepoError, SemVer};
+
+    #[test]
+    fn query() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", crate::CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.yank("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.add_crate(
+            Metadata::new("hello_moon", "Lazy Person", crate::CrateKind::Library),
+            SemVer::new(0, 1, 0),
+        )?;
+        let req = "^0.1".parse().unwrap();
+        repo.set_dependencies(
+            "hello_bin",
+            SemVer::new(1, 0, 0),
+            vec![Dependency::new("hello_moon", req).kind(DependencyKind::Build)],
+        )
+        .unwrap();
+
+        let response = repo.graphql(
+            "query($name: String!) {
+                crate(name: $name) {
+                    kind latest
+                    releases { version yanked channel }
+                    owners { user role }
+                }
+                crates(after: \"hello_bin\") { name }
+                missing: crate(name: \"stuxnet\") { name }
+            }",
+            json!({ "name": "hello_bin" }),
+        );
+        assert_eq!(
+            json!({
+                "crate": {
+                    "kind": "BINARY",
+                    "latest": "1.0.0",
+                    "releases": [
+                        { "version": "1.0.0", "yanked": false, "channel": "STABLE" },
+                        { "version": "1.1.0", "yanked": true, "channel": "STABLE" },
+                    ],
+                    "owners": [{ "user": "Busy Person", "role": "ADMIN" }],
+                },
+                "crates": [{ "name": "hello_moon" }],
+                "missing": null,
+            }),
+            response["data"]
+        );
+
+        let response = repo.graphql(
+            "{ crate(name: \"hello_bin\") { releases { dependencies { name req kind optional } } } }",
+            json!({}),
+        );
+        assert_eq!(
+            json!({
+                "crate": {
+                    "releases": [
+                        {
+                            "dependencies": [
+                                { "name": "hello_moon", "req": "^0.1", "kind": "BUILD", "optional": false },
+                            ]
+                        },
+                        { "dependencies": [] },
+                    ],
+                },
+            }),
+            response["data"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn limits() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.create_org("acme", "Busy Person")?;
+        for name in ["acme_a", "acme_b", "acme_c"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", crate::CrateKind::Library).with_org("acme"),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+
+        let response = repo.graphql(
+            "{ organization(name: \"acme\") { crates(after: \"acme_a\", first: 1) { name } } }",
+            json!({}),
+        );
+        assert_eq!(
+            json!({ "organization": { "crates": [{ "name": "acme_b" }] } }),
+            response["data"]
+        );
+
+        // every level multiplies the number of crates
+        let response = repo.graphql(
+            "{ crates { organization { crates { organization { crates { name } } } } } }",
+            json!({}),
+        );
+        assert!(response["data"].is_null());
+        assert!(response["errors"][0]["message"]
+            .as_str()
+            .unwrap()
+            .contains("complex"));
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+//! gRPC transport for the most common requests, behind the `grpc` feature.
+//!
+//! The service is defined in `proto/registry.proto`. Every call is translated into the matching
+//! [`ApiRequest`] and handled exactly like one arriving over TCP, including authentication,
+//! quotas, auditing and webhooks. Users authenticate with `authorization: Bearer <token>`
+//! metadata. Enable it with [`crate::server::ServerConfig::grpc_listen`], clients can use the
+//! generated [`RegistryClient`].
+
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{ReceiverStream, TcpListenerStream};
+use tonic::{Code, Request, Response, Status};
+
+use crate::api::{ApiError, ApiRequest, ApiResult, CrateSummary};
+use crate::events;
+use crate::kinds::ParseKindError;
+use crate::license::LicenseError;
+use crate::server::{self, Shared, ShutdownHandle};
+use crate::source_url::SourceUrlError;
+use crate::{CrateKind, Metadata, RepoError, SemVer};
+
+/// the generated messages, server and client
+pub mod proto {
+    tonic::include_proto!("semver_repo");
+}
+
+pub use proto::registry_client::RegistryClient;
+use proto::registry_server::{Registry, RegistryServer};
+
+/// how often a shutdown is checked for
+const SHUTDOWN_POLL: Duration = Duration::from_millis(100);
+
+impl From<SemVer> for proto::SemVer {
+    fn from(version: SemVer) -> Self {
+        Self {
+            major: version.major(),
+            minor: version.minor(),
+            patch: version.patch(),
+        }
+    }
+}
+
+/// `version` of a request, which has to be given
+fn required_version(version: Option<proto::SemVer>) -> Result<SemVer, Status> {
+    let version = version.ok_or_else(|| Status::invalid_argument("missing version"))?;
+    Ok(SemVer::new(version.major, version.minor, version.patch))
+}
+
+impl From<&Metadata> for proto::Metadata {
+    fn from(metadata: &Metadata) -> Self {
+        let (kind, other_kind) = match metadata.kind() {
+            CrateKind::Binary => (proto::CrateKind::Binary, None),
+            CrateKind::Library => (proto::CrateKind::Library, None),
+            CrateKind::ProcMacro => (proto::CrateKind::ProcMacro, None),
+            CrateKind::Cdylib => (proto::CrateKind::Cdylib, None),
+            CrateKind::Workspace => (proto::CrateKind::Workspace, None),
+            CrateKind::Other(name) => (proto::CrateKind::Other, Some(name.clone())),
+        };
+        Self {
+            name: metadata.name().to_string(),
+            author: metadata.author().to_string(),
+            kind: kind.into(),
+            description: metadata.description().to_string(),
+            keywords: metadata.keywords().to_vec(),
+            org: metadata.org().map(String::from),
+            other_kind,
+            license: metadata.license().map(ToString::to_string),
+            repository: metadata.repository().map(ToString::to_string),
+            homepage: metadata.homepage().map(ToString::to_string),
+        }
+    }
+}
+
+impl TryFrom<Option<proto::Metadata>> for Metadata {
+    type Error = Status;
+
+    fn try_from(metadata: Option<proto::Metadata>) -> Result<Self, Self::Error> {
+        let metadata = metadata.ok_or_else(|| Status::invalid_argument("missing metadata"))?;
+        let kind = match proto::CrateKind::try_from(metadata.kind) {
+            Ok(proto::CrateKind::Binary) => CrateKind::Binary,
+            Ok(proto::CrateKind::Library) => CrateKind::Library,
+            Ok(proto::CrateKind::ProcMacro) => CrateKind::ProcMacro,
+            Ok(proto::CrateKind::Cdylib) => CrateKind::Cdylib,
+            Ok(proto::CrateKind::Workspace) => CrateKind::Workspace,
+            Ok(proto::CrateKind::Other) => metadata
+                .other_kind
+                .as_deref()
+                .unwrap_or_default()
+                .parse()
+                .map_err(|e: ParseKindError| Status::invalid_argument(e.to_string()))?,
+            Err(_) => return Err(Status::invalid_argument("unknown crate kind")),
+        };
+        let mut converted = Metadata::new(metadata.name, metadata.author, kind)
+            .with_description(metadata.description)
+            .with_keywords(metadata.keywords);
+        if let Some(org) = metadata.org {
+            converted = converted.with_org(org);
+        }
+        let invalid = |e: String| Status::invalid_argument(e);
+        if let Some(license) = metadata.license {
+            let license = license
+                .parse()
+                .map_err(|e: LicenseError| invalid(e.to_string()))?;
+            converted = converted.with_license(license);
+        }
+        if let Some(url) = metadata.repository {
+            let url = url
+                .parse()
+                .map_err(|e: SourceUrlError| invalid(e.to_string()))?;
+            converted = converted.with_repository(url);
+        }
+        if let Some(url) = metadata.homepage {
+            let url = url
+                .parse()
+                .map_err(|e: SourceUrlError| invalid(e.to_string()))?;
+            converted = converted.with_homepage(url);
+        }
+        Ok(converted)
+    }
+}
+
+impl From<&crate::Crate> for proto::Crate {
+    fn from(crt: &crate::Crate) -> Self {
+        Self {
+            metadata: Some(crt.metadata().into()),
+            releases: crt
+                .releases()
+                .iter()
+                .map(|version| proto::Release {
+                    version: Some((*version).into()),
+                    yanked: crt.is_yanked(*version),
+                    published_at: crt
+                        .published_at(*version)
+                        .map(|at| at.to_rfc3339())
+                        .unwrap_or_default(),
+                })
+                .collect(),
+            revision: crt.revision(),
+        }
+    }
+}
+
+impl From<CrateSummary<'_>> for proto::CrateSummary {
+    fn from(summary: CrateSummary) -> Self {
+        Self {
+            name: summary.name.into_owned(),
+            author: summary.author.into_owned(),
+            latest: summary.latest.map(Into::into),
+        }
+    }
+}
+
+impl From<&events::Change> for proto::Change {
+    fn from(change: &events::Change) -> Self {
+        Self {
+            seq: change.seq,
+            at: change.at.to_rfc3339(),
+            event_json: serde_json::to_string(&change.event).unwrap_or_default(),
+        }
+    }
+}
+
+fn status(e: ApiError) -> Status {
+    let code = match &e {
+        ApiError::Internal => Code::Internal,
+        ApiError::Unauthorized => Code::Unauthenticated,
+        ApiError::Repo(RepoError::NotFound) | ApiError::UnknownRegistry(_) => Code::NotFound,
+        ApiError::Repo(RepoError::AlreadyExists | RepoError::DuplicateVersion) => {
+            Code::AlreadyExists
+        }
+        ApiError::Repo(RepoError::Forbidden | RepoError::NotOwner)
+        | ApiError::PolicyViolation(_)
+        | ApiError::OutOfScope(_) => Code::PermissionDenied,
+        ApiError::Repo(_) | ApiError::InvalidPattern(_) | ApiError::NoCrate => {
+            Code::InvalidArgument
+        }
+        ApiError::ReadOnly
+        | ApiError::Conflict { .. }
+        | ApiError::IdempotencyKeyReused
+        | ApiError::InvalidSettings(_)
+        | ApiError::InvalidConfirmation
+        | ApiError::ConfirmationRequired
+        | ApiError::NotDryRunnable
+        | ApiError::DependenciesRejected(_) => Code::FailedPrecondition,
+        ApiError::QuotaExceeded(_) => Code::ResourceExhausted,
+        ApiError::Upstream(_) | ApiError::Overloaded => Code::Unavailable,
+        ApiError::BatchAborted { .. } => Code::Aborted,
+    };
+    Status::new(code, e.to_string())
+}
+
+#[derive(Clone)]
+struct RegistryService {
+    shared: Arc<Shared>,
+    shutdown: ShutdownHandle,
+}
+
+impl RegistryService {
+    /// handles `api` on behalf of whoever sent `request`
+    async fn call<T: DeserializeOwned, R>(
+        &self,
+        request: &Request<R>,
+        api: ApiRequest,
+    ) -> Result<T, Status> {
+        let peer = request
+            .remote_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_else(|| "unknown".to_string());
+        let token = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        let api = match token {
+            Some(token) => ApiRequest::Authenticated {
+                token: token.to_string(),
+                request: Box::new(api),
+            },
+            None => api,
+        };
+        let shared = self.shared.clone();
+        // the repository is behind a blocking lock, and subscriptions wait for changes
+        let response =
+            tokio::task::spawn_blocking(move || server::dispatch(api, &peer, &shared, false))
+                .await
+                .map_err(|_| Status::internal("request handler panicked"))?;
+        let result: ApiResult<T> =
+            serde_json::from_str(&response).map_err(|_| Status::internal("unexpected response"))?;
+        result.map_err(status)
+    }
+}
+
+#[tonic::async_trait]
+impl Registry for RegistryService {
+    async fn find_exact(
+        &self,
+        request: Request<proto::FindExactRequest>,
+    ) -> Result<Response<proto::FindExactResponse>, Status> {
+        let name = request.get_ref().name.clone();
+        let crt: Option<crate::Crate> = self.call(&request, ApiRequest::FindExact(name)).await?;
+        Ok(Response::new(proto::FindExactResponse {
+            found: crt.as_ref().map(Into::into),
+        }))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SummaryList>, Status> {
+        let query = request.get_ref().query.clone();
+        let crates: Vec<CrateSummary> = self.call(&request, ApiRequest::Search(query)).await?;
+        Ok(Response::new(proto::SummaryList {
+            crates: crates.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn add_crate(
+        &self,
+        request: Request<proto::AddCrateRequest>,
+    ) -> Result<Response<proto::AddResponse>, Status> {
+        let metadata = Metadata::try_from(request.get_ref().metadata.clone())?;
+        let version = required_version(request.get_ref().version)?;
+        self.call::<(), _>(&request, ApiRequest::AddCrate(metadata, version))
+            .await?;
+        Ok(Response::new(proto::AddResponse {}))
+    }
+
+    async fn add_release(
+        &self,
+        request: Request<proto::AddReleaseRequest>,
+    ) -> Result<Response<proto::AddResponse>, Status> {
+        let name = request.get_ref().name.clone();
+        let version = required_version(request.get_ref().version)?;
+        self.call::<(), _>(&request, ApiRequest::AddRelease(name, version))
+            .await?;
+        Ok(Response::new(proto::AddResponse {}))
+    }
+
+    type SubscribeStream = ReceiverStream<Result<proto::Change, Status>>;
+
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let (tx, rx) = mpsc::channel(64);
+        let mut since = request.get_ref().since;
+        let service = self.clone();
+        tokio::spawn(async move {
+            // each round waits for changes until the subscribe timeout
+            while !tx.is_closed() && !service.shutdown.is_shutdown() {
+                let changes: Vec<events::Change> = match service
+                    .call(&request, ApiRequest::Subscribe { since })
+                    .await
+                {
+                    Ok(changes) => changes,
+                    Err(status) => {
+                        let _ = tx.send(Err(status)).await;
+                        return;
+                    }
+                };
+                for change in &changes {
+                    since = change.seq;
+                    if tx.send(Ok(change.into())).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        Ok(Response::new(ReceiverStream::new(rx)))
+    }
+}
+
+/// Serves gRPC on `listener` until `shutdown`, blocking the calling thread.
+pub(crate) fn serve(
+    listener: TcpListener,
+    shared: Arc<Shared>,
+    shutdown: ShutdownHandle,
+) -> std::io::Result<()> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    let service = RegistryService {
+        shared,
+        shutdown: shutdown.clone(),
+    };
+    let result = runtime.block_on(async {
+        listener.set_nonblocking(true)?;
+        let incoming = TcpListenerStream::new(tokio::net::TcpListener::from_std(listener)?);
+        let stopped = async {
+            while !shutdown.is_shutdown() {
+                tokio::time::sleep(SHUTDOWN_POLL).await;
+            }
+        };
+        tonic::transport::Server::builder()
+            .add_service(RegistryServer::new(service))
+            .serve_with_incoming_shutdown(incoming, stopped)
+            .await
+            .map_err(std::io::Error::other)
+    });
+    // don't wait for subscriptions still blocked on the repository
+    runtime.shutdown_timeout(SHUTDOWN_POLL);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::thread;
+
+    use super::*;
+    use crate::server::{Server, ServerConfig};
+
+    #[test]
+    fn grpc() -> Result<(), Box<dyn std::error::Error>> {
+        let store_dir = tempfile::tempdir()?;
+        let mut config = ServerConfig::new(store_dir.path().join("store.json"))
+            .with_listen(vec![SocketAddr::from(([127, 0, 0, 1], 0))]);
+        config.grpc_listen = Some(SocketAddr::from(([127, 0, 0, 1], 0)));
+        config.subscribe_timeout = Duration::from_millis(200);
+        let server = Server::bind(config)?;
+        let addr = server.grpc_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let thread = thread::spawn(move || server.serve());
+
+        let component = Metadata::new("component", "someone", CrateKind::Other("wasm".into()))
+            .with_license("MIT OR Apache-2.0".parse()?)
+            .with_repository("https://example.com/component.git".parse()?);
+        let converted: Metadata = Some(proto::Metadata::from(&component)).try_into()?;
+        assert_eq!(component, converted);
+
+        let runtime = tokio::runtime::Runtime::new()?;
+        runtime.block_on(async {
+            let mut client = RegistryClient::connect(format!("http://{addr}")).await?;
+            let metadata = Metadata::new("hello_bin", "Busy Person", CrateKind::Binary);
+            client
+                .add_crate(proto::AddCrateRequest {
+                    metadata: Some((&metadata).into()),
+                    version: Some(SemVer::new(1, 0, 0).into()),
+                })
+                .await?;
+            let duplicate = client
+                .add_crate(proto::AddCrateRequest {
+                    metadata: Some((&metadata).into()),
+                    version: Some(SemVer::new(1, 0, 0).into()),
+                })
+                .await;
+            assert_eq!(Code::AlreadyExists, duplicate.unwrap_err().code());
+            client
+                .add_release(proto::AddReleaseRequest {
+                    name: "hello_bin".to_string(),
+                    version: Some(SemVer::new(1, 1, 0).into()),
+                })
+                .await?;
+
+            let found = client
+                .find_exact(proto::FindExactRequest {
+                    name: "hello_bin".to_string(),
+                })
+                .await?
+                .into_inner()
+                .found
+                .unwrap();
+            assert_eq!(Some((&metadata).into()), found.metadata);
+            assert_eq!(2, found.releases.len());
+
+            let mut changes = client
+                .subscribe(proto::SubscribeRequest { since: 1 })
+                .await?
+                .into_inner();
+            let change = changes.message().await?.unwrap();
+            assert_eq!(2, change.seq);
+            assert!(change.event_json.contains("ReleaseAdded"));
+            Ok::<_, Box<dyn std::error::Error>>(())
+        })?;
+
+        shutdown.shutdown();
+        thread.join().unwrap()?;
+        Ok(())
+    }
+}
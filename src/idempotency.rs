@@ -0,0 +1,98 @@
+//! Replaying responses of retried requests instead of executing them twice.
+//!
+//! Clients wrap a request in [`crate::api::ApiRequest::Idempotent`] with a key of their choosing,
+//! e.g. a random UUID per logical operation. The server remembers the response for that key, so
+//! a retry after a lost response gets the original answer rather than `AlreadyExists`.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::api::ApiError;
+
+#[derive(Debug)]
+struct Entry {
+    at: Instant,
+    request: String,
+    response: String,
+}
+
+/// Responses by idempotency key, forgotten after `ttl` or when more than `capacity` are stored.
+#[derive(Debug)]
+pub struct IdempotencyCache {
+    ttl: Duration,
+    capacity: usize,
+    entries: HashMap<String, Entry>,
+}
+
+impl IdempotencyCache {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            entries: HashMap::new(),
+        }
+    }
+
+    /// The response recorded for `key`, if any. Reusing a key for a different request is an
+    /// error, as replaying would answer a question that wasn't asked.
+    pub fn replay(&self, key: &str, request: &str) -> Option<Result<String, ApiError>> {
+        let entry = self
+            .entries
+            .get(key)
+            .filter(|entry| entry.at.elapsed() < self.ttl)?;
+        if entry.request != request {
+            return Some(Err(ApiError::IdempotencyKeyReused));
+        }
+        Some(Ok(entry.response.clone()))
+    }
+
+    pub fn record(&mut self, key: String, request: String, response: String) {
+        let ttl = self.ttl;
+        self.entries.retain(|_, entry| entry.at.elapsed() < ttl);
+        while self.entries.len() >= self.capacity {
+            let oldest = match self.entries.iter().min_by_key(|(_, entry)| entry.at) {
+                Some((key, _)) => key.clone(),
+                None => break,
+            };
+            self.entries.remove(&oldest);
+        }
+        self.entries.insert(
+            key,
+            Entry {
+                at: Instant::now(),
+                request,
+                response,
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replays_and_evicts() {
+        let mut cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        assert!(cache.replay("a", "req").is_none());
+
+        cache.record("a".to_string(), "req".to_string(), "res".to_string());
+        assert_eq!(
+            Some("res".to_string()),
+            cache.replay("a", "req").unwrap().ok()
+        );
+        assert!(matches!(
+            cache.replay("a", "other"),
+            Some(Err(ApiError::IdempotencyKeyReused))
+        ));
+
+        cache.record("b".to_string(), "req".to_string(), "res".to_string());
+        cache.record("c".to_string(), "req".to_string(), "res".to_string());
+        assert!(cache.replay("a", "req").is_none());
+        assert!(cache.replay("c", "req").is_some());
+
+        let mut cache = IdempotencyCache::new(Duration::ZERO, 2);
+        cache.record("a".to_string(), "req".to_string(), "res".to_string());
+        assert!(cache.replay("a", "req").is_none());
+    }
+}
@@ -0,0 +1,244 @@
+//! Backfilling historical releases when migrating from another registry.
+//!
+//! Unlike regular publishing, imports may be out of order, so they're only available to admins,
+//! see [`crate::admin::AdminRequest::ImportReleases`].
+
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::events::Event;
+use crate::{Crate, Metadata, ParseError, RepoError, Repository, SemVer};
+
+/// a historical release
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Release {
+    pub version: SemVer,
+    /// when it was originally published, the time of the import if unknown
+    #[serde(default)]
+    pub published_at: Option<DateTime<Utc>>,
+}
+
+impl Release {
+    pub fn new(version: SemVer) -> Self {
+        Self {
+            version,
+            published_at: None,
+        }
+    }
+}
+
+/// Versions like `1.2.3`, or also like `v1.2` if `lenient`, see [`SemVer::parse_lenient`]
+pub fn parse_versions(
+    versions: impl IntoIterator<Item = impl AsRef<str>>,
+    lenient: bool,
+) -> Result<Vec<SemVer>, ParseError> {
+    versions
+        .into_iter()
+        .map(|version| {
+            let version = version.as_ref();
+            match lenient {
+                true => SemVer::parse_lenient(version),
+                false => version.parse(),
+            }
+        })
+        .collect()
+}
+
+/// [`parse_versions`], as releases without a publishing time
+pub fn parse_releases(
+    versions: impl IntoIterator<Item = impl AsRef<str>>,
+    lenient: bool,
+) -> Result<Vec<Release>, ParseError> {
+    let versions = parse_versions(versions, lenient)?;
+    Ok(versions.into_iter().map(Release::new).collect())
+}
+
+impl Crate {
+    /// merges `releases` into the history, returns how many of them were new
+    pub(crate) fn import_releases(&mut self, releases: &[Release], at: DateTime<Utc>) -> usize {
+        self.published_at
+            .resize(self.release_history.len(), DateTime::default());
+        let mut history: Vec<(SemVer, DateTime<Utc>)> = self
+            .release_history
+            .iter()
+            .copied()
+            .zip(self.published_at.iter().copied())
+            .collect();
+        let before = history.len();
+        for release in releases {
+            if !history.iter().any(|(v, _)| *v == release.version) {
+                history.push((release.version, release.published_at.unwrap_or(at)));
+            }
+        }
+        let imported = history.len() - before;
+        if imported > 0 {
+            history.sort_by_key(|(version, _)| *version);
+            (self.release_history, self.published_at) = history.into_iter().unzip();
+            self.revision += 1;
+        }
+        imported
+    }
+}
+
+impl Repository {
+    /// Adds `releases` to the crate called `name` in any order, skipping versions that exist
+    /// already. The history ends up sorted by version. Returns the number of added releases.
+    pub fn import_releases(
+        &mut self,
+        name: impl AsRef<str>,
+        mut releases: Vec<Release>,
+    ) -> Result<usize, RepoError> {
+        let name = name.as_ref();
+        let crt = self
+            .crates
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        releases.sort_by_key(|release| release.version);
+        releases.dedup_by_key(|release| release.version);
+        releases.retain(|release| !crt.release_history.contains(&release.version));
+        if releases.is_empty() {
+            return Ok(0);
+        }
+        let seq = self.changes.record(Event::ReleasesImported {
+            name: crt.metadata.name.clone(),
+            releases: releases.clone(),
+        });
+        // dated like the change, so followers end up with the same history
+        let at = self.changes.since(seq - 1)[0].at;
+        Ok(crt.import_releases(&releases, at))
+    }
+
+    /// Adds a crate with `releases` in any order, like [`Repository::add_crate`] followed by
+    /// [`Repository::import_releases`], except that the oldest release keeps its date as well.
+    /// Returns the number of added releases.
+    pub fn import_crate(
+        &mut self,
+        metadata: Metadata,
+        mut releases: Vec<Release>,
+    ) -> Result<usize, RepoError> {
+        releases.sort_by_key(|release| release.version);
+        let first = releases.first().ok_or(RepoError::InvalidVersion)?;
+        let name = metadata.name.clone();
+        self.insert_crate(metadata, first.version, first.published_at)?;
+        Ok(1 + self.import_releases(name, releases)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::admin::{AdminRequest, AdminResponse};
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn import() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(2, 0, 0),
+        )?;
+
+        let long_ago = DateTime::parse_from_rfc3339("2015-05-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let releases = vec![
+            Release::new(SemVer::new(1, 1, 0)),
+            Release {
+                version: SemVer::new(1, 0, 0),
+                published_at: Some(long_ago),
+            },
+            Release::new(SemVer::new(1, 1, 0)),
+            Release::new(SemVer::new(2, 0, 0)),
+        ];
+        assert_eq!(2, repo.import_releases("hello_bin", releases.clone())?);
+        assert_eq!(0, repo.import_releases("hello_bin", releases)?);
+
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!(
+            &[
+                SemVer::new(1, 0, 0),
+                SemVer::new(1, 1, 0),
+                SemVer::new(2, 0, 0)
+            ],
+            crt.releases()
+        );
+        assert_eq!(Some(long_ago), crt.published_at(SemVer::new(1, 0, 0)));
+        assert_eq!(Some(SemVer::new(2, 0, 0)), crt.latest());
+        // regular publishing stays monotonic
+        assert_eq!(
+            Err(RepoError::NotMonotonic),
+            repo.add_release("hello_bin", SemVer::new(1, 2, 0))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn import_crate() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let long_ago = DateTime::parse_from_rfc3339("2015-05-15T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        let releases = vec![
+            Release::new(SemVer::new(1, 1, 0)),
+            Release {
+                version: SemVer::new(1, 0, 0),
+                published_at: Some(long_ago),
+            },
+        ];
+        let metadata = Metadata::new("hello_bin", "Busy Person", CrateKind::Binary);
+        assert_eq!(2, repo.import_crate(metadata, releases)?);
+
+        let follower_store = NamedTempFile::new().unwrap();
+        let mut follower = Repository::new(&follower_store);
+        for change in repo.changes().since(0).to_vec() {
+            follower.apply(change)?;
+        }
+        for repo in [&repo, &follower] {
+            let crt = repo.find_exact("hello_bin").unwrap();
+            assert_eq!(Some(long_ago), crt.published_at(SemVer::new(1, 0, 0)));
+            assert_eq!(Some(SemVer::new(1, 1, 0)), crt.latest());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn import_versions() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(2, 0, 0),
+        )?;
+        let versions = || vec!["v1.2".to_string(), "1.0.0".to_string()];
+        assert!(parse_releases(versions(), false).is_err());
+        assert_eq!(
+            vec![
+                Release::new(SemVer::new(1, 2, 0)),
+                Release::new(SemVer::new(1, 0, 0))
+            ],
+            parse_releases(versions(), true).unwrap()
+        );
+
+        let import = |lenient| AdminRequest::ImportVersions {
+            name: "hello_bin".to_string(),
+            versions: versions(),
+            lenient,
+        };
+        assert_eq!(
+            Err(RepoError::InvalidVersion),
+            repo.handle_admin(import(false))
+        );
+        assert_eq!(
+            Ok(AdminResponse::Imported { releases: 2 }),
+            repo.handle_admin(import(true))
+        );
+        Ok(())
+    }
+}
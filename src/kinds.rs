@@ -0,0 +1,285 @@
+//! Rules depending on a crate's [`CrateKind`], configured per repository with
+//! [`Repository::set_kind_policy`], e.g. binaries that may republish a version or libraries that
+//! need a description. They apply to new crates and releases, existing crates aren't checked.
+//!
+//! Kinds are stored by name, `Binary`, `Library`, `ProcMacro`, `Cdylib`, `Workspace` or that of
+//! an [`CrateKind::Other`] kind, so stores remain readable by builds knowing other kinds.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+use crate::{CrateKind, Metadata, RepoError, Repository, VersionPolicy};
+
+/// Everything off by default, i.e. the repository's version policy applies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KindPolicy {
+    /// replaces the repository's version policy for crates of the kind
+    pub version_policy: Option<VersionPolicy>,
+    /// publishing an existing version again replaces it, un-yanking it, instead of failing
+    pub allow_republish: bool,
+    /// new crates and releases are rejected with [`RepoError::DescriptionRequired`] without one
+    pub require_description: bool,
+}
+
+/// longest name of an [`CrateKind::Other`] kind
+const MAX_KIND_LEN: usize = 32;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum ParseKindError {
+    #[error("invalid crate kind '{0}', expected up to 32 letters, digits, '-' or '_'")]
+    Kind(String),
+    #[error("unknown kind policy '{0}', expected strict, unique, republish or description")]
+    Policy(String),
+    #[error("invalid kind policies '{0}', expected e.g. binary:republish;library:description")]
+    Policies(String),
+}
+
+impl CrateKind {
+    /// A kind called `name`, one of the known kinds if it names one, e.g. `proc-macro`, ignoring
+    /// case. Other names are kept as they are.
+    pub fn other(name: impl AsRef<str>) -> Result<Self, ParseKindError> {
+        let name = name.as_ref();
+        let known = match name.to_lowercase().replace('_', "-").as_str() {
+            "binary" | "bin" => Some(CrateKind::Binary),
+            "library" | "lib" => Some(CrateKind::Library),
+            "procmacro" | "proc-macro" => Some(CrateKind::ProcMacro),
+            "cdylib" => Some(CrateKind::Cdylib),
+            "workspace" => Some(CrateKind::Workspace),
+            _ => None,
+        };
+        if let Some(known) = known {
+            return Ok(known);
+        }
+        let valid = !name.is_empty()
+            && name.len() <= MAX_KIND_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        match valid {
+            true => Ok(CrateKind::Other(name.to_string())),
+            false => Err(ParseKindError::Kind(name.to_string())),
+        }
+    }
+
+    /// as stored
+    pub fn as_str(&self) -> &str {
+        match self {
+            CrateKind::Binary => "Binary",
+            CrateKind::Library => "Library",
+            CrateKind::ProcMacro => "ProcMacro",
+            CrateKind::Cdylib => "Cdylib",
+            CrateKind::Workspace => "Workspace",
+            CrateKind::Other(name) => name,
+        }
+    }
+}
+
+/// see [`CrateKind::other`]
+impl FromStr for CrateKind {
+    type Err = ParseKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CrateKind::other(s)
+    }
+}
+
+impl Display for CrateKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CrateKind::Binary => write!(f, "binary"),
+            CrateKind::Library => write!(f, "library"),
+            CrateKind::ProcMacro => write!(f, "proc-macro"),
+            CrateKind::Cdylib => write!(f, "cdylib"),
+            CrateKind::Workspace => write!(f, "workspace"),
+            CrateKind::Other(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+impl Serialize for CrateKind {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for CrateKind {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        CrateKind::other(name).map_err(serde::de::Error::custom)
+    }
+}
+
+/// comma separated, e.g. `unique,republish`, or empty for the default
+impl FromStr for KindPolicy {
+    type Err = ParseKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = KindPolicy::default();
+        for part in s.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+            match part {
+                "strict" => policy.version_policy = Some(VersionPolicy::StrictlyIncreasing),
+                "unique" => policy.version_policy = Some(VersionPolicy::Unique),
+                "republish" => policy.allow_republish = true,
+                "description" => policy.require_description = true,
+                _ => return Err(ParseKindError::Policy(part.to_string())),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+/// policies of several kinds separated by `;`, e.g. `binary:unique,republish;library:description`
+pub fn parse_kind_policies(s: &str) -> Result<Vec<(CrateKind, KindPolicy)>, ParseKindError> {
+    s.split(';')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| {
+            let (kind, policy) = entry
+                .split_once(':')
+                .ok_or_else(|| ParseKindError::Policies(s.to_string()))?;
+            Ok((kind.trim().parse()?, policy.parse()?))
+        })
+        .collect()
+}
+
+impl Repository {
+    pub fn kind_policy(&self, kind: &CrateKind) -> KindPolicy {
+        self.kind_policies.get(kind).copied().unwrap_or_default()
+    }
+
+    /// applies to crates and releases added from now on
+    pub fn set_kind_policy(&mut self, kind: CrateKind, policy: KindPolicy) {
+        self.mark_dirty();
+        match policy == KindPolicy::default() {
+            true => self.kind_policies.remove(&kind),
+            false => self.kind_policies.insert(kind, policy),
+        };
+    }
+
+    /// the version policy releases of crates of `kind` are checked against
+    pub fn version_policy_for(&self, kind: &CrateKind) -> VersionPolicy {
+        self.kind_policy(kind)
+            .version_policy
+            .unwrap_or(self.version_policy)
+    }
+
+    /// whether crates described by `metadata` may get new releases
+    pub(crate) fn check_kind_policy(&self, metadata: &Metadata) -> Result<(), RepoError> {
+        let policy = self.kind_policy(&metadata.kind);
+        if policy.require_description && metadata.description.trim().is_empty() {
+            return Err(RepoError::DescriptionRequired);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::SemVer;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Ok(vec![
+                (
+                    CrateKind::Binary,
+                    KindPolicy {
+                        version_policy: Some(VersionPolicy::Unique),
+                        allow_republish: true,
+                        require_description: false,
+                    }
+                ),
+                (
+                    CrateKind::Library,
+                    KindPolicy {
+                        require_description: true,
+                        ..KindPolicy::default()
+                    }
+                ),
+            ]),
+            parse_kind_policies("binary:unique,republish; Library:description")
+        );
+        assert_eq!(Ok(vec![]), parse_kind_policies(""));
+        assert!(parse_kind_policies("binary").is_err());
+        assert!(parse_kind_policies("no kind:unique").is_err());
+        assert!(parse_kind_policies("binary:lenient").is_err());
+    }
+
+    #[test]
+    fn kinds() {
+        for (name, kind) in [
+            ("bin", CrateKind::Binary),
+            ("Proc_Macro", CrateKind::ProcMacro),
+            ("cdylib", CrateKind::Cdylib),
+            ("wasm-component", CrateKind::Other("wasm-component".into())),
+        ] {
+            assert_eq!(Ok(kind.clone()), name.parse(), "{}", name);
+            assert_eq!(Ok(kind.clone()), kind.to_string().parse());
+        }
+        for invalid in ["", "two words", &"x".repeat(33)] {
+            assert!(invalid.parse::<CrateKind>().is_err(), "{}", invalid);
+        }
+
+        // stores name the kinds like before they could be anything
+        let kinds = vec![
+            CrateKind::Library,
+            CrateKind::ProcMacro,
+            CrateKind::Other("wasm-component".into()),
+        ];
+        let json = serde_json::to_string(&kinds).unwrap();
+        assert_eq!(r#"["Library","ProcMacro","wasm-component"]"#, json);
+        assert_eq!(
+            kinds,
+            serde_json::from_str::<Vec<CrateKind>>(&json).unwrap()
+        );
+        assert!(serde_json::from_str::<CrateKind>(r#""no kind""#).is_err());
+    }
+
+    #[test]
+    fn policies_by_kind() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(store.path());
+        repo.set_kind_policy(
+            CrateKind::Binary,
+            KindPolicy {
+                allow_republish: true,
+                ..KindPolicy::default()
+            },
+        );
+        repo.set_kind_policy(
+            CrateKind::Library,
+            KindPolicy {
+                require_description: true,
+                ..KindPolicy::default()
+            },
+        );
+
+        let tool = Metadata::new("tool", "someone", CrateKind::Binary);
+        repo.add_crate(tool, SemVer::new(1, 0, 0))?;
+        repo.yank("tool", SemVer::new(1, 0, 0))?;
+        repo.add_release("tool", SemVer::new(1, 0, 0))?;
+        let crt = repo.find_exact("tool").unwrap();
+        assert_eq!(&[SemVer::new(1, 0, 0)], crt.releases());
+        assert!(!crt.is_yanked(SemVer::new(1, 0, 0)));
+        assert_eq!(3, repo.changes().last_seq());
+
+        let lib = Metadata::new("lib", "someone", CrateKind::Library);
+        assert_eq!(
+            Err(RepoError::DescriptionRequired),
+            repo.add_crate(lib.clone(), SemVer::new(1, 0, 0))
+        );
+        repo.add_crate(lib.with_description("a library"), SemVer::new(1, 0, 0))?;
+        assert_eq!(
+            Err(RepoError::DuplicateVersion),
+            repo.add_release("lib", SemVer::new(1, 0, 0))
+        );
+        assert!(repo.verify().is_empty());
+        Ok(())
+    }
+}
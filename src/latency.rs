@@ -0,0 +1,115 @@
+//! Summarizing measured latencies, e.g. of `client ping`.
+
+use std::fmt;
+use std::time::Duration;
+
+/// Measured latencies, in no particular order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Latencies {
+    samples: Vec<Duration>,
+}
+
+impl Latencies {
+    pub fn record(&mut self, latency: Duration) {
+        self.samples.push(latency);
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+
+    /// the latency `p` percent of the samples are at most, nearest rank
+    pub fn percentile(&self, p: f64) -> Option<Duration> {
+        let mut sorted = self.samples.clone();
+        sorted.sort();
+        let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted.get(rank.clamp(1, sorted.len().max(1)) - 1).copied()
+    }
+
+    /// How many samples fall into each bucket, by the bucket's upper bound. Bounds double from
+    /// 100µs until the slowest sample fits.
+    pub fn histogram(&self) -> Vec<(Duration, usize)> {
+        let Some(max) = self.samples.iter().max() else {
+            return vec![];
+        };
+        let mut buckets = vec![(Duration::from_micros(100), 0)];
+        while buckets.last().is_some_and(|(bound, _)| bound < max) {
+            let bound = buckets
+                .last()
+                .map(|(bound, _)| *bound * 2)
+                .unwrap_or_default();
+            buckets.push((bound, 0));
+        }
+        for sample in &self.samples {
+            if let Some((_, count)) = buckets.iter_mut().find(|(bound, _)| sample <= bound) {
+                *count += 1;
+            }
+        }
+        buckets
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        let total: Duration = self.samples.iter().sum();
+        Some(total / u32::try_from(self.samples.len()).ok().filter(|n| *n > 0)?)
+    }
+}
+
+impl Extend<Duration> for Latencies {
+    fn extend<I: IntoIterator<Item = Duration>>(&mut self, iter: I) {
+        self.samples.extend(iter);
+    }
+}
+
+/// `min`, `p50`, `p90`, `p99` and `max` in milliseconds
+impl fmt::Display for Latencies {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |p| {
+            self.percentile(p)
+                .map(|latency: Duration| latency.as_secs_f64() * 1000.0)
+                .unwrap_or_default()
+        };
+        write!(
+            f,
+            "min {:.3}ms  p50 {:.3}ms  p90 {:.3}ms  p99 {:.3}ms  max {:.3}ms",
+            ms(0.0),
+            ms(50.0),
+            ms(90.0),
+            ms(99.0),
+            ms(100.0)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles() {
+        let mut latencies = Latencies::default();
+        assert_eq!(None, latencies.percentile(50.0));
+        assert_eq!(None, latencies.mean());
+        latencies.extend((1..=100).rev().map(Duration::from_millis));
+        assert_eq!(Some(Duration::from_millis(1)), latencies.percentile(0.0));
+        assert_eq!(Some(Duration::from_millis(50)), latencies.percentile(50.0));
+        assert_eq!(Some(Duration::from_millis(99)), latencies.percentile(99.0));
+        assert_eq!(
+            Some(Duration::from_millis(100)),
+            latencies.percentile(100.0)
+        );
+        assert_eq!(Some(Duration::from_micros(50_500)), latencies.mean());
+        let histogram = latencies.histogram();
+        assert_eq!(
+            Some(&(Duration::from_micros(102_400), 49)),
+            histogram.last()
+        );
+        assert_eq!(100, histogram.iter().map(|(_, count)| count).sum::<usize>());
+        assert!(latencies
+            .to_string()
+            .starts_with("min 1.000ms  p50 50.000ms"));
+    }
+}
@@ -1,180 +1,272 @@
 use std::{
-    collections::HashMap,
-    convert::{Infallible, TryFrom},
-    fmt::Display,
-    fs::File,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
     hash::Hash,
-    num::ParseIntError,
+    io::{Read, Write},
+    ops::Bound,
     path::{Path, PathBuf},
-    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
 };
 
+use audit::AuditLog;
+use chrono::{DateTime, Utc};
+use compression::Compression;
+use encryption::StoreKey;
+use events::{ChangeLog, Event};
+use fulltext::SearchIndex;
+use license::License;
+use names::{NameRules, Namespaces};
+use orgs::Organization;
+use search::SearchOptions;
 use serde::{Deserialize, Serialize};
+use shards::Sharding;
+use source_url::SourceUrl;
+
+/// starts a [`telemetry::span`] lasting until the end of the enclosing block, if built with the
+/// `otel` feature
+macro_rules! span {
+    ($name:expr) => {
+        #[cfg(feature = "otel")]
+        let _span = crate::telemetry::span($name);
+    };
+}
+
+pub mod access_log;
+pub mod admin;
+pub mod advisories;
 pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod backpressure;
+pub mod bench;
+pub mod blobs;
+pub mod blocklist;
+pub mod changelog;
+pub mod channels;
+pub mod client;
+pub mod compression;
+pub mod confirmation;
+pub mod convert;
+pub mod dependencies;
+pub mod deprecation;
+pub mod dump;
+pub mod encryption;
+pub mod events;
+pub mod export;
+pub mod features;
+pub mod feed;
+pub mod fsck;
+pub mod fulltext;
+mod glob;
+pub mod graph;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod idempotency;
+pub mod import;
+pub mod kinds;
+pub mod latency;
+pub mod license;
+pub mod lockfile;
+pub mod names;
+pub mod net;
+pub mod orgs;
+pub mod panics;
+pub mod platform;
+pub mod policy;
+pub mod preflight;
+pub mod query;
+pub mod quota;
+pub mod readme;
+pub mod registries;
+pub mod render;
+pub mod replication;
+pub mod resolve;
+pub mod retention;
+pub mod scheduler;
+pub mod search;
+pub mod server;
+pub mod settings;
+pub mod shards;
+pub mod source_url;
+pub mod sparse_index;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+pub mod transaction;
+pub mod upstream;
+pub mod view;
+pub mod webhooks;
+#[cfg(feature = "websocket")]
+pub mod websocket;
+
+pub use semver_core::{ParseError, SemVer};
+
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// shared with the repository's key for the crate
+    name: Arc<str>,
+    /// shared by all crates of the author, see [`Repository::intern_author`]
+    author: Arc<str>,
+    kind: CrateKind,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    keywords: Vec<String>,
+    /// organization owning the crate, see [`orgs`]
+    #[serde(default)]
+    org: Option<String>,
+    #[serde(default)]
+    license: Option<License>,
+    /// where the source lives
+    #[serde(default)]
+    repository: Option<SourceUrl>,
+    #[serde(default)]
+    homepage: Option<SourceUrl>,
+}
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Debug, Clone, Copy, Hash, Serialize, Deserialize)]
-pub struct SemVer {
-    major: u16,
-    minor: u16,
-    patch: u16,
+/// longest crate name [`Metadata::try_new`] accepts, in bytes
+pub const MAX_NAME_LEN: usize = 128;
+/// longest author [`Metadata::try_new`] accepts, in bytes
+pub const MAX_AUTHOR_LEN: usize = 128;
+
+/// `field` trimmed, if that is neither empty, longer than `max_len` nor contains control
+/// characters
+fn checked_field(field: &str, max_len: usize) -> Option<&str> {
+    let field = field.trim();
+    (!field.is_empty() && field.len() <= max_len && !field.chars().any(char::is_control))
+        .then_some(field)
 }
 
-impl SemVer {
-    pub fn new(major: u16, minor: u16, patch: u16) -> SemVer {
-        SemVer {
-            major,
-            minor,
-            patch,
+impl Metadata {
+    /// Unchecked, see [`Metadata::try_new`] for names and authors from users.
+    pub fn new(name: impl AsRef<str>, author: impl AsRef<str>, kind: CrateKind) -> Self {
+        Self {
+            name: name.as_ref().into(),
+            author: author.as_ref().into(),
+            kind,
+            description: String::new(),
+            keywords: vec![],
+            org: None,
+            license: None,
+            repository: None,
+            homepage: None,
         }
     }
 
-    fn new_short(major: u16) -> SemVer {
-        Self::new(major, 0, 0)
+    /// Like [`Metadata::new`], but trims `name` and `author` and fails with
+    /// [`RepoError::InvalidName`] or [`RepoError::InvalidAuthor`] if they end up empty, longer
+    /// than [`MAX_NAME_LEN`] or [`MAX_AUTHOR_LEN`], or contain control characters.
+    pub fn try_new(
+        name: impl AsRef<str>,
+        author: impl AsRef<str>,
+        kind: CrateKind,
+    ) -> Result<Self, RepoError> {
+        let name = checked_field(name.as_ref(), MAX_NAME_LEN).ok_or(RepoError::InvalidName)?;
+        let author =
+            checked_field(author.as_ref(), MAX_AUTHOR_LEN).ok_or(RepoError::InvalidAuthor)?;
+        Ok(Self::new(name, author, kind))
     }
-}
 
-impl Default for SemVer {
-    fn default() -> Self {
-        Self::new_short(1)
+    /// the same metadata with name and author checked by [`Metadata::try_new`], e.g. after
+    /// receiving it
+    pub fn validated(self) -> Result<Self, RepoError> {
+        let checked = Self::try_new(&self.name, &self.author, self.kind.clone())?;
+        Ok(Self {
+            name: checked.name,
+            author: checked.author,
+            ..self
+        })
     }
-}
 
-impl Display for SemVer {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    pub fn with_description(mut self, description: impl AsRef<str>) -> Self {
+        self.description = description.as_ref().to_string();
+        self
     }
-}
 
-// auto-implements Into<SemVer> for &str
-impl From<&str> for SemVer {
-    fn from(s: &str) -> Self {
-        let vs: Vec<u16> = s.split(".").filter_map(|item| item.parse().ok()).collect();
-        assert!(vs.len() == 3);
-        SemVer {
-            major: vs[0],
-            minor: vs[1],
-            patch: vs[2],
-        }
+    pub fn with_keywords(mut self, keywords: impl IntoIterator<Item = impl AsRef<str>>) -> Self {
+        self.keywords = keywords
+            .into_iter()
+            .map(|k| k.as_ref().to_string())
+            .collect();
+        self
     }
-}
 
-#[derive(thiserror::Error, Debug)]
-pub enum ParseError {
-    #[error("wrong number of parts, {0} (expected: 3)")]
-    WrongNumberOfParts(usize),
-    #[error("could not parse integer")]
-    ParseInt(#[from] ParseIntError),
-}
-
-// impl std::fmt::Display for ParseError {
-//     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-//         f.write_str("parse error")
-//     }
-// }
-
-// impl std::error::Error for ParseError {}
-
-// impl From<ParseIntError> for ParseError {
-//     fn from(e: ParseIntError) -> Self {
-//         ParseError::ParseInt(e)
-//     }
-// }
-
-impl FromStr for SemVer {
-    type Err = ParseError;
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split(".").collect();
-        let num_parts = parts.len();
-        if num_parts != 3 {
-            return Err(ParseError::WrongNumberOfParts(num_parts));
-        }
-        let res = SemVer {
-            major: parts[0].parse()?,
-            minor: parts[1].parse()?,
-            patch: parts[2].parse()?,
-        };
-
-        Ok(res)
+    pub fn with_org(mut self, org: impl AsRef<str>) -> Self {
+        self.org = Some(org.as_ref().to_string());
+        self
     }
-}
 
-impl TryFrom<String> for SemVer {
-    type Error = ParseError;
+    /// e.g. `"MIT OR Apache-2.0".parse()?`, see [`license`]
+    pub fn with_license(mut self, license: License) -> Self {
+        self.license = Some(license);
+        self
+    }
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        value.parse()
+    pub fn with_repository(mut self, repository: SourceUrl) -> Self {
+        self.repository = Some(repository);
+        self
     }
-}
 
-impl From<[u16; 3]> for SemVer {
-    fn from(value: [u16; 3]) -> Self {
-        SemVer {
-            major: value[0],
-            minor: value[1],
-            patch: value[2],
-        }
+    pub fn with_homepage(mut self, homepage: SourceUrl) -> Self {
+        self.homepage = Some(homepage);
+        self
     }
-}
 
-#[derive(Debug, Serialize, Deserialize)]
-struct FileURL(String);
+    /// Get a reference to the metadata's name.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        self.name.as_ref()
+    }
 
-#[derive(Debug)]
-enum FileURLError {
-    InvalidScheme,
-    EmptyPath,
-}
+    /// Get a reference to the metadata's author.
+    #[must_use]
+    pub fn author(&self) -> &str {
+        self.author.as_ref()
+    }
 
-impl TryFrom<String> for FileURL {
-    type Error = FileURLError;
+    /// Get the metadata's kind.
+    #[must_use]
+    pub fn kind(&self) -> &CrateKind {
+        &self.kind
+    }
 
-    fn try_from(value: String) -> Result<Self, Self::Error> {
-        if !value.starts_with("file://") {
-            return Err(FileURLError::InvalidScheme);
-        }
-        if value.len() < 8 {
-            return Err(FileURLError::EmptyPath);
-        }
-        Ok(Self(value))
+    /// Get a reference to the metadata's description, empty if there is none.
+    #[must_use]
+    pub fn description(&self) -> &str {
+        self.description.as_ref()
     }
-}
 
-impl AsRef<Path> for FileURL {
-    fn as_ref(&self) -> &Path {
-        let inner_string = &self.0;
-        let path_part = &inner_string[7..];
-        Path::new(path_part)
+    #[must_use]
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
     }
-}
 
-#[derive(Debug, Hash, Clone, Serialize, Deserialize)]
-pub struct Metadata {
-    name: String,
-    author: String,
-    kind: CrateKind,
-    // repo: FileURL,
-}
+    #[must_use]
+    pub fn org(&self) -> Option<&str> {
+        self.org.as_deref()
+    }
 
-impl Metadata {
-    pub fn new(name: impl AsRef<str>, author: impl AsRef<str>, kind: CrateKind) -> Self {
-        Self {
-            name: name.as_ref().to_string(),
-            author: author.as_ref().to_string(),
-            kind,
-        }
+    #[must_use]
+    pub fn license(&self) -> Option<&License> {
+        self.license.as_ref()
     }
 
-    /// Get a reference to the metadata's name.
     #[must_use]
-    pub fn name(&self) -> &str {
-        self.name.as_ref()
+    pub fn repository(&self) -> Option<&SourceUrl> {
+        self.repository.as_ref()
     }
 
-    /// Get a reference to the metadata's author.
     #[must_use]
-    pub fn author(&self) -> &str {
-        self.author.as_ref()
+    pub fn homepage(&self) -> Option<&SourceUrl> {
+        self.homepage.as_ref()
     }
 }
 
@@ -182,6 +274,49 @@ impl Metadata {
 pub struct Crate {
     metadata: Metadata,
     release_history: Vec<SemVer>,
+    #[serde(default)]
+    yanked: Vec<SemVer>,
+    /// bumped on every change to the crate, for optimistic concurrency
+    #[serde(default = "first_revision")]
+    revision: u64,
+    /// publication time of each release in `release_history`.
+    /// Releases from stores predating timestamps are dated to the unix epoch.
+    #[serde(default)]
+    published_at: Vec<DateTime<Utc>>,
+    #[serde(default)]
+    downloads: u64,
+    /// releases published to a channel other than [`channels::Channel::Stable`]
+    #[serde(default)]
+    channels: Vec<(SemVer, channels::Channel)>,
+    #[serde(default)]
+    deprecation: Option<deprecation::Deprecation>,
+    #[serde(default)]
+    deprecated_versions: Vec<(SemVer, deprecation::Deprecation)>,
+    /// see [`changelog`]
+    #[serde(default)]
+    release_notes: Vec<(SemVer, String)>,
+    /// see [`readme`]
+    #[serde(default)]
+    readmes: Vec<(SemVer, blobs::BlobId)>,
+    /// see [`platform`]
+    #[serde(default)]
+    platforms: Vec<(SemVer, platform::Platform)>,
+    /// see [`features`]
+    #[serde(default)]
+    features: Vec<(SemVer, features::Features)>,
+    /// see [`dependencies`]
+    #[serde(default)]
+    dependencies: Vec<(SemVer, Vec<dependencies::Dependency>)>,
+    /// see [`advisories`]
+    #[serde(default)]
+    advisories: Vec<advisories::Advisory>,
+    /// see [`blocklist`]
+    #[serde(default)]
+    blocked: Vec<blocklist::BlockedVersion>,
+}
+
+fn first_revision() -> u64 {
+    1
 }
 
 impl Crate {
@@ -189,22 +324,116 @@ impl Crate {
         Self {
             metadata,
             release_history: vec![],
+            yanked: vec![],
+            revision: first_revision(),
+            published_at: vec![],
+            downloads: 0,
+            channels: vec![],
+            deprecation: None,
+            deprecated_versions: vec![],
+            release_notes: vec![],
+            readmes: vec![],
+            platforms: vec![],
+            features: vec![],
+            dependencies: vec![],
+            advisories: vec![],
+            blocked: vec![],
         }
     }
 
+    #[must_use]
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// all published releases, oldest first, including yanked ones
+    #[must_use]
+    pub fn releases(&self) -> &[SemVer] {
+        &self.release_history
+    }
+
+    /// the newest stable release that hasn't been yanked, see [`Crate::latest_in`]
+    #[must_use]
+    pub fn latest(&self) -> Option<SemVer> {
+        self.latest_in(channels::Channel::Stable)
+    }
+
+    /// when `version` was published
+    #[must_use]
+    pub fn published_at(&self, version: SemVer) -> Option<DateTime<Utc>> {
+        let index = self.release_history.iter().position(|v| *v == version)?;
+        Some(self.published_at.get(index).copied().unwrap_or_default())
+    }
+
+    /// how often the crate has been fetched, see [`Repository::record_download`]
+    #[must_use]
+    pub fn downloads(&self) -> u64 {
+        self.downloads
+    }
+
+    /// Starts at 1 and increases with every change. Crates that don't exist are at revision 0.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// adds a release newer than all existing ones, see [`VersionPolicy::StrictlyIncreasing`]
     pub fn add_release(&mut self, release: SemVer) -> Result<(), RepoError> {
-        let is_newer_hence_valid = self
-            .release_history
-            .last()
-            .map(|v| &release > v)
-            .unwrap_or(true);
-
-        if is_newer_hence_valid {
-            self.release_history.push(release);
-            Ok(())
-        } else {
-            Err(RepoError::InvalidVersion)
+        self.add_release_at(release, Utc::now(), VersionPolicy::StrictlyIncreasing)
+    }
+
+    /// like [`Crate::add_release`], for releases published at time `at` and validated by `policy`
+    pub(crate) fn add_release_at(
+        &mut self,
+        release: SemVer,
+        at: DateTime<Utc>,
+        policy: VersionPolicy,
+    ) -> Result<(), RepoError> {
+        if self.release_history.contains(&release) {
+            return Err(RepoError::DuplicateVersion);
+        }
+        if policy == VersionPolicy::StrictlyIncreasing
+            && self.release_history.iter().any(|v| *v > release)
+        {
+            return Err(RepoError::NotMonotonic);
         }
+        self.push_release(release, at);
+        self.revision += 1;
+        Ok(())
+    }
+
+    /// appends without validation, e.g. the initial release of a new crate
+    pub(crate) fn push_release(&mut self, release: SemVer, at: DateTime<Utc>) {
+        self.published_at
+            .resize(self.release_history.len(), DateTime::default());
+        self.release_history.push(release);
+        self.published_at.push(at);
+    }
+
+    /// publishes `release` again at time `at`, un-yanking it, see [`kinds::KindPolicy`]
+    pub(crate) fn republish(&mut self, release: SemVer, at: DateTime<Utc>) {
+        if let Some(index) = self.release_history.iter().position(|v| *v == release) {
+            self.published_at
+                .resize(self.release_history.len(), DateTime::default());
+            self.published_at[index] = at;
+        }
+        self.yanked.retain(|v| *v != release);
+        self.revision += 1;
+    }
+
+    /// Marks a published release as yanked. It stays in the history, yanking twice is a no-op.
+    pub fn yank(&mut self, version: SemVer) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        if !self.is_yanked(version) {
+            self.yanked.push(version);
+            self.revision += 1;
+        }
+        Ok(())
+    }
+
+    pub fn is_yanked(&self, version: SemVer) -> bool {
+        self.yanked.contains(&version)
     }
 }
 
@@ -221,16 +450,91 @@ impl PartialEq for Crate {
 
 impl Eq for Crate {}
 
-#[derive(Debug, Hash, Clone, Copy, Serialize, Deserialize)]
+/// Which versions may be added to a crate, see [`Repository::set_version_policy`]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VersionPolicy {
+    /// every release has to be newer than all previous ones
+    #[default]
+    StrictlyIncreasing,
+    /// any version not published before, e.g. backporting 1.4.9 after 2.0.0
+    Unique,
+}
+
+/// What a crate builds, see [`kinds`] for rules depending on the kind. Stored by name, so stores
+/// written by builds knowing more kinds read back as [`CrateKind::Other`].
+#[derive(Debug, Hash, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum CrateKind {
     Binary,
     Library,
+    ProcMacro,
+    /// a C-compatible dynamic library
+    Cdylib,
+    /// a virtual manifest grouping other crates
+    Workspace,
+    /// Any other kind, validated by [`CrateKind::other`]. Never the name of one of the kinds
+    /// above.
+    Other(String),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Repository {
-    crates: HashMap<String, Crate>,
+    /// sorted by name, for prefix lookups. Keys are the names of the crates' metadata.
+    /// Sharded stores keep them in separate files.
+    #[serde(default)]
+    crates: BTreeMap<Arc<str>, Arc<Crate>>,
     store: PathBuf,
+    #[serde(default)]
+    changes: ChangeLog,
+    #[serde(default)]
+    audit: AuditLog,
+    /// old name → current name of renamed crates
+    #[serde(default)]
+    aliases: BTreeMap<String, String>,
+    #[serde(default)]
+    namespaces: Namespaces,
+    #[serde(default)]
+    name_rules: NameRules,
+    #[serde(default)]
+    version_policy: VersionPolicy,
+    /// see [`kinds`]
+    #[serde(default)]
+    kind_policies: BTreeMap<CrateKind, kinds::KindPolicy>,
+    #[serde(default)]
+    orgs: BTreeMap<String, Organization>,
+    #[serde(default)]
+    tokens: auth::Tokens,
+    /// rebuilt on load, see [`fulltext`]
+    #[serde(skip)]
+    index: SearchIndex,
+    /// lowercase name → name, rebuilt on load. Of names differing only in case, the smallest
+    /// wins.
+    #[serde(skip)]
+    names_ignoring_case: HashMap<String, Arc<str>>,
+    /// every author once, shared by their crates' metadata. Rebuilt on load.
+    #[serde(skip)]
+    authors: HashSet<Arc<str>>,
+    /// modifications since loading that don't show up in the change feed, like downloads
+    #[serde(skip)]
+    mutations: u64,
+    /// the mutation count at the last save, see [`Repository::is_dirty`]
+    #[serde(skip)]
+    saved: AtomicU64,
+    /// how the store is written, see [`compression`]
+    #[serde(skip)]
+    compression: Compression,
+    /// encrypts the store if set, see [`encryption`]
+    #[serde(skip)]
+    key: Option<StoreKey>,
+    /// splits the store into a directory of files, see [`shards`]
+    #[serde(default)]
+    sharding: Option<Sharding>,
+    /// saves into an SQLite database instead of files, see `semver_repo::sqlite`
+    #[serde(skip)]
+    sqlite: bool,
+    /// the crates as of the last save of a sharded or SQLite store, `None` if every crate has to
+    /// be written
+    #[serde(skip)]
+    saved_crates: Mutex<Option<BTreeMap<Arc<str>, Arc<Crate>>>>,
 }
 
 #[derive(thiserror::Error, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -239,74 +543,671 @@ pub enum RepoError {
     NotFound,
     #[error("invalid version")]
     InvalidVersion,
+    #[error("version already published")]
+    DuplicateVersion,
+    #[error("version not newer than all published ones")]
+    NotMonotonic,
     #[error("already exists")]
     AlreadyExists,
+    #[error("invalid name")]
+    InvalidName,
+    #[error("invalid author")]
+    InvalidAuthor,
+    #[error("unknown namespace")]
+    UnknownNamespace,
+    #[error("not an owner of the namespace")]
+    NotOwner,
+    #[error("not permitted")]
+    Forbidden,
+    #[error("name is reserved")]
+    NameReserved,
+    #[error("description required")]
+    DescriptionRequired,
+    #[error("release notes too long")]
+    ReleaseNotesTooLong,
+    #[error("README too long")]
+    ReadmeTooLong,
+    #[error("invalid target")]
+    InvalidTarget,
+    #[error("invalid feature")]
+    InvalidFeature,
+    #[error("invalid dependency")]
+    InvalidDependency,
+    #[error("invalid advisory")]
+    InvalidAdvisory,
+}
+
+/// why [`Repository::open`] failed
+#[derive(thiserror::Error, Debug)]
+pub enum StoreError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid store: {0}")]
+    Invalid(#[from] serde_json::Error),
+    #[error("the store is encrypted, but no key was given")]
+    Encrypted,
+    #[error("the store can't be decrypted with the given key")]
+    WrongKey,
+    #[error("the store is an SQLite database, but this build lacks the sqlite feature")]
+    SqliteUnsupported,
+    #[error("the store already holds a repository")]
+    Exists,
+}
+
+/// Renames `store` to `<store>.corrupt`, so an empty repository can take its place without
+/// replacing it. Returns the new path.
+pub(crate) fn move_aside(store: &Path) -> std::io::Result<PathBuf> {
+    let mut aside = store.as_os_str().to_owned();
+    aside.push(".corrupt");
+    std::fs::rename(store, &aside)?;
+    Ok(aside.into())
 }
 
 impl Repository {
+    /// Starts an empty repository to be saved at `store`, without looking at what is there.
+    /// Its first save replaces the store, so use [`Repository::open`] to load a store and
+    /// [`Repository::create`] to refuse replacing one.
     pub fn new(store: impl AsRef<Path>) -> Self {
-        let maybe_contents: Option<Self> = match File::open(&store) {
-            Ok(f) => serde_json::from_reader(f).ok(),
-            Err(_) => None,
+        Self::empty(store)
+    }
+
+    /// Creates an empty repository to be saved at `store`, failing with [`StoreError::Exists`]
+    /// if the store already holds one, so it can't be replaced by accident.
+    pub fn create(store: impl AsRef<Path>) -> Result<Self, StoreError> {
+        let repo = Self::open(store, None)?;
+        // nothing was ever published there
+        match repo.crates.is_empty() && repo.changes.last_seq() == 0 {
+            true => Ok(repo),
+            false => Err(StoreError::Exists),
+        }
+    }
+
+    /// Loads the repository saved at `store`, decrypting it with `key`, see [`encryption`]. A
+    /// missing or empty store gives an empty repository. With a `key`, the store is saved
+    /// encrypted, unencrypted stores are encrypted on the next save.
+    /// A directory is opened as a sharded store, see [`shards`], an SQLite database or a missing
+    /// store ending in `.sqlite` as an SQLite store, see `semver_repo::sqlite`.
+    pub fn open(store: impl AsRef<Path>, key: Option<StoreKey>) -> Result<Self, StoreError> {
+        if is_sqlite(store.as_ref()) {
+            #[cfg(feature = "sqlite")]
+            return Self::open_sqlite(store.as_ref(), key);
+            #[cfg(not(feature = "sqlite"))]
+            return Err(StoreError::SqliteUnsupported);
+        }
+        if store.as_ref().is_dir() {
+            return Self::open_sharded(store.as_ref(), key);
+        }
+        let Some((contents, encrypted)) = read_store_file(store.as_ref(), key.as_ref())? else {
+            let mut repo = Self::empty(store);
+            repo.key = key;
+            return Ok(repo);
         };
+        // much faster than deserializing from the file directly
+        let mut repo: Self = serde_json::from_slice(&contents)?;
+        repo.reindex_all();
+        repo.normalize_histories();
+        if key.is_some() && !encrypted {
+            repo.mark_dirty();
+        }
+        repo.key = key;
+        Ok(repo)
+    }
 
-        maybe_contents.unwrap_or(Self {
-            crates: HashMap::new(),
+    fn empty(store: impl AsRef<Path>) -> Self {
+        Self {
+            crates: BTreeMap::new(),
             store: store.as_ref().into(),
-        })
+            changes: ChangeLog::default(),
+            audit: AuditLog::default(),
+            aliases: BTreeMap::new(),
+            namespaces: Namespaces::new(),
+            name_rules: NameRules::default(),
+            version_policy: VersionPolicy::default(),
+            kind_policies: BTreeMap::new(),
+            orgs: BTreeMap::new(),
+            tokens: auth::Tokens::default(),
+            index: SearchIndex::default(),
+            names_ignoring_case: HashMap::new(),
+            authors: HashSet::new(),
+            mutations: 0,
+            saved: AtomicU64::new(0),
+            compression: Compression::default(),
+            key: None,
+            sharding: None,
+            sqlite: false,
+            saved_crates: Mutex::new(None),
+        }
     }
 
-    /// exact search
+    /// Like [`Repository::new`], but with room for `crates` crates in an empty repository.
+    /// Saves growing the indices when populating it, see [`Repository::bulk_load`].
+    pub fn with_capacity(store: impl AsRef<Path>, crates: usize) -> Self {
+        let mut repo = Self::new(store);
+        repo.index = SearchIndex::with_capacity(crates);
+        repo.names_ignoring_case.reserve(crates);
+        repo.changes.reserve(crates);
+        repo
+    }
+
+    /// all crates, ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = &Crate> {
+        self.crates.values().map(Arc::as_ref)
+    }
+
+    /// same as [`Repository::iter`], combine with [`query::CrateQuery`] for reports
+    pub fn crates(&self) -> impl Iterator<Item = &Crate> {
+        self.iter()
+    }
+
+    /// exact search, which also finds renamed crates by their old name; check the returned crate's
+    /// name to tell
     pub fn find_exact(&self, name: impl AsRef<str>) -> Option<&Crate> {
-        self.crates.get(name.as_ref())
+        let name = name.as_ref();
+        self.crates
+            .get(name)
+            .or_else(|| self.crates.get(self.aliases.get(name)?.as_str()))
+            .map(Arc::as_ref)
     }
 
-    /// case insensitive substring search, may return multiple results
-    pub fn find_containing(&self, name_part: impl AsRef<str>) -> Vec<&Crate> {
+    /// the current name of a crate that used to be called `name`
+    pub fn resolve_alias(&self, name: impl AsRef<str>) -> Option<&str> {
+        self.aliases.get(name.as_ref()).map(String::as_str)
+    }
+
+    /// case insensitive substring search, may return multiple results, ordered and paged by
+    /// `options`
+    pub fn find_containing(
+        &self,
+        name_part: impl AsRef<str>,
+        options: SearchOptions,
+    ) -> Vec<&Crate> {
         let name_part_lower = name_part.as_ref().to_lowercase();
         let mut res = vec![];
 
         for (k, v) in self.crates.iter() {
-            if k.to_lowercase().contains(&name_part_lower) {
-                res.push(v);
+            if contains_ignoring_case(k, &name_part_lower) {
+                res.push(v.as_ref());
             }
         }
         // the same iteration as
         // for kv in self.crates.iter() {
         //     let (k, v) = kv;
         // }
-        res
+        options.apply(name_part.as_ref(), res)
     }
 
+    /// Like [`Repository::find_containing`], but one page at a time, starting at `cursor`.
+    /// Only references are sorted, so large result sets don't need to be copied.
+    pub fn find_containing_page(
+        &self,
+        name_part: impl AsRef<str>,
+        options: SearchOptions,
+        cursor: Option<search::Cursor>,
+    ) -> search::Page<&Crate> {
+        let name_part_lower = name_part.as_ref().to_lowercase();
+        let res = self
+            .crates
+            .iter()
+            .filter(|(k, _)| contains_ignoring_case(k, &name_part_lower))
+            .map(|(_, v)| v.as_ref())
+            .collect();
+        options.page(name_part.as_ref(), res, cursor)
+    }
+
+    /// Like [`Repository::find_exact`], but `LINUX.exe` also finds `linux.exe`.
+    /// An exact match is preferred over one differing in case.
+    pub fn find_ignoring_case(&self, name: impl AsRef<str>) -> Option<&Crate> {
+        self.find_exact(name.as_ref()).or_else(|| {
+            let canonical = self
+                .names_ignoring_case
+                .get(&name.as_ref().to_lowercase())?;
+            self.find_exact(canonical)
+        })
+    }
+
+    /// Makes `metadata` share its author with the other crates of the author. The table only
+    /// grows, authors of deleted crates stay until the store is loaded again.
+    pub(crate) fn intern_author(&mut self, metadata: &mut Metadata) {
+        intern(&mut self.authors, &mut metadata.author);
+    }
+
+    /// updates derived indices after the crate called `name` was added, changed or removed
+    pub(crate) fn reindex(&mut self, name: &str) {
+        span!("index.update");
+        let lowercase = name.to_lowercase();
+        let key = self.crates.get_key_value(name).map(|(key, _)| key.clone());
+        if let (Some(key), Some(crt)) = (key, self.crates.get_mut(name).map(Arc::make_mut)) {
+            // the metadata might come from elsewhere, e.g. a replicated change
+            crt.metadata.name = key;
+            intern(&mut self.authors, &mut crt.metadata.author);
+        }
+        match self.crates.get(name) {
+            Some(crt) => {
+                self.index.insert(crt);
+                let canonical = self
+                    .names_ignoring_case
+                    .entry(lowercase)
+                    .or_insert_with(|| crt.metadata.name.clone());
+                if name < canonical.as_ref() {
+                    *canonical = crt.metadata.name.clone();
+                }
+            }
+            None => {
+                self.index.remove(name);
+                if self.names_ignoring_case.get(&lowercase).map(AsRef::as_ref) == Some(name) {
+                    self.names_ignoring_case.remove(&lowercase);
+                    // another crate might differ only in case
+                    if let Some(other) = self.crates.keys().find(|k| k.to_lowercase() == lowercase)
+                    {
+                        self.names_ignoring_case.insert(lowercase, other.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    pub(crate) fn reindex_all(&mut self) {
+        span!("index.rebuild");
+        for (name, crt) in &mut self.crates {
+            let author = self.authors.get(crt.metadata.author());
+            if Arc::ptr_eq(name, &crt.metadata.name)
+                && author.is_some_and(|author| Arc::ptr_eq(author, &crt.metadata.author))
+            {
+                // don't copy crates shared with a view for nothing
+                continue;
+            }
+            let crt = Arc::make_mut(crt);
+            crt.metadata.name = name.clone();
+            intern(&mut self.authors, &mut crt.metadata.author);
+        }
+        // the change feed repeats them, e.g. after loading the store
+        for event in self.changes.events_mut() {
+            event.intern(&self.crates, &mut self.authors);
+        }
+        self.index = SearchIndex::build(self.iter());
+        self.names_ignoring_case.clear();
+        // in name order, so the smallest of names differing only in case wins
+        for name in self.crates.keys() {
+            self.names_ignoring_case
+                .entry(name.to_lowercase())
+                .or_insert_with(|| name.clone());
+        }
+    }
+
+    /// Crates whose name starts with `prefix`, ordered by name. Case sensitive, unlike
+    /// [`Repository::find_containing`].
+    pub fn find_prefix(&self, prefix: impl AsRef<str>) -> Vec<&Crate> {
+        let prefix = prefix.as_ref();
+        self.crates
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(|(name, _)| name.starts_with(prefix))
+            .map(|(_, crt)| crt.as_ref())
+            .collect()
+    }
+
+    /// Crates whose name matches a glob `pattern`, where `*` matches any number of characters
+    /// and `?` exactly one. Case sensitive, ordered by name.
+    pub fn find_matching(&self, pattern: impl AsRef<str>) -> Vec<&Crate> {
+        let pattern = pattern.as_ref();
+        // everything before the first wildcard narrows the search down
+        let literal = pattern
+            .find(['*', '?'])
+            .map(|i| &pattern[..i])
+            .unwrap_or(pattern);
+        self.find_prefix(literal)
+            .into_iter()
+            .filter(|crt| glob::matches(pattern, crt.metadata().name()))
+            .collect()
+    }
+
+    /// Crates whose name matches `pattern` anywhere, ordered by name. Compile user supplied
+    /// patterns with [`search::compile_pattern`].
+    pub fn find_regex(&self, pattern: &regex::Regex) -> Vec<&Crate> {
+        self.iter()
+            .filter(|crt| pattern.is_match(crt.metadata().name()))
+            .collect()
+    }
+
+    /// Counts a download of the crate called `name`. Downloads aren't changes, they don't appear
+    /// in the change feed.
+    pub fn record_download(&mut self, name: impl AsRef<str>) {
+        let name = name.as_ref();
+        let name = self.aliases.get(name).map_or(name, String::as_str);
+        if let Some(crt) = self.crates.get_mut(name).map(Arc::make_mut) {
+            crt.downloads += 1;
+            self.mutations += 1;
+        }
+    }
+
+    /// Name and author are checked by [`Metadata::validated`]. Scoped names like
+    /// `@myorg/http-client` need a registered namespace, who may publish in it is up to the
+    /// caller, see [`Repository::check_namespace`].
     pub fn add_crate(&mut self, metadata: Metadata, version: SemVer) -> Result<(), RepoError> {
-        if self.crates.contains_key(&metadata.name) {
+        self.insert_crate(metadata, version, None)
+    }
+
+    /// [`Repository::add_crate`], with `version` published at `published_at` if it is known
+    pub(crate) fn insert_crate(
+        &mut self,
+        metadata: Metadata,
+        version: SemVer,
+        published_at: Option<DateTime<Utc>>,
+    ) -> Result<(), RepoError> {
+        let mut metadata = metadata.validated()?;
+        self.check_name(metadata.name())?;
+        self.check_kind_policy(&metadata)?;
+        if self.crates.contains_key(metadata.name()) {
             Err(RepoError::AlreadyExists)
         } else {
-            let mut crt = Crate::new(metadata);
-            crt.release_history.push(version);
-            self.crates.insert(crt.metadata.name.clone(), crt);
+            self.intern_author(&mut metadata);
+            let mut crt = Crate::new(metadata.clone());
+            crt.push_release(version, published_at.unwrap_or_else(Utc::now));
+            self.crates.insert(crt.metadata.name.clone(), Arc::new(crt));
+            // the name is taken now, it no longer refers to a renamed crate
+            self.aliases.remove(metadata.name());
+            self.reindex(metadata.name());
+            self.changes.record(Event::CrateAdded {
+                metadata,
+                version,
+                published_at,
+            });
             Ok(())
         }
     }
 
+    /// Adds crates with their stable releases, oldest first, like [`Repository::add_crate`]
+    /// followed by [`Repository::add_release`] for every further release. The search indices are
+    /// rebuilt once at the end instead of after each crate, so prefer it for filling new
+    /// repositories. Stops at the first crate that can't be added, keeping the ones before it.
+    /// Returns the number of added crates.
+    pub fn bulk_load(
+        &mut self,
+        crates: impl IntoIterator<Item = (Metadata, Vec<SemVer>)>,
+    ) -> Result<usize, RepoError> {
+        let mut added = 0;
+        let mut res = Ok(());
+        for (metadata, releases) in crates {
+            res = self.load_one(metadata, releases);
+            if res.is_err() {
+                break;
+            }
+            added += 1;
+        }
+        self.reindex_all();
+        res.map(|()| added)
+    }
+
+    fn load_one(&mut self, mut metadata: Metadata, releases: Vec<SemVer>) -> Result<(), RepoError> {
+        self.check_name(metadata.name())?;
+        self.check_kind_policy(&metadata)?;
+        if self.crates.contains_key(metadata.name()) {
+            return Err(RepoError::AlreadyExists);
+        }
+        let (&first, rest) = releases.split_first().ok_or(RepoError::InvalidVersion)?;
+        self.intern_author(&mut metadata);
+        let now = Utc::now();
+        let policy = self.version_policy_for(&metadata.kind);
+        let mut crt = Crate::new(metadata.clone());
+        crt.push_release(first, now);
+        for &version in rest {
+            crt.add_release_at(version, now, policy)?;
+        }
+        self.crates.insert(metadata.name.clone(), Arc::new(crt));
+        self.aliases.remove(metadata.name());
+        let name = metadata.name.clone();
+        self.changes.record(Event::CrateAdded {
+            metadata,
+            version: first,
+            published_at: None,
+        });
+        for &version in rest {
+            self.changes.record(Event::ReleaseAdded {
+                name: name.clone(),
+                version,
+                channel: channels::Channel::Stable,
+            });
+        }
+        Ok(())
+    }
+
+    /// publishes a stable release, see [`Repository::add_release_to`] for other channels
     pub fn add_release(&mut self, name: impl AsRef<str>, version: SemVer) -> Result<(), RepoError> {
+        self.add_release_to(name, version, channels::Channel::Stable)
+    }
+
+    pub fn yank(&mut self, name: impl AsRef<str>, version: SemVer) -> Result<(), RepoError> {
+        let name = name.as_ref();
+        let name = self.aliases.get(name).map_or(name, String::as_str);
         let crt = self
             .crates
-            .get_mut(name.as_ref())
+            .get_mut(name)
+            .map(Arc::make_mut)
             .ok_or(RepoError::NotFound)?;
 
-        crt.add_release(version)
+        if crt.is_yanked(version) {
+            return Ok(());
+        }
+        crt.yank(version)?;
+        self.changes.record(Event::Yanked {
+            name: crt.metadata.name.clone(),
+            version,
+        });
+        Ok(())
+    }
+
+    /// writes the repository to its store. Also happens automatically on drop, if it is dirty.
+    pub fn save(&self) -> Result<(), std::io::Error> {
+        span!("store.save");
+        if self.sqlite {
+            #[cfg(feature = "sqlite")]
+            self.save_sqlite()?;
+        } else if let Some(sharding) = self.sharding {
+            self.save_sharded(sharding)?;
+        } else {
+            // here we make use of the fact serde_json errors can be converted into std::io::Error
+            // (I learned this today while chasing down the dyn problem…)
+            let tmp = tmp_path(&self.store);
+            self.write_store_file(&tmp, |writer| Ok(serde_json::to_writer(writer, self)?))?;
+            // a store that used to be sharded
+            if self.store.is_dir() {
+                fs::remove_dir_all(&self.store)?;
+            }
+            fs::rename(&tmp, &self.store)?;
+        }
+        self.saved.store(self.mutation_count(), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Writes a file of the store to `tmp`, compressed and encrypted as configured. Files are
+    /// written next to their final place and moved there, so readers never see partial ones.
+    fn write_store_file(
+        &self,
+        tmp: &Path,
+        write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> std::io::Result<()> {
+        match &self.key {
+            None => File::create(tmp).and_then(|f| self.compression.encode(f, write).map(drop)),
+            Some(_) => fs::write(tmp, self.encode_store(write)?),
+        }
+    }
+
+    /// what [`Repository::write_store_file`] writes, in memory
+    fn encode_store(
+        &self,
+        write: impl FnOnce(&mut dyn Write) -> std::io::Result<()>,
+    ) -> std::io::Result<Vec<u8>> {
+        let plain = self.compression.encode(vec![], write)?;
+        Ok(match &self.key {
+            Some(key) => encryption::encrypt(key, &plain),
+            None => plain,
+        })
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// how the store is written from now on, it is read in any format
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// whether the store is saved encrypted, see [`encryption`]
+    pub fn is_encrypted(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Saves the store encrypted with `key` from now on, or unencrypted if `None`. The store is
+    /// rewritten right away, so the old key no longer opens it.
+    pub fn rekey(&mut self, key: Option<StoreKey>) -> Result<(), std::io::Error> {
+        self.key = key;
+        *self.saved_crates.get_mut().unwrap() = None;
+        self.save()
+    }
+
+    /// Rewrites the whole store, including unchanged shards, in the current format and the
+    /// repository's compression, e.g. after upgrading or switching compression.
+    pub fn migrate(&mut self) -> Result<(), std::io::Error> {
+        *self.saved_crates.get_mut().unwrap() = None;
+        self.save()
+    }
+
+    /// [`Repository::save`] if the repository is dirty, returns whether it saved
+    pub fn save_if_dirty(&self) -> Result<bool, std::io::Error> {
+        let dirty = self.is_dirty();
+        if dirty {
+            self.save()?;
+        }
+        Ok(dirty)
+    }
+
+    /// whether the repository changed since it was last saved or loaded
+    pub fn is_dirty(&self) -> bool {
+        self.unsaved_changes() > 0
+    }
+
+    /// Number of modifications since the last save, 0 if the store is up to date. Changes undone
+    /// by a rollback don't count, so neither do failed transactions.
+    pub fn unsaved_changes(&self) -> u64 {
+        self.mutation_count()
+            .abs_diff(self.saved.load(Ordering::Relaxed))
+    }
+
+    fn mutation_count(&self) -> u64 {
+        self.mutations + self.changes.mutations()
+    }
+
+    /// for modifications that aren't recorded in the change feed
+    pub(crate) fn mark_dirty(&mut self) {
+        self.mutations += 1;
+    }
+
+    /// revision of the crate called `name`, 0 if there is none
+    pub fn revision(&self, name: impl AsRef<str>) -> u64 {
+        self.find_exact(name).map(Crate::revision).unwrap_or(0)
+    }
+
+    /// the change feed, see [`events`]
+    pub fn changes(&self) -> &ChangeLog {
+        &self.changes
+    }
+
+    pub fn version_policy(&self) -> VersionPolicy {
+        self.version_policy
+    }
+
+    /// applies to releases added from now on, existing histories aren't checked
+    pub fn set_version_policy(&mut self, policy: VersionPolicy) {
+        self.mark_dirty();
+        self.version_policy = policy;
+    }
+}
+
+/// Reads a file of the store, decrypting it with `key` and decompressing it. `None` if it is
+/// missing or empty, otherwise the JSON and whether it was encrypted.
+fn read_store_file(
+    path: &Path,
+    key: Option<&StoreKey>,
+) -> Result<Option<(Vec<u8>, bool)>, StoreError> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if contents.is_empty() {
+        return Ok(None);
+    }
+    decode_store(contents, key).map(Some)
+}
+
+/// `contents` written by [`Repository::encode_store`], decrypted and decompressed, and whether
+/// they were encrypted
+fn decode_store(contents: Vec<u8>, key: Option<&StoreKey>) -> Result<(Vec<u8>, bool), StoreError> {
+    let encrypted = encryption::is_encrypted(&contents);
+    let contents = match (key, encrypted) {
+        (None, true) => return Err(StoreError::Encrypted),
+        (Some(key), true) => encryption::decrypt(key, &contents).ok_or(StoreError::WrongKey)?,
+        (_, false) => contents,
+    };
+    Ok((compression::decompress(contents)?, encrypted))
+}
+
+/// whether `path` is an SQLite database, or missing and named like one
+fn is_sqlite(path: &Path) -> bool {
+    const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+    let mut header = [0; 16];
+    match File::open(path) {
+        Ok(mut file) => file.read_exact(&mut header).is_ok() && &header == MAGIC,
+        Err(_) => path
+            .extension()
+            .is_some_and(|extension| extension == "sqlite"),
+    }
+}
+
+/// `path` with `.tmp` appended
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    tmp.into()
+}
+
+/// whether the lowercase of `s` contains `part_lower`, without allocating for ASCII names
+fn contains_ignoring_case(s: &str, part_lower: &str) -> bool {
+    if s.is_ascii() && part_lower.is_ascii() {
+        part_lower.is_empty()
+            || s.as_bytes()
+                .windows(part_lower.len())
+                .any(|window| window.eq_ignore_ascii_case(part_lower.as_bytes()))
+    } else {
+        s.to_lowercase().contains(part_lower)
+    }
+}
+
+/// replaces `s` with the equal string in `table`, or adds it
+fn intern(table: &mut HashSet<Arc<str>>, s: &mut Arc<str>) {
+    match table.get(s) {
+        Some(shared) => *s = shared.clone(),
+        None => {
+            table.insert(s.clone());
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Repository {
+    type Item = &'a Crate;
+    type IntoIter = std::iter::Map<
+        std::collections::btree_map::Values<'a, Arc<str>, Arc<Crate>>,
+        fn(&'a Arc<Crate>) -> &'a Crate,
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.crates.values().map(Arc::as_ref)
     }
 }
 
 impl Drop for Repository {
     fn drop(&mut self) {
-        // here we make use of the fact serde_json errors can be converted into std::io::Error
-        // (I learned this today while chasing down the dyn problem…)
-        let res: Result<_, std::io::Error> = File::create(&self.store)
-            .and_then(|f| serde_json::to_writer(f, self).map_err(|e| e.into()));
-
-        if let Err(e) = res {
+        if let Err(e) = self.save_if_dirty() {
             eprintln!("could not save repository: {:?}", e);
         }
     }
@@ -329,16 +1230,16 @@ mod tests {
 
     fn create_crate() -> Crate {
         Crate::new(Metadata::new(
-            "linux.exe".to_string(),
-            "Linus Torvalds".to_string(),
+            "linux.exe",
+            "Linus Torvalds",
             CrateKind::Binary,
         ))
     }
 
     fn create_shouty_crate() -> Crate {
         Crate::new(Metadata::new(
-            "LINUX.EXE!!".to_string(),
-            "LINUS TORVALDS!!!!!".to_string(),
+            "LINUX.EXE!!",
+            "LINUS TORVALDS!!!!!",
             CrateKind::Binary,
         ))
     }
@@ -356,6 +1257,33 @@ mod tests {
         assert_eq!(s1, s2);
     }
 
+    #[test]
+    fn checked_metadata() {
+        let metadata = Metadata::try_new(" hello_bin\n", "Busy Person ", CrateKind::Binary);
+        assert_eq!(
+            Ok(Metadata::new("hello_bin", "Busy Person", CrateKind::Binary)),
+            metadata
+        );
+        for (name, author) in [
+            ("  ", "someone"),
+            ("hello\u{7}bin", "someone"),
+            (&"x".repeat(MAX_NAME_LEN + 1), "someone"),
+        ] {
+            assert_eq!(
+                Err(RepoError::InvalidName),
+                Metadata::try_new(name, author, CrateKind::Library)
+            );
+        }
+        assert_eq!(
+            Err(RepoError::InvalidAuthor),
+            Metadata::try_new("hello_bin", "", CrateKind::Library)
+        );
+        assert_eq!(
+            Err(RepoError::InvalidAuthor),
+            Metadata::new("hello_bin", "\t", CrateKind::Library).validated()
+        );
+    }
+
     #[test]
     fn add_crate() -> Result<(), RepoError> {
         let (_store, mut repo) = create_repo();
@@ -382,11 +1310,104 @@ mod tests {
         repo.add_release(&metadata.name, SemVer::new(1, 0, 1))?;
 
         assert_eq!(
-            Err(RepoError::InvalidVersion),
+            Err(RepoError::DuplicateVersion),
             repo.add_release(&metadata.name, SemVer::new(1, 0, 1))
         );
         repo.add_release(&metadata.name, SemVer::new(2, 0, 0))?;
+        assert_eq!(
+            Err(RepoError::NotMonotonic),
+            repo.add_release(&metadata.name, SemVer::new(1, 4, 9))
+        );
+
+        // backports
+        repo.set_version_policy(VersionPolicy::Unique);
+        repo.add_release(&metadata.name, SemVer::new(1, 4, 9))?;
+        assert_eq!(
+            Err(RepoError::DuplicateVersion),
+            repo.add_release(&metadata.name, SemVer::new(1, 0, 1))
+        );
+        let crt = repo.find_exact(&metadata.name).unwrap();
+        assert_eq!(Some(SemVer::new(2, 0, 0)), crt.latest());
+
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_and_glob() -> Result<(), RepoError> {
+        let (_store, mut repo) = create_repo();
+        for name in [
+            "tokio",
+            "tokio-util",
+            "tokio-stream",
+            "serde_json",
+            "serde",
+            "tokiox",
+        ] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        let names = |crates: Vec<&Crate>| -> Vec<String> {
+            crates
+                .iter()
+                .map(|c| c.metadata().name().to_string())
+                .collect()
+        };
 
+        assert_eq!(
+            vec!["tokio-stream", "tokio-util"],
+            names(repo.find_prefix("tokio-"))
+        );
+        assert!(repo.find_prefix("tokyo").is_empty());
+        assert_eq!(vec!["serde_json"], names(repo.find_matching("serde*json")));
+        assert_eq!(
+            vec!["tokio-stream", "tokio-util", "tokiox"],
+            names(repo.find_matching("tokio?*"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn find_ignoring_case() -> Result<(), RepoError> {
+        let (_store, mut repo) = create_repo();
+        repo.add_crate(create_crate().metadata, SemVer::new(1, 0, 0))?;
+        assert_eq!(None, repo.find_exact("LINUX.exe"));
+        assert_eq!(
+            "linux.exe",
+            repo.find_ignoring_case("LINUX.exe")
+                .unwrap()
+                .metadata()
+                .name()
+        );
+
+        repo.add_crate(
+            Metadata::new("Linux.exe", "Someone Else", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert_eq!(
+            "Linux.exe",
+            repo.find_ignoring_case("Linux.exe")
+                .unwrap()
+                .metadata()
+                .name()
+        );
+        assert_eq!(
+            "Linux.exe",
+            repo.find_ignoring_case("LINUX.EXE")
+                .unwrap()
+                .metadata()
+                .name()
+        );
+        repo.delete_crate("Linux.exe")?;
+        assert_eq!(
+            "linux.exe",
+            repo.find_ignoring_case("LINUX.EXE")
+                .unwrap()
+                .metadata()
+                .name()
+        );
+        assert_eq!(None, repo.find_ignoring_case("linux"));
         Ok(())
     }
 
@@ -415,11 +1436,268 @@ mod tests {
 
         let c1 = create_crate();
         let c2 = create_shouty_crate();
-        cmp.extend(vec![&c1, &c2].into_iter());
+        cmp.extend(vec![&c1, &c2]);
         // the same as:
         //cmp.insert(&c1); cmp.insert(&c2);
-        let search_result = repo.find_containing("NuX").into_iter().collect();
+        let search_result = repo
+            .find_containing("NuX", SearchOptions::default())
+            .into_iter()
+            .collect();
         assert_eq!(cmp, search_result);
         Ok(())
     }
+
+    #[test]
+    fn yank_and_changes() -> Result<(), RepoError> {
+        let (_store, mut repo) = create_repo();
+        let metadata = create_crate().metadata;
+        repo.add_crate(metadata.clone(), SemVer::new(1, 0, 0))?;
+        repo.add_release("linux.exe", SemVer::new(1, 1, 0))?;
+
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.yank("linux.exe", SemVer::new(3, 0, 0))
+        );
+        repo.yank("linux.exe", SemVer::new(1, 0, 0))?;
+        // yanking twice changes nothing
+        repo.yank("linux.exe", SemVer::new(1, 0, 0))?;
+        assert!(repo
+            .find_exact("linux.exe")
+            .unwrap()
+            .is_yanked(SemVer::new(1, 0, 0)));
+
+        assert_eq!(3, repo.changes().last_seq());
+        let events: Vec<_> = repo.changes().since(1).iter().map(|c| &c.event).collect();
+        assert_eq!(
+            vec![
+                &Event::ReleaseAdded {
+                    name: "linux.exe".into(),
+                    version: SemVer::new(1, 1, 0),
+                    channel: channels::Channel::Stable,
+                },
+                &Event::Yanked {
+                    name: "linux.exe".into(),
+                    version: SemVer::new(1, 0, 0)
+                },
+            ],
+            events
+        );
+        assert!(repo.changes().since(3).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn accessors() -> Result<(), RepoError> {
+        let (_store, mut repo) = create_repo();
+        repo.add_crate(create_crate().metadata, SemVer::new(1, 0, 0))?;
+        repo.add_release("linux.exe", SemVer::new(1, 1, 0))?;
+        repo.yank("linux.exe", SemVer::new(1, 1, 0))?;
+
+        let crt = repo.find_exact("linux.exe").unwrap();
+        assert_eq!(&CrateKind::Binary, crt.metadata().kind());
+        assert_eq!(
+            &[SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)],
+            crt.releases()
+        );
+        assert_eq!(Some(SemVer::new(1, 0, 0)), crt.latest());
+
+        assert_eq!(1, repo.iter().count());
+        let names: Vec<&str> = (&repo).into_iter().map(|c| c.metadata().name()).collect();
+        assert_eq!(vec!["linux.exe"], names);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_semver() {
+        assert_eq!(SemVer::new(1, 22, 333), "1.22.333".parse().unwrap());
+        assert!(matches!(
+            "1.2".parse::<SemVer>(),
+            Err(ParseError::WrongNumberOfParts(2))
+        ));
+        assert!(matches!(
+            "1.2.3.4".parse::<SemVer>(),
+            Err(ParseError::WrongNumberOfParts(4))
+        ));
+        assert!(matches!(
+            "1.x.3".parse::<SemVer>(),
+            Err(ParseError::ParseInt(_))
+        ));
+        assert!(matches!(
+            "1.+2.3".parse::<SemVer>(),
+            Err(ParseError::NotANumber(part)) if part == "+2"
+        ));
+
+        // wider than u16, e.g. date based versions and build numbers
+        assert_eq!(
+            SemVer::new(2024, 10, 3_000_000_000),
+            "2024.10.3000000000".parse().unwrap()
+        );
+        assert!(matches!(
+            "2024.01.03".parse::<SemVer>(),
+            Err(ParseError::LeadingZero(part)) if part == "01"
+        ));
+        assert_eq!(
+            SemVer::new(2024, 1, 3),
+            SemVer::parse_lenient("2024.01.03").unwrap()
+        );
+        for (lenient, version) in [
+            ("v1.2.3", SemVer::new(1, 2, 3)),
+            ("1.2", SemVer::new(1, 2, 0)),
+            ("v7", SemVer::new(7, 0, 0)),
+        ] {
+            assert_eq!(version, SemVer::parse_lenient(lenient).unwrap());
+            assert!(lenient.parse::<SemVer>().is_err());
+        }
+        for invalid in ["", "v", "1.2.3.4", "1..3", "vv1.2.3", "V1.2.3"] {
+            assert!(SemVer::parse_lenient(invalid).is_err(), "{}", invalid);
+        }
+        assert_eq!(SemVer::new(1, 0, 0), "1.0.0".parse().unwrap());
+
+        // stores written with u16 parts still load
+        let stored: SemVer =
+            serde_json::from_str(r#"{"major":1,"minor":2,"patch":65535}"#).unwrap();
+        assert_eq!(SemVer::new(1, 2, 65535), stored);
+    }
+
+    #[test]
+    fn compatible() {
+        let compatible = |a: &str, b: &str| {
+            let (a, b): (SemVer, SemVer) = (a.parse().unwrap(), b.parse().unwrap());
+            a.is_compatible_with(&b)
+        };
+        assert!(compatible("1.4.2", "1.9.0"));
+        assert!(compatible("1.9.0", "1.4.2"));
+        assert!(!compatible("1.4.2", "2.0.0"));
+        assert!(compatible("0.3.1", "0.3.7"));
+        assert!(!compatible("0.3.1", "0.4.0"));
+        assert!(!compatible("0.3.1", "1.3.1"));
+        assert!(compatible("0.0.3", "0.0.3"));
+        assert!(!compatible("0.0.3", "0.0.4"));
+        assert!(!compatible("0.1.0", "0.0.1"));
+    }
+
+    #[test]
+    fn bulk_load() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::with_capacity(&store, 3);
+        let crates = ["Linux.exe", "hello_bin", "hello_bin", "hello_moon"].map(|name| {
+            (
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                vec![SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)],
+            )
+        });
+        // stops at the duplicate
+        assert_eq!(Err(RepoError::AlreadyExists), repo.bulk_load(crates));
+        assert_eq!(2, repo.iter().count());
+        assert_eq!(
+            &[SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)],
+            repo.find_exact("hello_bin").unwrap().releases()
+        );
+        assert_eq!(4, repo.changes().last_seq());
+        // indices are up to date
+        assert!(repo.find_ignoring_case("linux.EXE").is_some());
+        assert_eq!(1, repo.search("hello").len());
+        assert_eq!(
+            1,
+            repo.find_containing("LINUX", SearchOptions::default())
+                .len()
+        );
+
+        let invalid = (
+            Metadata::new("hello_moon", "Busy Person", CrateKind::Binary),
+            vec![SemVer::new(1, 1, 0), SemVer::new(1, 0, 0)],
+        );
+        assert_eq!(Err(RepoError::NotMonotonic), repo.bulk_load([invalid]));
+        assert!(repo.find_exact("hello_moon").is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn shared_strings() -> Result<(), RepoError> {
+        fn check(repo: &Repository) {
+            let (key, crt) = repo.crates.first_key_value().unwrap();
+            assert!(Arc::ptr_eq(key, &crt.metadata.name));
+            let authors: Vec<_> = repo.iter().map(|crt| &crt.metadata.author).collect();
+            assert!(Arc::ptr_eq(authors[0], authors[1]));
+            match &repo.changes().since(0)[0].event {
+                Event::CrateAdded { metadata, .. } => {
+                    assert!(Arc::ptr_eq(key, &metadata.name));
+                    assert!(Arc::ptr_eq(authors[0], &metadata.author));
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+
+        let (store, mut repo) = create_repo();
+        for name in ["hello_bin", "hello_moon"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        check(&repo);
+        repo.save().unwrap();
+        check(&Repository::open(&store, None).unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_store() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        std::fs::write(&store, "{\"crates\": ").unwrap();
+        assert!(matches!(
+            Repository::open(&store, None),
+            Err(StoreError::Invalid(_))
+        ));
+        assert!(matches!(
+            Repository::create(&store),
+            Err(StoreError::Invalid(_))
+        ));
+        // left alone until something is saved
+        drop(Repository::new(&store));
+        assert_eq!("{\"crates\": ", std::fs::read_to_string(&store).unwrap());
+
+        let (store, mut repo) = create_repo();
+        repo.add_crate(
+            Metadata::new("linux.exe", "Linus Torvalds", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        drop(repo);
+        assert!(matches!(
+            Repository::create(&store),
+            Err(StoreError::Exists)
+        ));
+        let repo = Repository::open(&store, None).unwrap();
+        assert!(repo.find_exact("linux.exe").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn dirty_tracking() -> Result<(), RepoError> {
+        let (store, mut repo) = create_repo();
+        assert!(!repo.is_dirty());
+        repo.add_crate(
+            Metadata::new("dirty", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.record_download("dirty");
+        assert_eq!(2, repo.unsaved_changes());
+        assert!(repo.save_if_dirty().unwrap());
+        assert!(!repo.is_dirty());
+        assert!(!repo.save_if_dirty().unwrap());
+
+        // undone changes leave it clean
+        let failed: Result<(), RepoError> = repo.transaction(|repo| {
+            repo.add_release("dirty", SemVer::new(1, 1, 0))?;
+            Err(RepoError::Forbidden)
+        });
+        assert!(failed.is_err());
+        assert!(!repo.is_dirty());
+
+        drop(repo);
+        let repo = Repository::open(&store, None).unwrap();
+        assert!(!repo.is_dirty());
+        assert_eq!(1, repo.find_exact("dirty").unwrap().downloads());
+        Ok(())
+    }
 }
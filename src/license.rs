@@ -0,0 +1,310 @@
+//! SPDX license expressions like `MIT OR Apache-2.0`, see [`crate::Metadata::with_license`].
+//!
+//! Identifiers are checked against the licenses and exceptions crates commonly use, ignoring
+//! case. Licenses missing from the list can be named with `LicenseRef-<name>`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// SPDX identifiers of licenses that are accepted, including deprecated ones still in wide use
+const LICENSES: &[&str] = &[
+    "0BSD",
+    "AFL-3.0",
+    "AGPL-3.0",
+    "AGPL-3.0-only",
+    "AGPL-3.0-or-later",
+    "Apache-1.1",
+    "Apache-2.0",
+    "Artistic-2.0",
+    "BlueOak-1.0.0",
+    "BSD-1-Clause",
+    "BSD-2-Clause",
+    "BSD-2-Clause-Patent",
+    "BSD-3-Clause",
+    "BSD-3-Clause-Clear",
+    "BSD-4-Clause",
+    "BSL-1.0",
+    "BUSL-1.1",
+    "CC-BY-4.0",
+    "CC-BY-SA-4.0",
+    "CC0-1.0",
+    "CDDL-1.0",
+    "CECILL-2.1",
+    "EPL-1.0",
+    "EPL-2.0",
+    "EUPL-1.1",
+    "EUPL-1.2",
+    "GPL-2.0",
+    "GPL-2.0-only",
+    "GPL-2.0-or-later",
+    "GPL-3.0",
+    "GPL-3.0-only",
+    "GPL-3.0-or-later",
+    "ISC",
+    "LGPL-2.0",
+    "LGPL-2.0-only",
+    "LGPL-2.0-or-later",
+    "LGPL-2.1",
+    "LGPL-2.1-only",
+    "LGPL-2.1-or-later",
+    "LGPL-3.0",
+    "LGPL-3.0-only",
+    "LGPL-3.0-or-later",
+    "MIT",
+    "MIT-0",
+    "MPL-1.1",
+    "MPL-2.0",
+    "MS-PL",
+    "MS-RL",
+    "NCSA",
+    "OFL-1.1",
+    "OpenSSL",
+    "OSL-3.0",
+    "PostgreSQL",
+    "Python-2.0",
+    "Unicode-3.0",
+    "Unicode-DFS-2016",
+    "Unlicense",
+    "UPL-1.0",
+    "W3C",
+    "WTFPL",
+    "X11",
+    "Zlib",
+    "zlib-acknowledgement",
+    "ZPL-2.1",
+];
+
+/// SPDX identifiers of exceptions that are accepted after `WITH`
+const EXCEPTIONS: &[&str] = &[
+    "Autoconf-exception-3.0",
+    "Bison-exception-2.2",
+    "Classpath-exception-2.0",
+    "GCC-exception-3.1",
+    "LLVM-exception",
+    "OpenJDK-assembly-exception-1.0",
+];
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum LicenseError {
+    #[error("empty license expression")]
+    Empty,
+    #[error("unknown SPDX license '{0}'")]
+    UnknownLicense(String),
+    #[error("unknown SPDX exception '{0}'")]
+    UnknownException(String),
+    #[error("invalid license expression '{0}'")]
+    Syntax(String),
+}
+
+/// A valid SPDX license expression, kept as written
+#[derive(Debug, Hash, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct License(String);
+
+impl License {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for License {
+    type Err = LicenseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Err(LicenseError::Empty);
+        }
+        let mut parser = Parser {
+            expression: s,
+            tokens: &tokens,
+        };
+        parser.or()?;
+        match parser.tokens {
+            [] => Ok(License(s.trim().to_string())),
+            _ => Err(LicenseError::Syntax(s.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for License {
+    type Error = LicenseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<License> for String {
+    fn from(license: License) -> Self {
+        license.0
+    }
+}
+
+impl Display for License {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// words and parentheses
+fn tokenize(s: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut start = None;
+    for (i, c) in s.char_indices() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if let Some(start) = start.take() {
+                tokens.push(&s[start..i]);
+            }
+            if !c.is_whitespace() {
+                tokens.push(&s[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(start) = start {
+        tokens.push(&s[start..]);
+    }
+    tokens
+}
+
+/// recursive descent over `or := and (OR and)*`, `and := with (AND with)*`,
+/// `with := term (WITH exception)?` and `term := id | id+ | ( or )`
+struct Parser<'a> {
+    expression: &'a str,
+    tokens: &'a [&'a str],
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let (first, rest) = self.tokens.split_first()?;
+        self.tokens = rest;
+        Some(first)
+    }
+
+    fn eat(&mut self, keyword: &str) -> bool {
+        match self.tokens.first() {
+            Some(token) if *token == keyword => {
+                self.tokens = &self.tokens[1..];
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn syntax(&self) -> LicenseError {
+        LicenseError::Syntax(self.expression.to_string())
+    }
+
+    fn or(&mut self) -> Result<(), LicenseError> {
+        self.and()?;
+        while self.eat("OR") {
+            self.and()?;
+        }
+        Ok(())
+    }
+
+    fn and(&mut self) -> Result<(), LicenseError> {
+        self.with()?;
+        while self.eat("AND") {
+            self.with()?;
+        }
+        Ok(())
+    }
+
+    fn with(&mut self) -> Result<(), LicenseError> {
+        self.term()?;
+        if self.eat("WITH") {
+            let exception = self.next().ok_or_else(|| self.syntax())?;
+            if !known(EXCEPTIONS, exception) {
+                return Err(LicenseError::UnknownException(exception.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    fn term(&mut self) -> Result<(), LicenseError> {
+        match self.next() {
+            Some("(") => {
+                self.or()?;
+                match self.eat(")") {
+                    true => Ok(()),
+                    false => Err(self.syntax()),
+                }
+            }
+            Some(")" | "AND" | "OR" | "WITH") | None => Err(self.syntax()),
+            Some(id) => {
+                let license = id.strip_suffix('+').unwrap_or(id);
+                match license.strip_prefix("LicenseRef-") {
+                    Some(name) if is_id(name) => Ok(()),
+                    Some(_) => Err(self.syntax()),
+                    None if known(LICENSES, license) => Ok(()),
+                    None => Err(LicenseError::UnknownLicense(id.to_string())),
+                }
+            }
+        }
+    }
+}
+
+fn known(list: &[&str], id: &str) -> bool {
+    list.iter().any(|known| known.eq_ignore_ascii_case(id))
+}
+
+/// letters, digits, `.` and `-`, like SPDX ids
+fn is_id(s: &str) -> bool {
+    !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expressions() {
+        for valid in [
+            "MIT",
+            "MIT OR Apache-2.0",
+            "apache-2.0 WITH LLVM-exception",
+            "(MIT OR Apache-2.0) AND Unicode-3.0",
+            "GPL-2.0-or-later OR (BSD-3-Clause AND Zlib)",
+            "LGPL-2.1+",
+            "LicenseRef-Proprietary",
+        ] {
+            assert_eq!(
+                valid,
+                valid.parse::<License>().map(|l| l.to_string()).unwrap()
+            );
+        }
+        assert_eq!(Err(LicenseError::Empty), " ".parse::<License>());
+        assert_eq!(
+            Err(LicenseError::UnknownLicense("MTI".into())),
+            "MTI OR Apache-2.0".parse::<License>()
+        );
+        assert_eq!(
+            Err(LicenseError::UnknownException("Magic-exception".into())),
+            "MIT WITH Magic-exception".parse::<License>()
+        );
+        for invalid in [
+            "MIT OR",
+            "(MIT",
+            "MIT)",
+            "MIT Apache-2.0",
+            "AND MIT",
+            "LicenseRef-",
+        ] {
+            assert!(
+                matches!(invalid.parse::<License>(), Err(LicenseError::Syntax(_))),
+                "{}",
+                invalid
+            );
+        }
+        assert!(serde_json::from_str::<License>(r#""MIT""#).is_ok());
+        assert!(serde_json::from_str::<License>(r#""Beerware""#).is_err());
+    }
+}
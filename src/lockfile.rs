@@ -0,0 +1,144 @@
+//! Bootstrapping a private mirror from the dependencies in a project's `Cargo.lock`.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::api::ApiRequest;
+use crate::{CrateKind, Metadata, SemVer};
+
+#[derive(Error, Debug)]
+pub enum LockfileError {
+    #[error("invalid Cargo.lock: {0}")]
+    Toml(#[from] toml::de::Error),
+}
+
+#[derive(Debug, Deserialize)]
+struct Lockfile {
+    #[serde(default)]
+    package: Vec<Package>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Package {
+    name: String,
+    version: String,
+    /// missing for the workspace's own crates
+    source: Option<String>,
+}
+
+/// Exact versions of the dependencies in `lockfile`, oldest first. The workspace's own crates
+/// are left out, as are versions with pre-release or build tags.
+pub fn dependencies(lockfile: &str) -> Result<BTreeMap<String, Vec<SemVer>>, LockfileError> {
+    let lockfile: Lockfile = toml::from_str(lockfile)?;
+    let mut dependencies: BTreeMap<String, Vec<SemVer>> = BTreeMap::new();
+    for package in lockfile.package {
+        if package.source.is_none() {
+            continue;
+        }
+        match package.version.parse() {
+            Ok(version) => dependencies.entry(package.name).or_default().push(version),
+            Err(_) => log::warn!(
+                "skipping {} {}, unsupported version",
+                package.name,
+                package.version
+            ),
+        }
+    }
+    for versions in dependencies.values_mut() {
+        versions.sort();
+        versions.dedup();
+    }
+    Ok(dependencies)
+}
+
+/// Requests registering `dependencies` as crates by `author`, for a non-transactional
+/// [`ApiRequest::Batch`]. Crates that exist already fail to be added, but still get the releases
+/// they lack, including the oldest one. For new crates, that one's `AddRelease` fails instead.
+pub fn register_requests(
+    dependencies: &BTreeMap<String, Vec<SemVer>>,
+    author: &str,
+) -> Vec<ApiRequest> {
+    let mut requests = vec![];
+    for (name, versions) in dependencies {
+        let first = match versions.first() {
+            Some(first) => first,
+            None => continue,
+        };
+        requests.push(ApiRequest::AddCrate(
+            Metadata::new(name, author, CrateKind::Library),
+            *first,
+        ));
+        for version in versions {
+            requests.push(ApiRequest::AddRelease(name.clone(), *version));
+        }
+    }
+    requests
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCKFILE: &str = r#"
+# This file is automatically @generated by Cargo.
+version = 3
+
+[[package]]
+name = "my-app"
+version = "0.1.0"
+dependencies = ["serde"]
+
+[[package]]
+name = "serde"
+version = "1.0.190"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+checksum = "91d3c334ca1ee894a2c6f6ad698fe8c435b76d504b13d436f0685d648d6d96f7"
+
+[[package]]
+name = "syn"
+version = "2.0.38"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "syn"
+version = "1.0.109"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "unstable"
+version = "0.1.0-alpha.1"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+    #[test]
+    fn parse() -> Result<(), LockfileError> {
+        let dependencies = dependencies(LOCKFILE)?;
+        assert_eq!(
+            BTreeMap::from([
+                ("serde".to_string(), vec![SemVer::new(1, 0, 190)]),
+                (
+                    "syn".to_string(),
+                    vec![SemVer::new(1, 0, 109), SemVer::new(2, 0, 38)]
+                ),
+            ]),
+            dependencies
+        );
+
+        let requests = register_requests(&dependencies, "Busy Person");
+        assert_eq!(5, requests.len());
+        // in case syn exists already
+        assert!(matches!(
+            &requests[3],
+            ApiRequest::AddRelease(name, version)
+                if name == "syn" && *version == SemVer::new(1, 0, 109)
+        ));
+        assert!(matches!(
+            &requests[4],
+            ApiRequest::AddRelease(name, version)
+                if name == "syn" && *version == SemVer::new(2, 0, 38)
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+//! Crate names, optionally scoped to a namespace like `@myorg/http-client`.
+//!
+//! Scoped crates can only be published by an owner of their namespace, see
+//! [`Repository::check_namespace`], and namespaces have to be registered by an admin first.
+//! Unscoped names are first come, first served, except for [`NameRules`] reserving names like
+//! `std` or blocking abusive terms.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{glob, Crate, RepoError, Repository};
+
+/// A validated crate name, borrowing from the string it was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrateName<'a> {
+    namespace: Option<&'a str>,
+    name: &'a str,
+}
+
+fn valid_namespace(namespace: &str) -> bool {
+    !namespace.is_empty()
+        && namespace.len() <= 64
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl<'a> CrateName<'a> {
+    /// `@namespace/name` or just `name`. Namespaces consist of ASCII letters, digits, `-` and `_`,
+    /// no name may be empty or contain a `/`.
+    pub fn parse(full: &'a str) -> Result<Self, RepoError> {
+        let (namespace, name) = match full.strip_prefix('@') {
+            Some(scoped) => {
+                let (namespace, name) = scoped.split_once('/').ok_or(RepoError::InvalidName)?;
+                if !valid_namespace(namespace) {
+                    return Err(RepoError::InvalidName);
+                }
+                (Some(namespace), name)
+            }
+            None => (None, full),
+        };
+        if name.is_empty() || name.contains('/') {
+            return Err(RepoError::InvalidName);
+        }
+        Ok(Self { namespace, name })
+    }
+
+    pub fn namespace(&self) -> Option<&'a str> {
+        self.namespace
+    }
+
+    /// the name without its namespace
+    pub fn name(&self) -> &'a str {
+        self.name
+    }
+}
+
+impl Display for CrateName<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.namespace {
+            Some(namespace) => write!(f, "@{}/{}", namespace, self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Namespace {
+    /// users allowed to publish crates in the namespace
+    pub owners: Vec<String>,
+}
+
+/// registered namespaces by name, without the `@`
+pub type Namespaces = BTreeMap<String, Namespace>;
+
+/// Names nobody may publish, both lists are case insensitive.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameRules {
+    /// glob patterns of whole names, e.g. `std` or `acme-*`
+    pub reserved: Vec<String>,
+    /// terms that mustn't appear anywhere in a name
+    pub blocked: Vec<String>,
+}
+
+impl NameRules {
+    pub fn allows(&self, name: &str) -> bool {
+        let name = name.to_lowercase();
+        let reserved = self
+            .reserved
+            .iter()
+            .any(|pattern| glob::matches(&pattern.to_lowercase(), &name));
+        let blocked = self
+            .blocked
+            .iter()
+            .any(|term| name.contains(&term.to_lowercase()));
+        !reserved && !blocked
+    }
+
+    pub fn reserve(&mut self, pattern: impl AsRef<str>) {
+        add_rule(&mut self.reserved, pattern.as_ref());
+    }
+
+    pub fn block(&mut self, term: impl AsRef<str>) {
+        add_rule(&mut self.blocked, term.as_ref());
+    }
+
+    /// removes `rule` from both lists, returns whether there was such a rule
+    pub fn remove(&mut self, rule: impl AsRef<str>) -> bool {
+        let before = self.reserved.len() + self.blocked.len();
+        self.reserved.retain(|r| r != rule.as_ref());
+        self.blocked.retain(|r| r != rule.as_ref());
+        before != self.reserved.len() + self.blocked.len()
+    }
+}
+
+fn add_rule(rules: &mut Vec<String>, rule: &str) {
+    if !rule.is_empty() && !rules.iter().any(|r| r == rule) {
+        rules.push(rule.to_string());
+    }
+}
+
+impl Repository {
+    /// Makes sure a crate may be called `name`: allowed by the rules, and in a registered
+    /// namespace if scoped.
+    pub(crate) fn check_name(&self, name: &str) -> Result<(), RepoError> {
+        if !self.name_rules.allows(name) {
+            return Err(RepoError::NameReserved);
+        }
+        if let Some(namespace) = CrateName::parse(name)?.namespace() {
+            self.namespaces
+                .get(namespace)
+                .ok_or(RepoError::UnknownNamespace)?;
+        }
+        Ok(())
+    }
+
+    /// Makes sure `user` may publish a crate called `name`: owns its namespace if it's scoped.
+    pub fn check_namespace(&self, user: Option<&str>, name: &str) -> Result<(), RepoError> {
+        let namespace = match CrateName::parse(name)?.namespace() {
+            Some(namespace) => namespace,
+            None => return Ok(()),
+        };
+        let owners = &self
+            .namespaces
+            .get(namespace)
+            .ok_or(RepoError::UnknownNamespace)?
+            .owners;
+        match user {
+            Some(user) if owners.iter().any(|owner| owner == user) => Ok(()),
+            _ => Err(RepoError::NotOwner),
+        }
+    }
+
+    /// creates the namespace or replaces its owners
+    pub fn register_namespace(
+        &mut self,
+        namespace: impl AsRef<str>,
+        owners: Vec<String>,
+    ) -> Result<(), RepoError> {
+        let namespace = namespace.as_ref();
+        if !valid_namespace(namespace) {
+            return Err(RepoError::InvalidName);
+        }
+        self.namespaces
+            .insert(namespace.to_string(), Namespace { owners });
+        self.mark_dirty();
+        Ok(())
+    }
+
+    pub fn name_rules(&self) -> &NameRules {
+        &self.name_rules
+    }
+
+    /// Changes the rules for new names, existing crates aren't affected.
+    pub fn name_rules_mut(&mut self) -> &mut NameRules {
+        self.mark_dirty();
+        &mut self.name_rules
+    }
+
+    pub fn namespaces(&self) -> &Namespaces {
+        &self.namespaces
+    }
+
+    /// crates in `namespace`, ordered by name
+    pub fn namespace_crates(&self, namespace: impl AsRef<str>) -> Vec<&Crate> {
+        self.find_prefix(format!("@{}/", namespace.as_ref()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn parse() {
+        let scoped = CrateName::parse("@myorg/http-client").unwrap();
+        assert_eq!(Some("myorg"), scoped.namespace());
+        assert_eq!("http-client", scoped.name());
+        assert_eq!("@myorg/http-client", scoped.to_string());
+        assert_eq!(None, CrateName::parse("linux.exe").unwrap().namespace());
+
+        for invalid in [
+            "",
+            "@myorg",
+            "@/x",
+            "@my org/x",
+            "@myorg/",
+            "@myorg/a/b",
+            "a/b",
+        ] {
+            assert_eq!(Err(RepoError::InvalidName), CrateName::parse(invalid));
+        }
+    }
+
+    #[test]
+    fn namespace_ownership() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let publish = |repo: &mut Repository, name, author| {
+            repo.add_crate(
+                Metadata::new(name, author, CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )
+        };
+
+        assert_eq!(
+            Err(RepoError::UnknownNamespace),
+            publish(&mut repo, "@myorg/http-client", "Busy Person")
+        );
+        repo.register_namespace("myorg", vec!["Busy Person".to_string()])?;
+        repo.check_namespace(Some("Busy Person"), "@myorg/http-client")?;
+        repo.check_namespace(None, "http-client")?;
+        for user in [Some("Someone Else"), None] {
+            assert_eq!(
+                Err(RepoError::NotOwner),
+                repo.check_namespace(user, "@myorg/http-client")
+            );
+        }
+        publish(&mut repo, "@myorg/http-client", "Busy Person")?;
+        publish(&mut repo, "@myorg/http-server", "Busy Person")?;
+        publish(&mut repo, "http-client", "Someone Else")?;
+
+        let names: Vec<_> = repo
+            .namespace_crates("myorg")
+            .iter()
+            .map(|c| c.metadata().name())
+            .collect();
+        assert_eq!(vec!["@myorg/http-client", "@myorg/http-server"], names);
+        Ok(())
+    }
+
+    #[test]
+    fn name_rules() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let publish = |repo: &mut Repository, name| {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )
+        };
+        let rules = repo.name_rules_mut();
+        rules.reserve("std");
+        rules.reserve("acme-*");
+        rules.block("badword");
+
+        for name in ["std", "STD", "acme-http", "my-BadWord-crate"] {
+            assert_eq!(Err(RepoError::NameReserved), publish(&mut repo, name));
+        }
+        publish(&mut repo, "stdx")?;
+        publish(&mut repo, "not-acme-http")?;
+
+        assert!(repo.name_rules_mut().remove("std"));
+        assert!(!repo.name_rules_mut().remove("std"));
+        publish(&mut repo, "std")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,139 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+
+pub const DEFAULT_PORT: u16 = 7878;
+pub const DEFAULT_HOST: &str = "127.0.0.1";
+
+#[derive(thiserror::Error, Debug)]
+pub enum AddrError {
+    #[error("empty address")]
+    Empty,
+    #[error("unsupported scheme '{0}' (expected: tcp)")]
+    UnsupportedScheme(String),
+    #[error("invalid port in '{0}'")]
+    InvalidPort(String),
+    #[error("malformed address '{0}'")]
+    Malformed(String),
+    #[error("could not resolve '{0}': {1}")]
+    Resolve(String, std::io::Error),
+}
+
+/// splits `host`, `host:port`, `[v6]:port`, bare `v6` or `tcp://host:port/` into its parts,
+/// using `default_port` if none was given
+fn split_host_port(spec: &str, default_port: u16) -> Result<(String, u16), AddrError> {
+    let mut rest = spec.trim();
+    if let Some(idx) = rest.find("://") {
+        let scheme = &rest[..idx];
+        if !scheme.eq_ignore_ascii_case("tcp") {
+            return Err(AddrError::UnsupportedScheme(scheme.to_string()));
+        }
+        rest = &rest[idx + 3..];
+    }
+    let rest = rest.trim_end_matches('/');
+    if rest.is_empty() {
+        return Err(AddrError::Empty);
+    }
+
+    let parse_port = |port: &str| {
+        port.parse::<u16>()
+            .map_err(|_| AddrError::InvalidPort(spec.to_string()))
+    };
+
+    if let Some(bracketed) = rest.strip_prefix('[') {
+        // [::1] or [::1]:7878
+        let end = bracketed
+            .find(']')
+            .ok_or_else(|| AddrError::Malformed(spec.to_string()))?;
+        let host = &bracketed[..end];
+        let port = match &bracketed[end + 1..] {
+            "" => default_port,
+            tail => parse_port(
+                tail.strip_prefix(':')
+                    .ok_or_else(|| AddrError::Malformed(spec.to_string()))?,
+            )?,
+        };
+        return Ok((host.to_string(), port));
+    }
+
+    match rest.matches(':').count() {
+        0 => Ok((rest.to_string(), default_port)),
+        1 => {
+            let (host, port) = rest.split_once(':').unwrap();
+            if host.is_empty() {
+                return Err(AddrError::Malformed(spec.to_string()));
+            }
+            Ok((host.to_string(), parse_port(port)?))
+        }
+        // more than one colon and no brackets: a bare IPv6 address
+        _ => Ok((rest.to_string(), default_port)),
+    }
+}
+
+/// Parses a comma separated list of listen addresses, e.g. `127.0.0.1:7878,[::1]:7878`.
+/// Host names are resolved, so `localhost` may yield more than one address.
+pub fn parse_listen_addrs(spec: &str, default_port: u16) -> Result<Vec<SocketAddr>, AddrError> {
+    let mut res = vec![];
+    for entry in spec.split(',').filter(|e| !e.trim().is_empty()) {
+        let (host, port) = split_host_port(entry, default_port)?;
+        let resolved = (host.as_str(), port)
+            .to_socket_addrs()
+            .map_err(|e| AddrError::Resolve(entry.trim().to_string(), e))?;
+        for addr in resolved {
+            if !res.contains(&addr) {
+                res.push(addr);
+            }
+        }
+    }
+
+    if res.is_empty() {
+        return Err(AddrError::Empty);
+    }
+    Ok(res)
+}
+
+/// Normalizes a client target (`host`, `host:port`, `[::1]:port` or `tcp://host:port`)
+/// into a `host:port` string suitable for `TcpStream::connect`
+pub fn parse_target(spec: &str, default_port: u16) -> Result<String, AddrError> {
+    let (host, port) = split_host_port(spec, default_port)?;
+    if host.contains(':') {
+        Ok(format!("[{}]:{}", host, port))
+    } else {
+        Ok(format!("{}:{}", host, port))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn targets() -> Result<(), AddrError> {
+        assert_eq!("example.com:7878", parse_target("example.com", 7878)?);
+        assert_eq!("example.com:80", parse_target("example.com:80", 7878)?);
+        assert_eq!("[::1]:7878", parse_target("::1", 7878)?);
+        assert_eq!("[::1]:9000", parse_target("[::1]:9000", 7878)?);
+        assert_eq!("10.0.0.1:9000", parse_target("tcp://10.0.0.1:9000/", 7878)?);
+
+        assert!(matches!(
+            parse_target("http://example.com", 7878),
+            Err(AddrError::UnsupportedScheme(_))
+        ));
+        assert!(matches!(
+            parse_target("example.com:http", 7878),
+            Err(AddrError::InvalidPort(_))
+        ));
+        assert!(matches!(parse_target("", 7878), Err(AddrError::Empty)));
+        Ok(())
+    }
+
+    #[test]
+    fn listen_addrs() -> Result<(), AddrError> {
+        let addrs = parse_listen_addrs("127.0.0.1, [::1]:9000,0.0.0.0:1234", 7878)?;
+        let expected: Vec<SocketAddr> = vec![
+            "127.0.0.1:7878".parse().unwrap(),
+            "[::1]:9000".parse().unwrap(),
+            "0.0.0.0:1234".parse().unwrap(),
+        ];
+        assert_eq!(expected, addrs);
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Organizations owning crates, with members in different roles.
+//!
+//! Publishing, yanking and editing the metadata of a crate owned by an organization (see
+//! [`crate::Metadata::with_org`]) requires the user to be a member in a sufficient role.
+//! Users are identified by their token, see [`crate::auth`]. Crates without an organization
+//! are unrestricted as before.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Metadata, RepoError, Repository};
+
+/// ordered by privilege, each role can do everything the previous ones can
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Role {
+    /// member without any rights on the organization's crates
+    Reader,
+    /// publishes and yanks releases
+    Publisher,
+    /// edits metadata and manages members
+    Admin,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Organization {
+    /// user → role
+    pub members: BTreeMap<String, Role>,
+}
+
+impl Organization {
+    pub fn role(&self, user: &str) -> Option<Role> {
+        self.members.get(user).copied()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum OrgRequest {
+    /// creates an organization with the requesting user as its only admin
+    Create(String),
+    /// adds, changes or with `role: None` removes a member, requires the admin role
+    SetMember {
+        org: String,
+        user: String,
+        role: Option<Role>,
+    },
+    Get(String),
+}
+
+impl OrgRequest {
+    pub fn is_mutating(&self) -> bool {
+        !matches!(self, OrgRequest::Get(_))
+    }
+}
+
+impl Repository {
+    pub fn org(&self, name: impl AsRef<str>) -> Option<&Organization> {
+        self.orgs.get(name.as_ref())
+    }
+
+    /// Makes sure `user` has at least the `required` role in `org`.
+    pub fn check_org_role(
+        &self,
+        user: Option<&str>,
+        org: &str,
+        required: Role,
+    ) -> Result<(), RepoError> {
+        let role = user.and_then(|user| self.org(org)?.role(user));
+        match role {
+            Some(role) if role >= required => Ok(()),
+            _ => Err(RepoError::Forbidden),
+        }
+    }
+
+    /// Makes sure `user` has at least the `required` role in the organization owning the crate
+    /// called `name`, if any.
+    pub fn check_role(
+        &self,
+        user: Option<&str>,
+        name: impl AsRef<str>,
+        required: Role,
+    ) -> Result<(), RepoError> {
+        match self.find_exact(name).and_then(|crt| crt.metadata().org()) {
+            Some(org) => self.check_org_role(user, org, required),
+            None => Ok(()),
+        }
+    }
+
+    /// Makes sure `user` owns the crate called `name`: is an admin of the owning organization,
+    /// or the author for crates without one.
+    pub fn check_owner(&self, user: &str, name: impl AsRef<str>) -> Result<(), RepoError> {
+        let metadata = self.find_exact(name).ok_or(RepoError::NotFound)?.metadata();
+        match metadata.org() {
+            Some(org) => self.check_org_role(Some(user), org, Role::Admin),
+            None if metadata.author() != user => Err(RepoError::Forbidden),
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the metadata of a crate on behalf of `user`: an admin of the owning organization,
+    /// or the author for crates without one. Moving a crate to an organization requires the
+    /// admin role there as well.
+    pub fn update_metadata(&mut self, user: &str, metadata: Metadata) -> Result<(), RepoError> {
+        self.check_owner(user, metadata.name())?;
+        let current = self
+            .find_exact(metadata.name())
+            .ok_or(RepoError::NotFound)?
+            .metadata();
+        if let Some(org) = metadata.org().filter(|org| Some(*org) != current.org()) {
+            self.check_org_role(Some(user), org, Role::Admin)?;
+        }
+        self.edit_metadata(metadata)
+    }
+
+    pub fn handle_org(
+        &mut self,
+        user: &str,
+        request: OrgRequest,
+    ) -> Result<Organization, RepoError> {
+        match request {
+            OrgRequest::Create(name) => self.create_org(name, user),
+            OrgRequest::SetMember {
+                org,
+                user: member,
+                role,
+            } => self.set_member(user, org, member, role),
+            OrgRequest::Get(name) => self.org(name).cloned().ok_or(RepoError::NotFound),
+        }
+    }
+
+    /// creates an organization with `admin` as its only member
+    pub fn create_org(
+        &mut self,
+        name: impl AsRef<str>,
+        admin: impl AsRef<str>,
+    ) -> Result<Organization, RepoError> {
+        let name = name.as_ref();
+        if name.is_empty() {
+            return Err(RepoError::InvalidName);
+        }
+        if self.orgs.contains_key(name) {
+            return Err(RepoError::AlreadyExists);
+        }
+        let org = Organization {
+            members: BTreeMap::from([(admin.as_ref().to_string(), Role::Admin)]),
+        };
+        self.orgs.insert(name.to_string(), org.clone());
+        self.mark_dirty();
+        Ok(org)
+    }
+
+    /// Changes the role of `member` on behalf of the org admin `user`, `None` removes them.
+    /// The last admin can't be removed or demoted.
+    pub fn set_member(
+        &mut self,
+        user: &str,
+        org: impl AsRef<str>,
+        member: impl AsRef<str>,
+        role: Option<Role>,
+    ) -> Result<Organization, RepoError> {
+        let org = org.as_ref();
+        self.check_org_role(Some(user), org, Role::Admin)?;
+        let organization = self.orgs.get_mut(org).ok_or(RepoError::NotFound)?;
+        let mut members = organization.members.clone();
+        match role {
+            Some(role) => members.insert(member.as_ref().to_string(), role),
+            None => members.remove(member.as_ref()),
+        };
+        if !members.values().any(|role| *role == Role::Admin) {
+            return Err(RepoError::Forbidden);
+        }
+        organization.members = members;
+        let organization = organization.clone();
+        self.mark_dirty();
+        Ok(organization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, SemVer};
+
+    #[test]
+    fn roles() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.create_org("rustaceans", "ferris")?;
+        assert_eq!(
+            Err(RepoError::AlreadyExists),
+            repo.create_org("rustaceans", "someone").map(|_| ())
+        );
+        repo.set_member("ferris", "rustaceans", "corro", Some(Role::Publisher))?;
+        repo.set_member("ferris", "rustaceans", "lurker", Some(Role::Reader))?;
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.set_member("corro", "rustaceans", "corro", Some(Role::Admin))
+                .map(|_| ())
+        );
+        // someone has to stay in charge
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.set_member("ferris", "rustaceans", "ferris", None)
+                .map(|_| ())
+        );
+
+        let metadata = Metadata::new("crab", "ferris", CrateKind::Library).with_org("rustaceans");
+        repo.add_crate(metadata.clone(), SemVer::new(1, 0, 0))?;
+        repo.check_role(Some("corro"), "crab", Role::Publisher)?;
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.check_role(Some("lurker"), "crab", Role::Publisher)
+        );
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.check_role(None, "crab", Role::Reader)
+        );
+
+        let described = metadata.with_description("🦀");
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.update_metadata("corro", described.clone())
+        );
+        let mut authorless = described.clone();
+        authorless.author = " ".into();
+        assert_eq!(
+            Err(RepoError::InvalidAuthor),
+            repo.update_metadata("ferris", authorless)
+        );
+        repo.update_metadata("ferris", described)?;
+        assert_eq!(
+            "🦀",
+            repo.find_exact("crab").unwrap().metadata().description()
+        );
+
+        // crates without an organization are edited by their author
+        repo.add_crate(
+            Metadata::new("solo", "corro", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.check_role(None, "solo", Role::Admin)?;
+        let adopted = Metadata::new("solo", "corro", CrateKind::Binary).with_org("rustaceans");
+        assert_eq!(
+            Err(RepoError::Forbidden),
+            repo.update_metadata("corro", adopted.clone())
+        );
+        repo.set_member("ferris", "rustaceans", "corro", Some(Role::Admin))?;
+        repo.update_metadata("corro", adopted)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,129 @@
+//! Keeps a panic while handling a request, e.g. from a bug in an index, from taking down the
+//! whole server. The request is answered with [`crate::api::ApiError::Internal`] and the panic
+//! logged with its backtrace, see [`isolate`].
+//!
+//! Any other panic might crash the process, so it first runs the handlers registered with
+//! [`on_crash`], e.g. to save the repository.
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::{Cell, RefCell};
+use std::panic::{self, AssertUnwindSafe, PanicHookInfo};
+use std::sync::{Mutex, Once};
+
+thread_local! {
+    /// whether a panic on this thread is caught by [`isolate`]
+    static ISOLATED: Cell<bool> = const { Cell::new(false) };
+    /// what the hook made of the last isolated panic on this thread
+    static REPORT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+static HOOK: Once = Once::new();
+
+/// run on panics that aren't isolated, dropped once they answer `false`
+type CrashHandler = Box<dyn Fn() -> bool + Send>;
+
+static CRASH_HANDLERS: Mutex<Vec<CrashHandler>> = Mutex::new(Vec::new());
+
+/// Runs `handler` on every panic that isn't isolated, once the previous hook is done with it,
+/// until `handler` returns `false`. Only works with the hook installed, see [`install_hook`].
+pub fn on_crash(handler: impl Fn() -> bool + Send + 'static) {
+    CRASH_HANDLERS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(Box::new(handler));
+}
+
+fn crashed() {
+    // held if a handler panicked, nothing more to be done then
+    if let Ok(mut handlers) = CRASH_HANDLERS.try_lock() {
+        handlers.retain(|handler| handler());
+    }
+}
+
+/// Installs a panic hook recording isolated panics with a backtrace instead of printing them,
+/// others are passed on to the hook that was installed before and then to the crash handlers.
+/// Only installs it once.
+pub fn install_hook() {
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info: &PanicHookInfo| {
+            if !ISOLATED.get() {
+                previous(info);
+                return crashed();
+            }
+            let location = info
+                .location()
+                .map(|location| format!(" at {}", location))
+                .unwrap_or_default();
+            let report = format!(
+                "{}{}\n{}",
+                message(info.payload()),
+                location,
+                Backtrace::force_capture()
+            );
+            REPORT.set(Some(report));
+        }));
+    });
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .unwrap_or("panic"),
+    }
+}
+
+/// Runs `f`, catching a panic. Fails with the panic's message, location and backtrace if the
+/// hook is installed, see [`install_hook`], otherwise with just the message.
+pub fn isolate<T>(f: impl FnOnce() -> T) -> Result<T, String> {
+    let was_isolated = ISOLATED.replace(true);
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    ISOLATED.set(was_isolated);
+    res.map_err(|payload| {
+        REPORT
+            .take()
+            .unwrap_or_else(|| message(payload.as_ref()).to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn isolates_panics() {
+        install_hook();
+        assert_eq!(Ok(1), isolate(|| 1));
+        let report = isolate(|| -> () { panic!("index out of bounds") }).unwrap_err();
+        assert!(report.starts_with("index out of bounds at src/panics.rs:"));
+        // nested ones are caught by the innermost
+        let outer = isolate(|| isolate(|| -> () { panic!("inner") }).unwrap_err());
+        assert!(outer.unwrap().starts_with("inner"));
+    }
+
+    #[test]
+    fn runs_crash_handlers() {
+        install_hook();
+        let crashes = Arc::new(AtomicUsize::new(0));
+        let weak = Arc::downgrade(&crashes);
+        on_crash(move || match weak.upgrade() {
+            Some(crashes) => {
+                crashes.fetch_add(1, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        });
+        assert!(isolate(|| -> () { panic!("isolated") }).is_err());
+        assert_eq!(0, crashes.load(Ordering::SeqCst));
+        assert!(thread::spawn(|| panic!("crash")).join().is_err());
+        assert!(crashes.load(Ordering::SeqCst) >= 1);
+    }
+}
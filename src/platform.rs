@@ -0,0 +1,317 @@
+//! What a release builds with: the minimum supported Rust version and the targets it supports,
+//! so consumers can find releases that work for them, see [`crate::search::SearchOptions`].
+//! Releases without them are assumed to build anywhere.
+
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// most targets a single release may list
+pub const MAX_TARGETS: usize = 64;
+/// longest target triple
+const MAX_TARGET_LEN: usize = 64;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid Rust version '{0}', expected e.g. 1.70 or 1.70.1")]
+pub struct ParseRustVersionError(String);
+
+/// A Rust version like `1.70`, without pre-release or build suffixes
+#[derive(Debug, Hash, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct RustVersion {
+    pub major: u16,
+    pub minor: u16,
+    pub patch: u16,
+}
+
+impl RustVersion {
+    pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+        Self {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+/// `1.70` or `1.70.1`, like the `rust-version` of a manifest
+impl FromStr for RustVersion {
+    type Err = ParseRustVersionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ParseRustVersionError(s.to_string());
+        let mut parts = s.trim().split('.').map(|part| match part {
+            "" => Err(invalid()),
+            part if part.bytes().all(|b| b.is_ascii_digit()) => {
+                part.parse::<u16>().map_err(|_| invalid())
+            }
+            _ => Err(invalid()),
+        });
+        let major = parts.next().ok_or_else(invalid)??;
+        let minor = parts.next().ok_or_else(invalid)??;
+        let patch = parts.next().transpose()?.unwrap_or(0);
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self::new(major, minor, patch))
+    }
+}
+
+impl TryFrom<String> for RustVersion {
+    type Error = ParseRustVersionError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<RustVersion> for String {
+    fn from(version: RustVersion) -> Self {
+        version.to_string()
+    }
+}
+
+/// without the patch version if it is 0, like manifests usually have it
+impl Display for RustVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.patch {
+            0 => write!(f, "{}.{}", self.major, self.minor),
+            patch => write!(f, "{}.{}.{}", self.major, self.minor, patch),
+        }
+    }
+}
+
+/// What a release builds with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Platform {
+    /// the oldest Rust version the release builds with, any if `None`
+    #[serde(default)]
+    pub rust_version: Option<RustVersion>,
+    /// target triples like `x86_64-unknown-linux-gnu` the release supports, any if empty
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+impl Platform {
+    /// whether a release of this platform builds with `rust_version` for `target`, either
+    /// unknown if `None`
+    pub fn supports(&self, rust_version: Option<RustVersion>, target: Option<&str>) -> bool {
+        let rust = match (self.rust_version, rust_version) {
+            (Some(required), Some(available)) => required <= available,
+            _ => true,
+        };
+        let target = match target {
+            Some(target) if !self.targets.is_empty() => {
+                self.targets.iter().any(|t| t.eq_ignore_ascii_case(target))
+            }
+            _ => true,
+        };
+        rust && target
+    }
+
+    fn validate(&self) -> Result<(), RepoError> {
+        let valid_target = |target: &String| {
+            !target.is_empty()
+                && target.len() <= MAX_TARGET_LEN
+                && target
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        };
+        match self.targets.len() <= MAX_TARGETS && self.targets.iter().all(valid_target) {
+            true => Ok(()),
+            false => Err(RepoError::InvalidTarget),
+        }
+    }
+}
+
+impl Crate {
+    /// what `version` builds with, `None` if nothing is known
+    pub fn platform(&self, version: SemVer) -> Option<&Platform> {
+        self.platforms
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, platform)| platform)
+    }
+
+    /// the newest release that isn't yanked or blocked and builds with `rust_version` for `target`,
+    /// see [`Platform::supports`]
+    pub fn latest_compatible(
+        &self,
+        rust_version: Option<RustVersion>,
+        target: Option<&str>,
+    ) -> Option<SemVer> {
+        self.release_history
+            .iter()
+            .filter(|v| !self.is_yanked(**v) && !self.is_blocked(**v))
+            .filter(|v| {
+                self.platform(**v)
+                    .is_none_or(|platform| platform.supports(rust_version, target))
+            })
+            .max()
+            .copied()
+    }
+
+    /// Replaces what `version` builds with, the default platform removes it
+    pub(crate) fn set_platform(
+        &mut self,
+        version: SemVer,
+        platform: Platform,
+    ) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        platform.validate()?;
+        self.platforms.retain(|(v, _)| *v != version);
+        if platform != Platform::default() {
+            self.platforms.push((version, platform));
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    /// Records the minimum Rust version and targets of `version` of the crate called `name`,
+    /// replacing what was known before
+    pub fn set_platform(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        platform: Platform,
+    ) -> Result<(), RepoError> {
+        let crt = self
+            .crates
+            .get_mut(name.as_ref())
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_platform(version, platform.clone())?;
+        self.changes.record(Event::PlatformChanged {
+            name: crt.metadata.name.clone(),
+            version,
+            platform,
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::search::SearchOptions;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn rust_versions() {
+        assert_eq!(Ok(RustVersion::new(1, 70, 0)), "1.70".parse());
+        assert_eq!(Ok(RustVersion::new(1, 70, 1)), " 1.70.1".parse());
+        for invalid in ["", "1", "1.", "1.70.0.0", "1.70-beta", "+1.70", "1.99999"] {
+            assert!(invalid.parse::<RustVersion>().is_err(), "{}", invalid);
+        }
+        assert_eq!("1.70", RustVersion::new(1, 70, 0).to_string());
+        assert!(RustVersion::new(1, 9, 0) < RustVersion::new(1, 70, 0));
+        assert_eq!(
+            r#"{"rust_version":"1.56.1","targets":[]}"#,
+            serde_json::to_string(&Platform {
+                rust_version: Some(RustVersion::new(1, 56, 1)),
+                targets: vec![],
+            })
+            .unwrap()
+        );
+    }
+
+    #[test]
+    fn compatible_releases() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.add_release("hello_bin", SemVer::new(2, 0, 0))?;
+        repo.set_platform(
+            "hello_bin",
+            SemVer::new(1, 1, 0),
+            Platform {
+                rust_version: Some(RustVersion::new(1, 60, 0)),
+                targets: vec!["x86_64-unknown-linux-gnu".into()],
+            },
+        )?;
+        repo.set_platform(
+            "hello_bin",
+            SemVer::new(2, 0, 0),
+            Platform {
+                rust_version: Some(RustVersion::new(1, 70, 0)),
+                targets: vec![],
+            },
+        )?;
+        assert_eq!(
+            Err(RepoError::InvalidTarget),
+            repo.set_platform(
+                "hello_bin",
+                SemVer::new(1, 0, 0),
+                Platform {
+                    rust_version: None,
+                    targets: vec!["not a target".into()],
+                },
+            )
+        );
+
+        let crt = repo.find_exact("hello_bin").unwrap();
+        let msrv = |v| Some(RustVersion::new(1, v, 0));
+        assert_eq!(
+            Some(SemVer::new(2, 0, 0)),
+            crt.latest_compatible(None, None)
+        );
+        assert_eq!(
+            Some(SemVer::new(2, 0, 0)),
+            crt.latest_compatible(msrv(70), None)
+        );
+        assert_eq!(
+            Some(SemVer::new(1, 1, 0)),
+            crt.latest_compatible(msrv(65), Some("x86_64-unknown-linux-gnu"))
+        );
+        assert_eq!(
+            Some(SemVer::new(1, 0, 0)),
+            crt.latest_compatible(msrv(65), Some("aarch64-apple-darwin"))
+        );
+        assert_eq!(None, crt.platform(SemVer::new(1, 0, 0)));
+
+        repo.add_crate(
+            Metadata::new("hello_new", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        let bleeding_edge = Platform {
+            rust_version: Some(RustVersion::new(1, 80, 0)),
+            targets: vec![],
+        };
+        repo.set_platform("hello_new", SemVer::new(1, 0, 0), bleeding_edge)?;
+        let options = SearchOptions {
+            rust_version: msrv(70),
+            ..SearchOptions::default()
+        };
+        let found: Vec<_> = repo
+            .find_containing("hello", options)
+            .iter()
+            .map(|crt| crt.metadata().name())
+            .collect();
+        assert_eq!(vec!["hello_bin"], found);
+
+        repo.set_platform("hello_bin", SemVer::new(2, 0, 0), Platform::default())?;
+        let crt = repo.find_exact("hello_bin").unwrap();
+        assert_eq!(None, crt.platform(SemVer::new(2, 0, 0)));
+        assert_eq!(
+            Some(SemVer::new(2, 0, 0)),
+            crt.latest_compatible(msrv(50), None)
+        );
+        Ok(())
+    }
+}
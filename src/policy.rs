@@ -0,0 +1,167 @@
+//! Operator rules deciding whether a publish is accepted, on top of what the repository itself
+//! checks. Servers run the policies of [`crate::server::ServerConfig::policies`], implemented by
+//! whoever embeds the server, and the built-in ones listed in the settings file like
+//!
+//! ```toml
+//! [[policies]]
+//! policy = "author_email_domain"
+//! domain = "example.com"
+//!
+//! [[policies]]
+//! policy = "require_stable"
+//! ```
+//!
+//! see [`crate::settings`]. Rejections are answered with
+//! [`crate::api::ApiError::PolicyViolation`], naming the policy and why.
+
+use std::fmt::Debug;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Metadata, SemVer};
+
+/// A publish about to happen, a new crate or a release of an existing one
+#[derive(Debug, Clone, Copy)]
+pub struct Publish<'a> {
+    /// of the new crate, or the existing one for releases
+    pub metadata: &'a Metadata,
+    pub version: SemVer,
+    pub new_crate: bool,
+    /// the authenticated user, see [`crate::api::ApiRequest::Authenticated`]
+    pub user: Option<&'a str>,
+}
+
+pub trait PublishPolicy: Debug + Send + Sync {
+    /// named in rejections
+    fn name(&self) -> &str;
+
+    /// `Err` with the reason if `publish` must be rejected
+    fn check(&self, publish: &Publish) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub policy: String,
+    pub reason: String,
+}
+
+/// the verdict of the first policy rejecting `publish`
+pub fn check_all<'a>(
+    policies: impl IntoIterator<Item = &'a dyn PublishPolicy>,
+    publish: &Publish,
+) -> Result<(), PolicyViolation> {
+    for policy in policies {
+        policy.check(publish).map_err(|reason| PolicyViolation {
+            policy: policy.name().to_string(),
+            reason,
+        })?;
+    }
+    Ok(())
+}
+
+/// Policies configured by name in the settings file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case", deny_unknown_fields)]
+pub enum BuiltinPolicy {
+    /// authors must give an address at `domain`, e.g. `Jane Doe <jane@example.com>`
+    AuthorEmailDomain { domain: String },
+    /// no 0.x versions, e.g. for production registries
+    RequireStable,
+    /// names must start with `prefix`, e.g. `acme-`
+    NamePrefix { prefix: String },
+}
+
+impl PublishPolicy for BuiltinPolicy {
+    fn name(&self) -> &str {
+        match self {
+            BuiltinPolicy::AuthorEmailDomain { .. } => "author_email_domain",
+            BuiltinPolicy::RequireStable => "require_stable",
+            BuiltinPolicy::NamePrefix { .. } => "name_prefix",
+        }
+    }
+
+    fn check(&self, publish: &Publish) -> Result<(), String> {
+        match self {
+            BuiltinPolicy::AuthorEmailDomain { domain } => {
+                let author = publish.metadata.author();
+                // either a bare address or a name with the address in angle brackets
+                let email = match (author.rfind('<'), author.rfind('>')) {
+                    (Some(start), Some(end)) if start < end => &author[start + 1..end],
+                    _ => author,
+                };
+                match email.rsplit_once('@') {
+                    Some((user, host)) if !user.is_empty() && host.eq_ignore_ascii_case(domain) => {
+                        Ok(())
+                    }
+                    _ => Err(format!("author must use an @{} address", domain)),
+                }
+            }
+            BuiltinPolicy::RequireStable if publish.version.major() == 0 => {
+                Err(format!("{} is not a stable version", publish.version))
+            }
+            BuiltinPolicy::RequireStable => Ok(()),
+            BuiltinPolicy::NamePrefix { prefix }
+                if !publish.metadata.name().starts_with(prefix) =>
+            {
+                Err(format!("name must start with '{}'", prefix))
+            }
+            BuiltinPolicy::NamePrefix { .. } => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CrateKind;
+
+    fn publish(metadata: &Metadata, version: SemVer) -> Publish<'_> {
+        Publish {
+            metadata,
+            version,
+            new_crate: true,
+            user: None,
+        }
+    }
+
+    #[test]
+    fn builtin_policies() {
+        let email = BuiltinPolicy::AuthorEmailDomain {
+            domain: "example.com".into(),
+        };
+        for (author, ok) in [
+            ("Jane Doe <jane@Example.com>", true),
+            ("jane@example.com", true),
+            ("Jane Doe <jane@example.org>", false),
+            ("@example.com", false),
+            ("Jane Doe", false),
+        ] {
+            let metadata = Metadata::new("acme-tools", author, CrateKind::Library);
+            let res = email.check(&publish(&metadata, SemVer::new(1, 0, 0)));
+            assert_eq!(ok, res.is_ok(), "{}", author);
+        }
+
+        let metadata = Metadata::new("tools", "jane@example.com", CrateKind::Library);
+        let policies = [
+            email,
+            BuiltinPolicy::RequireStable,
+            BuiltinPolicy::NamePrefix {
+                prefix: "acme-".into(),
+            },
+        ];
+        let all = || policies.iter().map(|policy| policy as &dyn PublishPolicy);
+        assert_eq!(
+            Err(PolicyViolation {
+                policy: "require_stable".into(),
+                reason: "0.1.0 is not a stable version".into(),
+            }),
+            check_all(all(), &publish(&metadata, SemVer::new(0, 1, 0)))
+        );
+        assert_eq!(
+            "name_prefix",
+            check_all(all(), &publish(&metadata, SemVer::new(1, 0, 0)))
+                .unwrap_err()
+                .policy
+        );
+    }
+}
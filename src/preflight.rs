@@ -0,0 +1,196 @@
+//! Pre-flight checks of a release before it is published, see
+//! [`crate::api::ApiRequest::CheckPublish`]. Unlike a dry run, they also point out what would be
+//! accepted but looks like a mistake, so CI can gate releases on stricter policies than the
+//! repository enforces.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::ApiError;
+use crate::{Metadata, Repository, SemVer};
+
+/// most crates named like a new one that are pointed out
+const MAX_SIMILAR: usize = 5;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum PublishWarning {
+    /// publishing would fail with this error
+    Rejected(ApiError),
+    /// e.g. 3.0.0 after 1.2.0
+    SkipsMajor {
+        latest: SemVer,
+    },
+    /// newer, but not the next major, minor or patch version after `latest`, e.g. 1.4.0 after
+    /// 1.2.0
+    SkipsVersions {
+        latest: SemVer,
+    },
+    DescriptionMissing,
+    KeywordsMissing,
+    /// a new crate named much like an existing one, e.g. `serde-json` next to `serde_json`
+    SimilarName(String),
+}
+
+impl Display for PublishWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublishWarning::Rejected(e) => write!(f, "would be rejected: {}", e),
+            PublishWarning::SkipsMajor { latest } => {
+                write!(f, "version skips a major, the latest is {}", latest)
+            }
+            PublishWarning::SkipsVersions { latest } => {
+                write!(f, "version skips versions, the latest is {}", latest)
+            }
+            PublishWarning::DescriptionMissing => write!(f, "description missing"),
+            PublishWarning::KeywordsMissing => write!(f, "keywords missing"),
+            PublishWarning::SimilarName(name) => {
+                write!(f, "name similar to existing crate {}", name)
+            }
+        }
+    }
+}
+
+impl Repository {
+    /// What's wrong or suspicious about publishing `version` of the crate described by
+    /// `metadata`, a release if there is such a crate, otherwise a new crate. Nothing is published,
+    /// permissions and quotas of the publisher aren't checked.
+    pub fn check_publish(&mut self, metadata: &Metadata, version: SemVer) -> Vec<PublishWarning> {
+        let mut warnings = vec![];
+        let name = metadata.name();
+        let latest = self
+            .find_exact(name)
+            .map(|crt| crt.releases().iter().max().copied());
+
+        let checkpoint = self.checkpoint();
+        let res = match latest {
+            Some(_) => self.add_release(name, version),
+            None => self.add_crate(metadata.clone(), version),
+        };
+        self.rollback(checkpoint);
+        if let Err(e) = res {
+            warnings.push(PublishWarning::Rejected(e.into()));
+        }
+
+        if let Some(Some(latest)) = latest {
+            if version.major() > latest.major().saturating_add(1) {
+                warnings.push(PublishWarning::SkipsMajor { latest });
+            } else if version > latest && !next_versions(latest).contains(&version) {
+                warnings.push(PublishWarning::SkipsVersions { latest });
+            }
+        }
+        if metadata.description().trim().is_empty() {
+            warnings.push(PublishWarning::DescriptionMissing);
+        }
+        if metadata.keywords().is_empty() {
+            warnings.push(PublishWarning::KeywordsMissing);
+        }
+        if latest.is_none() {
+            let similar = self
+                .iter()
+                .map(|crt| crt.metadata().name())
+                .filter(|existing| similar(existing, name))
+                .take(MAX_SIMILAR)
+                .map(|existing| PublishWarning::SimilarName(existing.to_string()));
+            warnings.extend(similar);
+        }
+        warnings
+    }
+}
+
+/// the next major, minor and patch version
+fn next_versions(latest: SemVer) -> [SemVer; 3] {
+    let (major, minor, patch) = (latest.major(), latest.minor(), latest.patch());
+    [
+        SemVer::new(major.saturating_add(1), 0, 0),
+        SemVer::new(major, minor.saturating_add(1), 0),
+        SemVer::new(major, minor, patch.saturating_add(1)),
+    ]
+}
+
+/// Whether `a` and `b` differ only in case and `-` versus `_`, or, unless they are short, by a
+/// single character inserted, removed or replaced.
+fn similar(a: &str, b: &str) -> bool {
+    let normalize = |s: &str| s.to_lowercase().replace('-', "_");
+    let (a, b) = (normalize(a), normalize(b));
+    if a == b {
+        return true;
+    }
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    if a.len().min(b.len()) < 5 || a.len().abs_diff(b.len()) > 1 {
+        return false;
+    }
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    a.len().max(b.len()) - prefix - suffix <= 1
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, RepoError};
+
+    #[test]
+    fn similar_names() {
+        assert!(similar("serde_json", "Serde-JSON"));
+        assert!(similar("serde_json", "serde_jsn"));
+        assert!(similar("tokio-util", "tokio-utils"));
+        assert!(similar("reqwest", "reqwests"));
+        assert!(!similar("log", "lag"));
+        assert!(!similar("serde_json", "serde_yaml"));
+        assert!(!similar("hello", "world"));
+    }
+
+    #[test]
+    fn warnings() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(store.path());
+        let metadata = Metadata::new("serde_json", "someone", CrateKind::Library)
+            .with_description("JSON")
+            .with_keywords(["json"]);
+        repo.add_crate(metadata.clone(), SemVer::new(1, 2, 0))?;
+
+        assert!(repo
+            .check_publish(&metadata, SemVer::new(1, 2, 1))
+            .is_empty());
+        assert!(matches!(
+            repo.check_publish(&metadata, SemVer::new(3, 0, 0))[..],
+            [PublishWarning::SkipsMajor { .. }]
+        ));
+        assert!(matches!(
+            repo.check_publish(&metadata, SemVer::new(1, 4, 0))[..],
+            [PublishWarning::SkipsVersions { .. }]
+        ));
+        assert!(matches!(
+            repo.check_publish(&metadata, SemVer::new(1, 1, 0))[..],
+            [PublishWarning::Rejected(ApiError::Repo(
+                RepoError::NotMonotonic
+            ))]
+        ));
+
+        let typo = Metadata::new("serde-json", "mallory", CrateKind::Library);
+        let warnings = repo.check_publish(&typo, SemVer::new(1, 0, 0));
+        assert_eq!(
+            vec![
+                "description missing",
+                "keywords missing",
+                "name similar to existing crate serde_json"
+            ],
+            warnings.iter().map(ToString::to_string).collect::<Vec<_>>()
+        );
+        // nothing got published
+        assert!(repo.find_exact("serde-json").is_none());
+        assert_eq!(
+            Some(SemVer::new(1, 2, 0)),
+            repo.find_exact("serde_json").unwrap().latest()
+        );
+        Ok(())
+    }
+}
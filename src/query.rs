@@ -0,0 +1,93 @@
+//! Combinators for building reports over a repository's crates.
+//!
+//! ```
+//! use semver_repo::query::CrateQuery;
+//! use semver_repo::{CrateKind, Repository};
+//!
+//! # let store = tempfile::NamedTempFile::new().unwrap();
+//! let repo = Repository::new(&store);
+//! let binaries: Vec<_> = repo
+//!     .crates()
+//!     .filter_by_kind(CrateKind::Binary)
+//!     .sorted_by_name()
+//!     .map(|crt| crt.metadata().name())
+//!     .collect();
+//! # assert!(binaries.is_empty());
+//! ```
+
+use chrono::{DateTime, Utc};
+
+use crate::{Crate, CrateKind};
+
+/// Query helpers for any iterator over crates, e.g. [`crate::Repository::crates`]
+pub trait CrateQuery<'a>: Iterator<Item = &'a Crate> + Sized {
+    fn filter_by_kind(self, kind: CrateKind) -> impl Iterator<Item = &'a Crate> {
+        self.filter(move |crt| *crt.metadata().kind() == kind)
+    }
+
+    /// crates with at least one release published after `since`
+    fn published_after(self, since: DateTime<Utc>) -> impl Iterator<Item = &'a Crate> {
+        self.filter(move |crt| {
+            crt.releases()
+                .iter()
+                .any(|v| crt.published_at(*v).is_some_and(|at| at > since))
+        })
+    }
+
+    fn sorted_by_name(self) -> std::vec::IntoIter<&'a Crate> {
+        let mut crates: Vec<_> = self.collect();
+        crates.sort_by(|a, b| a.metadata().name().cmp(b.metadata().name()));
+        crates.into_iter()
+    }
+}
+
+impl<'a, I: Iterator<Item = &'a Crate>> CrateQuery<'a> for I {}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{Metadata, RepoError, Repository, SemVer};
+
+    #[test]
+    fn queries() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for (name, kind) in [
+            ("zebra", CrateKind::Binary),
+            ("serde", CrateKind::Library),
+            ("alpaca", CrateKind::Binary),
+        ] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", kind),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+
+        let names: Vec<_> = repo
+            .crates()
+            .filter_by_kind(CrateKind::Binary)
+            .sorted_by_name()
+            .map(|crt| crt.metadata().name())
+            .collect();
+        assert_eq!(vec!["alpaca", "zebra"], names);
+
+        let now = Utc::now();
+        assert_eq!(
+            3,
+            repo.crates().published_after(DateTime::default()).count()
+        );
+        assert_eq!(0, repo.crates().published_after(now).count());
+        repo.add_release("serde", SemVer::new(1, 1, 0))?;
+        let recent: Vec<_> = repo.crates().published_after(now).collect();
+        assert_eq!(
+            vec!["serde"],
+            recent
+                .iter()
+                .map(|c| c.metadata().name())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Limits keeping a shared instance healthy, configured with
+//! [`crate::server::ServerConfig::quotas`].
+//!
+//! Violations are answered with [`crate::api::ApiError::QuotaExceeded`].
+
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::Repository;
+
+/// all unlimited by default
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quotas {
+    /// Crates with the same author, counted for the authenticated user publishing a new one.
+    /// Requires clients to authenticate for publishing crates.
+    pub max_crates_per_author: Option<usize>,
+    /// releases of a crate published within 24 hours
+    pub max_releases_per_day: Option<usize>,
+    /// Size of a request in bytes. Crates are published as part of the request, so this limits
+    /// the size of what gets published as well.
+    pub max_request_size: Option<usize>,
+}
+
+/// the quota that was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Quota {
+    CratesPerAuthor,
+    ReleasesPerDay,
+    RequestSize,
+}
+
+impl Repository {
+    /// whether `author` may publish another crate
+    pub fn check_crate_quota(&self, quotas: &Quotas, author: &str) -> Result<(), Quota> {
+        let max = match quotas.max_crates_per_author {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let crates = self
+            .iter()
+            .filter(|crt| crt.metadata().author() == author)
+            .count();
+        if crates >= max {
+            return Err(Quota::CratesPerAuthor);
+        }
+        Ok(())
+    }
+
+    /// whether `count` more releases of the crate called `name` may be published today
+    pub fn check_release_quota(
+        &self,
+        quotas: &Quotas,
+        name: &str,
+        count: usize,
+    ) -> Result<(), Quota> {
+        let max = match quotas.max_releases_per_day {
+            Some(max) => max,
+            None => return Ok(()),
+        };
+        let since = Utc::now() - Duration::days(1);
+        let recent = self
+            .find_exact(name)
+            .map(|crt| {
+                crt.releases()
+                    .iter()
+                    .filter_map(|v| crt.published_at(*v))
+                    .filter(|at| *at > since)
+                    .count()
+            })
+            .unwrap_or(0);
+        if recent + count > max {
+            return Err(Quota::ReleasesPerDay);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, SemVer};
+
+    #[test]
+    fn quotas() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let quotas = Quotas {
+            max_crates_per_author: Some(1),
+            max_releases_per_day: Some(2),
+            max_request_size: None,
+        };
+
+        assert_eq!(Ok(()), repo.check_crate_quota(&quotas, "Busy Person"));
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert_eq!(
+            Err(Quota::CratesPerAuthor),
+            repo.check_crate_quota(&quotas, "Busy Person")
+        );
+        assert_eq!(Ok(()), repo.check_crate_quota(&quotas, "Idle Person"));
+
+        assert_eq!(Ok(()), repo.check_release_quota(&quotas, "hello_bin", 1));
+        assert_eq!(
+            Err(Quota::ReleasesPerDay),
+            repo.check_release_quota(&quotas, "hello_bin", 2)
+        );
+        assert_eq!(
+            Ok(()),
+            repo.check_release_quota(&Quotas::default(), "hello_bin", 99)
+        );
+        Ok(())
+    }
+}
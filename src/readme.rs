@@ -0,0 +1,171 @@
+//! Markdown READMEs attached to single versions. Their content is kept in the [`crate::blobs`]
+//! next to the store, the store only records which blob belongs to which version.
+
+use std::io;
+use std::sync::Arc;
+
+use log::error;
+use thiserror::Error;
+
+use crate::api::ApiError;
+use crate::blobs::{BlobId, BlobStore};
+use crate::events::Event;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// largest README of a single version, in bytes
+pub const MAX_README_LEN: usize = 512 * 1024;
+
+#[derive(Error, Debug)]
+pub enum ReadmeError {
+    #[error(transparent)]
+    Repo(#[from] RepoError),
+    #[error("blob storage failed: {0}")]
+    Io(#[from] io::Error),
+}
+
+impl From<ReadmeError> for ApiError {
+    fn from(e: ReadmeError) -> Self {
+        match e {
+            ReadmeError::Repo(e) => ApiError::Repo(e),
+            ReadmeError::Io(e) => {
+                error!("README blob storage failed: {}", e);
+                ApiError::Internal
+            }
+        }
+    }
+}
+
+impl Crate {
+    /// the blob holding the README of `version`
+    pub fn readme_id(&self, version: SemVer) -> Option<&BlobId> {
+        self.readmes
+            .iter()
+            .find(|(v, _)| *v == version)
+            .map(|(_, id)| id)
+    }
+
+    /// the blobs holding this crate's READMEs
+    pub(crate) fn readme_ids(&self) -> impl Iterator<Item = &BlobId> {
+        self.readmes.iter().map(|(_, id)| id)
+    }
+
+    /// Replaces the README of `version`, `None` removes it
+    pub(crate) fn set_readme(
+        &mut self,
+        version: SemVer,
+        readme: Option<BlobId>,
+    ) -> Result<(), RepoError> {
+        if !self.release_history.contains(&version) {
+            return Err(RepoError::NotFound);
+        }
+        self.readmes.retain(|(v, _)| *v != version);
+        if let Some(readme) = readme {
+            self.readmes.push((version, readme));
+        }
+        self.revision += 1;
+        Ok(())
+    }
+}
+
+impl Repository {
+    pub fn blobs(&self) -> BlobStore {
+        BlobStore::beside(&self.store)
+    }
+
+    /// Attaches a markdown README of up to [`MAX_README_LEN`] bytes to `version` of the crate
+    /// called `name`, replacing an earlier one. `None` or an empty README removes it.
+    pub fn set_readme(
+        &mut self,
+        name: impl AsRef<str>,
+        version: SemVer,
+        readme: Option<&str>,
+    ) -> Result<(), ReadmeError> {
+        let name = name.as_ref();
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        if !crt.releases().contains(&version) {
+            return Err(RepoError::NotFound.into());
+        }
+        let readme = readme.filter(|readme| !readme.trim().is_empty());
+        if readme.map(str::len) > Some(MAX_README_LEN) {
+            return Err(RepoError::ReadmeTooLong.into());
+        }
+        let blob = readme
+            .map(|readme| self.blobs().put(readme.as_bytes()))
+            .transpose()?;
+        let crt = self
+            .crates
+            .get_mut(name)
+            .map(Arc::make_mut)
+            .ok_or(RepoError::NotFound)?;
+        crt.set_readme(version, blob.clone())?;
+        self.changes.record(Event::ReadmeChanged {
+            name: crt.metadata.name.clone(),
+            version,
+            blob,
+        });
+        Ok(())
+    }
+
+    /// The README of `version` of the crate called `name`, `None` if it has none or its blob is
+    /// missing, e.g. on a follower that hasn't fetched it yet
+    pub fn readme(
+        &self,
+        name: impl AsRef<str>,
+        version: SemVer,
+    ) -> Result<Option<String>, ReadmeError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        let Some(id) = crt.readme_id(version) else {
+            return Ok(None);
+        };
+        Ok(self
+            .blobs()
+            .get(id)?
+            .map(|content| String::from_utf8_lossy(&content).into_owned()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[test]
+    fn readmes() -> Result<(), ReadmeError> {
+        let dir = tempdir()?;
+        let mut repo = Repository::new(dir.path().join("store.json"));
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+
+        repo.set_readme("hello_bin", SemVer::new(1, 0, 0), Some("# Hello"))?;
+        repo.set_readme("hello_bin", SemVer::new(1, 1, 0), Some("# Hello"))?;
+        assert!(matches!(
+            repo.set_readme("hello_bin", SemVer::new(2, 0, 0), Some("# Hello")),
+            Err(ReadmeError::Repo(RepoError::NotFound))
+        ));
+        let huge = "x".repeat(MAX_README_LEN + 1);
+        assert!(matches!(
+            repo.set_readme("hello_bin", SemVer::new(1, 1, 0), Some(&huge)),
+            Err(ReadmeError::Repo(RepoError::ReadmeTooLong))
+        ));
+        // both versions share the blob
+        assert_eq!(1, std::fs::read_dir(repo.blobs().dir())?.count());
+        assert_eq!(
+            Some("# Hello".to_string()),
+            repo.readme("hello_bin", SemVer::new(1, 1, 0))?
+        );
+
+        repo.set_readme("hello_bin", SemVer::new(1, 0, 0), None)?;
+        assert_eq!(None, repo.readme("hello_bin", SemVer::new(1, 0, 0))?);
+        assert!(matches!(
+            repo.readme("nope", SemVer::new(1, 0, 0)),
+            Err(ReadmeError::Repo(RepoError::NotFound))
+        ));
+        assert_eq!(5, repo.changes().last_seq());
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! Several independent registries in one process, e.g. per team or environment.
+//!
+//! A [`RepositoryManager`] keeps the registries in one directory, each in its own store named
+//! `<name>.json`, which is a directory if sharded. Servers address them
+//! with [`crate::api::ApiRequest::Registry`], requests without one go to the default registry.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+use crate::encryption::StoreKey;
+use crate::{Repository, StoreError};
+
+#[derive(Error, Debug)]
+pub enum RegistryError {
+    #[error("invalid registry name '{0}', use lowercase letters, digits, '-' and '_'")]
+    InvalidName(String),
+    #[error("registry '{0}' exists already")]
+    AlreadyExists(String),
+    #[error("could not open registry '{0}': {1}")]
+    Store(String, StoreError),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+/// whether `name` can name a registry, and its store
+pub fn is_valid_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_')
+}
+
+/// The registries stored in a directory, each loaded and saved on its own
+#[derive(Debug)]
+pub struct RepositoryManager {
+    dir: PathBuf,
+    key: Option<StoreKey>,
+    repositories: BTreeMap<String, Arc<Mutex<Repository>>>,
+}
+
+impl RepositoryManager {
+    /// Opens every registry in `dir`, creating the directory if needed. All stores are encrypted
+    /// with `key`, see [`crate::encryption`].
+    pub fn open(dir: impl AsRef<Path>, key: Option<StoreKey>) -> Result<Self, RegistryError> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let mut manager = Self {
+            dir: dir.to_path_buf(),
+            key,
+            repositories: BTreeMap::new(),
+        };
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension != "json") {
+                continue;
+            }
+            if let Some(name) = path.file_stem().and_then(|name| name.to_str()) {
+                if is_valid_name(name) {
+                    manager.load(name.to_string(), &path)?;
+                }
+            }
+        }
+        Ok(manager)
+    }
+
+    fn load(
+        &mut self,
+        name: String,
+        store: &Path,
+    ) -> Result<Arc<Mutex<Repository>>, RegistryError> {
+        let repository = Repository::open(store, self.key.clone())
+            .map_err(|e| RegistryError::Store(name.clone(), e))?;
+        let repository = Arc::new(Mutex::new(repository));
+        self.repositories.insert(name, repository.clone());
+        Ok(repository)
+    }
+
+    /// a new, empty registry, saved on the next save
+    pub fn create(
+        &mut self,
+        name: impl Into<String>,
+    ) -> Result<Arc<Mutex<Repository>>, RegistryError> {
+        let name = name.into();
+        if !is_valid_name(&name) {
+            return Err(RegistryError::InvalidName(name));
+        }
+        if self.repositories.contains_key(&name) {
+            return Err(RegistryError::AlreadyExists(name));
+        }
+        let store = self.dir.join(format!("{}.json", name));
+        let repository = self.load(name, &store)?;
+        repository.lock().unwrap().mark_dirty();
+        Ok(repository)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Mutex<Repository>>> {
+        self.repositories.get(name)
+    }
+
+    /// the registries, ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Arc<Mutex<Repository>>)> {
+        self.repositories
+            .iter()
+            .map(|(name, repository)| (name.as_str(), repository))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.repositories.keys().map(String::as_str)
+    }
+
+    /// saves the registries that changed, returns how many were saved
+    pub fn save_all(&self) -> io::Result<usize> {
+        let mut saved = 0;
+        for repository in self.repositories.values() {
+            if repository.lock().unwrap().save_if_dirty()? {
+                saved += 1;
+            }
+        }
+        Ok(saved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::shards::Sharding;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn registries() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let mut manager = RepositoryManager::open(dir.path(), None)?;
+        assert_eq!(0, manager.names().count());
+        for (name, author) in [("team-a", "alice"), ("team-b", "bob")] {
+            manager.create(name)?.lock().unwrap().add_crate(
+                Metadata::new("tools", author, CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        assert!(matches!(
+            manager.create("team-a"),
+            Err(RegistryError::AlreadyExists(_))
+        ));
+        assert!(matches!(
+            manager.create("../escape"),
+            Err(RegistryError::InvalidName(_))
+        ));
+        manager
+            .get("team-b")
+            .unwrap()
+            .lock()
+            .unwrap()
+            .set_sharding(Some(Sharding::default()));
+        assert_eq!(2, manager.save_all()?);
+        assert_eq!(0, manager.save_all()?);
+        drop(manager);
+
+        // both come back, each with its own crates
+        let manager = RepositoryManager::open(dir.path(), None)?;
+        assert_eq!(
+            vec!["team-a", "team-b"],
+            manager.names().collect::<Vec<_>>()
+        );
+        for (name, author) in [("team-a", "alice"), ("team-b", "bob")] {
+            let repository = manager.get(name).unwrap().lock().unwrap();
+            let crt = repository.find_exact("tools").unwrap();
+            assert_eq!(author, crt.metadata().author());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+//! Rendering the repository into a static, read-only website.
+//!
+//! The site consists of an `index.html` listing all crates and a page per crate under `crates/`
+//! with its version history and the README of its latest release. It only uses relative links,
+//! so it can be served from any directory.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use pulldown_cmark::{CowStr, Event, Options, Parser, Tag};
+
+use crate::channels::Channel;
+use crate::{Crate, Repository};
+
+const STYLE: &str = "body{font-family:sans-serif;max-width:60em;margin:auto;padding:1em}\
+    table{border-collapse:collapse;width:100%}\
+    th,td{text-align:left;padding:.3em .6em;border-bottom:1px solid #ddd}\
+    .yanked{text-decoration:line-through;color:#888}\
+    .blocked{text-decoration:line-through;color:#c00}\
+    .deprecated{color:#a60}\
+    .readme{border:1px solid #ddd;padding:0 1em}";
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Markdown as HTML. Raw HTML is shown as text and links to scripts lead nowhere, READMEs are
+/// written by anyone who can publish.
+fn markdown(readme: &str) -> String {
+    let harmless = |url: CowStr<'static>| -> CowStr<'static> {
+        let scheme = url
+            .split(':')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match url.contains(':') && matches!(scheme.trim(), "javascript" | "vbscript" | "data") {
+            true => "#".into(),
+            false => url,
+        }
+    };
+    let events = Parser::new_ext(
+        readme,
+        Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH,
+    )
+    .map(|event| match event {
+        Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: harmless(dest_url.into_static()),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: harmless(dest_url.into_static()),
+            title,
+            id,
+        }),
+        event => event,
+    });
+    let mut html = String::with_capacity(readme.len() * 3 / 2);
+    pulldown_cmark::html::push_html(&mut html, events);
+    html
+}
+
+/// File name of the crate's page. Everything but ASCII letters, digits, `-`, `_` and `.` is
+/// written as `~` and two hex digits per byte, so the name is safe both on disk and in links.
+fn page_name(name: &str) -> String {
+    let mut page = String::with_capacity(name.len() + 5);
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => page.push(byte as char),
+            byte => {
+                let _ = write!(page, "~{byte:02X}");
+            }
+        }
+    }
+    page.push_str(".html");
+    page
+}
+
+fn page(title: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>{}</title>\n<style>{STYLE}</style>\n</head>\n<body>\n{body}</body>\n</html>\n",
+        escape(title)
+    )
+}
+
+fn index_page(repo: &Repository) -> String {
+    let mut body = String::from(
+        "<h1>Crates</h1>\n<table>\n\
+         <tr><th>Name</th><th>Latest</th><th>Author</th><th>Description</th></tr>\n",
+    );
+    for crt in repo.iter() {
+        let metadata = crt.metadata();
+        let latest = crt.latest().map(|v| v.to_string()).unwrap_or_default();
+        let deprecated = if crt.deprecation().is_some() {
+            " <span class=\"deprecated\">deprecated</span>"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            body,
+            "<tr><td><a href=\"crates/{}\">{}</a>{deprecated}</td><td>{}</td><td>{}</td>\
+             <td>{}</td></tr>",
+            page_name(metadata.name()),
+            escape(metadata.name()),
+            latest,
+            escape(metadata.author()),
+            escape(metadata.description()),
+        );
+    }
+    body.push_str("</table>\n");
+    page("Crates", &body)
+}
+
+fn crate_page(crt: &Crate, readme: Option<&str>) -> String {
+    let metadata = crt.metadata();
+    let mut body = format!(
+        "<p><a href=\"../index.html\">All crates</a></p>\n<h1>{}</h1>\n<p>{}</p>\n\
+         <p>by {}, {:?}</p>\n",
+        escape(metadata.name()),
+        escape(metadata.description()),
+        escape(metadata.author()),
+        metadata.kind(),
+    );
+    if let Some(deprecation) = crt.deprecation() {
+        body.push_str("<p class=\"deprecated\">Deprecated");
+        if let Some(message) = &deprecation.message {
+            let _ = write!(body, ": {}", escape(message));
+        }
+        if let Some(replacement) = &deprecation.replacement {
+            let _ = write!(
+                body,
+                ", use <a href=\"{}\">{}</a> instead",
+                page_name(replacement),
+                escape(replacement)
+            );
+        }
+        body.push_str("</p>\n");
+    }
+    if let Some(readme) = readme {
+        let _ = write!(
+            body,
+            "<h2>README</h2>\n<div class=\"readme\">\n{}</div>\n",
+            markdown(readme)
+        );
+    }
+    body.push_str(
+        "<h2>Versions</h2>\n<table>\n<tr><th>Version</th><th>Published</th><th>Channel</th></tr>\n",
+    );
+    // newest first
+    let mut versions = crt.releases().to_vec();
+    versions.sort_by(|a, b| b.cmp(a));
+    for version in versions {
+        let class = if crt.is_blocked(version) {
+            " class=\"blocked\""
+        } else if crt.is_yanked(version) {
+            " class=\"yanked\""
+        } else if crt.deprecated_versions.iter().any(|(v, _)| *v == version) {
+            " class=\"deprecated\""
+        } else {
+            ""
+        };
+        let published = crt
+            .published_at(version)
+            .map(|at| at.format("%Y-%m-%d").to_string())
+            .unwrap_or_default();
+        let channel = match crt.channel(version) {
+            Channel::Stable => String::new(),
+            channel => format!("{channel:?}"),
+        };
+        let _ = writeln!(
+            body,
+            "<tr{class}><td>{version}</td><td>{published}</td><td>{channel}</td></tr>"
+        );
+    }
+    body.push_str("</table>\n");
+    page(metadata.name(), &body)
+}
+
+impl Repository {
+    /// Writes the static site into `out`, creating it if needed. Returns the number of crate
+    /// pages. Pages of crates that were removed since an earlier render are left behind.
+    pub fn render(&self, out: impl AsRef<Path>) -> io::Result<usize> {
+        let out = out.as_ref();
+        let crates = out.join("crates");
+        fs::create_dir_all(&crates)?;
+        fs::write(out.join("index.html"), index_page(self))?;
+        let mut pages = 0;
+        let blobs = self.blobs();
+        for crt in self.iter() {
+            let readme = match crt.latest().and_then(|latest| crt.readme_id(latest)) {
+                Some(id) => blobs.get(id)?,
+                None => None,
+            };
+            let readme = readme.map(|readme| String::from_utf8_lossy(&readme).into_owned());
+            fs::write(
+                crates.join(page_name(crt.metadata().name())),
+                crate_page(crt, readme.as_deref()),
+            )?;
+            pages += 1;
+        }
+        Ok(pages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, NamedTempFile};
+
+    use super::*;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn render() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy <Person>", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.yank("hello_bin", SemVer::new(1, 1, 0))?;
+        let readme = "# Hello\n\n<script>alert(1)</script>\n\n[docs](javascript:alert(1))";
+        repo.set_readme("hello_bin", SemVer::new(1, 0, 0), Some(readme))?;
+        repo.register_namespace("acme", vec!["Busy Person".to_string()])?;
+        repo.add_crate(
+            Metadata::new("@acme/http", "Busy Person", CrateKind::Library),
+            SemVer::new(0, 1, 0),
+        )?;
+
+        let site = tempdir()?;
+        assert_eq!(2, repo.render(site.path())?);
+
+        let index = fs::read_to_string(site.path().join("index.html"))?;
+        assert!(index.contains("<a href=\"crates/hello_bin.html\">hello_bin</a>"));
+        assert!(index.contains("<a href=\"crates/~40acme~2Fhttp.html\">@acme/http</a>"));
+        assert!(index.contains("Busy &lt;Person&gt;"));
+
+        let page = fs::read_to_string(site.path().join("crates/hello_bin.html"))?;
+        assert!(page.contains("<tr class=\"yanked\"><td>1.1.0</td>"));
+        assert!(page.find("1.1.0") < page.find("1.0.0"));
+        assert!(page.contains("<h1>Hello</h1>"));
+        assert!(page.contains("&lt;script&gt;"));
+        assert!(page.contains("<a href=\"#\">docs</a>"));
+        assert!(site.path().join("crates/~40acme~2Fhttp.html").is_file());
+        Ok(())
+    }
+}
@@ -0,0 +1,380 @@
+//! Keeping a follower repository in sync with a primary server by tailing its change feed.
+//!
+//! A follower applies the primary's changes verbatim, including their sequence numbers, so its
+//! own change feed can in turn be followed. If it falls too far behind, or the changes it needs
+//! have been compacted away, it starts over from a full [`Snapshot`]. READMEs it doesn't have
+//! yet are fetched from the primary afterwards, see [`crate::readme`].
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::blobs::{BlobId, BlobStore};
+use crate::client::{Client, ClientError};
+use crate::events::{Change, Event};
+use crate::{Crate, RepoError, Repository, SemVer, VersionPolicy};
+
+/// full state of a repository at sequence number `last_seq`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub last_seq: u64,
+    pub crates: Vec<Arc<Crate>>,
+    /// old name → current name of renamed crates
+    #[serde(default)]
+    pub aliases: BTreeMap<String, String>,
+}
+
+/// where a server's change feed currently stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeedStatus {
+    /// oldest change still available, anything before has been compacted away
+    pub oldest_seq: u64,
+    pub last_seq: u64,
+}
+
+impl Repository {
+    /// see [`crate::view::View::snapshot`] to take it without holding on to the repository
+    pub fn snapshot(&self) -> Snapshot {
+        self.view().snapshot()
+    }
+
+    /// replaces the whole repository, including its change feed, with `snapshot`
+    pub fn restore(&mut self, snapshot: Snapshot) {
+        self.crates = snapshot
+            .crates
+            .into_iter()
+            .map(|crt| (crt.metadata.name.clone(), crt))
+            .collect::<BTreeMap<_, _>>();
+        self.aliases = snapshot.aliases;
+        self.changes.reset(snapshot.last_seq);
+        self.reindex_all();
+    }
+
+    pub fn feed_status(&self) -> FeedStatus {
+        FeedStatus {
+            oldest_seq: self.changes.oldest_seq(),
+            last_seq: self.changes.last_seq(),
+        }
+    }
+
+    /// Applies a change recorded by another repository. Changes that have already been applied
+    /// are skipped, returns whether `change` was applied.
+    pub fn apply(&mut self, mut change: Change) -> Result<bool, RepoError> {
+        if change.seq <= self.changes.last_seq() {
+            return Ok(false);
+        }
+
+        match &change.event {
+            Event::CrateAdded {
+                metadata,
+                version,
+                published_at,
+            } => {
+                if self.crates.contains_key(metadata.name()) {
+                    return Err(RepoError::AlreadyExists);
+                }
+                self.aliases.remove(metadata.name());
+                let mut crt = Crate::new(metadata.clone());
+                crt.push_release(*version, published_at.unwrap_or(change.at));
+                self.crates.insert(metadata.name.clone(), Arc::new(crt));
+            }
+            Event::ReleaseAdded {
+                name,
+                version,
+                channel,
+            } => {
+                let crt = self
+                    .crates
+                    .get_mut(name)
+                    .map(Arc::make_mut)
+                    .ok_or(RepoError::NotFound)?;
+                // validated by the primary, which might use a more lenient policy
+                match crt.releases().contains(version) {
+                    true => crt.republish(*version, change.at),
+                    false => crt.add_release_at(*version, change.at, VersionPolicy::Unique)?,
+                }
+                crt.set_channel(*version, *channel);
+            }
+            Event::Yanked { name, version } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .yank(*version)?,
+            Event::CrateDeleted { name } => {
+                // after compaction the feed may announce deletions of crates we never saw
+                self.crates.remove(name);
+                self.aliases
+                    .retain(|_, target| target.as_str() != name.as_ref());
+            }
+            Event::CrateRenamed { from, to } => self.move_crate(from, to)?,
+            Event::ReleasesImported { name, releases } => {
+                self.crates
+                    .get_mut(name)
+                    .map(Arc::make_mut)
+                    .ok_or(RepoError::NotFound)?
+                    .import_releases(releases, change.at);
+            }
+            Event::Deprecated {
+                name,
+                version,
+                deprecation,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_deprecation(*version, deprecation.clone())?,
+            Event::ReleaseNotesChanged {
+                name,
+                version,
+                notes,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_release_notes(*version, notes.clone())?,
+            Event::ReadmeChanged {
+                name,
+                version,
+                blob,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_readme(*version, blob.clone())?,
+            Event::PlatformChanged {
+                name,
+                version,
+                platform,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_platform(*version, platform.clone())?,
+            Event::FeaturesChanged {
+                name,
+                version,
+                features,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_features(*version, features.clone())?,
+            Event::DependenciesChanged {
+                name,
+                version,
+                dependencies,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .set_dependencies(*version, dependencies.clone())?,
+            Event::AdvisoryFiled { name, advisory } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .file_advisory(advisory.clone())?,
+            Event::AdvisoryWithdrawn { name, id } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .withdraw_advisory(id)?,
+            Event::VersionBlocked {
+                name,
+                version,
+                reason,
+            } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .block(*version, reason.clone())?,
+            Event::VersionUnblocked { name, version } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .unblock(*version)?,
+            Event::ReleasesPruned { name, versions } => self
+                .crates
+                .get_mut(name)
+                .map(Arc::make_mut)
+                .ok_or(RepoError::NotFound)?
+                .remove_releases(versions),
+            Event::MetadataChanged { metadata } => {
+                let crt = self
+                    .crates
+                    .get_mut(metadata.name())
+                    .map(Arc::make_mut)
+                    .ok_or(RepoError::NotFound)?;
+                crt.metadata = metadata.clone();
+                crt.revision += 1;
+            }
+        }
+        self.reindex(change.event.crate_name());
+        change.event.intern(&self.crates, &mut self.authors);
+        self.changes.replicate(change);
+        Ok(true)
+    }
+}
+
+/// Follows a primary server, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct Follower {
+    primary: Client,
+    /// how many changes the follower may lag behind before restoring a snapshot instead
+    pub max_lag: u64,
+}
+
+impl Follower {
+    pub fn new(primary: Client) -> Self {
+        Self {
+            primary,
+            max_lag: 10_000,
+        }
+    }
+
+    /// Brings `repository` up to date, waiting for new changes if there are none.
+    /// The lock is not held while waiting. Returns the number of applied changes.
+    pub fn sync_once(&self, repository: &Mutex<Repository>) -> Result<u64, ClientError> {
+        let local_seq = repository.lock().unwrap().changes().last_seq();
+        let status = self.primary.feed_status()?;
+
+        let missed_changes = local_seq + 1 < status.oldest_seq;
+        if missed_changes || status.last_seq.saturating_sub(local_seq) > self.max_lag {
+            return self.restore(repository, local_seq);
+        }
+
+        let changes = self.primary.subscribe(local_seq)?;
+        let mut locked = repository.lock().unwrap();
+        let blobs = locked.blobs();
+        let mut readmes = vec![];
+        let mut applied = 0;
+        for change in changes {
+            let seq = change.seq;
+            if let Event::ReadmeChanged {
+                name,
+                version,
+                blob: Some(blob),
+            } = &change.event
+            {
+                readmes.push((name.to_string(), *version, blob.clone()));
+            }
+            match locked.apply(change) {
+                Ok(true) => applied += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    warn!(
+                        "change #{} does not apply ({}), falling back to snapshot",
+                        seq, e
+                    );
+                    drop(locked);
+                    return self.restore(repository, local_seq);
+                }
+            }
+        }
+        drop(locked);
+        self.fetch_readmes(&blobs, readmes);
+        Ok(applied)
+    }
+
+    /// Stores the READMEs of `readmes` that aren't in `blobs` yet. Failures are only logged, the
+    /// README is fetched again the next time it changes or a snapshot is restored.
+    fn fetch_readmes(&self, blobs: &BlobStore, readmes: Vec<(String, SemVer, BlobId)>) {
+        for (name, version, blob) in readmes {
+            if blobs.contains(&blob) {
+                continue;
+            }
+            let fetched = match self.primary.readme(&name, version) {
+                Ok(Some(readme)) => blobs.put(readme.as_bytes()).map_err(|e| e.to_string()),
+                Ok(None) => continue,
+                Err(e) => Err(e.to_string()),
+            };
+            match fetched {
+                Ok(id) if id != blob => {
+                    warn!("README of {} {} changed while fetching it", name, version)
+                }
+                Ok(_) => {}
+                Err(e) => warn!("could not fetch README of {} {}: {}", name, version, e),
+            }
+        }
+    }
+
+    fn restore(&self, repository: &Mutex<Repository>, local_seq: u64) -> Result<u64, ClientError> {
+        let snapshot = self.primary.snapshot()?;
+        info!(
+            "restoring snapshot at #{} (local feed at #{})",
+            snapshot.last_seq, local_seq
+        );
+        let applied = snapshot.last_seq.saturating_sub(local_seq);
+        let readmes = snapshot
+            .crates
+            .iter()
+            .flat_map(|crt| {
+                crt.readmes.iter().map(|(version, blob)| {
+                    (crt.metadata.name().to_string(), *version, blob.clone())
+                })
+            })
+            .collect();
+        let mut locked = repository.lock().unwrap();
+        let blobs = locked.blobs();
+        locked.restore(snapshot);
+        drop(locked);
+        self.fetch_readmes(&blobs, readmes);
+        Ok(applied)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn apply_and_restore() -> Result<(), RepoError> {
+        let (primary_store, follower_store) =
+            (NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap());
+        let mut primary = Repository::new(&primary_store);
+        let mut follower = Repository::new(&follower_store);
+
+        primary.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        primary.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        primary.yank("hello_bin", SemVer::new(1, 0, 0))?;
+
+        for change in primary.changes().since(0).to_vec() {
+            assert!(follower.apply(change)?);
+        }
+        // replaying is harmless
+        assert!(!follower.apply(primary.changes().since(0)[0].clone())?);
+
+        let crt = follower.find_exact("hello_bin").unwrap();
+        assert_eq!(
+            vec![SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)],
+            crt.release_history
+        );
+        assert!(crt.is_yanked(SemVer::new(1, 0, 0)));
+        assert_eq!(primary.feed_status(), follower.feed_status());
+
+        let fresh_store = NamedTempFile::new().unwrap();
+        let mut fresh = Repository::new(&fresh_store);
+        fresh.restore(primary.snapshot());
+        assert!(fresh.find_exact("hello_bin").is_some());
+        assert_eq!(3, fresh.changes().last_seq());
+        Ok(())
+    }
+}
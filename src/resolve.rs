@@ -0,0 +1,159 @@
+//! Resolving a version requirement like `^1.2` or `>=1.0, <2.0` to a single release on the
+//! server, so clients don't need the full version history.
+//!
+//! Requirements follow cargo, see [`VersionReq`]. Pre-releases are releases published to a
+//! channel other than [`Channel::Stable`], see [`crate::channels`].
+
+use serde::{Deserialize, Serialize};
+
+pub use semver_core::req::{ParseVersionReqError, VersionReq};
+
+use crate::channels::Channel;
+use crate::{Crate, RepoError, Repository, SemVer};
+
+/// why a release matching the requirement wasn't chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// by an admin, see [`crate::blocklist`], whatever is included
+    Blocked,
+    Yanked,
+    /// published to this channel, and pre-releases weren't included
+    Prerelease(Channel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Skipped {
+    pub version: SemVer,
+    pub reason: SkipReason,
+}
+
+/// The outcome of resolving a requirement
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Resolution {
+    /// the newest matching release that wasn't skipped, `None` if there is none
+    pub version: Option<SemVer>,
+    /// matching releases newer than `version` that were skipped, newest first
+    pub skipped: Vec<Skipped>,
+    /// ids of the advisories affecting `version`, see [`crate::advisories`]
+    #[serde(default)]
+    pub advisories: Vec<String>,
+}
+
+impl Crate {
+    /// the newest release matching `req`, skipping blocked releases, and yanked releases and
+    /// pre-releases unless they are included
+    pub fn resolve(
+        &self,
+        req: &VersionReq,
+        include_prerelease: bool,
+        include_yanked: bool,
+    ) -> Resolution {
+        let mut candidates: Vec<_> = self
+            .release_history
+            .iter()
+            .copied()
+            .filter(|v| req.matches(*v))
+            .collect();
+        candidates.sort_by(|a, b| b.cmp(a));
+        let mut resolution = Resolution::default();
+        for version in candidates {
+            let channel = self.channel(version);
+            let reason = if self.is_blocked(version) {
+                Some(SkipReason::Blocked)
+            } else if self.is_yanked(version) && !include_yanked {
+                Some(SkipReason::Yanked)
+            } else if channel != Channel::Stable && !include_prerelease {
+                Some(SkipReason::Prerelease(channel))
+            } else {
+                None
+            };
+            match reason {
+                Some(reason) => resolution.skipped.push(Skipped { version, reason }),
+                None => {
+                    resolution.version = Some(version);
+                    resolution.advisories = self
+                        .advisories_for(version)
+                        .map(|advisory| advisory.id.clone())
+                        .collect();
+                    break;
+                }
+            }
+        }
+        resolution
+    }
+}
+
+impl Repository {
+    /// resolves `req` against the releases of the crate called `name`, see [`Crate::resolve`]
+    pub fn resolve(
+        &self,
+        name: impl AsRef<str>,
+        req: &VersionReq,
+        include_prerelease: bool,
+        include_yanked: bool,
+    ) -> Result<Resolution, RepoError> {
+        let crt = self.find_exact(name).ok_or(RepoError::NotFound)?;
+        Ok(crt.resolve(req, include_prerelease, include_yanked))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    fn req(s: &str) -> VersionReq {
+        s.parse().unwrap()
+    }
+
+    fn v(s: &str) -> SemVer {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn resolve() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            v("1.0.0"),
+        )?;
+        repo.add_release("hello_lib", v("1.1.0"))?;
+        repo.add_release("hello_lib", v("1.2.0"))?;
+        repo.add_release_to("hello_lib", v("1.3.0"), Channel::Beta)?;
+        repo.add_release("hello_lib", v("2.0.0"))?;
+        repo.yank("hello_lib", v("1.2.0"))?;
+
+        let resolution = repo.resolve("hello_lib", &req("^1"), false, false)?;
+        assert_eq!(Some(v("1.1.0")), resolution.version);
+        assert_eq!(
+            vec![
+                Skipped {
+                    version: v("1.3.0"),
+                    reason: SkipReason::Prerelease(Channel::Beta)
+                },
+                Skipped {
+                    version: v("1.2.0"),
+                    reason: SkipReason::Yanked
+                },
+            ],
+            resolution.skipped
+        );
+        let resolution = repo.resolve("hello_lib", &req("^1"), true, false)?;
+        assert_eq!(Some(v("1.3.0")), resolution.version);
+        assert!(resolution.skipped.is_empty());
+        let resolution = repo.resolve("hello_lib", &req("~1.2"), false, true)?;
+        assert_eq!(Some(v("1.2.0")), resolution.version);
+        assert_eq!(
+            Resolution::default(),
+            repo.resolve("hello_lib", &req(">2"), true, true)?
+        );
+        assert_eq!(
+            Err(RepoError::NotFound),
+            repo.resolve("nope", &VersionReq::STAR, false, false)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,354 @@
+//! Retention policies, pruning old pre-releases, yanked releases and tombstones.
+//!
+//! [`Repository::prune_report`] tells what a policy would remove without changing anything,
+//! [`Repository::prune`] removes it. Pruned releases are announced in the change feed, so
+//! followers prune them too. A crate always keeps its newest release.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::channels::Channel;
+use crate::events::Event;
+use crate::{Crate, Repository, SemVer};
+
+/// What to prune, everything is kept by default
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// pre-releases of a channel other than `Stable` to keep per crate, the newest are kept
+    #[serde(default)]
+    pub keep_prereleases: Option<usize>,
+    /// yanked releases published longer ago are removed
+    #[serde(default)]
+    pub yanked_days: Option<u32>,
+    /// deletions and renames recorded longer ago are removed from the change feed. Followers
+    /// that haven't seen them yet restore a snapshot instead.
+    #[serde(default)]
+    pub tombstone_days: Option<u32>,
+}
+
+/// what [`Repository::prune`] removed or would remove
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PruneReport {
+    /// crate name → removed releases
+    pub releases: BTreeMap<String, Vec<SemVer>>,
+    /// sequence numbers of removed changes
+    pub tombstones: Vec<u64>,
+}
+
+impl PruneReport {
+    pub fn is_empty(&self) -> bool {
+        self.releases.is_empty() && self.tombstones.is_empty()
+    }
+}
+
+impl Display for PruneReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (name, versions) in &self.releases {
+            let versions: Vec<String> = versions.iter().map(SemVer::to_string).collect();
+            writeln!(f, "{} {}", name, versions.join(", "))?;
+        }
+        for seq in &self.tombstones {
+            writeln!(f, "change #{}", seq)?;
+        }
+        Ok(())
+    }
+}
+
+impl Crate {
+    /// releases of the crate `policy` removes at `now`
+    fn expired(&self, policy: &RetentionPolicy, now: DateTime<Utc>) -> Vec<SemVer> {
+        let mut expired = HashSet::new();
+        if let Some(keep) = policy.keep_prereleases {
+            let mut prereleases: Vec<SemVer> = self
+                .channels
+                .iter()
+                .filter(|(_, channel)| *channel != Channel::Stable)
+                .map(|(version, _)| *version)
+                .collect();
+            prereleases.sort_unstable_by(|a, b| b.cmp(a));
+            expired.extend(prereleases.into_iter().skip(keep));
+        }
+        if let Some(days) = policy.yanked_days {
+            let cutoff = now - Duration::days(days.into());
+            expired.extend(self.yanked.iter().copied().filter(|version| {
+                self.published_at(*version)
+                    .is_some_and(|published| published < cutoff)
+            }));
+        }
+        // the newest release stays, so no crate ends up without any
+        if let Some(newest) = self.release_history.iter().max() {
+            expired.remove(newest);
+        }
+        let mut expired: Vec<SemVer> = expired.into_iter().collect();
+        expired.sort_unstable();
+        expired
+    }
+
+    /// removes `versions` and everything about them
+    pub(crate) fn remove_releases(&mut self, versions: &[SemVer]) {
+        self.published_at
+            .resize(self.release_history.len(), DateTime::default());
+        (self.release_history, self.published_at) = self
+            .release_history
+            .iter()
+            .zip(&self.published_at)
+            .filter(|(version, _)| !versions.contains(version))
+            .unzip();
+        self.yanked.retain(|version| !versions.contains(version));
+        self.channels
+            .retain(|(version, _)| !versions.contains(version));
+        self.deprecated_versions
+            .retain(|(version, _)| !versions.contains(version));
+        self.release_notes
+            .retain(|(version, _)| !versions.contains(version));
+        self.readmes
+            .retain(|(version, _)| !versions.contains(version));
+        self.platforms
+            .retain(|(version, _)| !versions.contains(version));
+        self.features
+            .retain(|(version, _)| !versions.contains(version));
+        self.dependencies
+            .retain(|(version, _)| !versions.contains(version));
+        self.blocked
+            .retain(|blocked| !versions.contains(&blocked.version));
+        self.revision += 1;
+    }
+}
+
+impl Repository {
+    /// what [`Repository::prune`] would remove, a dry run
+    pub fn prune_report(&self, policy: &RetentionPolicy) -> PruneReport {
+        self.prune_report_at(policy, Utc::now())
+    }
+
+    fn prune_report_at(&self, policy: &RetentionPolicy, now: DateTime<Utc>) -> PruneReport {
+        let releases = self
+            .crates
+            .iter()
+            .map(|(name, crt)| (name.to_string(), crt.expired(policy, now)))
+            .filter(|(_, expired)| !expired.is_empty())
+            .collect();
+        let tombstones = match policy.tombstone_days {
+            Some(days) => {
+                let cutoff = now - Duration::days(days.into());
+                self.changes
+                    .since(0)
+                    .iter()
+                    .filter(|change| change.at < cutoff && is_tombstone(&change.event))
+                    .map(|change| change.seq)
+                    .collect()
+            }
+            None => vec![],
+        };
+        PruneReport {
+            releases,
+            tombstones,
+        }
+    }
+
+    /// Removes what `policy` says should go, returns what was removed
+    pub fn prune(&mut self, policy: &RetentionPolicy) -> PruneReport {
+        let report = self.prune_report(policy);
+        for (name, versions) in &report.releases {
+            let Some(crt) = self.crates.get_mut(name.as_str()).map(Arc::make_mut) else {
+                continue;
+            };
+            crt.remove_releases(versions);
+            self.changes.record(Event::ReleasesPruned {
+                name: crt.metadata.name.clone(),
+                versions: versions.clone(),
+            });
+            self.reindex(name);
+        }
+        if !report.tombstones.is_empty() {
+            let tombstones: HashSet<u64> = report.tombstones.iter().copied().collect();
+            self.changes
+                .retain(|change| !tombstones.contains(&change.seq));
+        }
+        report
+    }
+}
+
+fn is_tombstone(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::CrateDeleted { .. } | Event::CrateRenamed { .. }
+    )
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error(
+    "invalid retention policy '{0}', expected e.g. prereleases=5,yanked_days=365,tombstone_days=90"
+)]
+pub struct ParsePolicyError(String);
+
+/// comma separated `prereleases=<n>`, `yanked_days=<n>` and `tombstone_days=<n>`
+impl FromStr for RetentionPolicy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParsePolicyError(s.to_string());
+        let mut policy = RetentionPolicy::default();
+        for setting in s.split(',').filter(|setting| !setting.is_empty()) {
+            let (key, value) = setting.split_once('=').ok_or_else(err)?;
+            match key.trim() {
+                "prereleases" => policy.keep_prereleases = Some(value.parse().map_err(|_| err())?),
+                "yanked_days" => policy.yanked_days = Some(value.parse().map_err(|_| err())?),
+                "tombstone_days" => policy.tombstone_days = Some(value.parse().map_err(|_| err())?),
+                _ => return Err(err()),
+            }
+        }
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError};
+
+    #[test]
+    fn parse() {
+        assert_eq!(Ok(RetentionPolicy::default()), "".parse());
+        assert_eq!(
+            Ok(RetentionPolicy {
+                keep_prereleases: Some(2),
+                yanked_days: None,
+                tombstone_days: Some(30),
+            }),
+            "prereleases=2,tombstone_days=30".parse()
+        );
+        assert!("prereleases=-1".parse::<RetentionPolicy>().is_err());
+        assert!("forever".parse::<RetentionPolicy>().is_err());
+    }
+
+    #[test]
+    fn prune() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("engine", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        for minor in 1..=3 {
+            repo.add_release_to("engine", SemVer::new(1, minor, 0), Channel::Beta)?;
+        }
+        repo.add_release("engine", SemVer::new(2, 0, 0))?;
+        repo.yank("engine", SemVer::new(1, 0, 0))?;
+        repo.add_crate(
+            Metadata::new("gone", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.delete_crate("gone")?;
+
+        let policy: RetentionPolicy = "prereleases=1,yanked_days=365,tombstone_days=30"
+            .parse()
+            .unwrap();
+        // only the older betas go right away, yanked releases and tombstones are still recent
+        assert_eq!(
+            PruneReport {
+                releases: BTreeMap::from([(
+                    "engine".to_string(),
+                    vec![SemVer::new(1, 1, 0), SemVer::new(1, 2, 0)]
+                )]),
+                tombstones: vec![],
+            },
+            repo.prune_report(&policy)
+        );
+        let in_two_years = Utc::now() + Duration::days(730);
+        let report = repo.prune_report_at(&policy, in_two_years);
+        let deletion = repo.changes().last_seq();
+        assert_eq!(
+            PruneReport {
+                releases: BTreeMap::from([(
+                    "engine".to_string(),
+                    vec![
+                        SemVer::new(1, 0, 0),
+                        SemVer::new(1, 1, 0),
+                        SemVer::new(1, 2, 0)
+                    ]
+                )]),
+                tombstones: vec![deletion],
+            },
+            report
+        );
+
+        // a dry run changes nothing
+        assert_eq!(5, repo.find_exact("engine").unwrap().releases().len());
+
+        let policy = RetentionPolicy {
+            yanked_days: Some(0),
+            ..policy
+        };
+        let report = repo.prune(&policy);
+        assert_eq!(
+            vec![
+                SemVer::new(1, 0, 0),
+                SemVer::new(1, 1, 0),
+                SemVer::new(1, 2, 0)
+            ],
+            report.releases["engine"]
+        );
+        let crt = repo.find_exact("engine").unwrap();
+        assert_eq!(
+            &[SemVer::new(1, 3, 0), SemVer::new(2, 0, 0)],
+            crt.releases()
+        );
+        assert!(matches!(
+            repo.changes().since(deletion).last().unwrap().event,
+            Event::ReleasesPruned { .. }
+        ));
+        assert_eq!(Vec::<crate::fsck::Problem>::new(), repo.verify());
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_newest_release() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("abandoned", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.yank("abandoned", SemVer::new(1, 0, 0))?;
+        let policy = RetentionPolicy {
+            yanked_days: Some(0),
+            ..Default::default()
+        };
+        assert!(repo.prune(&policy).is_empty());
+        assert_eq!(1, repo.find_exact("abandoned").unwrap().releases().len());
+        Ok(())
+    }
+
+    #[test]
+    fn followers_prune() -> Result<(), RepoError> {
+        let (primary_store, follower_store) =
+            (NamedTempFile::new().unwrap(), NamedTempFile::new().unwrap());
+        let mut primary = Repository::new(&primary_store);
+        primary.add_crate(
+            Metadata::new("engine", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        primary.add_release_to("engine", SemVer::new(1, 1, 0), Channel::Nightly)?;
+        primary.add_release_to("engine", SemVer::new(1, 2, 0), Channel::Nightly)?;
+        primary.prune(&"prereleases=1".parse().unwrap());
+
+        let mut follower = Repository::new(&follower_store);
+        for change in primary.changes().since(0) {
+            follower.apply(change.clone())?;
+        }
+        assert_eq!(
+            &[SemVer::new(1, 0, 0), SemVer::new(1, 2, 0)],
+            follower.find_exact("engine").unwrap().releases()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+//! Periodic background work of the server, like autosaving and pruning.
+//!
+//! Tasks run one at a time on the thread calling [`Scheduler::run`], each once per interval.
+//! Admins list them with `AdminRequest::Tasks` and run one right away with `AdminRequest::RunTask`.
+
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use log::{debug, error};
+use serde::{Deserialize, Serialize};
+
+/// a task's work, failures are logged and kept in its [`TaskInfo`]
+pub type TaskFn = Box<dyn FnMut() -> Result<(), String> + Send>;
+
+/// what the scheduler knows about a task
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskInfo {
+    pub name: String,
+    pub interval: Duration,
+    pub runs: u64,
+    pub failures: u64,
+    pub last_run: Option<DateTime<Utc>>,
+    /// the error of the last run, `None` if it succeeded
+    pub last_error: Option<String>,
+}
+
+struct Task {
+    info: TaskInfo,
+    /// taken while the task is running
+    run: Option<TaskFn>,
+    next_run: Instant,
+}
+
+#[derive(Default)]
+struct State {
+    tasks: Vec<Task>,
+    stopped: bool,
+}
+
+#[derive(Default)]
+pub struct Scheduler {
+    state: Mutex<State>,
+    /// notified when tasks are added or triggered, and on stop
+    wake: Condvar,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `run` every `interval`, the first time one interval from now.
+    /// Replaces a task of the same name.
+    pub fn add(
+        &self,
+        name: impl Into<String>,
+        interval: Duration,
+        run: impl FnMut() -> Result<(), String> + Send + 'static,
+    ) {
+        let name = name.into();
+        let mut state = self.state.lock().unwrap();
+        state.tasks.retain(|task| task.info.name != name);
+        state.tasks.push(Task {
+            info: TaskInfo {
+                name,
+                interval,
+                runs: 0,
+                failures: 0,
+                last_run: None,
+                last_error: None,
+            },
+            run: Some(Box::new(run)),
+            next_run: Instant::now() + interval,
+        });
+        self.wake.notify_all();
+    }
+
+    pub fn tasks(&self) -> Vec<TaskInfo> {
+        let state = self.state.lock().unwrap();
+        state.tasks.iter().map(|task| task.info.clone()).collect()
+    }
+
+    /// Runs the task called `name` as soon as possible instead of when its interval is up.
+    /// Returns whether there is such a task.
+    pub fn trigger(&self, name: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let Some(task) = state.tasks.iter_mut().find(|task| task.info.name == name) else {
+            return false;
+        };
+        task.next_run = Instant::now();
+        self.wake.notify_all();
+        true
+    }
+
+    /// makes [`Scheduler::run`] return once the running task, if any, is done
+    pub fn stop(&self) {
+        self.state.lock().unwrap().stopped = true;
+        self.wake.notify_all();
+    }
+
+    /// runs tasks when they are due until [`Scheduler::stop`] is called
+    pub fn run(&self) {
+        let mut state = self.state.lock().unwrap();
+        while !state.stopped {
+            let now = Instant::now();
+            let due = state
+                .tasks
+                .iter()
+                .position(|task| task.next_run <= now && task.run.is_some());
+            let Some(index) = due else {
+                let timeout = state
+                    .tasks
+                    .iter()
+                    .map(|task| task.next_run.saturating_duration_since(now))
+                    .min()
+                    .unwrap_or(Duration::from_secs(60 * 60));
+                state = self.wake.wait_timeout(state, timeout).unwrap().0;
+                continue;
+            };
+            let task = &mut state.tasks[index];
+            let name = task.info.name.clone();
+            let mut run = task.run.take().expect("due tasks aren't running");
+            debug!("running task {}", name);
+            // tasks may take a while, others can still be listed or triggered meanwhile
+            drop(state);
+            let res = run();
+            state = self.state.lock().unwrap();
+            // tasks are only ever replaced, not removed, so it is still around
+            let Some(task) = state.tasks.iter_mut().find(|task| task.info.name == name) else {
+                continue;
+            };
+            if task.run.is_none() {
+                task.run = Some(run);
+            }
+            task.next_run = Instant::now() + task.info.interval;
+            task.info.runs += 1;
+            task.info.last_run = Some(Utc::now());
+            if let Err(e) = &res {
+                error!("task {} failed: {}", name, e);
+                task.info.failures += 1;
+            }
+            task.info.last_error = res.err();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+
+    use super::*;
+
+    fn wait_for(scheduler: &Scheduler, name: &str, runs: u64) -> TaskInfo {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let task = scheduler
+                .tasks()
+                .into_iter()
+                .find(|task| task.name == name)
+                .unwrap();
+            if task.runs >= runs {
+                return task;
+            }
+            assert!(Instant::now() < deadline, "{} didn't run", name);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn run_and_trigger() {
+        let scheduler = Arc::new(Scheduler::new());
+        let counter = Arc::new(AtomicU64::new(0));
+        let count = counter.clone();
+        scheduler.add("count", Duration::from_secs(3600), move || {
+            count.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        });
+        scheduler.add("fail", Duration::from_millis(10), || Err("broken".into()));
+        let running = thread::spawn({
+            let scheduler = scheduler.clone();
+            move || scheduler.run()
+        });
+
+        let failing = wait_for(&scheduler, "fail", 2);
+        assert_eq!(failing.runs, failing.failures);
+        assert_eq!(Some("broken".to_string()), failing.last_error);
+
+        assert_eq!(0, counter.load(Ordering::SeqCst));
+        assert!(scheduler.trigger("count"));
+        assert!(!scheduler.trigger("missing"));
+        let task = wait_for(&scheduler, "count", 1);
+        assert_eq!((1, 0, None), (task.runs, task.failures, task.last_error));
+        assert_eq!(1, counter.load(Ordering::SeqCst));
+
+        scheduler.stop();
+        running.join().unwrap();
+    }
+}
@@ -0,0 +1,337 @@
+//! Ordering and paging of search results, see [`crate::Repository::find_containing`], and
+//! regular expressions for [`crate::Repository::find_regex`].
+
+use std::cmp::{Ordering, Reverse};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::channels::Channel;
+use crate::names::CrateName;
+use crate::platform::RustVersion;
+use crate::Crate;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchSort {
+    #[default]
+    Name,
+    /// exact matches first, then names starting with the query, then shorter names
+    Relevance,
+    /// most recently published release first
+    RecentRelease,
+    Downloads,
+}
+
+/// default number of results per [`Page`]
+pub const PAGE_SIZE: usize = 100;
+/// upper bound of [`SearchOptions::limit`] for pages
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+/// Where the next [`Page`] starts. Opaque to clients, just pass it back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Cursor(usize);
+
+/// Part of a large result set. Cursors are positions in the ordered results, so changes between
+/// requests may shift the following pages by a few crates.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// where the next page starts, `None` on the last page
+    pub next: Option<Cursor>,
+}
+
+impl<T> Page<T> {
+    pub fn has_more(&self) -> bool {
+        self.next.is_some()
+    }
+
+    /// converts the items, keeping the cursor
+    pub fn map<U>(self, f: impl FnMut(T) -> U) -> Page<U> {
+        Page {
+            items: self.items.into_iter().map(f).collect(),
+            next: self.next,
+        }
+    }
+}
+
+/// How to order and page search results. Ties are always broken by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchOptions {
+    #[serde(default)]
+    pub sort: SearchSort,
+    /// maximum number of results, all of them if `None`
+    #[serde(default)]
+    pub limit: Option<usize>,
+    /// number of results to skip
+    #[serde(default)]
+    pub offset: usize,
+    /// only crates in this namespace, see [`crate::names`]
+    #[serde(default)]
+    pub namespace: Option<String>,
+    /// only crates with a release in this channel or a more stable one,
+    /// see [`Crate::latest_in`]
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    /// only crates with a release building with this Rust version, see
+    /// [`Crate::latest_compatible`]
+    #[serde(default)]
+    pub rust_version: Option<RustVersion>,
+    /// only crates with a release supporting this target triple
+    #[serde(default)]
+    pub target: Option<String>,
+}
+
+impl SearchOptions {
+    pub fn sorted_by(sort: SearchSort) -> Self {
+        Self {
+            sort,
+            ..Self::default()
+        }
+    }
+
+    /// sorts `results` of searching for `query` and applies offset and limit
+    pub(crate) fn apply<'a>(&self, query: &str, results: Vec<&'a Crate>) -> Vec<&'a Crate> {
+        self.sort(query, results)
+            .into_iter()
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect()
+    }
+
+    /// Sorts `results` of searching for `query` and picks the page starting at `cursor`, or at
+    /// the offset for the first page. The limit is the page size, see [`PAGE_SIZE`].
+    pub(crate) fn page<'a>(
+        &self,
+        query: &str,
+        results: Vec<&'a Crate>,
+        cursor: Option<Cursor>,
+    ) -> Page<&'a Crate> {
+        let results = self.sort(query, results);
+        let start = cursor.map_or(self.offset, |Cursor(start)| start);
+        let size = self.limit.unwrap_or(PAGE_SIZE).clamp(1, MAX_PAGE_SIZE);
+        let end = start.saturating_add(size);
+        Page {
+            items: results.iter().skip(start).take(size).copied().collect(),
+            next: (end < results.len()).then_some(Cursor(end)),
+        }
+    }
+
+    /// filters and orders, ties are broken by name
+    fn sort<'a>(&self, query: &str, mut results: Vec<&'a Crate>) -> Vec<&'a Crate> {
+        let query = query.to_lowercase();
+        if let Some(namespace) = &self.namespace {
+            results.retain(|crt| {
+                CrateName::parse(crt.metadata().name())
+                    .is_ok_and(|name| name.namespace() == Some(namespace.as_str()))
+            });
+        }
+        if let Some(channel) = self.channel {
+            results.retain(|crt| crt.latest_in(channel).is_some());
+        }
+        if self.rust_version.is_some() || self.target.is_some() {
+            results.retain(|crt| {
+                crt.latest_compatible(self.rust_version, self.target.as_deref())
+                    .is_some()
+            });
+        }
+        results.sort_by(|a, b| {
+            let primary = match self.sort {
+                SearchSort::Name => Ordering::Equal,
+                SearchSort::Relevance => relevance(&query, a).cmp(&relevance(&query, b)),
+                SearchSort::RecentRelease => {
+                    Reverse(last_published(a)).cmp(&Reverse(last_published(b)))
+                }
+                SearchSort::Downloads => Reverse(a.downloads()).cmp(&Reverse(b.downloads())),
+            };
+            primary.then_with(|| a.metadata().name().cmp(b.metadata().name()))
+        });
+        results
+    }
+}
+
+/// longest pattern accepted by [`compile_pattern`]
+pub const MAX_PATTERN_LEN: usize = 256;
+/// bound on the compiled size, large counted repetitions like `a{1000}{1000}` exceed it
+const MAX_COMPILED_SIZE: usize = 1 << 20;
+
+#[derive(Error, Debug)]
+pub enum PatternError {
+    #[error("pattern longer than {MAX_PATTERN_LEN} characters")]
+    TooLong,
+    #[error("{0}")]
+    Invalid(#[from] regex::Error),
+}
+
+/// Compiles a user supplied regular expression. Matching is linear in the name length, so only
+/// the pattern itself needs to be bounded.
+pub fn compile_pattern(pattern: &str) -> Result<Regex, PatternError> {
+    if pattern.chars().count() > MAX_PATTERN_LEN {
+        return Err(PatternError::TooLong);
+    }
+    Ok(RegexBuilder::new(pattern)
+        .size_limit(MAX_COMPILED_SIZE)
+        .dfa_size_limit(MAX_COMPILED_SIZE)
+        .build()?)
+}
+
+/// lower is better, scoped crates are ranked by their name without namespace
+fn relevance(query: &str, crt: &Crate) -> (u8, usize) {
+    let full = crt.metadata().name();
+    let name = CrateName::parse(full)
+        .map(|name| name.name())
+        .unwrap_or(full)
+        .to_lowercase();
+    let kind = if name == query {
+        0
+    } else if name.starts_with(query) {
+        1
+    } else {
+        2
+    };
+    (kind, name.len())
+}
+
+fn last_published(crt: &Crate) -> Option<chrono::DateTime<chrono::Utc>> {
+    crt.releases()
+        .last()
+        .and_then(|version| crt.published_at(*version))
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer};
+
+    fn names(crates: Vec<&Crate>) -> Vec<&str> {
+        crates.iter().map(|c| c.metadata().name()).collect()
+    }
+
+    #[test]
+    fn sorting_and_paging() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["serde_json", "serde", "miniserde", "serde_derive"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.add_release("miniserde", SemVer::new(1, 1, 0))?;
+        repo.record_download("serde_json");
+
+        let search = |options| names(repo.find_containing("serde", options));
+        assert_eq!(
+            vec!["miniserde", "serde", "serde_derive", "serde_json"],
+            search(SearchOptions::default())
+        );
+        assert_eq!(
+            vec!["serde", "serde_json", "serde_derive", "miniserde"],
+            search(SearchOptions::sorted_by(SearchSort::Relevance))
+        );
+        assert_eq!(
+            "miniserde",
+            search(SearchOptions::sorted_by(SearchSort::RecentRelease))[0]
+        );
+        assert_eq!(
+            vec!["serde_json", "miniserde", "serde", "serde_derive"],
+            search(SearchOptions::sorted_by(SearchSort::Downloads))
+        );
+        assert_eq!(
+            vec!["serde", "serde_derive"],
+            search(SearchOptions {
+                limit: Some(2),
+                offset: 1,
+                ..SearchOptions::default()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn cursors() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for i in 0..5 {
+            repo.add_crate(
+                Metadata::new(format!("serde_{i}"), "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+
+        let options = SearchOptions {
+            limit: Some(2),
+            ..SearchOptions::default()
+        };
+        let mut pages = vec![];
+        let mut cursor = None;
+        loop {
+            let page = repo.find_containing_page("serde", options.clone(), cursor);
+            pages.push(names(page.items));
+            cursor = page.next;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(
+            vec![
+                vec!["serde_0", "serde_1"],
+                vec!["serde_2", "serde_3"],
+                vec!["serde_4"]
+            ],
+            pages
+        );
+
+        let page = repo.find_containing_page("serde", SearchOptions::default(), None);
+        assert_eq!(5, page.items.len());
+        assert!(!page.has_more());
+        Ok(())
+    }
+
+    #[test]
+    fn namespaces() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.register_namespace("myorg", vec!["Busy Person".to_string()])?;
+        for name in ["http-client-ext", "@myorg/http-client", "@myorg/http"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+
+        let search = |options| names(repo.find_containing("http", options));
+        assert_eq!(
+            vec!["@myorg/http", "@myorg/http-client", "http-client-ext"],
+            search(SearchOptions::sorted_by(SearchSort::Relevance))
+        );
+        assert_eq!(
+            vec!["@myorg/http", "@myorg/http-client"],
+            search(SearchOptions {
+                namespace: Some("myorg".to_string()),
+                ..SearchOptions::default()
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(compile_pattern("^serde(_json)?$").is_ok());
+        assert!(matches!(
+            compile_pattern("serde("),
+            Err(PatternError::Invalid(_))
+        ));
+        assert!(matches!(
+            compile_pattern(&"a".repeat(MAX_PATTERN_LEN + 1)),
+            Err(PatternError::TooLong)
+        ));
+        assert!(matches!(
+            compile_pattern("(a{1000}){1000}"),
+            Err(PatternError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,2520 @@
+use std::collections::BTreeMap;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use log::{debug, error};
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::access_log::{self, AccessLog, AccessLogConfig};
+use crate::admin::{token_matches, AdminRequest, AdminResponse};
+use crate::api::{
+    AdminResult, AdvisoriesResult, ApiError, ApiRequest, ApiResult, AuditLogResult, BatchResult,
+    ChangelogResult, CheckPublishResult, CrateSummary, DryRunOutcome, DryRunResult,
+    FeedStatusResult, FindExactResult, GetFeaturesResult, GetReadmeResult, LatestCompatibleResult,
+    LatestVersionResult, OrgResult, PingResult, ResolveResult, ServerInfo, SetDependenciesResult,
+    SnapshotResult, SubscribeResult, TaggedRequest, TaggedResponse, TokensResult,
+};
+use crate::auth::TokenInfo;
+use crate::backpressure::Limiter;
+use crate::compression::Compression;
+use crate::confirmation::{self, Confirmations};
+use crate::encryption::StoreKey;
+use crate::feed;
+use crate::idempotency::IdempotencyCache;
+use crate::import;
+use crate::kinds::KindPolicy;
+use crate::orgs::Role;
+use crate::panics;
+use crate::policy::{self, BuiltinPolicy, Publish, PublishPolicy};
+use crate::preflight::PublishWarning;
+use crate::quota::{Quota, Quotas};
+use crate::registries::{RegistryError, RepositoryManager};
+use crate::replication::Follower;
+use crate::retention::RetentionPolicy;
+use crate::scheduler::Scheduler;
+use crate::search;
+use crate::settings::{LiveSettings, Settings, SettingsError};
+use crate::upstream::{ProxyCache, UpstreamConfig};
+use crate::view::View;
+use crate::webhooks::{Dispatcher, WebhookConfig};
+use crate::{
+    net, Crate, CrateKind, Metadata, RepoError, Repository, SemVer, StoreError, VersionPolicy,
+};
+
+/// Opens the store of `config`, moving it aside for an empty one if it is corrupt and
+/// [`ServerConfig::force_new`] is set
+fn open_store(config: &ServerConfig) -> Result<Repository, ServerError> {
+    match Repository::open(&config.store, config.store_key.clone()) {
+        Err(e @ StoreError::Invalid(_)) if config.force_new => {
+            let aside = crate::move_aside(&config.store)?;
+            error!(
+                "{} is corrupt ({}), moved it to {} and starting anew",
+                config.store.display(),
+                e,
+                aside.display()
+            );
+            Ok(Repository::open(&config.store, config.store_key.clone())?)
+        }
+        res => Ok(res?),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// addresses to listen on, one listener each. Use port 0 for an ephemeral port.
+    pub listen: Vec<SocketAddr>,
+    /// path of the JSON store backing the repository
+    pub store: PathBuf,
+    /// Starts with an empty repository if the store is corrupt, moving it aside to
+    /// `<store>.corrupt`. Otherwise the server refuses to start then.
+    pub force_new: bool,
+    /// how long a `Subscribe` request waits for new changes before answering with an empty list
+    pub subscribe_timeout: Duration,
+    pub webhooks: WebhookConfig,
+    /// if set, Atom feeds of recent releases are kept up to date in this directory
+    pub feed_dir: Option<PathBuf>,
+    /// number of entries per feed
+    pub feed_limit: usize,
+    /// enables `ApiRequest::Admin` for requests carrying this token
+    pub admin_token: Option<String>,
+    /// if not empty, admin requests are only accepted on these addresses
+    pub admin_listen: Vec<SocketAddr>,
+    /// rejects all mutating requests with `ApiError::ReadOnly`, e.g. for mirrors
+    pub read_only: bool,
+    /// Replaces `read_only`, `quotas` and the hooks of `webhooks` on startup and whenever
+    /// [`ReloadHandle::reload`] or `AdminRequest::Reload` asks for it, see [`crate::settings`].
+    pub settings_file: Option<PathBuf>,
+    /// mirror another server instead of accepting changes, implies `read_only`
+    pub follow: Option<Follower>,
+    /// where to look for crates that aren't published here
+    pub upstream: Option<UpstreamConfig>,
+    /// Connections served at once on the listeners, more are answered with
+    /// `ApiError::Overloaded` right away, see [`crate::backpressure`]
+    pub max_connections: Option<usize>,
+    /// requests of a registry waiting for or holding its repository at once, more are answered
+    /// with `ApiError::Overloaded`. Long-polling `Subscribe` requests don't count.
+    pub max_queued_requests: Option<usize>,
+    /// how long responses to `ApiRequest::Idempotent` are remembered
+    pub idempotency_ttl: Duration,
+    /// Deleting crates and transferring their ownership has to be confirmed within this window,
+    /// see [`crate::confirmation`]. `None` executes them right away.
+    pub confirmation_window: Option<Duration>,
+    /// `FindExact` ignores case, preferring exact matches, see [`Repository::find_ignoring_case`]
+    pub case_insensitive_lookup: bool,
+    /// added to the repository's reserved names on startup, see [`crate::names::NameRules`]
+    pub reserved_names: Vec<String>,
+    /// added to the repository's blocked terms on startup
+    pub blocked_terms: Vec<String>,
+    pub quotas: Quotas,
+    /// replaces the repository's version policy on startup if set
+    pub version_policy: Option<VersionPolicy>,
+    /// replace the repository's policies of these kinds on startup, see [`crate::kinds`]
+    pub kind_policies: Vec<(CrateKind, KindPolicy)>,
+    /// decide whether publishes are accepted, before the ones of the settings file, see
+    /// [`crate::policy`]
+    pub policies: Vec<Arc<dyn PublishPolicy>>,
+    /// saves changes in the background instead of only on shutdown
+    pub autosave: Option<Autosave>,
+    /// records every request, see [`crate::access_log`]
+    pub access_log: Option<AccessLogConfig>,
+    /// how the store is written, any format is read
+    pub compression: Compression,
+    /// the store is encrypted with this key, see [`crate::encryption`]
+    pub store_key: Option<StoreKey>,
+    /// prunes the repository periodically, ignored by read-only servers
+    pub retention: Option<ScheduledRetention>,
+    /// further registries served next to the one in `store`
+    pub registries: Option<Registries>,
+    /// exports request spans to an OpenTelemetry collector, see [`crate::telemetry`]
+    #[cfg(feature = "otel")]
+    pub telemetry: Option<crate::telemetry::TelemetryConfig>,
+    /// also serves the gRPC API on this address, see [`crate::grpc`]
+    #[cfg(feature = "grpc")]
+    pub grpc_listen: Option<SocketAddr>,
+    /// also accepts WebSocket connections on this address, see [`crate::websocket`]
+    #[cfg(feature = "websocket")]
+    pub websocket_listen: Option<SocketAddr>,
+}
+
+impl ServerConfig {
+    /// listens on `127.0.0.1:7878` by default
+    pub fn new(store: impl Into<PathBuf>) -> Self {
+        Self {
+            listen: vec![SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::LOCALHOST),
+                net::DEFAULT_PORT,
+            )],
+            store: store.into(),
+            force_new: false,
+            subscribe_timeout: Duration::from_secs(30),
+            webhooks: WebhookConfig::default(),
+            feed_dir: None,
+            feed_limit: 50,
+            admin_token: None,
+            admin_listen: vec![],
+            read_only: false,
+            settings_file: None,
+            follow: None,
+            upstream: None,
+            max_connections: None,
+            max_queued_requests: None,
+            idempotency_ttl: Duration::from_secs(24 * 60 * 60),
+            confirmation_window: Some(Duration::from_secs(60)),
+            case_insensitive_lookup: false,
+            reserved_names: vec![],
+            blocked_terms: vec![],
+            quotas: Quotas::default(),
+            version_policy: None,
+            kind_policies: vec![],
+            policies: vec![],
+            autosave: None,
+            access_log: None,
+            compression: Compression::default(),
+            store_key: None,
+            retention: None,
+            registries: None,
+            #[cfg(feature = "otel")]
+            telemetry: None,
+            #[cfg(feature = "grpc")]
+            grpc_listen: None,
+            #[cfg(feature = "websocket")]
+            websocket_listen: None,
+        }
+    }
+
+    pub fn with_listen(mut self, listen: Vec<SocketAddr>) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    /// registers `policy` after the ones already registered
+    pub fn with_policy(mut self, policy: impl PublishPolicy + 'static) -> Self {
+        self.policies.push(Arc::new(policy));
+        self
+    }
+}
+
+/// When to save a dirty repository while serving, whichever comes first. The repository is locked
+/// while it is written, unchanged repositories are never written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Autosave {
+    /// longest time changes stay unsaved
+    pub interval: Duration,
+    /// number of unsaved changes that trigger a save before `interval` is up
+    pub after_mutations: u64,
+}
+
+impl Default for Autosave {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(60),
+            after_mutations: 1000,
+        }
+    }
+}
+
+/// Runs [`Repository::prune`] with `policy` every `interval`, the first time one interval after
+/// starting. Followers learn about pruned releases from the change feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScheduledRetention {
+    pub policy: RetentionPolicy,
+    pub interval: Duration,
+}
+
+/// Registries addressed with [`ApiRequest::Registry`], see [`crate::registries`]. They share the
+/// server's configuration, except that webhooks, the upstream and following only apply to the
+/// default registry, and their feeds go into a subdirectory of `feed_dir` named after them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registries {
+    /// every registry stored here is served
+    pub dir: PathBuf,
+    /// registries created on startup unless they exist
+    pub names: Vec<String>,
+}
+
+#[derive(Error, Debug)]
+pub enum ServerError {
+    #[error("no listen addresses configured")]
+    NoListenAddrs,
+    #[error("could not bind {0}: {1}")]
+    Bind(SocketAddr, std::io::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("could not open store: {0}")]
+    Store(#[from] StoreError),
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+    #[error(transparent)]
+    Settings(#[from] SettingsError),
+    #[cfg(feature = "otel")]
+    #[error(transparent)]
+    Telemetry(#[from] crate::telemetry::TelemetryError),
+}
+
+pub struct Server {
+    /// listeners with a flag telling whether they are admin listeners
+    listeners: Vec<(TcpListener, bool)>,
+    follow: Option<Follower>,
+    retention: Option<ScheduledRetention>,
+    #[cfg(feature = "grpc")]
+    grpc: Option<TcpListener>,
+    #[cfg(feature = "websocket")]
+    websocket: Option<TcpListener>,
+    shared: Arc<Shared>,
+    shutdown: ShutdownHandle,
+}
+
+/// state shared between all listener threads
+pub(crate) struct Shared {
+    repository: Arc<Mutex<Repository>>,
+    /// notified after every mutation, wakes up waiting subscribers
+    changed: Condvar,
+    subscribe_timeout: Duration,
+    webhooks: Option<Dispatcher>,
+    feed_dir: Option<PathBuf>,
+    feed_limit: usize,
+    admin_token: Option<String>,
+    /// admin requests are restricted to admin listeners
+    admin_listeners_only: bool,
+    /// the same for all registries
+    settings: Arc<LiveSettings>,
+    settings_file: Option<PathBuf>,
+    /// mirrors are read-only whatever the settings say
+    following: bool,
+    upstream: Option<ProxyCache>,
+    case_insensitive_lookup: bool,
+    policies: Vec<Arc<dyn PublishPolicy>>,
+    /// only locked while holding `repository`, so a retry can't race its original
+    idempotency: Mutex<IdempotencyCache>,
+    /// see [`ServerConfig::max_connections`], only used by the default registry
+    connections: Limiter,
+    /// see [`ServerConfig::max_queued_requests`]
+    queue: Limiter,
+    /// `None` if destructive admin requests don't need confirming
+    confirmations: Option<Mutex<Confirmations>>,
+    /// the same for all registries
+    scheduler: Arc<Scheduler>,
+    autosave: Option<Autosave>,
+    /// the same for all registries
+    access_log: Option<Arc<AccessLog>>,
+    /// the other registries by name, only set for the default one
+    registries: BTreeMap<String, Arc<Shared>>,
+}
+
+/// upper bound of remembered idempotency keys, the oldest are forgotten first
+const IDEMPOTENCY_CAPACITY: usize = 10_000;
+
+impl Shared {
+    /// `registry` is `None` for the default registry
+    fn new(
+        config: &ServerConfig,
+        repository: Arc<Mutex<Repository>>,
+        scheduler: Arc<Scheduler>,
+        settings: Arc<LiveSettings>,
+        access_log: Option<Arc<AccessLog>>,
+        registry: Option<&str>,
+    ) -> Self {
+        let default = registry.is_none();
+        let hooks = settings.get().webhooks.clone();
+        Self {
+            repository,
+            changed: Condvar::new(),
+            subscribe_timeout: config.subscribe_timeout,
+            // reloading might add hooks later
+            webhooks: (default && (!hooks.is_empty() || config.settings_file.is_some())).then(
+                || {
+                    Dispatcher::start(WebhookConfig {
+                        hooks,
+                        ..config.webhooks.clone()
+                    })
+                },
+            ),
+            feed_dir: match registry {
+                Some(name) => config.feed_dir.as_ref().map(|dir| dir.join(name)),
+                None => config.feed_dir.clone(),
+            },
+            feed_limit: config.feed_limit,
+            admin_token: config.admin_token.clone(),
+            admin_listeners_only: !config.admin_listen.is_empty(),
+            settings,
+            settings_file: config.settings_file.clone(),
+            following: config.follow.is_some(),
+            upstream: config
+                .upstream
+                .clone()
+                .filter(|_| default)
+                .map(ProxyCache::new),
+            case_insensitive_lookup: config.case_insensitive_lookup,
+            policies: config.policies.clone(),
+            idempotency: Mutex::new(IdempotencyCache::new(
+                config.idempotency_ttl,
+                IDEMPOTENCY_CAPACITY,
+            )),
+            connections: Limiter::new(config.max_connections),
+            queue: Limiter::new(config.max_queued_requests),
+            confirmations: config
+                .confirmation_window
+                .map(|window| Mutex::new(Confirmations::new(window))),
+            scheduler,
+            autosave: config.autosave,
+            access_log,
+            registries: BTreeMap::new(),
+        }
+    }
+
+    /// this registry and all others
+    fn all(&self) -> impl Iterator<Item = &Shared> {
+        std::iter::once(self).chain(self.registries.values().map(AsRef::as_ref))
+    }
+
+    /// the active settings, which stay the same while the snapshot is held
+    pub(crate) fn settings(&self) -> Arc<Settings> {
+        self.settings.get()
+    }
+
+    fn read_only(&self) -> bool {
+        self.following || self.settings().read_only
+    }
+
+    /// replaces the active settings with those in the settings file if it is valid
+    fn reload(&self) -> Result<(), SettingsError> {
+        let path = self.settings_file.as_ref().ok_or(SettingsError::NoFile)?;
+        let settings = Settings::load(path)?;
+        apply_log_level(&settings)?;
+        if let Some(webhooks) = &self.webhooks {
+            webhooks.set_hooks(settings.webhooks.clone());
+        }
+        self.settings.replace(settings);
+        log::info!("reloaded settings from {}", path.display());
+        Ok(())
+    }
+
+    fn admin_allowed(&self, token: &str, admin_listener: bool) -> bool {
+        let listener_ok = admin_listener || !self.admin_listeners_only;
+        let token_ok = match &self.admin_token {
+            Some(expected) => token_matches(expected, token),
+            None => false,
+        };
+        listener_ok && token_ok
+    }
+}
+
+/// Stops a running [`Server`]. Cheap to clone and safe to send to other threads.
+#[derive(Debug, Clone)]
+pub struct ShutdownHandle {
+    flag: Arc<AtomicBool>,
+    addrs: Vec<SocketAddr>,
+}
+
+impl ShutdownHandle {
+    pub fn shutdown(&self) {
+        self.flag.store(true, Ordering::SeqCst);
+        // accept() blocks, so poke every listener once to make it look at the flag
+        for addr in &self.addrs {
+            let _ = TcpStream::connect(wake_addr(*addr));
+        }
+    }
+
+    pub fn is_shutdown(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+}
+
+/// Reloads the settings of a running [`Server`], see [`ServerConfig::settings_file`]. Cheap to
+/// clone and safe to send to other threads, e.g. one waiting for SIGHUP.
+#[derive(Clone)]
+pub struct ReloadHandle {
+    shared: Arc<Shared>,
+}
+
+impl ReloadHandle {
+    /// fails without changing anything if the settings file can't be read or is invalid
+    pub fn reload(&self) -> Result<(), SettingsError> {
+        self.shared.reload()
+    }
+}
+
+/// the settings a server starts with
+fn initial_settings(config: &ServerConfig) -> Result<Settings, SettingsError> {
+    let settings = match &config.settings_file {
+        Some(path) => Settings::load(path)?,
+        None => Settings {
+            log_level: None,
+            read_only: config.read_only,
+            quotas: config.quotas.clone(),
+            webhooks: config.webhooks.hooks.clone(),
+            policies: vec![],
+        },
+    };
+    apply_log_level(&settings)?;
+    Ok(settings)
+}
+
+fn apply_log_level(settings: &Settings) -> Result<(), SettingsError> {
+    if let Some(level) = settings.log_level()? {
+        log::set_max_level(level);
+    }
+    Ok(())
+}
+
+/// applies the configured name rules, version and kind policies and compression
+fn configure(repository: &mut Repository, config: &ServerConfig) {
+    let rules = repository.name_rules_mut();
+    config
+        .reserved_names
+        .iter()
+        .for_each(|name| rules.reserve(name));
+    config
+        .blocked_terms
+        .iter()
+        .for_each(|term| rules.block(term));
+    if let Some(policy) = config.version_policy {
+        repository.set_version_policy(policy);
+    }
+    for (kind, policy) in &config.kind_policies {
+        repository.set_kind_policy(kind.clone(), *policy);
+    }
+    repository.set_compression(config.compression);
+}
+
+/// unspecified addresses can be listened on, but not connected to
+fn wake_addr(addr: SocketAddr) -> SocketAddr {
+    match addr.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), addr.port())
+        }
+        IpAddr::V6(ip) if ip.is_unspecified() => {
+            SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), addr.port())
+        }
+        _ => addr,
+    }
+}
+
+impl Server {
+    pub fn bind(config: ServerConfig) -> Result<Self, ServerError> {
+        if config.listen.is_empty() {
+            return Err(ServerError::NoListenAddrs);
+        }
+
+        panics::install_hook();
+        let mut listeners = vec![];
+        let public = config.listen.iter().map(|addr| (addr, false));
+        let admin = config.admin_listen.iter().map(|addr| (addr, true));
+        for (addr, is_admin) in public.chain(admin) {
+            let listener = TcpListener::bind(addr).map_err(|e| ServerError::Bind(*addr, e))?;
+            listeners.push((listener, is_admin));
+        }
+        #[allow(unused_mut)]
+        let mut addrs = listeners
+            .iter()
+            .map(|(l, _)| l.local_addr())
+            .collect::<Result<Vec<_>, _>>()?;
+        #[cfg(feature = "websocket")]
+        let websocket = match config.websocket_listen {
+            Some(addr) => {
+                let listener = TcpListener::bind(addr).map_err(|e| ServerError::Bind(addr, e))?;
+                addrs.push(listener.local_addr()?);
+                Some(listener)
+            }
+            None => None,
+        };
+
+        #[cfg(feature = "grpc")]
+        let grpc = config
+            .grpc_listen
+            .map(|addr| TcpListener::bind(addr).map_err(|e| ServerError::Bind(addr, e)))
+            .transpose()?;
+
+        #[cfg(feature = "otel")]
+        if let Some(telemetry) = config.telemetry.clone() {
+            crate::telemetry::install(telemetry)?;
+        }
+        let mut repository = open_store(&config)?;
+        configure(&mut repository, &config);
+        let scheduler = Arc::new(Scheduler::new());
+        let settings = Arc::new(LiveSettings::new(initial_settings(&config)?));
+        let access_log = config
+            .access_log
+            .as_ref()
+            .map(AccessLog::open)
+            .transpose()?
+            .map(Arc::new);
+        let mut shared = Shared::new(
+            &config,
+            Arc::new(Mutex::new(repository)),
+            scheduler.clone(),
+            settings.clone(),
+            access_log.clone(),
+            None,
+        );
+        if let Some(registries) = &config.registries {
+            let mut manager = RepositoryManager::open(&registries.dir, config.store_key.clone())?;
+            for name in &registries.names {
+                if manager.get(name).is_none() {
+                    manager.create(name)?;
+                }
+            }
+            for (name, repository) in manager.iter() {
+                configure(&mut repository.lock().unwrap(), &config);
+                let registry = Shared::new(
+                    &config,
+                    repository.clone(),
+                    scheduler.clone(),
+                    settings.clone(),
+                    access_log.clone(),
+                    Some(name),
+                );
+                shared
+                    .registries
+                    .insert(name.to_string(), Arc::new(registry));
+            }
+        }
+
+        Ok(Self {
+            listeners,
+            follow: config.follow.clone(),
+            retention: config.retention,
+            #[cfg(feature = "grpc")]
+            grpc,
+            #[cfg(feature = "websocket")]
+            websocket,
+            shared: Arc::new(shared),
+            shutdown: ShutdownHandle {
+                flag: Arc::new(AtomicBool::new(false)),
+                addrs,
+            },
+        })
+    }
+
+    /// the actual bound addresses, useful when binding to port 0.
+    /// Public listeners come first, followed by admin listeners and the WebSocket listener.
+    pub fn local_addrs(&self) -> &[SocketAddr] {
+        &self.shutdown.addrs
+    }
+
+    /// the actual address of the gRPC listener, if enabled
+    #[cfg(feature = "grpc")]
+    pub fn grpc_addr(&self) -> Option<SocketAddr> {
+        self.grpc.as_ref().and_then(|l| l.local_addr().ok())
+    }
+
+    /// the actual address of the WebSocket listener, if enabled
+    #[cfg(feature = "websocket")]
+    pub fn websocket_addr(&self) -> Option<SocketAddr> {
+        self.websocket.as_ref().and_then(|l| l.local_addr().ok())
+    }
+
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        self.shutdown.clone()
+    }
+
+    pub fn reload_handle(&self) -> ReloadHandle {
+        ReloadHandle {
+            shared: self.shared.clone(),
+        }
+    }
+
+    /// Serves requests until [`ShutdownHandle::shutdown`] is called.
+    /// The repository is saved when this returns.
+    pub fn serve(self) -> Result<(), ServerError> {
+        // a panic elsewhere might take down the process, save what would be lost first
+        let shared = Arc::downgrade(&self.shared);
+        panics::on_crash(move || match shared.upgrade() {
+            Some(shared) => {
+                save_on_crash(&shared);
+                true
+            }
+            None => false,
+        });
+
+        let mut threads = vec![];
+        for (listener, is_admin) in self.listeners {
+            let kind = if is_admin {
+                "admin requests"
+            } else {
+                "requests"
+            };
+            log::info!("serving {} at {}", kind, listener.local_addr()?);
+            let shared = self.shared.clone();
+            let shutdown = self.shutdown.clone();
+            threads.push(thread::spawn(move || {
+                serve_listener(listener, is_admin, &shared, &shutdown)
+            }));
+        }
+
+        #[cfg(feature = "grpc")]
+        if let Some(listener) = self.grpc {
+            log::info!("serving gRPC at {}", listener.local_addr()?);
+            let shared = self.shared.clone();
+            let shutdown = self.shutdown.clone();
+            threads.push(thread::spawn(move || {
+                if let Err(e) = crate::grpc::serve(listener, shared, shutdown) {
+                    error!("gRPC server failed: {}", e);
+                }
+            }));
+        }
+
+        #[cfg(feature = "websocket")]
+        if let Some(listener) = self.websocket {
+            log::info!(
+                "serving WebSocket connections at {}",
+                listener.local_addr()?
+            );
+            let shared = self.shared.clone();
+            let shutdown = self.shutdown.clone();
+            threads.push(thread::spawn(move || {
+                crate::websocket::serve(listener, &shared, &shutdown)
+            }));
+        }
+
+        if let Some(follower) = self.follow {
+            // not joined, it might be waiting for the primary for a while
+            let shared = self.shared.clone();
+            let shutdown = self.shutdown.clone();
+            thread::spawn(move || follow(follower, &shared, &shutdown));
+        }
+
+        // tasks only hold on to `Shared` weakly, it owns them through the scheduler
+        if let Some(config) = self.shared.autosave {
+            let shared = Arc::downgrade(&self.shared);
+            self.shared
+                .scheduler
+                .add("autosave", config.interval, move || {
+                    shared.upgrade().map_or(Ok(()), |shared| autosave(&shared))
+                });
+        }
+        if let Some(retention) = self.retention.filter(|_| !self.shared.following) {
+            let shared = Arc::downgrade(&self.shared);
+            self.shared
+                .scheduler
+                .add("retention", retention.interval, move || {
+                    // read-only mode might have been switched on since
+                    if let Some(shared) = shared.upgrade().filter(|shared| !shared.read_only()) {
+                        prune(&retention.policy, &shared);
+                    }
+                    Ok(())
+                });
+        }
+        let scheduler = {
+            let shared = self.shared.clone();
+            thread::spawn(move || shared.scheduler.run())
+        };
+
+        for thread in threads {
+            thread.join().expect("listener thread panicked");
+        }
+        self.shared.scheduler.stop();
+        scheduler.join().expect("scheduler thread panicked");
+
+        // connection threads might still hold on to the repository, so don't wait for `Drop`
+        for shared in self.shared.all() {
+            shared.repository.lock().unwrap().save_if_dirty()?;
+        }
+        #[cfg(feature = "otel")]
+        crate::telemetry::flush();
+        Ok(())
+    }
+}
+
+fn serve_listener(
+    listener: TcpListener,
+    is_admin: bool,
+    shared: &Arc<Shared>,
+    shutdown: &ShutdownHandle,
+) {
+    // rejecting waits for the request, which mustn't hold up accepting connections
+    let (rejected, to_reject) = std::sync::mpsc::sync_channel::<TcpStream>(REJECT_QUEUE);
+    let rejecter = {
+        let shared = shared.clone();
+        thread::spawn(move || {
+            for stream in to_reject {
+                reject_overloaded(stream, &shared);
+            }
+        })
+    };
+    for connection in listener.incoming() {
+        if shutdown.is_shutdown() {
+            break;
+        }
+
+        let stream = match connection {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Connection error: {:?}", e);
+                continue;
+            }
+        };
+
+        let Some(permit) = shared.connections.try_acquire() else {
+            // if even the rejecter can't keep up, the connection is just closed
+            let _ = rejected.try_send(stream);
+            continue;
+        };
+        // subscriptions may block for a long time, so every connection gets its own thread
+        let shared = shared.clone();
+        thread::spawn(move || {
+            serve_connection(stream, &shared, is_admin);
+            drop(permit);
+        });
+    }
+    drop(rejected);
+    let _ = rejecter.join();
+}
+
+/// how long a crash waits for a repository to be unlocked before giving up on saving it
+const CRASH_SAVE_WAIT: Duration = Duration::from_millis(100);
+
+/// Saves the registries that are dirty, as far as possible. Repositories that stay locked are
+/// skipped, the panicking thread might be the one holding them.
+fn save_on_crash(shared: &Shared) {
+    for shared in shared.all() {
+        let deadline = Instant::now() + CRASH_SAVE_WAIT;
+        let repository = loop {
+            match shared.repository.try_lock() {
+                Ok(repository) => break Some(repository),
+                Err(TryLockError::Poisoned(e)) => break Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) if Instant::now() < deadline => {
+                    thread::sleep(Duration::from_millis(5))
+                }
+                Err(TryLockError::WouldBlock) => break None,
+            }
+        };
+        match repository.map(|repository| repository.save_if_dirty()) {
+            Some(Ok(true)) => log::warn!("saved repository after a panic"),
+            Some(Ok(false)) => {}
+            Some(Err(e)) => error!("could not save repository after a panic: {}", e),
+            None => error!("could not save repository after a panic, it is locked"),
+        }
+    }
+}
+
+/// the `autosave` task, saves the registries that are dirty
+fn autosave(shared: &Shared) -> Result<(), String> {
+    let mut failures = vec![];
+    for shared in shared.all() {
+        match shared.repository.lock().unwrap().save_if_dirty() {
+            Ok(true) => debug!("autosaved repository"),
+            Ok(false) => {}
+            Err(e) => failures.push(e.to_string()),
+        }
+    }
+    match failures.is_empty() {
+        true => Ok(()),
+        false => Err(format!("autosave failed: {}", failures.join(", "))),
+    }
+}
+
+/// saves before the autosave interval is up once enough changes piled up
+fn autosave_if_due(repository: &Repository, shared: &Shared) {
+    if let Some(config) = shared.autosave {
+        if repository.unsaved_changes() >= config.after_mutations {
+            shared.scheduler.trigger("autosave");
+        }
+    }
+}
+
+/// the `retention` task, prunes every registry
+fn prune(policy: &RetentionPolicy, shared: &Shared) {
+    for shared in shared.all() {
+        let mut repository = shared.repository.lock().unwrap();
+        let last_seq = repository.changes().last_seq();
+        let report = repository.prune(policy);
+        if !report.is_empty() {
+            log::info!(
+                "pruned {} releases and {} tombstones",
+                report.releases.values().map(Vec::len).sum::<usize>(),
+                report.tombstones.len()
+            );
+            after_changes(&mut repository, shared, "retention", last_seq);
+        }
+    }
+}
+
+fn follow(follower: Follower, shared: &Shared, shutdown: &ShutdownHandle) {
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+    let mut backoff = Duration::from_millis(500);
+    while !shutdown.is_shutdown() {
+        match follower.sync_once(&shared.repository) {
+            Ok(applied) => {
+                backoff = Duration::from_millis(500);
+                if applied > 0 {
+                    debug!("replicated {} changes", applied);
+                    shared.changed.notify_all();
+                    autosave_if_due(&shared.repository.lock().unwrap(), shared);
+                }
+            }
+            Err(e) => {
+                error!("replication failed, retrying in {:?}: {}", backoff, e);
+                thread::sleep(backoff);
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        }
+    }
+}
+
+trait JsonResponse: Serialize {
+    fn to_json(&self) -> String;
+}
+
+impl<T: Serialize> JsonResponse for Result<T, RepoError> {
+    fn to_json(&self) -> String {
+        // to avoid moving out of self, first convert to Result<&T, &E> using `as_ref()`
+        // then we need to dereference the repo error again so the generated `From` impl
+        // is available.
+        self.as_ref().map_err(|e| ApiError::from(*e)).to_json()
+    }
+}
+
+fn internal_error() -> String {
+    let err: ApiResult<()> = Err(ApiError::Internal);
+    serde_json::to_string(&err).unwrap()
+}
+
+impl<T: Serialize> JsonResponse for Result<T, ApiError> {
+    fn to_json(&self) -> String {
+        match serde_json::to_string(&self) {
+            Ok(s) => s,
+
+            // safe fallback
+            Err(_) => internal_error(),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+enum ParseError {
+    #[error("unreadable")]
+    Unreadable,
+    #[error("garbage: {0}")]
+    Garbage(String),
+    #[error("too large")]
+    TooLarge,
+}
+
+/// the next line, empty at the end of the stream
+fn read_line(reader: &mut impl BufRead, limit: Option<usize>) -> Result<String, ParseError> {
+    let mut buf = vec![];
+    let limit = limit.map(|limit| limit as u64).unwrap_or(u64::MAX);
+    reader
+        .take(limit.saturating_add(1))
+        .read_until(b'\n', &mut buf)
+        .map_err(|_| ParseError::Unreadable)?;
+    if buf.len() as u64 > limit {
+        return Err(ParseError::TooLarge);
+    }
+    String::from_utf8(buf).map_err(|_| ParseError::Unreadable)
+}
+
+/// how much of a request exceeding [`Quotas::max_request_size`] is read and thrown away, and for
+/// how long, so the client reads the answer rather than a reset without tying up the connection
+const DRAIN_LIMIT: u64 = 1 << 20;
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// rejected connections waiting for an answer, see [`reject_overloaded`]
+const REJECT_QUEUE: usize = 64;
+
+/// how long a rejected connection gets to send its request, so it reads the answer rather than
+/// a reset
+const REJECT_READ_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// answers the first request of a connection beyond [`ServerConfig::max_connections`] with
+/// `ApiError::Overloaded`
+fn reject_overloaded(mut stream: TcpStream, shared: &Shared) {
+    log::warn!("rejected connection, too many are open");
+    let _ = stream.set_read_timeout(Some(REJECT_READ_TIMEOUT));
+    let line = match stream.try_clone() {
+        Ok(reader) => read_line(
+            &mut BufReader::new(reader),
+            shared.settings().quotas.max_request_size,
+        )
+        .unwrap_or_default(),
+        Err(_) => String::new(),
+    };
+    let response = Err::<(), _>(ApiError::Overloaded).to_json();
+    let _ = match serde_json::from_str::<TaggedRequest>(&line) {
+        Ok(tagged) => writeln!(stream, "{}", tag(tagged.id, response)),
+        Err(_) => write!(stream, "{}", response),
+    };
+}
+
+/// Answers a single request, or a pipeline of tagged ones, see [`TaggedRequest`].
+fn serve_connection(mut stream: TcpStream, shared: &Arc<Shared>, admin_listener: bool) {
+    let peer = stream
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    let mut reader = match stream.try_clone() {
+        Ok(reader) => BufReader::new(reader),
+        Err(e) => {
+            error!("Connection error: {:?}", e);
+            return;
+        }
+    };
+    let line = match read_line(&mut reader, shared.settings().quotas.max_request_size) {
+        Ok(line) => line,
+        Err(e) => {
+            if let ParseError::TooLarge = e {
+                // the client only reads the response once it sent everything
+                let _ = stream.set_read_timeout(Some(DRAIN_TIMEOUT));
+                let _ = std::io::copy(&mut reader.take(DRAIN_LIMIT), &mut std::io::sink());
+            }
+            let _ = write!(stream, "{}", parse_error(e));
+            return;
+        }
+    };
+    if let Ok(tagged) = serde_json::from_str::<TaggedRequest>(&line) {
+        return serve_pipeline(tagged, reader, stream, peer, shared, admin_listener);
+    }
+    let response = match serde_json::from_str(&line) {
+        Ok(request) => dispatch(request, &peer, shared, admin_listener),
+        Err(_) => parse_error(ParseError::Garbage(line)),
+    };
+    debug!("sending response: {response}");
+    if let Err(e) = write!(stream, "{}", response) {
+        error!("error writing to stream: {:?}", e);
+    }
+}
+
+fn parse_error(e: ParseError) -> String {
+    match e {
+        ParseError::TooLarge => {
+            log::warn!("rejected request exceeding the size limit");
+            Err::<(), _>(ApiError::QuotaExceeded(Quota::RequestSize)).to_json()
+        }
+        e => {
+            log::warn!("could not parse request - {}", e);
+            internal_error()
+        }
+    }
+}
+
+fn tag(id: u64, response: String) -> String {
+    // responses are always valid JSON, but don't leave the client waiting if one isn't
+    let response = serde_json::value::RawValue::from_string(response)
+        .or_else(|_| serde_json::value::RawValue::from_string(internal_error()));
+    match response {
+        Ok(response) => serde_json::to_string(&TaggedResponse { id, response }).unwrap_or_default(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Handles tagged requests concurrently until the client is done sending, answering each as soon
+/// as it is done. Requests that can't be parsed end the connection, as they can't be answered.
+fn serve_pipeline(
+    first: TaggedRequest,
+    mut reader: BufReader<TcpStream>,
+    stream: TcpStream,
+    peer: String,
+    shared: &Arc<Shared>,
+    admin_listener: bool,
+) {
+    let writer = Arc::new(Mutex::new(stream));
+    let mut workers = vec![];
+    let mut next = Some(first);
+    loop {
+        let tagged = match next.take() {
+            Some(tagged) => tagged,
+            None => match read_line(&mut reader, shared.settings().quotas.max_request_size) {
+                Ok(line) if line.is_empty() => break,
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => match serde_json::from_str(&line) {
+                    Ok(tagged) => tagged,
+                    Err(_) => {
+                        log::warn!("could not parse pipelined request - garbage: {}", line);
+                        break;
+                    }
+                },
+                Err(e) => {
+                    log::warn!("could not read pipelined request - {}", e);
+                    break;
+                }
+            },
+        };
+        // pooled connections live long, don't keep every request's thread around
+        workers.retain(|worker: &thread::JoinHandle<()>| !worker.is_finished());
+        let (writer, shared, peer) = (writer.clone(), shared.clone(), peer.clone());
+        workers.push(thread::spawn(move || {
+            let TaggedRequest { id, request } = tagged;
+            let response = dispatch(request, &peer, &shared, admin_listener);
+            debug!("sending response {id}: {response}");
+            if let Err(e) = writeln!(writer.lock().unwrap(), "{}", tag(id, response)) {
+                error!("error writing to stream: {:?}", e);
+            }
+        }));
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+}
+
+/// Answers `request` from `peer`, whichever transport it came in on.
+pub(crate) fn dispatch(
+    request: ApiRequest,
+    peer: &str,
+    shared: &Shared,
+    admin_listener: bool,
+) -> String {
+    if shared.access_log.is_none() && !cfg!(feature = "otel") {
+        return answer_isolated(request, peer, shared, admin_listener);
+    }
+    #[cfg(feature = "otel")]
+    let mut span = request_span(&request, peer);
+    let started = Instant::now();
+    let (kind, crate_name) = (request.kind(), request.crate_name().map(String::from));
+    let response = answer_isolated(request, peer, shared, admin_listener);
+    let status = access_log::status_code(&response);
+    #[cfg(feature = "otel")]
+    {
+        span.set_attribute("status", status);
+        if status >= 500 {
+            span.set_error();
+        }
+    }
+    let access_log = match &shared.access_log {
+        Some(access_log) => access_log,
+        None => return response,
+    };
+    access_log.record(&access_log::Entry {
+        at: Utc::now(),
+        peer: peer.to_string(),
+        request: kind,
+        crate_name,
+        status,
+        bytes: response.len(),
+        latency: started.elapsed(),
+    });
+    response
+}
+
+/// the span of answering `request`, in the client's trace if it is `Traced`
+#[cfg(feature = "otel")]
+fn request_span(request: &ApiRequest, peer: &str) -> crate::telemetry::Span {
+    let remote_parent = match request {
+        ApiRequest::Traced { traceparent, .. } => {
+            crate::telemetry::SpanContext::from_traceparent(traceparent)
+        }
+        _ => None,
+    };
+    let mut span = crate::telemetry::server_span("request", remote_parent);
+    span.set_attribute("request", request.kind());
+    if let Some(name) = request.crate_name() {
+        span.set_attribute("crate", name);
+    }
+    span.set_attribute("peer", peer);
+    span
+}
+
+/// [`answer`], but a panic only fails this request, see [`crate::panics`]
+fn answer_isolated(
+    request: ApiRequest,
+    peer: &str,
+    shared: &Shared,
+    admin_listener: bool,
+) -> String {
+    let kind = request.kind();
+    match panics::isolate(|| answer(request, peer, shared, admin_listener)) {
+        Ok(response) => response,
+        Err(report) => {
+            error!(
+                "panicked answering {} request from {}: {}",
+                kind, peer, report
+            );
+            recover(shared);
+            internal_error()
+        }
+    }
+}
+
+/// Clears the poison a panic left on repositories, rebuilding their indices in case it struck
+/// halfway through updating them
+fn recover(shared: &Shared) {
+    for shared in shared.all() {
+        if shared.repository.is_poisoned() {
+            shared.repository.clear_poison();
+            let fixed = shared.repository.lock().unwrap().rebuild_indices();
+            log::warn!(
+                "rebuilt indices after a panic, {} crates were misfiled",
+                fixed
+            );
+        }
+    }
+}
+
+fn answer(request: ApiRequest, peer: &str, shared: &Shared, admin_listener: bool) -> String {
+    // the envelopes may come in any order
+    let (mut idempotency_key, mut token, mut registry) = (None, None, None);
+    let mut request = request;
+    let request = loop {
+        match request {
+            ApiRequest::Idempotent {
+                key,
+                request: inner,
+            } if idempotency_key.is_none() => {
+                idempotency_key = Some(key);
+                request = *inner;
+            }
+            ApiRequest::Authenticated {
+                token: t,
+                request: inner,
+            } if token.is_none() => {
+                token = Some(t);
+                request = *inner;
+            }
+            ApiRequest::Registry {
+                name,
+                request: inner,
+            } if registry.is_none() => {
+                registry = Some(name);
+                request = *inner;
+            }
+            // already part of the request's span, see `dispatch`
+            ApiRequest::Traced { request: inner, .. } => request = *inner,
+            request => break request,
+        }
+    };
+    let shared = match registry.map(|name| shared.registries.get(&name).ok_or(name)) {
+        None => shared,
+        Some(Ok(registry)) => registry,
+        Some(Err(name)) => return Err::<(), _>(ApiError::UnknownRegistry(name)).to_json(),
+    };
+    if let ApiRequest::Subscribe { since } = request {
+        return subscribe(since, shared).to_json();
+    }
+    // without waiting for the repository, so it measures the round trip alone
+    if let ApiRequest::Ping = request {
+        let res: PingResult = Ok(ServerInfo::current());
+        return res.to_json();
+    }
+    // waiting for the repository is what piles up under load
+    let Some(_queued) = shared.queue.try_acquire() else {
+        log::warn!("rejected {} request, too many are queued", request.kind());
+        return Err::<(), _>(ApiError::Overloaded).to_json();
+    };
+    if let Err(e) = authorize(&request, shared, admin_listener) {
+        let res: ApiResult<()> = Err(e);
+        return res.to_json();
+    }
+    let request = match hold_destructive(request, shared) {
+        Ok(request) => request,
+        Err(response) => return response,
+    };
+    if let ApiRequest::Admin { request, .. } = &request {
+        if let Some(res) = handle_task_request(request, shared) {
+            return res.to_json();
+        }
+    }
+
+    // only lock once the request has been read, so slow clients don't stall everyone else
+    let mut repository = shared.repository.lock().unwrap();
+    let token = match token
+        .map(|token| authenticate(&token, &request, &mut repository))
+        .transpose()
+    {
+        Ok(token) => token,
+        Err(e) => return Err::<(), _>(e).to_json(),
+    };
+    if let (ApiRequest::FindExact(name), Some(upstream)) = (&request, &shared.upstream) {
+        let ignore_case = shared.case_insensitive_lookup;
+        return find_exact_proxied(name, repository, ignore_case, upstream).to_json();
+    }
+    let user = token.as_ref().map(|token| token.user.clone());
+    let actor = match (&request, &user) {
+        (ApiRequest::Admin { .. }, _) => format!("admin@{}", peer),
+        (_, Some(user)) => format!("{}@{}", user, peer),
+        (_, None) => peer.to_string(),
+    };
+    // repeating reads is harmless, so only mutations are remembered. Keys are per user, so
+    // nobody is replayed someone else's response.
+    let idempotency = idempotency_key
+        .filter(|_| request.is_mutating())
+        .map(|key| format!("{}:{key}", user.as_deref().unwrap_or_default()))
+        .map(|key| (key, serde_json::to_string(&request).unwrap_or_default()));
+    if let Some((key, request_json)) = &idempotency {
+        if let Some(res) = shared.idempotency.lock().unwrap().replay(key, request_json) {
+            debug!("replaying response for idempotency key '{}'", key);
+            return res.unwrap_or_else(|e| Err::<(), _>(e).to_json());
+        }
+    }
+    if is_long_read(&request) {
+        // answered from a view, so publishes don't wait for it
+        let view = repository.view();
+        drop(repository);
+        return handle_long_read(request, &view);
+    }
+    let last_seq = repository.changes().last_seq();
+    let settings = shared.settings();
+    let context = RequestContext {
+        ignore_case: shared.case_insensitive_lookup,
+        user,
+        admin_scope: token.as_ref().is_some_and(TokenInfo::is_admin),
+        quotas: &settings.quotas,
+        policies: &shared.policies,
+        builtin_policies: &settings.policies,
+    };
+    let response = handle_request(request, &mut repository, &context);
+    if let Some((key, request_json)) = idempotency {
+        shared
+            .idempotency
+            .lock()
+            .unwrap()
+            .record(key, request_json, response.clone());
+    }
+    after_changes(&mut repository, shared, &actor, last_seq);
+    response
+}
+
+/// attributes changes after `last_seq` to `actor` and tells everyone interested about them
+fn after_changes(repository: &mut Repository, shared: &Shared, actor: &str, last_seq: u64) {
+    if repository.changes().last_seq() == last_seq {
+        return;
+    }
+    repository.audit(actor, last_seq);
+    shared.changed.notify_all();
+    if let Some(webhooks) = &shared.webhooks {
+        for change in repository.changes().since(last_seq) {
+            webhooks.notify(change);
+        }
+    }
+    if let Some(dir) = &shared.feed_dir {
+        update_feeds(repository, last_seq, dir, shared.feed_limit);
+    }
+    autosave_if_due(repository, shared);
+}
+
+/// Holds back destructive admin requests until they are confirmed, see [`crate::confirmation`].
+/// Passes on every other request, `Confirm` as the request it confirms.
+fn hold_destructive(request: ApiRequest, shared: &Shared) -> Result<ApiRequest, String> {
+    let Some(confirmations) = &shared.confirmations else {
+        return Ok(request);
+    };
+    match request {
+        ApiRequest::Admin {
+            token,
+            request: AdminRequest::Confirm(confirmation),
+        } => match confirmations.lock().unwrap().confirm(&confirmation) {
+            Some(request) => Ok(ApiRequest::Admin { token, request }),
+            None => Err(Err::<(), _>(ApiError::InvalidConfirmation).to_json()),
+        },
+        ApiRequest::Admin { request, .. } if confirmation::is_destructive(&request) => {
+            let res: AdminResult = Ok(confirmations.lock().unwrap().hold(request));
+            Err(res.to_json())
+        }
+        request if confirmation::contains_destructive(&request) => {
+            Err(Err::<(), _>(ApiError::ConfirmationRequired).to_json())
+        }
+        request => Ok(request),
+    }
+}
+
+/// answers admin requests about the scheduler and settings, which the repository knows nothing
+/// about
+fn handle_task_request(request: &AdminRequest, shared: &Shared) -> Option<AdminResult> {
+    match request {
+        AdminRequest::Reload => Some(
+            shared
+                .reload()
+                .map(|()| AdminResponse::Done)
+                .map_err(|e| ApiError::InvalidSettings(e.to_string())),
+        ),
+        AdminRequest::Tasks => Some(Ok(AdminResponse::Tasks(shared.scheduler.tasks()))),
+        AdminRequest::RunTask(name) => Some(match shared.scheduler.trigger(name) {
+            true => Ok(AdminResponse::Done),
+            false => Err(ApiError::Repo(RepoError::NotFound)),
+        }),
+        _ => None,
+    }
+}
+
+/// reads that take long enough to be served from a [`View`] instead of under the lock
+fn is_long_read(request: &ApiRequest) -> bool {
+    match request {
+        ApiRequest::Snapshot => true,
+        #[cfg(feature = "graphql")]
+        ApiRequest::GraphQL { .. } => true,
+        _ => false,
+    }
+}
+
+fn handle_long_read(request: ApiRequest, view: &View) -> String {
+    match request {
+        ApiRequest::Snapshot => {
+            let res: SnapshotResult = Ok(view.snapshot());
+            res.to_json()
+        }
+        #[cfg(feature = "graphql")]
+        ApiRequest::GraphQL { query, variables } => {
+            let res: crate::api::GraphQLResult = Ok(view.graphql(&query, variables));
+            res.to_json()
+        }
+        _ => internal_error(),
+    }
+}
+
+/// checks admin tokens and read-only mode, including every request of a batch
+fn authorize(request: &ApiRequest, shared: &Shared, admin_listener: bool) -> Result<(), ApiError> {
+    check_access(request, shared, admin_listener, false)
+}
+
+/// like [`authorize`], read-only mode doesn't apply to the requests of a dry run
+fn check_access(
+    request: &ApiRequest,
+    shared: &Shared,
+    admin_listener: bool,
+    dry_run: bool,
+) -> Result<(), ApiError> {
+    if let ApiRequest::Admin { token, request } = request {
+        if !shared.admin_allowed(token, admin_listener) {
+            log::warn!("rejected unauthorized admin request");
+            return Err(ApiError::Unauthorized);
+        }
+        if dry_run && request.has_side_effects() {
+            return Err(ApiError::NotDryRunnable);
+        }
+    }
+    if let ApiRequest::Batch { requests, .. } = request {
+        for request in requests {
+            check_access(request, shared, admin_listener, dry_run)?;
+        }
+    }
+    if let ApiRequest::Idempotent { request, .. }
+    | ApiRequest::IfRevision { request, .. }
+    | ApiRequest::Authenticated { request, .. }
+    | ApiRequest::Registry { request, .. }
+    | ApiRequest::Traced { request, .. } = request
+    {
+        check_access(request, shared, admin_listener, dry_run)?;
+    }
+    if let ApiRequest::DryRun { request } = request {
+        check_access(request, shared, admin_listener, true)?;
+    }
+    if request.is_mutating() && !dry_run && shared.read_only() {
+        return Err(ApiError::ReadOnly);
+    }
+    Ok(())
+}
+
+fn update_feeds(repository: &Repository, last_seq: u64, dir: &Path, limit: usize) {
+    let mut touched = vec![];
+    for change in repository.changes().since(last_seq) {
+        let name = change.event.crate_name();
+        if !touched.contains(&name) {
+            touched.push(name);
+        }
+    }
+    if let Err(e) = feed::write_feeds(repository, dir, touched, limit) {
+        error!("could not write feeds to {:?}: {}", dir, e);
+    }
+}
+
+fn find_exact_proxied(
+    name: &str,
+    mut repository: MutexGuard<'_, Repository>,
+    ignore_case: bool,
+    upstream: &ProxyCache,
+) -> FindExactResult {
+    let local_name = lookup_name(name.to_string(), &repository, ignore_case);
+    repository.record_download(&local_name);
+    if let Some(crt) = repository.find_exact(&local_name) {
+        return Ok(Some(crt.to_owned()));
+    }
+    drop(repository);
+    // the repository is unlocked while talking to upstream
+    upstream
+        .find_exact(name)
+        .map_err(|e| ApiError::Upstream(e.to_string()))
+}
+
+/// the name of the crate `name` refers to, if it differs only in case and `ignore_case` is set
+fn lookup_name(name: String, repository: &Repository, ignore_case: bool) -> String {
+    if !ignore_case {
+        return name;
+    }
+    match repository.find_ignoring_case(&name) {
+        Some(crt) => crt.metadata().name().to_string(),
+        None => name,
+    }
+}
+
+fn subscribe(since: u64, shared: &Shared) -> SubscribeResult {
+    let deadline = Instant::now() + shared.subscribe_timeout;
+    let mut repository = shared.repository.lock().unwrap();
+    loop {
+        let changes = repository.changes().since(since);
+        let now = Instant::now();
+        if !changes.is_empty() || now >= deadline {
+            return Ok(changes.to_vec());
+        }
+        repository = shared
+            .changed
+            .wait_timeout(repository, deadline - now)
+            .unwrap()
+            .0;
+    }
+}
+
+/// `token`, if it is known, hasn't expired and its scopes permit `request`
+fn authenticate(
+    token: &str,
+    request: &ApiRequest,
+    repository: &mut Repository,
+) -> Result<TokenInfo, ApiError> {
+    match repository.authenticate(token) {
+        Some(token) if token.permits(request) => Ok(token.clone()),
+        Some(token) => {
+            log::warn!(
+                "rejected {} request out of scope of token {}",
+                request.kind(),
+                token.id
+            );
+            Err(ApiError::OutOfScope(request.kind().to_string()))
+        }
+        None => {
+            log::warn!("rejected unknown or expired user token");
+            Err(ApiError::Unauthorized)
+        }
+    }
+}
+
+struct RequestContext<'a> {
+    /// makes `FindExact` case insensitive, see [`ServerConfig::case_insensitive_lookup`]
+    ignore_case: bool,
+    /// the authenticated user, see [`ApiRequest::Authenticated`]
+    user: Option<String>,
+    /// whether the user's token has the `admin` scope, see [`crate::auth::Scope::Admin`]
+    admin_scope: bool,
+    quotas: &'a Quotas,
+    /// see [`ServerConfig::policies`]
+    policies: &'a [Arc<dyn PublishPolicy>],
+    /// of the settings file
+    builtin_policies: &'a [BuiltinPolicy],
+}
+
+impl RequestContext<'_> {
+    fn user(&self) -> Result<&str, ApiError> {
+        self.user.as_deref().ok_or(ApiError::Unauthorized)
+    }
+
+    /// rejects `version` of the crate described by `metadata` if any policy says so
+    fn check_policies(
+        &self,
+        metadata: &Metadata,
+        version: SemVer,
+        new_crate: bool,
+    ) -> Result<(), ApiError> {
+        let publish = Publish {
+            metadata,
+            version,
+            new_crate,
+            user: self.user.as_deref(),
+        };
+        let policies = self.policies.iter().map(AsRef::as_ref).chain(
+            self.builtin_policies
+                .iter()
+                .map(|policy| policy as &dyn PublishPolicy),
+        );
+        policy::check_all(policies, &publish).map_err(ApiError::PolicyViolation)
+    }
+}
+
+/// checks roles in the owning organization, quotas and policies of publishing requests
+fn permit(
+    request: &ApiRequest,
+    repository: &Repository,
+    ctx: &RequestContext,
+) -> Result<(), ApiError> {
+    let user = ctx.user.as_deref();
+    let new_crate = |metadata: &Metadata, versions: &[SemVer]| -> Result<(), ApiError> {
+        if let Some(org) = metadata.org() {
+            repository.check_org_role(user, org, Role::Publisher)?;
+        }
+        repository.check_namespace(user, metadata.name())?;
+        // the author is whatever the client claims, so crates are counted for the user
+        if ctx.quotas.max_crates_per_author.is_some() {
+            repository
+                .check_crate_quota(ctx.quotas, ctx.user()?)
+                .map_err(ApiError::QuotaExceeded)?;
+        }
+        repository
+            .check_release_quota(ctx.quotas, metadata.name(), versions.len())
+            .map_err(ApiError::QuotaExceeded)?;
+        versions
+            .iter()
+            .try_for_each(|version| ctx.check_policies(metadata, *version, true))
+    };
+    match request {
+        ApiRequest::AddCrate(metadata, version) => new_crate(metadata, &[*version]),
+        ApiRequest::PublishAtomic { metadata, releases } => new_crate(metadata, releases),
+        ApiRequest::PublishVersions {
+            metadata,
+            versions,
+            lenient,
+        } => {
+            let releases = import::parse_versions(versions, *lenient)
+                .map_err(|_| RepoError::InvalidVersion)?;
+            new_crate(metadata, &releases)
+        }
+        ApiRequest::AddRelease(name, version) | ApiRequest::AddReleaseTo { name, version, .. } => {
+            repository.check_role(user, name, Role::Publisher)?;
+            repository
+                .check_release_quota(ctx.quotas, name, 1)
+                .map_err(ApiError::QuotaExceeded)?;
+            match repository.find_exact(name) {
+                Some(crt) => ctx.check_policies(crt.metadata(), *version, false),
+                // answered with `RepoError::NotFound` later
+                None => Ok(()),
+            }
+        }
+        ApiRequest::Yank(name, _) => Ok(repository.check_role(user, name, Role::Publisher)?),
+        // like the metadata, what is said about a release is up to the crate's owners
+        ApiRequest::Deprecate { name, .. }
+        | ApiRequest::SetReleaseNotes { name, .. }
+        | ApiRequest::SetReadme { name, .. }
+        | ApiRequest::SetPlatform { name, .. }
+        | ApiRequest::SetFeatures { name, .. }
+        | ApiRequest::SetDependencies { name, .. } => {
+            Ok(repository.check_owner(ctx.user()?, name)?)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn handle_request(
+    request: ApiRequest,
+    repository: &mut Repository,
+    ctx: &RequestContext,
+) -> String {
+    if let Err(e) = permit(&request, repository, ctx) {
+        return Err::<(), _>(e).to_json();
+    }
+    match request {
+        ApiRequest::FindExact(crate_name) => {
+            let crate_name = lookup_name(crate_name, repository, ctx.ignore_case);
+            repository.record_download(&crate_name);
+            let res: FindExactResult =
+                Ok(repository.find_exact(&crate_name).map(|crt| crt.to_owned()));
+            res.to_json()
+        }
+        ApiRequest::AddCrate(metadata, version) => {
+            repository.add_crate(metadata, version).to_json()
+        }
+        ApiRequest::AddRelease(name, version) => repository.add_release(name, version).to_json(),
+        ApiRequest::Yank(name, version) => repository.yank(name, version).to_json(),
+        ApiRequest::AddReleaseTo {
+            name,
+            version,
+            channel,
+        } => repository.add_release_to(name, version, channel).to_json(),
+        ApiRequest::LatestVersion { name, channel } => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: LatestVersionResult = Ok(repository.latest_version(name, channel));
+            res.to_json()
+        }
+        ApiRequest::LatestCompatible { name, base } => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: LatestCompatibleResult = Ok(repository.latest_compatible(name, base));
+            res.to_json()
+        }
+        ApiRequest::Resolve {
+            name,
+            req,
+            include_prerelease,
+            include_yanked,
+        } => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: ResolveResult = repository
+                .resolve(name, &req, include_prerelease, include_yanked)
+                .map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::Advisories { name, version } => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: AdvisoriesResult =
+                repository.advisories(name, version).map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::PublishAtomic { metadata, releases } => {
+            repository.publish_atomic(metadata, releases).to_json()
+        }
+        ApiRequest::PublishVersions {
+            metadata,
+            versions,
+            lenient,
+        } => import::parse_versions(versions, lenient)
+            .map_err(|_| RepoError::InvalidVersion)
+            .and_then(|releases| repository.publish_atomic(metadata, releases))
+            .to_json(),
+        ApiRequest::Deprecate {
+            name,
+            version,
+            deprecation,
+        } => repository.deprecate(name, version, deprecation).to_json(),
+        ApiRequest::SetReleaseNotes {
+            name,
+            version,
+            notes,
+        } => repository.set_release_notes(name, version, notes).to_json(),
+        ApiRequest::Changelog(name, from, to) => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: ChangelogResult = repository.changelog(name, from, to).map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::SetReadme {
+            name,
+            version,
+            readme,
+        } => {
+            let res: ApiResult<()> = repository
+                .set_readme(name, version, readme.as_deref())
+                .map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::SetPlatform {
+            name,
+            version,
+            platform,
+        } => repository.set_platform(name, version, platform).to_json(),
+        ApiRequest::SetFeatures {
+            name,
+            version,
+            features,
+        } => repository.set_features(name, version, features).to_json(),
+        ApiRequest::SetDependencies {
+            name,
+            version,
+            dependencies,
+        } => {
+            let res: SetDependenciesResult = repository
+                .set_dependencies(name, version, dependencies)
+                .map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::GetFeatures(name, version) => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: GetFeaturesResult = repository.features(name, version).map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::GetReadme(name, version) => {
+            let name = lookup_name(name, repository, ctx.ignore_case);
+            let res: GetReadmeResult = repository.readme(name, version).map_err(ApiError::from);
+            res.to_json()
+        }
+        ApiRequest::UpdateMetadata(metadata) => {
+            let res: ApiResult<()> = ctx.user().and_then(|user| {
+                repository
+                    .update_metadata(user, metadata)
+                    .map_err(ApiError::from)
+            });
+            res.to_json()
+        }
+        ApiRequest::Tokens => {
+            let res: TokensResult = ctx
+                .user()
+                .map(|user| repository.tokens((!ctx.admin_scope).then_some(user)));
+            res.to_json()
+        }
+        ApiRequest::RevokeToken(id) => {
+            let res: ApiResult<()> = ctx.user().and_then(|user| {
+                repository
+                    .revoke_token(&id, (!ctx.admin_scope).then_some(user))
+                    .map_err(ApiError::from)
+            });
+            res.to_json()
+        }
+        ApiRequest::Org(request) => {
+            let res: OrgResult = ctx
+                .user()
+                .and_then(|user| repository.handle_org(user, request).map_err(ApiError::from));
+            res.to_json()
+        }
+        ApiRequest::Authenticated { token, request } => {
+            match authenticate(&token, &request, repository) {
+                Ok(token) => {
+                    let ctx = RequestContext {
+                        admin_scope: token.is_admin(),
+                        user: Some(token.user),
+                        ..*ctx
+                    };
+                    handle_request(*request, repository, &ctx)
+                }
+                Err(e) => Err::<(), _>(e).to_json(),
+            }
+        }
+        // non-blocking variant, waiting for changes happens in `subscribe`
+        ApiRequest::Subscribe { since } => {
+            let res: SubscribeResult = Ok(repository.changes().since(since).to_vec());
+            res.to_json()
+        }
+        ApiRequest::FeedStatus => {
+            let res: FeedStatusResult = Ok(repository.feed_status());
+            res.to_json()
+        }
+        ApiRequest::Ping => {
+            let res: PingResult = Ok(ServerInfo::current());
+            res.to_json()
+        }
+        ApiRequest::Snapshot => {
+            let res: SnapshotResult = Ok(repository.snapshot());
+            res.to_json()
+        }
+        #[cfg(feature = "graphql")]
+        ApiRequest::GraphQL { query, variables } => {
+            let res: crate::api::GraphQLResult = Ok(repository.graphql(&query, variables));
+            res.to_json()
+        }
+        ApiRequest::AuditLog(name, since) => {
+            let res: AuditLogResult = Ok(repository.audit_log().since(&name, since).to_vec());
+            res.to_json()
+        }
+        // authorized in `handle`
+        ApiRequest::Admin { request, .. } => repository.handle_admin(request).to_json(),
+        ApiRequest::Batch {
+            requests,
+            transactional,
+        } => batch(requests, transactional, repository, ctx).to_json(),
+        // keys are only honoured at the top level, see `handle`
+        ApiRequest::Idempotent { request, .. } | ApiRequest::Traced { request, .. } => {
+            handle_request(*request, repository, ctx)
+        }
+        // chosen before locking a repository, it's too late to switch
+        ApiRequest::Registry { name, .. } => {
+            Err::<(), _>(ApiError::UnknownRegistry(name)).to_json()
+        }
+        ApiRequest::DryRun { request } => dry_run(*request, repository, ctx).to_json(),
+        ApiRequest::CheckPublish(metadata, version) => {
+            check_publish(metadata, version, repository, ctx).to_json()
+        }
+        ApiRequest::IfRevision { revision, request } => {
+            match check_revision(revision, &request, repository) {
+                Ok(()) => handle_request(*request, repository, ctx),
+                Err(e) => Err::<(), _>(e).to_json(),
+            }
+        }
+        // search results are serialized as summaries borrowing from the repository
+        ApiRequest::FindMatching(pattern) => summaries(repository.find_matching(pattern)),
+        ApiRequest::FindRegex(pattern) => match search::compile_pattern(&pattern) {
+            Ok(pattern) => summaries(repository.find_regex(&pattern)),
+            Err(e) => Err::<(), _>(ApiError::InvalidPattern(e.to_string())).to_json(),
+        },
+        ApiRequest::ListNamespace(namespace) => summaries(repository.namespace_crates(namespace)),
+        ApiRequest::Search(query) => summaries(repository.search(query)),
+        ApiRequest::FindAllContainingPage {
+            query,
+            options,
+            cursor,
+        } => {
+            let page = repository.find_containing_page(query, options, cursor);
+            let res: ApiResult<_> = Ok(page.map(CrateSummary::from));
+            res.to_json()
+        }
+        ApiRequest::FindAllContaining(name, options) => {
+            summaries(repository.find_containing(name, options))
+        }
+    }
+}
+
+fn summaries(crates: Vec<&Crate>) -> String {
+    let res: ApiResult<Vec<_>> = Ok(crates.into_iter().map(CrateSummary::from).collect());
+    res.to_json()
+}
+
+fn check_revision(
+    expected: u64,
+    request: &ApiRequest,
+    repository: &Repository,
+) -> Result<(), ApiError> {
+    let name = request.crate_name().ok_or(ApiError::NoCrate)?;
+    let actual = repository.revision(name);
+    if actual != expected {
+        return Err(ApiError::Conflict { expected, actual });
+    }
+    Ok(())
+}
+
+/// handles `request` and rolls back whatever it changed
+fn dry_run(request: ApiRequest, repository: &mut Repository, ctx: &RequestContext) -> DryRunResult {
+    let checkpoint = repository.checkpoint();
+    let last_seq = repository.changes().last_seq();
+    let response = handle_request(request, repository, ctx);
+    let changes = repository.changes().since(last_seq).to_vec();
+    repository.rollback(checkpoint);
+    let result: ApiResult<serde_json::Value> =
+        serde_json::from_str(&response).unwrap_or(Err(ApiError::Internal));
+    Ok(DryRunOutcome {
+        result: result?,
+        changes,
+    })
+}
+
+/// the warnings of [`Repository::check_publish`], after the ones of the publisher's roles and
+/// quotas
+fn check_publish(
+    metadata: Metadata,
+    version: SemVer,
+    repository: &mut Repository,
+    ctx: &RequestContext,
+) -> CheckPublishResult {
+    let publish = match repository.find_exact(metadata.name()) {
+        Some(_) => ApiRequest::AddRelease(metadata.name().to_string(), version),
+        None => ApiRequest::AddCrate(metadata.clone(), version),
+    };
+    let mut warnings = vec![];
+    if let Err(e) = permit(&publish, repository, ctx) {
+        warnings.push(PublishWarning::Rejected(e));
+    }
+    warnings.extend(repository.check_publish(&metadata, version));
+    Ok(warnings)
+}
+
+fn batch(
+    requests: Vec<ApiRequest>,
+    transactional: bool,
+    repository: &mut Repository,
+    ctx: &RequestContext,
+) -> BatchResult {
+    let checkpoint = transactional.then(|| repository.checkpoint());
+    let mut results = vec![];
+    for (index, request) in requests.into_iter().enumerate() {
+        let response = handle_request(request, repository, ctx);
+        let res: ApiResult<serde_json::Value> =
+            serde_json::from_str(&response).unwrap_or(Err(ApiError::Internal));
+        if let Err(e) = &res {
+            if let Some(checkpoint) = checkpoint {
+                repository.rollback(checkpoint);
+                return Err(ApiError::BatchAborted {
+                    index,
+                    error: Box::new(e.clone()),
+                });
+            }
+        }
+        results.push(res);
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::api::AddResult;
+    use crate::testing::TestServer;
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn ensure_safe_json() {
+        use std::collections::HashMap;
+        // serde_json only supports string-like map keys, so this fails to serialize
+        type DangerMap = HashMap<(u32, u32), u32>;
+        let mut danger: DangerMap = HashMap::default();
+        danger.insert((1, 2), 3);
+        let fails: Result<_, ApiError> = Ok(danger);
+        let cmp: Result<DangerMap, ApiError> = Err(ApiError::Internal);
+        assert_eq!(fails.to_json(), cmp.to_json())
+    }
+
+    #[test]
+    fn permissions() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        repo.register_namespace("myorg", vec!["owner".to_string()])?;
+        let quotas = Quotas::default();
+        let ctx = |user: Option<&str>| RequestContext {
+            ignore_case: false,
+            user: user.map(str::to_string),
+            admin_scope: false,
+            quotas: &quotas,
+            policies: &[],
+            builtin_policies: &[],
+        };
+        let add = |author: &str| {
+            ApiRequest::AddCrate(
+                Metadata::new("@myorg/http", author, CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )
+        };
+
+        // the author field is whatever the client claims
+        for user in [None, Some("mallory")] {
+            assert!(matches!(
+                permit(&add("owner"), &repo, &ctx(user)),
+                Err(ApiError::Repo(RepoError::NotOwner))
+            ));
+        }
+        permit(&add("someone"), &repo, &ctx(Some("owner")))?;
+
+        repo.add_crate(
+            Metadata::new("http", "owner", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        let notes = ApiRequest::SetReleaseNotes {
+            name: "http".into(),
+            version: SemVer::new(1, 0, 0),
+            notes: Some("defaced".into()),
+        };
+        let deprecate = ApiRequest::Deprecate {
+            name: "http".into(),
+            version: None,
+            deprecation: None,
+        };
+        for request in [notes, deprecate] {
+            assert!(matches!(
+                permit(&request, &repo, &ctx(None)),
+                Err(ApiError::Unauthorized)
+            ));
+            assert!(matches!(
+                permit(&request, &repo, &ctx(Some("mallory"))),
+                Err(ApiError::Repo(RepoError::Forbidden))
+            ));
+            permit(&request, &repo, &ctx(Some("owner")))?;
+        }
+
+        let quotas = Quotas {
+            max_crates_per_author: Some(1),
+            ..Quotas::default()
+        };
+        let ctx = |user: Option<&str>| RequestContext {
+            quotas: &quotas,
+            ..ctx(user)
+        };
+        repo.add_crate(
+            Metadata::new("@myorg/other", "owner", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert!(matches!(
+            permit(&add("someone"), &repo, &ctx(Some("owner"))),
+            Err(ApiError::QuotaExceeded(Quota::CratesPerAuthor))
+        ));
+        let anonymous = ApiRequest::AddCrate(
+            Metadata::new("http", "owner", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        );
+        assert!(matches!(
+            permit(&anonymous, &repo, &ctx(None)),
+            Err(ApiError::Unauthorized)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_store() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let store = dir.path().join("store.json");
+        std::fs::write(&store, "{\"crates\": ")?;
+        let mut config = ServerConfig::new(&store).with_listen(vec!["127.0.0.1:0".parse()?]);
+        assert!(matches!(
+            Server::bind(config.clone()),
+            Err(ServerError::Store(StoreError::Invalid(_)))
+        ));
+        assert_eq!("{\"crates\": ", std::fs::read_to_string(&store)?);
+
+        config.force_new = true;
+        let server = Server::bind(config)?;
+        assert_eq!(0, server.shared.repository.lock().unwrap().iter().count());
+        let aside = dir.path().join("store.json.corrupt");
+        assert_eq!("{\"crates\": ", std::fs::read_to_string(aside)?);
+        Ok(())
+    }
+
+    #[test]
+    fn serve_and_shutdown() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let config = ServerConfig::new(store.path()).with_listen(vec!["127.0.0.1:0".parse()?]);
+        let server = Server::bind(config)?;
+        let addr = server.local_addrs()[0];
+        let shutdown = server.shutdown_handle();
+        let running = thread::spawn(move || server.serve());
+
+        let request = ApiRequest::AddCrate(
+            Metadata::new("linux.exe", "Linus Torvalds", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        );
+        let mut connection = TcpStream::connect(addr)?;
+        writeln!(connection, "{}", serde_json::to_string(&request)?)?;
+        connection.shutdown(std::net::Shutdown::Write)?;
+        let mut buffer = String::new();
+        connection.read_to_string(&mut buffer)?;
+        let res: AddResult = serde_json::from_str(&buffer)?;
+        assert!(res.is_ok());
+
+        let info = crate::client::Client::new(addr.to_string()).ping()?;
+        assert_eq!(ServerInfo::current(), info);
+
+        shutdown.shutdown();
+        running.join().unwrap()?;
+
+        // the repository got saved on shutdown
+        assert!(Repository::open(store.path(), None)?
+            .find_exact("linux.exe")
+            .is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn dry_run() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+
+        let server = TestServer::start_with(|mut config| {
+            config.read_only = true;
+            config.admin_token = Some("s3cret".into());
+            config
+        })?;
+        let client = server.client();
+
+        let add = ApiRequest::AddCrate(
+            Metadata::new("dry", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        );
+        let outcome = client.dry_run(ApiRequest::Batch {
+            requests: vec![
+                add,
+                ApiRequest::AddRelease("dry".into(), SemVer::new(1, 1, 0)),
+            ],
+            transactional: true,
+        })?;
+        assert_eq!(2, outcome.changes.len());
+        assert!(client.find_exact("dry")?.is_none());
+        assert!(matches!(
+            client.dry_run(ApiRequest::Yank("dry".into(), SemVer::new(1, 0, 0))),
+            Err(ClientError::Api(ApiError::Repo(RepoError::NotFound)))
+        ));
+        assert!(matches!(
+            client.dry_run(ApiRequest::Admin {
+                token: "guess".into(),
+                request: AdminRequest::IssueToken {
+                    user: "mallory".into(),
+                    scopes: vec![],
+                    expires: None,
+                },
+            }),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        let compact = ApiRequest::Admin {
+            token: "s3cret".into(),
+            request: AdminRequest::Compact,
+        };
+        assert!(matches!(
+            client.dry_run(ApiRequest::Batch {
+                requests: vec![compact],
+                transactional: false,
+            }),
+            Err(ClientError::Api(ApiError::NotDryRunnable))
+        ));
+        assert!(!server.store_path().exists());
+        Ok(())
+    }
+
+    #[test]
+    fn check_publish() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::preflight::PublishWarning;
+
+        let server = TestServer::start_with(|mut config| {
+            config.read_only = true;
+            config
+        })?;
+        let client = server.client();
+
+        let metadata = Metadata::new("checked", "someone", CrateKind::Library)
+            .with_description("checked before publishing")
+            .with_keywords(["ci"]);
+        assert!(client
+            .check_publish(metadata.clone(), SemVer::new(1, 0, 0))?
+            .is_empty());
+        let warnings = client.check_publish(
+            Metadata::new("checked", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert!(matches!(
+            warnings[..],
+            [
+                PublishWarning::DescriptionMissing,
+                PublishWarning::KeywordsMissing
+            ]
+        ));
+        assert!(client.find_exact("checked")?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn survives_panics() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+        use crate::policy::{Publish, PublishPolicy};
+
+        /// panics while the repository is locked
+        #[derive(Debug)]
+        struct Buggy;
+
+        impl PublishPolicy for Buggy {
+            fn name(&self) -> &str {
+                "buggy"
+            }
+
+            fn check(&self, publish: &Publish) -> Result<(), String> {
+                match publish.metadata.name() {
+                    "boom" => panic!("index out of bounds"),
+                    _ => Ok(()),
+                }
+            }
+        }
+
+        let server = TestServer::start_with(|config| config.with_policy(Buggy))?;
+        let client = server.client();
+
+        let add = |name: &str| {
+            client.add_crate(
+                Metadata::new(name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )
+        };
+        assert!(matches!(
+            add("boom"),
+            Err(ClientError::Api(ApiError::Internal))
+        ));
+        add("fine")?;
+        assert!(client.find_exact("fine")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn saves_on_crash() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+        client.add_crate(
+            Metadata::new("unsaved", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        // nothing else saves before shutdown
+        assert!(thread::spawn(|| panic!("unexpected")).join().is_err());
+        assert!(Repository::open(server.store_path(), None)?
+            .find_exact("unsaved")
+            .is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn publish_policies() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+        use crate::policy::{Publish, PublishPolicy};
+
+        /// only releases for new crates
+        #[derive(Debug)]
+        struct NoReleases;
+
+        impl PublishPolicy for NoReleases {
+            fn name(&self) -> &str {
+                "no_releases"
+            }
+
+            fn check(&self, publish: &Publish) -> Result<(), String> {
+                match publish.new_crate {
+                    true => Ok(()),
+                    false => Err("releases are frozen".into()),
+                }
+            }
+        }
+
+        let settings = NamedTempFile::new()?;
+        std::fs::write(settings.path(), "[[policies]]\npolicy = \"require_stable\"")?;
+        let server = TestServer::start_with(|mut config| {
+            config.settings_file = Some(settings.path().to_path_buf());
+            config.with_policy(NoReleases)
+        })?;
+        let client = server.client();
+
+        let metadata = Metadata::new("policed", "someone", CrateKind::Library);
+        let rejected_by = |res: Result<(), ClientError>| match res {
+            Err(ClientError::Api(ApiError::PolicyViolation(violation))) => violation.policy,
+            res => panic!("not rejected by a policy: {:?}", res),
+        };
+        assert_eq!(
+            "require_stable",
+            rejected_by(client.add_crate(metadata.clone(), SemVer::new(0, 1, 0)))
+        );
+        client.add_crate(metadata, SemVer::new(1, 0, 0))?;
+        assert_eq!(
+            "no_releases",
+            rejected_by(client.add_release("policed", SemVer::new(1, 1, 0)))
+        );
+        assert_eq!(
+            Some(SemVer::new(1, 0, 0)),
+            client.find_exact("policed")?.unwrap().latest()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn connection_limit() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::{ClientError, RetryPolicy};
+
+        let server = TestServer::start_with(|mut config| {
+            config.max_connections = Some(1);
+            config
+        })?;
+
+        // takes up the only connection without sending anything
+        let idle = TcpStream::connect(server.addr())?;
+        let client = server.client();
+        assert!(matches!(
+            client.ping(),
+            Err(ClientError::Api(ApiError::Overloaded))
+        ));
+        drop(idle);
+        let patient = client.with_retry(RetryPolicy {
+            max_attempts: 20,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(50),
+        });
+        patient.ping()?;
+        Ok(())
+    }
+
+    #[test]
+    fn pooled_client() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::PoolConfig;
+
+        let server = TestServer::start()?;
+        let client = server.client().with_pool(PoolConfig::default());
+
+        for major in 1..=20 {
+            client.add_crate(
+                Metadata::new(format!("pooled-{}", major), "someone", CrateKind::Library),
+                SemVer::new(major, 0, 0),
+            )?;
+        }
+        assert!(client.find_exact("pooled-20")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn watch() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::events::Event;
+
+        let server = TestServer::start_with(|mut config| {
+            config.subscribe_timeout = Duration::from_millis(100);
+            config
+        })?;
+        let client = server.client();
+
+        let metadata = Metadata::new("watched", "someone", CrateKind::Library);
+        client.add_crate(metadata, SemVer::new(1, 0, 0))?;
+        let since = client.feed_status()?.last_seq;
+        let publisher = {
+            let client = client.clone();
+            thread::spawn(move || {
+                // outlasts a subscription or two
+                thread::sleep(Duration::from_millis(250));
+                client.add_release("watched", SemVer::new(1, 1, 0))
+            })
+        };
+        let change = client.watch(since).next().unwrap()?;
+        assert!(matches!(
+            change.event,
+            Event::ReleaseAdded { version, .. } if version == SemVer::new(1, 1, 0)
+        ));
+        publisher.join().unwrap()?;
+        Ok(())
+    }
+
+    #[test]
+    fn autosave_after_mutations() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.autosave = Some(Autosave {
+                interval: Duration::from_secs(3600),
+                after_mutations: 1,
+            });
+            config
+        })?;
+        server.client().add_crate(
+            Metadata::new("autosaved", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+
+        // saved while still serving, long before the interval is up
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Repository::open(server.store_path(), None)?
+            .find_exact("autosaved")
+            .is_none()
+        {
+            assert!(Instant::now() < deadline, "not autosaved");
+            thread::sleep(Duration::from_millis(20));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn admin_tasks() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config.autosave = Some(Autosave {
+                interval: Duration::from_secs(3600),
+                after_mutations: u64::MAX,
+            });
+            config
+        })?;
+        let client = server.client();
+
+        let tasks = || match client.admin("s3cret", AdminRequest::Tasks) {
+            Ok(AdminResponse::Tasks(tasks)) => tasks,
+            res => panic!("unexpected response {:?}", res),
+        };
+        let listed = tasks();
+        assert_eq!(
+            vec!["autosave"],
+            listed.iter().map(|t| &t.name).collect::<Vec<_>>()
+        );
+        assert_eq!(0, listed[0].runs);
+
+        client.add_crate(
+            Metadata::new("saved-on-demand", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert_eq!(
+            AdminResponse::Done,
+            client.admin("s3cret", AdminRequest::RunTask("autosave".to_string()))?
+        );
+        assert!(matches!(
+            client.admin("s3cret", AdminRequest::RunTask("missing".to_string())),
+            Err(ClientError::Api(ApiError::Repo(RepoError::NotFound)))
+        ));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while tasks()[0].runs == 0 {
+            assert!(Instant::now() < deadline, "task didn't run");
+            thread::sleep(Duration::from_millis(20));
+        }
+        assert!(Repository::open(server.store_path(), None)?
+            .find_exact("saved-on-demand")
+            .is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn registries() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+
+        let dir = tempfile::tempdir()?;
+        let server = TestServer::start_with(|mut config| {
+            config.registries = Some(Registries {
+                dir: dir.path().to_path_buf(),
+                names: vec!["team-a".to_string()],
+            });
+            config
+        })?;
+        let client = server.client();
+
+        let team_a = client.clone().with_registry("team-a");
+        team_a.add_crate(
+            Metadata::new("internal", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert!(team_a.find_exact("internal")?.is_some());
+        assert!(client.find_exact("internal")?.is_none());
+        assert!(matches!(
+            client.clone().with_registry("team-b").find_exact("internal"),
+            Err(ClientError::Api(ApiError::UnknownRegistry(name))) if name == "team-b"
+        ));
+
+        // saved on shutdown
+        drop(server);
+        let manager = RepositoryManager::open(dir.path(), None)?;
+        let repository = manager.get("team-a").unwrap().lock().unwrap();
+        assert!(repository.find_exact("internal").is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn reload_settings() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::client::ClientError;
+
+        let settings = NamedTempFile::new()?;
+        std::fs::write(settings.path(), "read_only = false")?;
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config.settings_file = Some(settings.path().to_path_buf());
+            config
+        })?;
+        let client = server.client();
+        let reload = server.reload_handle();
+
+        let add = |name: &str| {
+            client.add_crate(
+                Metadata::new(name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )
+        };
+        add("before")?;
+        std::fs::write(settings.path(), "read_only = true")?;
+        assert_eq!(
+            AdminResponse::Done,
+            client.admin("s3cret", AdminRequest::Reload)?
+        );
+        assert!(matches!(
+            add("after"),
+            Err(ClientError::Api(ApiError::ReadOnly))
+        ));
+
+        // invalid settings are rejected, the server stays read-only
+        std::fs::write(settings.path(), "read_only = false\nlog_level = \"loud\"")?;
+        assert!(matches!(
+            client.admin("s3cret", AdminRequest::Reload),
+            Err(ClientError::Api(ApiError::InvalidSettings(_)))
+        ));
+        assert!(reload.reload().is_err());
+        assert!(matches!(
+            add("after"),
+            Err(ClientError::Api(ApiError::ReadOnly))
+        ));
+
+        std::fs::write(settings.path(), "read_only = false")?;
+        reload.reload()?;
+        add("after")?;
+        Ok(())
+    }
+
+    #[test]
+    fn access_log() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::access_log::AccessLogFormat;
+
+        let log = NamedTempFile::new()?;
+        let server = TestServer::start_with(|mut config| {
+            config.access_log = Some(AccessLogConfig {
+                path: log.path().to_path_buf(),
+                format: AccessLogFormat::Json,
+            });
+            config
+        })?;
+        let client = server.client();
+
+        client.add_crate(
+            Metadata::new("logged", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert!(client.yank("missing", SemVer::new(1, 0, 0)).is_err());
+        // flushes the log
+        drop(server);
+
+        let entries = std::fs::read_to_string(log.path())?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?;
+        assert_eq!(2, entries.len());
+        assert_eq!(
+            ("AddCrate", "logged", 200),
+            (
+                entries[0]["request"].as_str().unwrap(),
+                entries[0]["crate"].as_str().unwrap(),
+                entries[0]["status"].as_u64().unwrap()
+            )
+        );
+        assert_eq!(404, entries[1]["status"]);
+        Ok(())
+    }
+}
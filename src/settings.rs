@@ -0,0 +1,173 @@
+//! Server settings that can change without a restart, read from a TOML file like
+//!
+//! ```toml
+//! log_level = "debug"
+//! read_only = false
+//!
+//! [quotas]
+//! max_releases_per_day = 20
+//!
+//! [[webhooks]]
+//! url = "http://ci.local/hook"
+//! secret = "s3cret"
+//!
+//! [[policies]]
+//! policy = "require_stable"
+//! ```
+//!
+//! Missing keys take their defaults. The server reads the file named by
+//! [`crate::server::ServerConfig::settings_file`] on startup and again on
+//! `AdminRequest::Reload` or SIGHUP, see [`crate::server::ReloadHandle`]. Invalid files are
+//! rejected as a whole, the active settings stay in place.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use log::LevelFilter;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::policy::BuiltinPolicy;
+use crate::quota::Quotas;
+use crate::webhooks::{Webhook, WebhookError};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// `off`, `error`, `warn`, `info`, `debug` or `trace`. `None` keeps the level of `RUST_LOG`.
+    pub log_level: Option<String>,
+    /// rejects mutating requests, see [`crate::server::ServerConfig::read_only`]
+    pub read_only: bool,
+    pub quotas: Quotas,
+    /// replaces the webhooks of the default registry, see [`crate::webhooks`]
+    pub webhooks: Vec<Webhook>,
+    /// checked after the policies of the server config, see [`crate::policy`]
+    pub policies: Vec<BuiltinPolicy>,
+}
+
+#[derive(Error, Debug)]
+pub enum SettingsError {
+    #[error("could not read settings: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid settings: {0}")]
+    Toml(#[from] toml::de::Error),
+    #[error("invalid log level '{0}'")]
+    LogLevel(String),
+    #[error("invalid webhook: {0}")]
+    Webhook(#[from] WebhookError),
+    #[error("no settings file configured")]
+    NoFile,
+}
+
+impl Settings {
+    /// reads and validates the settings file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SettingsError> {
+        let settings: Self = toml::from_str(&fs::read_to_string(path)?)?;
+        settings.log_level()?;
+        for hook in &settings.webhooks {
+            hook.validate()?;
+        }
+        Ok(settings)
+    }
+
+    pub fn log_level(&self) -> Result<Option<LevelFilter>, SettingsError> {
+        self.log_level
+            .as_deref()
+            .map(|level| {
+                level
+                    .parse()
+                    .map_err(|_| SettingsError::LogLevel(level.to_string()))
+            })
+            .transpose()
+    }
+}
+
+/// The active settings, swapped as a whole so readers never see half of a reload
+#[derive(Debug, Default)]
+pub(crate) struct LiveSettings(RwLock<Arc<Settings>>);
+
+impl LiveSettings {
+    pub(crate) fn new(settings: Settings) -> Self {
+        Self(RwLock::new(Arc::new(settings)))
+    }
+
+    pub(crate) fn get(&self) -> Arc<Settings> {
+        self.0.read().unwrap().clone()
+    }
+
+    pub(crate) fn replace(&self, settings: Settings) {
+        *self.0.write().unwrap() = Arc::new(settings);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use tempfile::NamedTempFile;
+
+    use super::*;
+
+    fn load(toml: &str) -> Result<Settings, SettingsError> {
+        let mut file = NamedTempFile::new()?;
+        file.write_all(toml.as_bytes())?;
+        Settings::load(file.path())
+    }
+
+    #[test]
+    fn parse_and_validate() {
+        let settings = load(
+            r#"
+            log_level = "debug"
+            read_only = true
+            [quotas]
+            max_releases_per_day = 20
+            [[webhooks]]
+            url = "http://ci.local/hook"
+            secret = "s3cret"
+            [[policies]]
+            policy = "author_email_domain"
+            domain = "example.com"
+            [[policies]]
+            policy = "require_stable"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(Some(LevelFilter::Debug), settings.log_level().unwrap());
+        assert!(settings.read_only);
+        assert_eq!(Some(20), settings.quotas.max_releases_per_day);
+        assert_eq!(
+            vec![Webhook::new("http://ci.local/hook", "s3cret")],
+            settings.webhooks
+        );
+        assert_eq!(
+            vec![
+                BuiltinPolicy::AuthorEmailDomain {
+                    domain: "example.com".into()
+                },
+                BuiltinPolicy::RequireStable
+            ],
+            settings.policies
+        );
+        assert_eq!(Settings::default(), load("").unwrap());
+
+        assert!(matches!(
+            load(r#"log_level = "loud""#),
+            Err(SettingsError::LogLevel(_))
+        ));
+        assert!(matches!(
+            load("[[webhooks]]\nurl = \"ftp://ci.local\"\nsecret = \"\""),
+            Err(SettingsError::Webhook(_))
+        ));
+        assert!(matches!(
+            load("[[policies]]\npolicy = \"no_fun\""),
+            Err(SettingsError::Toml(_))
+        ));
+        assert!(matches!(
+            load("read_ony = true"),
+            Err(SettingsError::Toml(_))
+        ));
+    }
+}
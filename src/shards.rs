@@ -0,0 +1,391 @@
+//! Sharded stores, a directory instead of a single file, for repositories too big to rewrite
+//! after every publish.
+//!
+//! Crates are grouped into shards by the first characters of their lowercase name, much like
+//! cargo's index. Each shard is a file in `crates/`, everything else goes into `repo`. Saving only
+//! rewrites the shards with changed crates, plus `repo`. Files are compressed and encrypted like a
+//! single file store. Every file is replaced atomically, but a save as a whole isn't: after a crash
+//! shards may be newer than `repo`.
+//!
+//! [`Repository::open`] opens directories as sharded stores, [`Repository::set_sharding`] converts
+//! between the layouts with the next save.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::audit::AuditLog;
+use crate::auth::Tokens;
+use crate::encryption::StoreKey;
+use crate::events::ChangeLog;
+use crate::kinds::KindPolicy;
+use crate::names::{NameRules, Namespaces};
+use crate::orgs::Organization;
+use crate::{read_store_file, tmp_path, Crate, CrateKind, Repository, StoreError, VersionPolicy};
+
+/// file holding everything but the crates
+const META_FILE: &str = "repo";
+const SHARD_DIR: &str = "crates";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sharding {
+    /// characters of the name telling the shard, more make smaller shards
+    pub prefix_len: usize,
+}
+
+impl Sharding {
+    /// the shard of the crate called `name`, characters unfit for file names are replaced by `_`
+    pub fn shard(&self, name: &str) -> String {
+        name.chars()
+            .take(self.prefix_len)
+            .flat_map(char::to_lowercase)
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect()
+    }
+}
+
+impl Default for Sharding {
+    fn default() -> Self {
+        Self { prefix_len: 2 }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+#[error("invalid sharding '{0}', expected a prefix length of 1 to 8")]
+pub struct ParseShardingError(String);
+
+/// the prefix length
+impl FromStr for Sharding {
+    type Err = ParseShardingError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(prefix_len @ 1..=8) => Ok(Self { prefix_len }),
+            _ => Err(ParseShardingError(s.to_string())),
+        }
+    }
+}
+
+impl Display for Sharding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.prefix_len)
+    }
+}
+
+/// the contents of `repo`, a [`Repository`] without its crates
+#[derive(Serialize)]
+pub(crate) struct Meta<'a> {
+    store: &'a PathBuf,
+    changes: &'a ChangeLog,
+    audit: &'a AuditLog,
+    aliases: &'a BTreeMap<String, String>,
+    namespaces: &'a Namespaces,
+    name_rules: &'a NameRules,
+    version_policy: VersionPolicy,
+    kind_policies: &'a BTreeMap<CrateKind, KindPolicy>,
+    orgs: &'a BTreeMap<String, Organization>,
+    tokens: &'a Tokens,
+    sharding: Option<Sharding>,
+}
+
+impl<'a> Meta<'a> {
+    pub(crate) fn new(repo: &'a Repository) -> Self {
+        Self {
+            store: &repo.store,
+            changes: &repo.changes,
+            audit: &repo.audit,
+            aliases: &repo.aliases,
+            namespaces: &repo.namespaces,
+            name_rules: &repo.name_rules,
+            version_policy: repo.version_policy,
+            kind_policies: &repo.kind_policies,
+            orgs: &repo.orgs,
+            tokens: &repo.tokens,
+            sharding: repo.sharding,
+        }
+    }
+}
+
+impl Repository {
+    pub fn sharding(&self) -> Option<Sharding> {
+        self.sharding
+    }
+
+    /// Saves the store sharded from now on, or as a single file if `None`. The layout changes
+    /// with the next save.
+    pub fn set_sharding(&mut self, sharding: Option<Sharding>) {
+        if sharding != self.sharding {
+            self.sharding = sharding;
+            *self.saved_crates.get_mut().unwrap() = None;
+            self.mark_dirty();
+        }
+    }
+
+    pub(crate) fn open_sharded(dir: &Path, key: Option<StoreKey>) -> Result<Self, StoreError> {
+        let mut repo = match read_store_file(&dir.join(META_FILE), key.as_ref())? {
+            Some((contents, _)) => serde_json::from_slice(&contents)?,
+            None => Self::empty(dir),
+        };
+        repo.sharding = Some(repo.sharding.unwrap_or_default());
+        let mut rewrite = false;
+        let entries = match fs::read_dir(dir.join(SHARD_DIR)) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            entries => entries?.collect(),
+        };
+        for entry in entries {
+            let path = entry?.path();
+            // left behind by an interrupted save
+            if path.extension().is_some_and(|extension| extension == "tmp") {
+                continue;
+            }
+            let Some((contents, encrypted)) = read_store_file(&path, key.as_ref())? else {
+                continue;
+            };
+            let crates: BTreeMap<Arc<str>, Arc<Crate>> = serde_json::from_slice(&contents)?;
+            repo.crates.extend(crates);
+            rewrite |= key.is_some() && !encrypted;
+        }
+        repo.reindex_all();
+        if rewrite {
+            repo.mark_dirty();
+        } else {
+            *repo.saved_crates.get_mut().unwrap() = Some(repo.crates.clone());
+        }
+        repo.normalize_histories();
+        repo.key = key;
+        Ok(repo)
+    }
+
+    /// Writes the shards with crates changed since the last save and `repo`, or all shards of a
+    /// new layout. A single file store is replaced once the directory is complete.
+    pub(crate) fn save_sharded(&self, sharding: Sharding) -> io::Result<()> {
+        let converting = !self.store.is_dir();
+        let dir = match converting {
+            true => tmp_path(&self.store),
+            false => self.store.clone(),
+        };
+        if converting && dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        let shard_dir = dir.join(SHARD_DIR);
+        fs::create_dir_all(&shard_dir)?;
+
+        let mut saved = self.saved_crates.lock().unwrap();
+        let mut shards: BTreeMap<String, BTreeMap<&str, &Crate>> = BTreeMap::new();
+        for (name, crt) in &self.crates {
+            shards
+                .entry(sharding.shard(name))
+                .or_default()
+                .insert(name, crt);
+        }
+        let changed: HashSet<String> = match saved.as_ref().filter(|_| !converting) {
+            Some(saved) => changed_names(saved, &self.crates)
+                .map(|name| sharding.shard(name))
+                .collect(),
+            None => {
+                // drop the files of a previous layout
+                let names: BTreeSet<String> = shards.keys().cloned().collect();
+                for entry in fs::read_dir(&shard_dir)? {
+                    let path = entry?.path();
+                    let name = path.file_name().unwrap_or_default().to_string_lossy();
+                    if !names.contains(name.as_ref()) {
+                        fs::remove_file(&path)?;
+                    }
+                }
+                names.into_iter().collect()
+            }
+        };
+        for shard in changed {
+            let path = shard_dir.join(&shard);
+            match shards.get(&shard) {
+                Some(crates) => {
+                    let tmp = tmp_path(&path);
+                    self.write_store_file(&tmp, |writer| {
+                        Ok(serde_json::to_writer(writer, crates)?)
+                    })?;
+                    fs::rename(&tmp, &path)?;
+                }
+                None => match fs::remove_file(&path) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                },
+            }
+        }
+
+        let meta = Meta {
+            sharding: Some(sharding),
+            ..Meta::new(self)
+        };
+        let path = dir.join(META_FILE);
+        let tmp = tmp_path(&path);
+        self.write_store_file(&tmp, |writer| Ok(serde_json::to_writer(writer, &meta)?))?;
+        fs::rename(&tmp, &path)?;
+
+        if converting {
+            match fs::remove_file(&self.store) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            fs::rename(&dir, &self.store)?;
+        }
+        *saved = Some(self.crates.clone());
+        Ok(())
+    }
+}
+
+/// names of crates added, removed or changed, changed crates were copied on write
+pub(crate) fn changed_names<'a>(
+    saved: &'a BTreeMap<Arc<str>, Arc<Crate>>,
+    crates: &'a BTreeMap<Arc<str>, Arc<Crate>>,
+) -> impl Iterator<Item = &'a str> {
+    let removed = saved
+        .keys()
+        .filter(|name| !crates.contains_key(*name))
+        .map(AsRef::as_ref);
+    let changed = crates
+        .iter()
+        .filter(|(name, crt)| {
+            saved
+                .get(*name)
+                .is_none_or(|saved| !Arc::ptr_eq(saved, crt))
+        })
+        .map(|(name, _)| name.as_ref());
+    removed.chain(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, SemVer};
+
+    fn shard_files(store: &Path) -> BTreeMap<String, std::time::SystemTime> {
+        fs::read_dir(store.join(SHARD_DIR))
+            .unwrap()
+            .map(|entry| {
+                let entry = entry.unwrap();
+                let name = entry.file_name().to_string_lossy().into_owned();
+                (name, entry.metadata().unwrap().modified().unwrap())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shard_names() {
+        let sharding = Sharding::default();
+        assert_eq!("se", sharding.shard("Serde"));
+        assert_eq!("a", sharding.shard("a"));
+        assert_eq!("_a", sharding.shard("@acme/tools"));
+        assert_eq!(Ok(Sharding { prefix_len: 3 }), "3".parse());
+        assert!("0".parse::<Sharding>().is_err());
+    }
+
+    #[test]
+    fn meta_is_complete() {
+        let dir = tempdir().unwrap();
+        let repo = Repository::new(dir.path().join("store"));
+        let mut expected = serde_json::to_value(&repo).unwrap();
+        expected.as_object_mut().unwrap().remove("crates");
+        let meta = Meta {
+            store: &repo.store,
+            changes: &repo.changes,
+            audit: &repo.audit,
+            aliases: &repo.aliases,
+            namespaces: &repo.namespaces,
+            name_rules: &repo.name_rules,
+            version_policy: repo.version_policy,
+            kind_policies: &repo.kind_policies,
+            orgs: &repo.orgs,
+            tokens: &repo.tokens,
+            sharding: repo.sharding,
+        };
+        // every field of the repository is in `repo` or a shard
+        assert_eq!(expected, serde_json::to_value(&meta).unwrap());
+    }
+
+    #[test]
+    fn sharded_store() -> Result<(), RepoError> {
+        let dir = tempdir().unwrap();
+        let store = dir.path().join("store");
+        let mut repo = Repository::new(&store);
+        for name in ["serde", "serde_json", "tokio", "rand"] {
+            repo.add_crate(
+                Metadata::new(name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.save().unwrap();
+        let single_file = serde_json::to_value(&repo).unwrap();
+
+        // converted with the next save
+        repo.set_sharding(Some(Sharding::default()));
+        repo.save().unwrap();
+        assert!(store.is_dir());
+        let files = shard_files(&store);
+        assert_eq!(vec!["ra", "se", "to"], files.keys().collect::<Vec<_>>());
+
+        // only the shard of the new release is rewritten
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        repo.add_release("tokio", SemVer::new(1, 1, 0))?;
+        repo.delete_crate("rand")?;
+        repo.save().unwrap();
+        let after = shard_files(&store);
+        assert_eq!(vec!["se", "to"], after.keys().collect::<Vec<_>>());
+        assert_eq!(files["se"], after["se"]);
+        assert_ne!(files["to"], after["to"]);
+        drop(repo);
+
+        let mut repo = Repository::open(&store, None).unwrap();
+        assert_eq!(Some(Sharding::default()), repo.sharding());
+        assert_eq!(3, repo.iter().count());
+        assert_eq!(2, repo.find_exact("tokio").unwrap().releases().len());
+        assert!(!repo.is_dirty());
+
+        // and back to a single file, with everything there was
+        repo.add_crate(
+            Metadata::new("rand", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.set_sharding(None);
+        drop(repo);
+        assert!(store.is_file());
+        let repo = Repository::open(&store, None).unwrap();
+        let mut expected = single_file;
+        expected["crates"] = serde_json::to_value(&repo.crates).unwrap();
+        expected["changes"] = serde_json::to_value(&repo.changes).unwrap();
+        assert_eq!(expected, serde_json::to_value(&repo).unwrap());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_shards() -> Result<(), RepoError> {
+        let dir = tempdir().unwrap();
+        let store = dir.path().join("store");
+        let key = StoreKey::generate();
+        let mut repo = Repository::open(&store, Some(key.clone())).unwrap();
+        repo.set_sharding(Some(Sharding { prefix_len: 1 }));
+        repo.add_crate(
+            Metadata::new("secret", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        drop(repo);
+
+        let shard = fs::read(store.join(SHARD_DIR).join("s")).unwrap();
+        assert!(crate::encryption::is_encrypted(&shard));
+        assert!(matches!(
+            Repository::open(&store, None),
+            Err(StoreError::Encrypted)
+        ));
+        let repo = Repository::open(&store, Some(key)).unwrap();
+        assert!(repo.find_exact("secret").is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,218 @@
+//! Exporting the repository in the layout of a cargo sparse registry index, for tools that read
+//! versions, features and Rust versions from it.
+//!
+//! Every crate gets a file of one JSON line per release, in publication order, under the path
+//! cargo expects, e.g. `se/rd/serde` or `3/l/log`. The repository keeps no `.crate` files, so
+//! checksums are empty and there is no `config.json` with a download URL; cargo itself can't
+//! install from the export. Crates and dependencies whose names aren't valid cargo names, like
+//! `@acme/http`, are skipped.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use serde::Serialize;
+
+use crate::dependencies::{Dependency, DependencyKind};
+use crate::features::Features;
+use crate::{Crate, Repository};
+
+/// a dependency in an index file
+#[derive(Debug, Serialize)]
+struct Dep<'a> {
+    name: &'a str,
+    req: String,
+    /// features enabled on the dependency, unknown to the repository
+    features: [(); 0],
+    optional: bool,
+    default_features: bool,
+    target: Option<&'a str>,
+    kind: &'a str,
+}
+
+impl<'a> From<&'a Dependency> for Dep<'a> {
+    fn from(dependency: &'a Dependency) -> Self {
+        Self {
+            name: &dependency.name,
+            req: dependency.req.to_string(),
+            features: [],
+            optional: dependency.optional,
+            default_features: true,
+            target: None,
+            kind: match dependency.kind {
+                DependencyKind::Normal => "normal",
+                DependencyKind::Dev => "dev",
+                DependencyKind::Build => "build",
+            },
+        }
+    }
+}
+
+/// one line of an index file
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    name: &'a str,
+    vers: String,
+    deps: Vec<Dep<'a>>,
+    /// empty, see the [module docs](self)
+    cksum: &'a str,
+    features: Features,
+    /// features using `dep:` or `?/` syntax, which old cargo versions can't read
+    #[serde(skip_serializing_if = "Features::is_empty")]
+    features2: Features,
+    yanked: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rust_version: Option<String>,
+    /// 2 if `features2` is used
+    v: u8,
+}
+
+/// whether cargo accepts `name` as a crate name
+fn is_cargo_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// where cargo looks for the index file of `name`, relative to the index root. `name` must be
+/// a valid cargo name.
+fn index_path(name: &str) -> PathBuf {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => Path::new("1").join(&name),
+        2 => Path::new("2").join(&name),
+        3 => Path::new("3").join(&name[..1]).join(&name),
+        _ => Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+/// the index file of `crt`, one line per release
+fn index_file(crt: &Crate) -> String {
+    let name = crt.metadata().name();
+    let mut lines = String::new();
+    for &version in crt.releases() {
+        let (features2, features) = crt
+            .features(version)
+            .cloned()
+            .unwrap_or_default()
+            .into_iter()
+            .partition::<Features, _>(|(_, enables)| {
+                enables
+                    .iter()
+                    .any(|entry| entry.starts_with("dep:") || entry.contains("?/"))
+            });
+        let entry = Entry {
+            name,
+            vers: version.to_string(),
+            deps: crt
+                .dependencies(version)
+                .iter()
+                .filter(|dependency| is_cargo_name(&dependency.name))
+                .map(Dep::from)
+                .collect(),
+            cksum: "",
+            v: if features2.is_empty() { 1 } else { 2 },
+            features,
+            features2,
+            // blocked releases are yanked as far as cargo is concerned
+            yanked: crt.is_yanked(version) || crt.is_blocked(version),
+            rust_version: crt
+                .platform(version)
+                .and_then(|platform| platform.rust_version)
+                .map(|v| v.to_string()),
+        };
+        // serializing plain strings and maps can't fail
+        lines.push_str(&serde_json::to_string(&entry).unwrap_or_default());
+        lines.push('\n');
+    }
+    lines
+}
+
+impl Repository {
+    /// Writes the index into `out`, creating it if needed. Returns the number of index files.
+    /// Files of crates that were removed since an earlier export are left behind.
+    pub fn export_index(&self, out: impl AsRef<Path>) -> io::Result<usize> {
+        let out = out.as_ref();
+        let mut files = 0;
+        for crt in self.iter() {
+            let name = crt.metadata().name();
+            if !is_cargo_name(name) {
+                warn!("skipping '{}', cargo can't name it", name);
+                continue;
+            }
+            let path = out.join(index_path(name));
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            fs::write(path, index_file(crt))?;
+            files += 1;
+        }
+        Ok(files)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::{tempdir, NamedTempFile};
+
+    use super::*;
+    use crate::platform::{Platform, RustVersion};
+    use crate::{CrateKind, Metadata, SemVer};
+
+    #[test]
+    fn paths() {
+        assert_eq!(Path::new("1/a"), index_path("a"));
+        assert_eq!(Path::new("2/io"), index_path("io"));
+        assert_eq!(Path::new("3/l/log"), index_path("log"));
+        assert_eq!(Path::new("se/rd/serde_json"), index_path("Serde_JSON"));
+    }
+
+    #[test]
+    fn export() -> Result<(), Box<dyn std::error::Error>> {
+        let store = NamedTempFile::new()?;
+        let mut repo = Repository::new(&store);
+        repo.add_crate(
+            Metadata::new("hello_lib", "Busy Person", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.add_release("hello_lib", SemVer::new(1, 1, 0))?;
+        repo.yank("hello_lib", SemVer::new(1, 0, 0))?;
+        let features = Features::from([
+            ("default".to_string(), vec!["std".to_string()]),
+            ("std".to_string(), vec!["dep:libc".to_string()]),
+        ]);
+        repo.set_features("hello_lib", SemVer::new(1, 1, 0), features)?;
+        let platform = Platform {
+            rust_version: Some(RustVersion::new(1, 70, 0)),
+            targets: vec![],
+        };
+        repo.set_platform("hello_lib", SemVer::new(1, 1, 0), platform)?;
+        let dependencies = vec![
+            Dependency::new("libc", "0.2".parse()?).optional(true),
+            Dependency::new("@acme/http", "1".parse()?),
+        ];
+        repo.set_dependencies("hello_lib", SemVer::new(1, 1, 0), dependencies)?;
+        repo.register_namespace("acme", vec!["Busy Person".to_string()])?;
+        repo.add_crate(
+            Metadata::new("@acme/http", "Busy Person", CrateKind::Library),
+            SemVer::new(0, 1, 0),
+        )?;
+
+        let index = tempdir()?;
+        assert_eq!(1, repo.export_index(index.path())?);
+        let file = fs::read_to_string(index.path().join("he/ll/hello_lib"))?;
+        let lines: Vec<_> = file.lines().collect();
+        assert_eq!(
+            r#"{"name":"hello_lib","vers":"1.0.0","deps":[],"cksum":"","features":{},"yanked":true,"v":1}"#,
+            lines[0]
+        );
+        assert_eq!(
+            r#"{"name":"hello_lib","vers":"1.1.0","deps":[{"name":"libc","req":"^0.2","features":[],"optional":true,"default_features":true,"target":null,"kind":"normal"}],"cksum":"","features":{"default":["std"]},"features2":{"std":["dep:libc"]},"yanked":false,"rust_version":"1.70","v":2}"#,
+            lines[1]
+        );
+        Ok(())
+    }
+}
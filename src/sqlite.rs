@@ -0,0 +1,190 @@
+//! SQLite stores, a single file like the default store, but with a row per crate so saving only
+//! rewrites the crates that changed, like a sharded store. Unlike a sharded store, a save is one
+//! transaction, so a crash never leaves a mix of old and new rows.
+//!
+//! Rows are compressed and encrypted like the files of the other stores. [`Repository::open`]
+//! opens SQLite databases and missing stores ending in `.sqlite` as SQLite stores, `repo convert`
+//! turns other stores into one and back.
+
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::encryption::StoreKey;
+use crate::shards::{changed_names, Meta};
+use crate::{decode_store, Crate, Repository, StoreError};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS crates (name TEXT PRIMARY KEY, data BLOB NOT NULL);
+    CREATE TABLE IF NOT EXISTS repo (id INTEGER PRIMARY KEY CHECK (id = 0), data BLOB NOT NULL);
+";
+
+fn connect(path: &Path) -> rusqlite::Result<Connection> {
+    let db = Connection::open(path)?;
+    db.execute_batch(SCHEMA)?;
+    Ok(db)
+}
+
+/// database errors are io errors to the callers, like the ones of the other stores
+fn sql(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
+
+impl Repository {
+    pub(crate) fn open_sqlite(path: &Path, key: Option<StoreKey>) -> Result<Self, StoreError> {
+        let mut repo = match path.exists() {
+            true => Self::read_sqlite(path, key.as_ref())?,
+            false => Self::empty(path),
+        };
+        repo.store = path.into();
+        repo.sharding = None;
+        repo.sqlite = true;
+        repo.key = key;
+        Ok(repo)
+    }
+
+    fn read_sqlite(path: &Path, key: Option<&StoreKey>) -> Result<Self, StoreError> {
+        let db = connect(path).map_err(sql)?;
+        let meta: Option<Vec<u8>> = db
+            .query_row("SELECT data FROM repo", [], |row| row.get(0))
+            .optional()
+            .map_err(sql)?;
+        let mut repo: Self = match meta {
+            Some(meta) => serde_json::from_slice(&decode_store(meta, key)?.0)?,
+            None => Self::empty(path),
+        };
+        let mut rewrite = false;
+        let mut rows = db.prepare("SELECT name, data FROM crates").map_err(sql)?;
+        let mut rows = rows.query([]).map_err(sql)?;
+        while let Some(row) = rows.next().map_err(sql)? {
+            let name: String = row.get(0).map_err(sql)?;
+            let (contents, encrypted) = decode_store(row.get(1).map_err(sql)?, key)?;
+            let crt: Crate = serde_json::from_slice(&contents)?;
+            repo.crates.insert(name.into(), Arc::new(crt));
+            rewrite |= key.is_some() && !encrypted;
+        }
+        repo.reindex_all();
+        if rewrite {
+            repo.mark_dirty();
+        } else {
+            *repo.saved_crates.get_mut().unwrap() = Some(repo.crates.clone());
+        }
+        repo.normalize_histories();
+        Ok(repo)
+    }
+
+    /// Writes the crates changed since the last save and everything else, or all crates of a
+    /// store that wasn't an SQLite store before, in one transaction.
+    pub(crate) fn save_sqlite(&self) -> io::Result<()> {
+        let mut db = connect(&self.store).map_err(sql)?;
+        let tx = db.transaction().map_err(sql)?;
+        let mut saved = self.saved_crates.lock().unwrap();
+        let changed: Vec<&str> = match saved.as_ref() {
+            Some(saved) => changed_names(saved, &self.crates).collect(),
+            None => {
+                tx.execute("DELETE FROM crates", []).map_err(sql)?;
+                self.crates.keys().map(AsRef::as_ref).collect()
+            }
+        };
+        for name in changed {
+            match self.crates.get(name) {
+                Some(crt) => {
+                    let data = self
+                        .encode_store(|writer| Ok(serde_json::to_writer(writer, crt.as_ref())?))?;
+                    tx.execute(
+                        "INSERT OR REPLACE INTO crates (name, data) VALUES (?1, ?2)",
+                        params![name, data],
+                    )
+                }
+                None => tx.execute("DELETE FROM crates WHERE name = ?1", params![name]),
+            }
+            .map_err(sql)?;
+        }
+        let meta =
+            self.encode_store(|writer| Ok(serde_json::to_writer(writer, &Meta::new(self))?))?;
+        tx.execute(
+            "INSERT OR REPLACE INTO repo (id, data) VALUES (0, ?1)",
+            params![meta],
+        )
+        .and_then(|_| tx.commit())
+        .map_err(sql)?;
+        *saved = Some(self.crates.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, SemVer};
+
+    /// crates in the store at `path` by name, as saved, for tests that look behind the repository
+    fn rows(path: &Path) -> BTreeMap<String, Vec<u8>> {
+        let db = connect(path).unwrap();
+        let mut rows = db.prepare("SELECT name, data FROM crates").unwrap();
+        rows.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn sqlite_store() -> Result<(), RepoError> {
+        let dir = tempdir().unwrap();
+        let store = dir.path().join("store.sqlite");
+        let mut repo = Repository::open(&store, None).unwrap();
+        for name in ["hello", "world"] {
+            repo.add_crate(
+                Metadata::new(name, "someone", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+        repo.save().unwrap();
+        let before = rows(&store);
+        assert_eq!(2, before.len());
+
+        repo.add_release("hello", SemVer::new(1, 1, 0))?;
+        drop(repo);
+        let after = rows(&store);
+        assert_ne!(before["hello"], after["hello"]);
+        assert_eq!(before["world"], after["world"]);
+
+        let mut repo = Repository::open(&store, None).unwrap();
+        assert!(!repo.is_dirty());
+        assert_eq!(
+            Some(SemVer::new(1, 1, 0)),
+            repo.find_exact("hello").unwrap().latest()
+        );
+        repo.delete_crate("world")?;
+        drop(repo);
+        assert_eq!(vec!["hello"], rows(&store).into_keys().collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[test]
+    fn encrypted_sqlite_store() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempdir()?;
+        let store = dir.path().join("store.sqlite");
+        let key = StoreKey::generate();
+        let mut repo = Repository::open(&store, Some(key.clone()))?;
+        repo.add_crate(
+            Metadata::new("secret", "someone", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        drop(repo);
+
+        assert!(matches!(
+            Repository::open(&store, None),
+            Err(StoreError::Encrypted)
+        ));
+        let repo = Repository::open(&store, Some(key))?;
+        assert!(repo.find_exact("secret").is_some());
+        Ok(())
+    }
+}
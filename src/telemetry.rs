@@ -0,0 +1,431 @@
+//! Request spans exported to an OpenTelemetry collector, using OTLP over HTTP with JSON bodies.
+//!
+//! Nothing is recorded until [`install`] starts the exporter. Spans started while another one is
+//! active on the same thread become its children, so saving the store and updating indices show
+//! up under the request that caused them. Clients put requests into their own traces by wrapping
+//! them in [`crate::api::ApiRequest::Traced`] with a W3C `traceparent`.
+//!
+//! Finished spans are queued and sent in batches by a background thread, over `http://` or
+//! `https://`. Spans that can't be delivered are dropped, tracing never slows down or fails
+//! requests.
+
+use std::cell::Cell;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+use url::Url;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TelemetryConfig {
+    /// the collector's OTLP/HTTP receiver, e.g. `http://localhost:4318` or
+    /// `https://otel.example.com`
+    pub endpoint: String,
+    /// reported as `service.name`
+    pub service_name: String,
+    /// most spans sent in one export
+    pub batch_size: usize,
+    /// longest time a finished span waits for its export
+    pub flush_interval: Duration,
+    /// for connecting to the collector and for each read and write of an export
+    pub timeout: Duration,
+}
+
+impl TelemetryConfig {
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            service_name: "semver_server".to_string(),
+            batch_size: 512,
+            flush_interval: Duration::from_secs(5),
+            timeout: Duration::from_secs(10),
+        }
+    }
+
+    fn traces_url(&self) -> String {
+        format!("{}/v1/traces", self.endpoint.trim_end_matches('/'))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TelemetryError {
+    #[error("invalid OTLP endpoint: {0}")]
+    Endpoint(String),
+    #[error("telemetry is installed already")]
+    AlreadyInstalled,
+}
+
+/// Identifies a span across processes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpanContext {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+}
+
+impl SpanContext {
+    /// parses a W3C `traceparent` like `00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01`
+    pub fn from_traceparent(traceparent: &str) -> Option<Self> {
+        let mut parts = traceparent.split('-');
+        let (version, trace_id, span_id) = (parts.next()?, parts.next()?, parts.next()?);
+        let mut context = SpanContext {
+            trace_id: [0; 16],
+            span_id: [0; 8],
+        };
+        hex::decode_to_slice(trace_id, &mut context.trace_id).ok()?;
+        hex::decode_to_slice(span_id, &mut context.span_id).ok()?;
+        let valid = version == "00" && parts.next().is_some_and(|flags| flags.len() == 2);
+        (valid && context.trace_id != [0; 16] && context.span_id != [0; 8]).then_some(context)
+    }
+
+    pub fn traceparent(&self) -> String {
+        format!(
+            "00-{}-{}-01",
+            hex::encode(self.trace_id),
+            hex::encode(self.span_id)
+        )
+    }
+}
+
+/// OTLP `SpanKind`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SpanKind {
+    Internal = 1,
+    Server = 2,
+}
+
+#[derive(Debug)]
+struct FinishedSpan {
+    context: SpanContext,
+    parent: Option<[u8; 8]>,
+    name: &'static str,
+    kind: SpanKind,
+    start: SystemTime,
+    end: SystemTime,
+    attributes: Vec<(&'static str, String)>,
+    error: bool,
+}
+
+enum Message {
+    Span(FinishedSpan),
+    /// exports everything queued, then answers
+    Flush(Sender<()>),
+}
+
+struct Exporter {
+    sender: Mutex<Sender<Message>>,
+    timeout: Duration,
+}
+
+static EXPORTER: OnceLock<Exporter> = OnceLock::new();
+
+thread_local! {
+    /// the innermost active span of this thread
+    static CURRENT: Cell<Option<SpanContext>> = const { Cell::new(None) };
+}
+
+/// Starts exporting spans to the collector in `config`, once per process
+pub fn install(config: TelemetryConfig) -> Result<(), TelemetryError> {
+    let valid = Url::parse(&config.traces_url())
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.has_host());
+    if !valid {
+        return Err(TelemetryError::Endpoint(config.endpoint));
+    }
+    let (sender, receiver) = mpsc::channel();
+    let exporter = Exporter {
+        sender: Mutex::new(sender),
+        timeout: config.timeout,
+    };
+    EXPORTER
+        .set(exporter)
+        .map_err(|_| TelemetryError::AlreadyInstalled)?;
+    thread::spawn(move || export_all(&config, receiver));
+    Ok(())
+}
+
+/// exports the spans finished so far, waiting at most for the configured timeout
+pub fn flush() {
+    if let Some(exporter) = EXPORTER.get() {
+        let (sender, receiver) = mpsc::channel();
+        if exporter
+            .sender
+            .lock()
+            .unwrap()
+            .send(Message::Flush(sender))
+            .is_ok()
+        {
+            let _ = receiver.recv_timeout(exporter.timeout);
+        }
+    }
+}
+
+/// An active span, finished and queued for export when dropped. Does nothing unless telemetry
+/// is installed.
+#[must_use]
+pub struct Span {
+    span: Option<FinishedSpan>,
+    /// restored as the current span when this one ends
+    previous: Option<SpanContext>,
+}
+
+/// a span of work inside the server, a child of the current span if there is one
+pub fn span(name: &'static str) -> Span {
+    start(name, SpanKind::Internal, CURRENT.get())
+}
+
+/// the span of handling a request, continuing the client's trace if it sent one
+pub fn server_span(name: &'static str, remote_parent: Option<SpanContext>) -> Span {
+    start(name, SpanKind::Server, remote_parent.or(CURRENT.get()))
+}
+
+fn start(name: &'static str, kind: SpanKind, parent: Option<SpanContext>) -> Span {
+    let previous = CURRENT.get();
+    if EXPORTER.get().is_none() {
+        return Span {
+            span: None,
+            previous,
+        };
+    }
+    let context = SpanContext {
+        trace_id: parent.map_or_else(random_id, |parent| parent.trace_id),
+        span_id: random_id(),
+    };
+    CURRENT.set(Some(context));
+    Span {
+        span: Some(FinishedSpan {
+            context,
+            parent: parent.map(|parent| parent.span_id),
+            name,
+            kind,
+            start: SystemTime::now(),
+            end: SystemTime::now(),
+            attributes: vec![],
+            error: false,
+        }),
+        previous,
+    }
+}
+
+fn random_id<const N: usize>() -> [u8; N] {
+    let mut id = [0; N];
+    getrandom::getrandom(&mut id).expect("no randomness available");
+    id
+}
+
+impl Span {
+    pub fn set_attribute(&mut self, key: &'static str, value: impl ToString) {
+        if let Some(span) = &mut self.span {
+            span.attributes.push((key, value.to_string()));
+        }
+    }
+
+    /// marks the span as failed
+    pub fn set_error(&mut self) {
+        if let Some(span) = &mut self.span {
+            span.error = true;
+        }
+    }
+
+    pub fn context(&self) -> Option<SpanContext> {
+        self.span.as_ref().map(|span| span.context)
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        CURRENT.set(self.previous);
+        if let (Some(mut span), Some(exporter)) = (self.span.take(), EXPORTER.get()) {
+            span.end = SystemTime::now();
+            let _ = exporter.sender.lock().unwrap().send(Message::Span(span));
+        }
+    }
+}
+
+fn export_all(config: &TelemetryConfig, receiver: Receiver<Message>) {
+    let agent = ureq::AgentBuilder::new()
+        .timeout_connect(config.timeout)
+        .timeout_read(config.timeout)
+        .timeout_write(config.timeout)
+        .user_agent(concat!("semver_repo/", env!("CARGO_PKG_VERSION")))
+        .build();
+    let mut batch = vec![];
+    let mut deadline = Instant::now() + config.flush_interval;
+    loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let flushed = match receiver.recv_timeout(timeout) {
+            Ok(Message::Span(span)) => {
+                batch.push(span);
+                if batch.len() < config.batch_size {
+                    continue;
+                }
+                None
+            }
+            Ok(Message::Flush(done)) => Some(done),
+            Err(RecvTimeoutError::Timeout) => None,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        if !batch.is_empty() {
+            export(&agent, config, &batch);
+            batch.clear();
+        }
+        if let Some(done) = flushed {
+            let _ = done.send(());
+        }
+        deadline = Instant::now() + config.flush_interval;
+    }
+}
+
+fn export(agent: &ureq::Agent, config: &TelemetryConfig, spans: &[FinishedSpan]) {
+    let body = serde_json::to_vec(&otlp_json(&config.service_name, spans)).unwrap_or_default();
+    let res = agent
+        .post(&config.traces_url())
+        .set("Content-Type", "application/json")
+        .send_bytes(&body);
+    if let Err(e) = res {
+        log::warn!("could not export {} spans: {}", spans.len(), e);
+    }
+}
+
+/// an `ExportTraceServiceRequest` in the JSON encoding of OTLP
+fn otlp_json(service_name: &str, spans: &[FinishedSpan]) -> Value {
+    let nanos = |time: SystemTime| {
+        let nanos = time
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        nanos.to_string()
+    };
+    let spans: Vec<Value> = spans
+        .iter()
+        .map(|span| {
+            let attributes: Vec<Value> = span
+                .attributes
+                .iter()
+                .map(|(key, value)| json!({ "key": key, "value": { "stringValue": value } }))
+                .collect();
+            json!({
+                "traceId": hex::encode(span.context.trace_id),
+                "spanId": hex::encode(span.context.span_id),
+                "parentSpanId": span.parent.map(hex::encode).unwrap_or_default(),
+                "name": span.name,
+                "kind": span.kind as u8,
+                "startTimeUnixNano": nanos(span.start),
+                "endTimeUnixNano": nanos(span.end),
+                "attributes": attributes,
+                // STATUS_CODE_ERROR, or unset
+                "status": { "code": if span.error { 2 } else { 0 } },
+            })
+        })
+        .collect();
+    json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [{ "key": "service.name", "value": { "stringValue": service_name } }],
+            },
+            "scopeSpans": [{
+                "scope": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+                "spans": spans,
+            }],
+        }],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn traceparent() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let context = SpanContext::from_traceparent(traceparent).unwrap();
+        assert_eq!(0x4b, context.trace_id[0]);
+        assert_eq!(0xb7, context.span_id[7]);
+        assert_eq!(traceparent, context.traceparent());
+        for invalid in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f35-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(None, SpanContext::from_traceparent(invalid), "{}", invalid);
+        }
+    }
+
+    /// the only test installing the exporter, it is global
+    #[test]
+    fn exports_nested_spans() {
+        const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let collector = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut config =
+            TelemetryConfig::new(format!("http://{}", collector.local_addr().unwrap()));
+        config.flush_interval = Duration::from_secs(3600);
+        config.batch_size = usize::MAX;
+        assert!(matches!(
+            install(TelemetryConfig::new("ftp://localhost")),
+            Err(TelemetryError::Endpoint(_))
+        ));
+        install(config.clone()).unwrap();
+        assert!(matches!(
+            install(config),
+            Err(TelemetryError::AlreadyInstalled)
+        ));
+
+        // other tests might export spans of their own, even without us
+        let received = thread::spawn(move || loop {
+            let (stream, _) = collector.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length: ") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            reader
+                .get_mut()
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let body: Value = serde_json::from_slice(&body).unwrap();
+            let spans: Vec<Value> = body["resourceSpans"][0]["scopeSpans"][0]["spans"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .filter(|span| span["traceId"] == TRACE_ID)
+                .cloned()
+                .collect();
+            if !spans.is_empty() {
+                return spans;
+            }
+        });
+        {
+            let remote =
+                SpanContext::from_traceparent(&format!("00-{}-00f067aa0ba902b7-01", TRACE_ID));
+            let mut request = server_span("request", remote);
+            request.set_attribute("request", "AddCrate");
+            let _save = span("store.save");
+        }
+        flush();
+
+        let spans = received.join().unwrap();
+        // children end first
+        let (save, request) = (&spans[0], &spans[1]);
+        assert_eq!("store.save", save["name"]);
+        assert_eq!(request["spanId"], save["parentSpanId"]);
+        assert_eq!("00f067aa0ba902b7", request["parentSpanId"]);
+        assert_eq!(TRACE_ID, save["traceId"]);
+        assert_eq!("AddCrate", request["attributes"][0]["value"]["stringValue"]);
+        assert_eq!(None, CURRENT.get());
+    }
+}
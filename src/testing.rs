@@ -0,0 +1,739 @@
+//! Helpers for end-to-end tests against a real, in-process server.
+//!
+//! ```no_run
+//! use semver_repo::testing::TestServer;
+//!
+//! let server = TestServer::start().unwrap();
+//! let client = server.client();
+//! assert_eq!(None, client.find_exact("nope").unwrap());
+//! ```
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+
+use tempfile::TempDir;
+
+use crate::client::Client;
+use crate::server::{ReloadHandle, Server, ServerConfig, ServerError, ShutdownHandle};
+
+/// A server on an ephemeral localhost port backed by a temporary store.
+/// Shuts down and cleans up after itself when dropped.
+pub struct TestServer {
+    addr: SocketAddr,
+    shutdown: ShutdownHandle,
+    reload: ReloadHandle,
+    thread: Option<JoinHandle<Result<(), ServerError>>>,
+    store: PathBuf,
+    // kept alive until the server has saved its store
+    _store_dir: TempDir,
+}
+
+impl TestServer {
+    pub fn start() -> Result<Self, ServerError> {
+        Self::start_with(|config| config)
+    }
+
+    /// like [`TestServer::start`], but lets the caller tweak the configuration.
+    /// The listen addresses and store path are filled in already.
+    pub fn start_with(
+        configure: impl FnOnce(ServerConfig) -> ServerConfig,
+    ) -> Result<Self, ServerError> {
+        let store_dir = tempfile::tempdir()?;
+        let config = ServerConfig::new(store_dir.path().join("store.json"))
+            .with_listen(vec![SocketAddr::from(([127, 0, 0, 1], 0))]);
+        let config = configure(config);
+        let store = config.store.clone();
+        let server = Server::bind(config)?;
+        let addr = server.local_addrs()[0];
+        let shutdown = server.shutdown_handle();
+        let reload = server.reload_handle();
+        let thread = thread::spawn(move || server.serve());
+
+        Ok(Self {
+            addr,
+            shutdown,
+            reload,
+            thread: Some(thread),
+            store,
+            _store_dir: store_dir,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    pub fn client(&self) -> Client {
+        Client::new(self.addr.to_string())
+    }
+
+    /// reloads the server's settings like SIGHUP does, see [`ServerConfig::settings_file`]
+    pub fn reload_handle(&self) -> ReloadHandle {
+        self.reload.clone()
+    }
+
+    /// where the server saves its repository, e.g. to check what it saved
+    pub fn store_path(&self) -> &Path {
+        &self.store
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            match thread.join() {
+                Ok(Err(e)) => log::error!("test server failed: {}", e),
+                Err(_) => log::error!("test server panicked"),
+                Ok(Ok(())) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::admin::{AdminRequest, AdminResponse};
+    use crate::api::{ApiError, ApiRequest, CrateSummary};
+    use crate::client::ClientError;
+    use crate::replication::Follower;
+    use crate::upstream::{RegistryUpstream, UpstreamConfig};
+    use crate::{Crate, CrateKind, Metadata, RepoError, SemVer};
+
+    /// issues a token for `user` on a server with the admin token `s3cret`
+    fn token(client: &Client, user: &str) -> Result<String, ClientError> {
+        let request = AdminRequest::IssueToken {
+            user: user.into(),
+            scopes: vec![],
+            expires: None,
+        };
+        match client.admin("s3cret", request)? {
+            AdminResponse::Token(token) => Ok(token),
+            other => panic!("unexpected response {:?}", other),
+        }
+    }
+
+    #[test]
+    fn round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+
+        assert_eq!(None, client.find_exact("hello_bin")?);
+        client.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        client.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        assert_eq!(
+            Some(SemVer::new(1, 1, 0)),
+            client.latest_compatible("hello_bin", SemVer::new(1, 0, 0))?
+        );
+
+        assert!(matches!(
+            client.add_crate(
+                Metadata::new("", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0)
+            ),
+            Err(ClientError::Api(ApiError::Repo(RepoError::InvalidName)))
+        ));
+        assert!(matches!(
+            client.add_release("hello_bin", SemVer::new(1, 0, 5)),
+            Err(ClientError::Api(ApiError::Repo(RepoError::NotMonotonic)))
+        ));
+        let found = client.find_containing("BIN", Default::default())?;
+        assert_eq!(
+            vec![CrateSummary {
+                name: "hello_bin".into(),
+                author: "Busy Person".into(),
+                latest: Some(SemVer::new(1, 1, 0)),
+                advisory: None,
+            }],
+            found
+        );
+        assert_eq!(1, client.find_regex("^hello_(bin|moon)$")?.len());
+        assert!(matches!(
+            client.find_regex("hello_("),
+            Err(ClientError::Api(ApiError::InvalidPattern(_)))
+        ));
+
+        let audit = client.audit_log("hello_bin", 0)?;
+        assert_eq!(2, audit.len());
+        assert_eq!("127.0.0.1", audit[0].actor);
+        Ok(())
+    }
+
+    #[test]
+    fn subscribe_waits_for_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+
+        let subscriber = {
+            let client = client.clone();
+            std::thread::spawn(move || client.subscribe(0))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        client.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+
+        let changes = subscriber.join().unwrap()?;
+        assert_eq!(1, changes.len());
+        assert_eq!(1, changes[0].seq);
+        Ok(())
+    }
+
+    #[test]
+    fn admin_requires_token() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let client = server.client();
+
+        assert!(matches!(
+            client.admin("guess", AdminRequest::Stats),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        match client.admin("s3cret", AdminRequest::Stats)? {
+            AdminResponse::Stats(stats) => assert_eq!(0, stats.crates),
+            other => panic!("unexpected response {:?}", other),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn read_only() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.read_only = true;
+            config
+        })?;
+        let client = server.client();
+
+        assert!(matches!(
+            client.add_crate(
+                Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            ),
+            Err(ClientError::Api(ApiError::ReadOnly))
+        ));
+        assert_eq!(None, client.find_exact("hello_bin")?);
+        Ok(())
+    }
+
+    #[test]
+    fn follower_replicates() -> Result<(), Box<dyn std::error::Error>> {
+        let primary = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        primary.client().add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+
+        let primary_client = primary.client();
+        let mirror = TestServer::start_with(move |mut config| {
+            config.follow = Some(Follower::new(primary_client));
+            config
+        })?;
+        let mirror_client = mirror.client();
+
+        primary
+            .client()
+            .add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        let owner = primary
+            .client()
+            .with_token(token(&primary.client(), "Busy Person")?);
+        owner.set_readme("hello_bin", SemVer::new(1, 1, 0), Some("# Hello".into()))?;
+        // wait for the mirror's feed to catch up
+        let mut seen = 0;
+        while seen < 3 {
+            seen = mirror_client
+                .subscribe(seen)?
+                .last()
+                .map_or(seen, |c| c.seq);
+        }
+
+        let crt = mirror_client.find_exact("hello_bin")?.unwrap();
+        assert_eq!(Some(&SemVer::new(1, 1, 0)), crt.release_history.last());
+        // fetched from the primary after applying the change
+        let mut readme = None;
+        for _ in 0..100 {
+            readme = mirror_client.readme("hello_bin", SemVer::new(1, 1, 0))?;
+            if readme.is_some() {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        assert_eq!(Some("# Hello".to_string()), readme);
+        assert!(matches!(
+            mirror_client.add_release("hello_bin", SemVer::new(2, 0, 0)),
+            Err(ClientError::Api(ApiError::ReadOnly))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn proxies_upstream() -> Result<(), Box<dyn std::error::Error>> {
+        let upstream = TestServer::start()?;
+        upstream.client().add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+
+        let upstream_client = upstream.client();
+        let proxy = TestServer::start_with(move |mut config| {
+            config.upstream = Some(UpstreamConfig::new(RegistryUpstream(upstream_client)));
+            config
+        })?;
+        let client = proxy.client();
+        assert!(client.find_exact("hello_bin")?.is_some());
+        assert_eq!(None, client.find_exact("hello_moon")?);
+        // the token is checked before asking upstream, as without one
+        assert!(matches!(
+            proxy.client().with_token("bogus").find_exact("hello_bin"),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+
+        // cached, so it survives upstream going away
+        drop(upstream);
+        assert!(client.find_exact("hello_bin")?.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn batches() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+        let metadata = Metadata::new("hello_bin", "Busy Person", CrateKind::Binary);
+
+        let results = client.batch(
+            vec![
+                ApiRequest::AddCrate(metadata.clone(), SemVer::new(1, 0, 0)),
+                ApiRequest::AddRelease("hello_bin".to_string(), SemVer::new(0, 1, 0)),
+                ApiRequest::FindExact("hello_bin".to_string()),
+            ],
+            false,
+        )?;
+        assert_eq!(3, results.len());
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(ApiError::Repo(RepoError::NotMonotonic))
+        ));
+        let found: Option<Crate> = serde_json::from_value(results[2].clone()?)?;
+        assert!(found.is_some());
+
+        let res = client.batch(
+            vec![
+                ApiRequest::AddRelease("hello_bin".to_string(), SemVer::new(1, 1, 0)),
+                ApiRequest::AddCrate(metadata, SemVer::new(1, 0, 0)),
+            ],
+            true,
+        );
+        assert!(matches!(
+            res,
+            Err(ClientError::Api(ApiError::BatchAborted { index: 1, .. }))
+        ));
+        let crt = client.find_exact("hello_bin")?.unwrap();
+        assert_eq!(vec![SemVer::new(1, 0, 0)], crt.release_history);
+        Ok(())
+    }
+
+    #[test]
+    fn idempotent_retries() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+        let add = || {
+            ApiRequest::AddCrate(
+                Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )
+        };
+
+        client.request_idempotent::<()>("publish-1", add())?;
+        client.request_idempotent::<()>("publish-1", add())?;
+        assert_eq!(1, client.feed_status()?.last_seq);
+        assert!(matches!(
+            client.request_idempotent::<()>("publish-2", add()),
+            Err(ClientError::Api(ApiError::Repo(RepoError::AlreadyExists)))
+        ));
+        assert!(matches!(
+            client.request_idempotent::<()>(
+                "publish-1",
+                ApiRequest::AddRelease("hello_bin".to_string(), SemVer::new(1, 1, 0))
+            ),
+            Err(ClientError::Api(ApiError::IdempotencyKeyReused))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn publish_versions() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+        let metadata = Metadata::new("hello_bin", "Busy Person", CrateKind::Binary);
+        let versions = vec!["v1.0".to_string(), "1.1.0".to_string()];
+
+        assert!(matches!(
+            client.publish_versions(metadata.clone(), versions.clone(), false),
+            Err(ClientError::Api(ApiError::Repo(RepoError::InvalidVersion)))
+        ));
+        client.publish_versions(metadata, versions, true)?;
+        let crt = client.find_exact("hello_bin")?.unwrap();
+        assert_eq!(
+            vec![SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)],
+            crt.release_history
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn idempotency_keys_per_user() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let ferris = server
+            .client()
+            .with_token(token(&server.client(), "ferris")?);
+        let corro = server
+            .client()
+            .with_token(token(&server.client(), "corro")?);
+        let add = |name: &str| {
+            ApiRequest::AddCrate(
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )
+        };
+
+        ferris.request_idempotent::<()>("publish", add("hello_bin"))?;
+        // the same key and request, but not a retry of ferris' publish
+        assert!(matches!(
+            corro.request_idempotent::<()>("publish", add("hello_bin")),
+            Err(ClientError::Api(ApiError::Repo(RepoError::AlreadyExists)))
+        ));
+        corro.request_idempotent::<()>("publish-moon", add("hello_moon"))?;
+        ferris.request_idempotent::<()>("publish", add("hello_bin"))?;
+        assert_eq!(2, server.client().feed_status()?.last_seq);
+        Ok(())
+    }
+
+    #[test]
+    fn revisions() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let client = server.client();
+        let release =
+            |minor| ApiRequest::AddRelease("hello_bin".to_string(), SemVer::new(1, minor, 0));
+
+        client.request_if_revision::<()>(
+            0,
+            ApiRequest::AddCrate(
+                Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            ),
+        )?;
+        let revision = client.find_exact("hello_bin")?.unwrap().revision();
+        assert_eq!(1, revision);
+
+        client.request_if_revision::<()>(revision, release(1))?;
+        assert!(matches!(
+            client.request_if_revision::<()>(revision, release(2)),
+            Err(ClientError::Api(ApiError::Conflict {
+                expected: 1,
+                actual: 2
+            }))
+        ));
+        assert!(matches!(
+            client.request_if_revision::<()>(0, ApiRequest::FeedStatus),
+            Err(ClientError::Api(ApiError::NoCrate))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn case_insensitive_lookup() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.case_insensitive_lookup = true;
+            config
+        })?;
+        let client = server.client();
+        client.add_crate(
+            Metadata::new("linux.exe", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        let crt = client.find_exact("LINUX.exe")?.unwrap();
+        assert_eq!("linux.exe", crt.metadata().name());
+        assert_eq!(1, crt.downloads());
+        Ok(())
+    }
+
+    #[test]
+    fn org_permissions() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::orgs::{OrgRequest, Role};
+
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let issue = |user: &str| -> Result<Client, ClientError> {
+            match server.client().admin(
+                "s3cret",
+                AdminRequest::IssueToken {
+                    user: user.into(),
+                    scopes: vec![],
+                    expires: None,
+                },
+            )? {
+                AdminResponse::Token(token) => Ok(server.client().with_token(token)),
+                other => panic!("unexpected response {:?}", other),
+            }
+        };
+        let (ferris, corro) = (issue("ferris")?, issue("corro")?);
+        let anonymous = server.client();
+
+        assert!(matches!(
+            anonymous.org(OrgRequest::Create("rustaceans".into())),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        assert!(matches!(
+            server.client().with_token("guess").find_exact("crab"),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        ferris.org(OrgRequest::Create("rustaceans".into()))?;
+        let org = ferris.org(OrgRequest::SetMember {
+            org: "rustaceans".into(),
+            user: "corro".into(),
+            role: Some(Role::Publisher),
+        })?;
+        assert_eq!(Some(Role::Publisher), org.role("corro"));
+
+        let metadata = Metadata::new("crab", "ferris", CrateKind::Library).with_org("rustaceans");
+        let forbidden = |res| {
+            matches!(
+                res,
+                Err(ClientError::Api(ApiError::Repo(RepoError::Forbidden)))
+            )
+        };
+        assert!(forbidden(
+            anonymous.add_crate(metadata.clone(), SemVer::new(1, 0, 0))
+        ));
+        corro.add_crate(metadata.clone(), SemVer::new(1, 0, 0))?;
+        corro.add_release("crab", SemVer::new(1, 1, 0))?;
+        assert!(forbidden(anonymous.yank("crab", SemVer::new(1, 1, 0))));
+        assert!(forbidden(
+            corro.update_metadata(metadata.clone().with_description("🦀"))
+        ));
+        ferris.update_metadata(metadata.with_description("🦀"))?;
+
+        let audit = anonymous.audit_log("crab", 0)?;
+        assert!(audit
+            .iter()
+            .all(|entry| entry.actor.starts_with("corro@") || entry.actor.starts_with("ferris@")));
+        Ok(())
+    }
+
+    #[test]
+    fn quotas() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::quota::Quota;
+
+        let server = TestServer::start_with(|mut config| {
+            config.quotas.max_releases_per_day = Some(2);
+            config.quotas.max_request_size = Some(512);
+            config
+        })?;
+        let client = server.client();
+        let exceeded = |res: Result<(), ClientError>, quota| match res {
+            Err(ClientError::Api(ApiError::QuotaExceeded(q))) => q == quota,
+            _ => false,
+        };
+
+        client.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        client.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        assert!(exceeded(
+            client.add_release("hello_bin", SemVer::new(1, 2, 0)),
+            Quota::ReleasesPerDay
+        ));
+        let huge = Metadata::new("hello_moon", "Busy Person", CrateKind::Binary)
+            .with_description("🌕".repeat(1000));
+        assert!(exceeded(
+            client.add_crate(huge, SemVer::new(1, 0, 0)),
+            Quota::RequestSize
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn token_scopes() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::auth::Scope;
+
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let issue = |scopes, expires| -> Result<Client, ClientError> {
+            let request = AdminRequest::IssueToken {
+                user: "ci".into(),
+                scopes,
+                expires,
+            };
+            match server.client().admin("s3cret", request)? {
+                AdminResponse::Token(token) => Ok(server.client().with_token(token)),
+                other => panic!("unexpected response {:?}", other),
+            }
+        };
+        let ci = issue(vec![Scope::Publish("acme-*".into())], None)?;
+        ci.add_crate(
+            Metadata::new("acme-http", "ci", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        assert!(matches!(
+            ci.add_crate(
+                Metadata::new("serde", "ci", CrateKind::Library),
+                SemVer::new(1, 0, 0),
+            ),
+            Err(ClientError::Api(ApiError::OutOfScope(kind))) if kind == "AddCrate"
+        ));
+        assert!(matches!(
+            ci.yank("acme-http", SemVer::new(1, 0, 0)),
+            Err(ClientError::Api(ApiError::OutOfScope(_)))
+        ));
+        assert!(ci.find_exact("acme-http")?.is_some());
+
+        let expired = issue(
+            vec![],
+            Some(chrono::Utc::now() - chrono::Duration::hours(1)),
+        )?;
+        assert!(matches!(
+            expired.find_exact("acme-http"),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+
+        let tokens = ci.tokens()?;
+        assert_eq!(2, tokens.len());
+        let used: Vec<_> = tokens.iter().filter(|t| t.last_used.is_some()).collect();
+        assert_eq!(1, used.len());
+        assert_eq!(vec![Scope::Publish("acme-*".into())], used[0].scopes);
+        ci.revoke_token(used[0].id.clone())?;
+        assert!(matches!(
+            ci.tokens(),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn confirm_deletion() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let client = server.client();
+        client.add_crate(
+            Metadata::new("crab", "ferris", CrateKind::Library),
+            SemVer::new(1, 0, 0),
+        )?;
+        let token = match client.admin("s3cret", AdminRequest::DeleteCrate("crab".into()))? {
+            AdminResponse::ConfirmationRequired { token, within_secs } => {
+                assert_eq!(60, within_secs);
+                token
+            }
+            other => panic!("unexpected response {:?}", other),
+        };
+        assert!(client.find_exact("crab")?.is_some());
+        assert!(matches!(
+            client.admin("s3cret", AdminRequest::Confirm("guess".into())),
+            Err(ClientError::Api(ApiError::InvalidConfirmation))
+        ));
+        assert!(matches!(
+            client.batch(
+                vec![ApiRequest::Admin {
+                    token: "s3cret".into(),
+                    request: AdminRequest::DeleteCrate("crab".into()),
+                }],
+                false
+            ),
+            Err(ClientError::Api(ApiError::ConfirmationRequired))
+        ));
+
+        assert_eq!(
+            AdminResponse::Done,
+            client.admin("s3cret", AdminRequest::Confirm(token.clone()))?
+        );
+        assert!(client.find_exact("crab")?.is_none());
+        assert!(matches!(
+            client.admin("s3cret", AdminRequest::Confirm(token)),
+            Err(ClientError::Api(ApiError::InvalidConfirmation))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn deprecation() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::deprecation::Deprecation;
+
+        let server = TestServer::start_with(|mut config| {
+            config.admin_token = Some("s3cret".to_string());
+            config
+        })?;
+        let client = server.client();
+        client.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        let deprecation = Deprecation {
+            message: Some("unmaintained".to_string()),
+            replacement: Some("hello_world".to_string()),
+        };
+        assert!(matches!(
+            client.deprecate("hello_bin", None, Some(deprecation.clone())),
+            Err(ClientError::Api(ApiError::Unauthorized))
+        ));
+        let owner = server.client().with_token(token(&client, "Busy Person")?);
+        owner.deprecate("hello_bin", None, Some(deprecation.clone()))?;
+
+        let crt = client.find_exact("hello_bin")?.unwrap();
+        assert_eq!(Some(&deprecation), crt.deprecation());
+        // searches only have summaries
+        let found = client.search("hello")?;
+        assert_eq!("hello_bin", found[0].name);
+        Ok(())
+    }
+
+    #[test]
+    fn pipelining() -> Result<(), Box<dyn std::error::Error>> {
+        let server = TestServer::start()?;
+        let pipeline = std::sync::Arc::new(server.client().pipeline()?);
+
+        // blocks until the crate is added over the same connection
+        let subscriber = {
+            let pipeline = pipeline.clone();
+            std::thread::spawn(move || {
+                pipeline.request::<Vec<crate::events::Change>>(&ApiRequest::Subscribe { since: 0 })
+            })
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        pipeline.request::<()>(&ApiRequest::AddCrate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        ))?;
+        let found: Option<Crate> = pipeline.request(&ApiRequest::FindExact("hello_bin".into()))?;
+        assert!(found.is_some());
+        assert!(matches!(
+            pipeline.request::<()>(&ApiRequest::AddRelease("nope".into(), SemVer::new(1, 0, 0))),
+            Err(ClientError::Api(ApiError::Repo(RepoError::NotFound)))
+        ));
+
+        let changes = subscriber.join().unwrap()?;
+        assert_eq!(1, changes.len());
+        // plain requests still work as before
+        assert!(server.client().find_exact("hello_bin")?.is_some());
+        Ok(())
+    }
+}
@@ -0,0 +1,242 @@
+//! All-or-nothing changes to a [`Repository`].
+
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+
+use log::warn;
+
+use crate::audit::AuditLog;
+use crate::auth::Tokens;
+use crate::blobs::BlobId;
+use crate::events::ChangeLog;
+use crate::kinds::KindPolicy;
+use crate::names::{NameRules, Namespaces};
+use crate::orgs::Organization;
+use crate::{Crate, CrateKind, Metadata, RepoError, Repository, SemVer, VersionPolicy};
+
+/// Repository state to go back to, see [`Repository::checkpoint`]
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    crates: BTreeMap<Arc<str>, Arc<Crate>>,
+    aliases: BTreeMap<String, String>,
+    changes: ChangeLog,
+    namespaces: Namespaces,
+    name_rules: NameRules,
+    orgs: BTreeMap<String, Organization>,
+    tokens: Tokens,
+    audit: AuditLog,
+    version_policy: VersionPolicy,
+    kind_policies: BTreeMap<CrateKind, KindPolicy>,
+    mutations: u64,
+}
+
+impl Repository {
+    /// Remembers the current state so it can be restored with [`Repository::rollback`].
+    /// Copies the whole repository, so keep transactions for when they're needed.
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            crates: self.crates.clone(),
+            aliases: self.aliases.clone(),
+            changes: self.changes.clone(),
+            namespaces: self.namespaces.clone(),
+            name_rules: self.name_rules.clone(),
+            orgs: self.orgs.clone(),
+            tokens: self.tokens.clone(),
+            audit: self.audit.clone(),
+            version_policy: self.version_policy,
+            kind_policies: self.kind_policies.clone(),
+            mutations: self.mutations,
+        }
+    }
+
+    /// Goes back to `checkpoint`, removing README blobs written since that nothing references
+    /// any more.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) {
+        let written: HashSet<BlobId> = self
+            .crates
+            .iter()
+            .filter(|(name, crt)| {
+                checkpoint
+                    .crates
+                    .get(*name)
+                    .is_none_or(|old| !Arc::ptr_eq(old, crt))
+            })
+            .flat_map(|(_, crt)| crt.readme_ids().cloned())
+            .collect();
+        self.crates = checkpoint.crates;
+        self.aliases = checkpoint.aliases;
+        self.changes = checkpoint.changes;
+        self.namespaces = checkpoint.namespaces;
+        self.name_rules = checkpoint.name_rules;
+        self.orgs = checkpoint.orgs;
+        self.tokens = checkpoint.tokens;
+        self.audit = checkpoint.audit;
+        self.version_policy = checkpoint.version_policy;
+        self.kind_policies = checkpoint.kind_policies;
+        self.mutations = checkpoint.mutations;
+        self.reindex_all();
+
+        if written.is_empty() {
+            return;
+        }
+        let kept: HashSet<&BlobId> = self
+            .crates
+            .values()
+            .flat_map(|crt| crt.readme_ids())
+            .collect();
+        let blobs = self.blobs();
+        for id in written.iter().filter(|id| !kept.contains(id)) {
+            if let Err(e) = blobs.remove(id) {
+                warn!("couldn't remove README blob {}: {}", id, e);
+            }
+        }
+    }
+
+    /// Runs `f`, undoing all of its changes if it fails.
+    pub fn transaction<T, E>(
+        &mut self,
+        f: impl FnOnce(&mut Repository) -> Result<T, E>,
+    ) -> Result<T, E> {
+        let checkpoint = self.checkpoint();
+        let res = f(self);
+        if res.is_err() {
+            self.rollback(checkpoint);
+        }
+        res
+    }
+
+    /// Adds a crate together with all of its `releases`, or nothing at all if any of them is
+    /// invalid. The first release is the crate's initial version.
+    pub fn publish_atomic(
+        &mut self,
+        metadata: Metadata,
+        releases: Vec<SemVer>,
+    ) -> Result<(), RepoError> {
+        let (first, rest) = releases.split_first().ok_or(RepoError::InvalidVersion)?;
+        self.transaction(|repo| {
+            let name = metadata.name().to_string();
+            repo.add_crate(metadata, *first)?;
+            for release in rest {
+                repo.add_release(&name, *release)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use crate::kinds::KindPolicy;
+    use crate::{CrateKind, Metadata, RepoError, Repository, SemVer, VersionPolicy};
+
+    #[test]
+    fn transaction_rolls_back() {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+
+        let res = repo.transaction(|repo| {
+            repo.add_crate(
+                Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )?;
+            repo.add_release("hello_bin", SemVer::new(0, 1, 0))
+        });
+        assert_eq!(Err(RepoError::NotMonotonic), res);
+        assert_eq!(None, repo.find_exact("hello_bin"));
+        assert_eq!(0, repo.changes().last_seq());
+    }
+
+    #[test]
+    fn rollback_restores_everything() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = tempfile::tempdir()?;
+        let mut repo = Repository::new(dir.path().join("store.json"));
+        repo.add_crate(
+            Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+        repo.set_readme("hello_bin", SemVer::new(1, 0, 0), Some("# kept"))?;
+        let kept = repo
+            .find_exact("hello_bin")
+            .unwrap()
+            .readme_id(SemVer::new(1, 0, 0))
+            .cloned();
+        let unsaved = repo.unsaved_changes();
+
+        let checkpoint = repo.checkpoint();
+        repo.audit("someone", 0);
+        repo.set_version_policy(VersionPolicy::Unique);
+        let policy = KindPolicy {
+            version_policy: Some(VersionPolicy::Unique),
+            ..KindPolicy::default()
+        };
+        repo.set_kind_policy(CrateKind::Binary, policy);
+        repo.set_readme("hello_bin", SemVer::new(1, 0, 0), Some("# dropped"))?;
+        let dropped = repo
+            .find_exact("hello_bin")
+            .unwrap()
+            .readme_id(SemVer::new(1, 0, 0))
+            .cloned();
+        repo.rollback(checkpoint);
+
+        assert!(repo.audit_log().since("hello_bin", 0).is_empty());
+        assert_eq!(VersionPolicy::default(), repo.version_policy());
+        assert_eq!(KindPolicy::default(), repo.kind_policy(&CrateKind::Binary));
+        assert_eq!(unsaved, repo.unsaved_changes());
+        assert!(repo.blobs().contains(&kept.unwrap()));
+        assert!(!repo.blobs().contains(&dropped.unwrap()));
+        assert_eq!(
+            Some("# kept".to_string()),
+            repo.readme("hello_bin", SemVer::new(1, 0, 0))?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn publish_atomic() {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        let metadata = Metadata::new("hello_bin", "Busy Person", CrateKind::Binary);
+
+        assert_eq!(
+            Err(RepoError::NotMonotonic),
+            repo.publish_atomic(
+                metadata.clone(),
+                vec![
+                    SemVer::new(1, 0, 0),
+                    SemVer::new(1, 1, 0),
+                    SemVer::new(1, 0, 1)
+                ]
+            )
+        );
+        assert!(repo.find_containing("hello", Default::default()).is_empty());
+        assert_eq!(
+            Err(RepoError::InvalidVersion),
+            repo.publish_atomic(metadata.clone(), vec![])
+        );
+        for (name, author, err) in [
+            (" ", "Busy Person", RepoError::InvalidName),
+            ("hello_bin", "\t", RepoError::InvalidAuthor),
+        ] {
+            assert_eq!(
+                Err(err),
+                repo.publish_atomic(
+                    Metadata::new(name, author, CrateKind::Binary),
+                    vec![SemVer::new(1, 0, 0)]
+                )
+            );
+        }
+        assert_eq!(0, repo.changes().last_seq());
+
+        assert_eq!(
+            Ok(()),
+            repo.publish_atomic(metadata, vec![SemVer::new(1, 0, 0), SemVer::new(1, 1, 0)])
+        );
+        assert_eq!(
+            2,
+            repo.find_exact("hello_bin").unwrap().release_history.len()
+        );
+        assert_eq!(2, repo.changes().last_seq());
+    }
+}
@@ -0,0 +1,337 @@
+//! Pull-through caching of crates from an upstream registry.
+//!
+//! When a `FindExact` misses locally, the server asks its [`Upstream`] and remembers the answer
+//! (including "doesn't exist") for a configurable time. The cache holds a bounded number of
+//! crates, evicting the least recently used ones, so popular crates stay in memory. In offline
+//! mode the upstream is never contacted and whatever is cached is served, no matter how old.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, warn};
+use thiserror::Error;
+
+use crate::client::{Client, ClientError};
+use crate::Crate;
+
+#[derive(Error, Debug)]
+pub enum UpstreamError {
+    #[error("upstream registry: {0}")]
+    Registry(#[from] ClientError),
+    #[error("upstream http: {0}")]
+    Http(String),
+    #[error("offline and not cached")]
+    Offline,
+}
+
+/// Somewhere crates can be looked up that aren't published locally
+pub trait Upstream: Debug + Send + Sync {
+    fn find_exact(&self, name: &str) -> Result<Option<Crate>, UpstreamError>;
+}
+
+/// another instance of this server
+#[derive(Debug, Clone)]
+pub struct RegistryUpstream(pub Client);
+
+impl Upstream for RegistryUpstream {
+    fn find_exact(&self, name: &str) -> Result<Option<Crate>, UpstreamError> {
+        Ok(self.0.find_exact(name)?)
+    }
+}
+
+#[cfg(feature = "crates-io")]
+pub use crates_io::CratesIo;
+
+#[cfg(feature = "crates-io")]
+mod crates_io {
+    use serde::Deserialize;
+
+    use super::{Upstream, UpstreamError};
+    use crate::{Crate, CrateKind, Metadata, SemVer};
+
+    /// the crates.io web API, `https://crates.io/api/v1` by default
+    #[derive(Debug, Clone)]
+    pub struct CratesIo {
+        pub base_url: String,
+        agent: ureq::Agent,
+    }
+
+    impl Default for CratesIo {
+        fn default() -> Self {
+            Self {
+                base_url: "https://crates.io/api/v1".to_string(),
+                // crates.io rejects requests without a meaningful user agent
+                agent: ureq::AgentBuilder::new()
+                    .user_agent(concat!("semver_repo/", env!("CARGO_PKG_VERSION")))
+                    .build(),
+            }
+        }
+    }
+
+    #[derive(Deserialize)]
+    struct CrateResponse {
+        #[serde(rename = "crate")]
+        krate: CrateInfo,
+        versions: Vec<VersionInfo>,
+    }
+
+    #[derive(Deserialize)]
+    struct CrateInfo {
+        name: String,
+    }
+
+    #[derive(Deserialize)]
+    struct VersionInfo {
+        num: String,
+        yanked: bool,
+        #[serde(default)]
+        bin_names: Vec<String>,
+        published_by: Option<User>,
+    }
+
+    #[derive(Deserialize)]
+    struct User {
+        login: String,
+    }
+
+    impl Upstream for CratesIo {
+        fn find_exact(&self, name: &str) -> Result<Option<Crate>, UpstreamError> {
+            let url = format!("{}/crates/{}", self.base_url, name);
+            let response: CrateResponse = match self.agent.get(&url).call() {
+                Ok(response) => response
+                    .into_json()
+                    .map_err(|e| UpstreamError::Http(e.to_string()))?,
+                Err(ureq::Error::Status(404, _)) => return Ok(None),
+                Err(e) => return Err(UpstreamError::Http(e.to_string())),
+            };
+
+            // crates.io lists versions newest first; pre-releases can't be represented here
+            let mut versions: Vec<(SemVer, &VersionInfo)> = response
+                .versions
+                .iter()
+                .filter_map(|v| Some((v.num.parse().ok()?, v)))
+                .collect();
+            versions.sort_by_key(|(version, _)| *version);
+            versions.dedup_by_key(|(version, _)| *version);
+            let newest = match versions.last() {
+                Some((_, info)) => info,
+                None => return Ok(None),
+            };
+
+            let author = newest
+                .published_by
+                .as_ref()
+                .map(|u| u.login.as_str())
+                .unwrap_or("crates.io");
+            let kind = if newest.bin_names.is_empty() {
+                CrateKind::Library
+            } else {
+                CrateKind::Binary
+            };
+            let mut crt = Crate::new(Metadata::new(&response.krate.name, author, kind));
+            for (version, info) in &versions {
+                // sorted and deduplicated, so this can't fail
+                let _ = crt.add_release(*version);
+                if info.yanked {
+                    let _ = crt.yank(*version);
+                }
+            }
+            Ok(Some(crt))
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct UpstreamConfig {
+    pub upstream: Arc<dyn Upstream>,
+    /// how long answers from upstream are served from the cache
+    pub ttl: Duration,
+    /// never contact upstream, serve cached answers regardless of their age
+    pub offline: bool,
+    /// most answers kept, the least recently used are evicted first
+    pub cache_capacity: usize,
+}
+
+impl UpstreamConfig {
+    pub fn new(upstream: impl Upstream + 'static) -> Self {
+        Self {
+            upstream: Arc::new(upstream),
+            ttl: Duration::from_secs(15 * 60),
+            offline: false,
+            cache_capacity: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// name → when it was fetched, the answer and when it was last used
+    map: HashMap<String, (Instant, Option<Crate>, u64)>,
+    /// last uses → name, oldest first
+    recency: BTreeMap<u64, String>,
+    clock: u64,
+}
+
+impl Entries {
+    fn get(&mut self, name: &str) -> Option<(Instant, Option<Crate>)> {
+        let (fetched, crt, used) = self.map.get_mut(name)?;
+        self.recency.remove(used);
+        self.clock += 1;
+        *used = self.clock;
+        self.recency.insert(self.clock, name.to_string());
+        Some((*fetched, crt.clone()))
+    }
+
+    fn insert(&mut self, name: &str, crt: Option<Crate>, capacity: usize) {
+        if let Some((_, _, used)) = self.map.remove(name) {
+            self.recency.remove(&used);
+        }
+        while self.map.len() >= capacity {
+            match self.recency.pop_first() {
+                Some((_, evicted)) => {
+                    self.map.remove(&evicted);
+                }
+                None => return,
+            }
+        }
+        self.clock += 1;
+        self.map
+            .insert(name.to_string(), (Instant::now(), crt, self.clock));
+        self.recency.insert(self.clock, name.to_string());
+    }
+}
+
+#[derive(Debug)]
+pub struct ProxyCache {
+    config: UpstreamConfig,
+    entries: Mutex<Entries>,
+}
+
+impl ProxyCache {
+    pub fn new(config: UpstreamConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(Entries::default()),
+        }
+    }
+
+    /// number of cached answers
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Looks `name` up upstream unless a fresh enough answer is cached.
+    /// If upstream fails, a stale cached answer is better than none.
+    pub fn find_exact(&self, name: &str) -> Result<Option<Crate>, UpstreamError> {
+        let cached = self.entries.lock().unwrap().get(name);
+        if let Some((fetched, crt)) = &cached {
+            if self.config.offline || fetched.elapsed() < self.config.ttl {
+                debug!("upstream cache hit for '{}'", name);
+                return Ok(crt.clone());
+            }
+        }
+        if self.config.offline {
+            return Err(UpstreamError::Offline);
+        }
+
+        match self.config.upstream.find_exact(name) {
+            Ok(crt) => {
+                self.entries
+                    .lock()
+                    .unwrap()
+                    .insert(name, crt.clone(), self.config.cache_capacity);
+                Ok(crt)
+            }
+            Err(e) => match cached {
+                Some((_, crt)) => {
+                    warn!("upstream failed, serving stale '{}': {}", name, e);
+                    Ok(crt)
+                }
+                None => Err(e),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::{CrateKind, Metadata};
+
+    #[derive(Debug, Default)]
+    struct Counting(AtomicUsize);
+
+    impl Upstream for Counting {
+        fn find_exact(&self, name: &str) -> Result<Option<Crate>, UpstreamError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok((name == "serde")
+                .then(|| Crate::new(Metadata::new(name, "dtolnay", CrateKind::Library))))
+        }
+    }
+
+    #[test]
+    fn caches_for_ttl() -> Result<(), UpstreamError> {
+        let upstream = Arc::new(Counting::default());
+        let mut config = UpstreamConfig {
+            upstream: upstream.clone(),
+            ..UpstreamConfig::new(Counting::default())
+        };
+        let cache = ProxyCache::new(config.clone());
+
+        assert!(cache.find_exact("serde")?.is_some());
+        assert!(cache.find_exact("serde")?.is_some());
+        assert_eq!(None, cache.find_exact("nope")?);
+        assert_eq!(None, cache.find_exact("nope")?);
+        assert_eq!(2, upstream.0.load(Ordering::SeqCst));
+
+        config.ttl = Duration::ZERO;
+        let cache = ProxyCache::new(config);
+        cache.find_exact("serde")?;
+        cache.find_exact("serde")?;
+        assert_eq!(4, upstream.0.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[test]
+    fn evicts_least_recently_used() -> Result<(), UpstreamError> {
+        let upstream = Arc::new(Counting::default());
+        let config = UpstreamConfig {
+            upstream: upstream.clone(),
+            cache_capacity: 2,
+            ..UpstreamConfig::new(Counting::default())
+        };
+        let cache = ProxyCache::new(config);
+        cache.find_exact("serde")?;
+        cache.find_exact("a")?;
+        // serde is used again, so `a` goes first
+        cache.find_exact("serde")?;
+        cache.find_exact("b")?;
+        assert_eq!(2, cache.len());
+        assert_eq!(3, upstream.0.load(Ordering::SeqCst));
+
+        cache.find_exact("serde")?;
+        assert_eq!(3, upstream.0.load(Ordering::SeqCst));
+        cache.find_exact("a")?;
+        assert_eq!(4, upstream.0.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    #[test]
+    fn offline() {
+        let mut config = UpstreamConfig::new(Counting::default());
+        config.offline = true;
+        let cache = ProxyCache::new(config);
+        assert!(matches!(
+            cache.find_exact("serde"),
+            Err(UpstreamError::Offline)
+        ));
+    }
+}
@@ -0,0 +1,117 @@
+//! Point-in-time views of a repository for long reads.
+//!
+//! The repository keeps its crates behind `Arc`s and copies them on write. Taking a [`View`]
+//! copies pointers only, and changes made afterwards copy just the crates they touch. The server
+//! takes a view while holding its lock and answers e.g. a snapshot of the whole repository from
+//! it after releasing the lock, so publishes don't wait for the reader and the reader never sees
+//! half of a change.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::orgs::Organization;
+use crate::replication::Snapshot;
+use crate::{Crate, Repository};
+
+/// The crates, aliases and organizations of a repository as of change `last_seq`
+#[derive(Debug, Clone)]
+pub struct View {
+    pub(crate) crates: BTreeMap<Arc<str>, Arc<Crate>>,
+    pub(crate) aliases: BTreeMap<String, String>,
+    pub(crate) orgs: BTreeMap<String, Organization>,
+    last_seq: u64,
+}
+
+impl View {
+    /// sequence number of the last change the view includes
+    pub fn last_seq(&self) -> u64 {
+        self.last_seq
+    }
+
+    /// all crates, ordered by name
+    pub fn iter(&self) -> impl Iterator<Item = &Crate> {
+        self.crates.values().map(Arc::as_ref)
+    }
+
+    /// like [`Repository::find_exact`]
+    pub fn find_exact(&self, name: impl AsRef<str>) -> Option<&Crate> {
+        let name = name.as_ref();
+        self.crates
+            .get(name)
+            .or_else(|| self.crates.get(self.aliases.get(name)?.as_str()))
+            .map(Arc::as_ref)
+    }
+
+    pub fn org(&self, name: impl AsRef<str>) -> Option<&Organization> {
+        self.orgs.get(name.as_ref())
+    }
+
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            last_seq: self.last_seq,
+            crates: self.crates.values().cloned().collect(),
+            aliases: self.aliases.clone(),
+        }
+    }
+}
+
+impl Repository {
+    /// the current state, unaffected by later changes, see the [module docs](self)
+    pub fn view(&self) -> View {
+        View {
+            crates: self.crates.clone(),
+            aliases: self.aliases.clone(),
+            orgs: self.orgs.clone(),
+            last_seq: self.changes.last_seq(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::NamedTempFile;
+
+    use super::*;
+    use crate::{CrateKind, Metadata, RepoError, SemVer};
+
+    #[test]
+    fn copy_on_write() -> Result<(), RepoError> {
+        let store = NamedTempFile::new().unwrap();
+        let mut repo = Repository::new(&store);
+        for name in ["hello_bin", "hello_moon", "hello_star"] {
+            repo.add_crate(
+                Metadata::new(name, "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            )?;
+        }
+
+        let view = repo.view();
+        repo.add_release("hello_bin", SemVer::new(1, 1, 0))?;
+        repo.delete_crate("hello_moon")?;
+        repo.add_crate(
+            Metadata::new("hello_sun", "Busy Person", CrateKind::Binary),
+            SemVer::new(1, 0, 0),
+        )?;
+
+        assert_eq!(3, view.last_seq());
+        let names: Vec<&str> = view.iter().map(|crt| crt.metadata().name()).collect();
+        assert_eq!(vec!["hello_bin", "hello_moon", "hello_star"], names);
+        assert_eq!(
+            &[SemVer::new(1, 0, 0)],
+            view.find_exact("hello_bin").unwrap().releases()
+        );
+        assert_eq!(2, repo.find_exact("hello_bin").unwrap().releases().len());
+
+        // unchanged crates are shared, changed ones were copied
+        let later = repo.view();
+        assert!(Arc::ptr_eq(
+            &view.crates["hello_star"],
+            &later.crates["hello_star"]
+        ));
+        assert!(!Arc::ptr_eq(
+            &view.crates["hello_bin"],
+            &later.crates["hello_bin"]
+        ));
+        Ok(())
+    }
+}
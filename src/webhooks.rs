@@ -0,0 +1,391 @@
+//! Notifies external services about new crates and releases.
+//!
+//! Every configured hook receives a `POST` with the [`Change`] as JSON body. The body is signed
+//! with HMAC-SHA256 using the hook's secret, the hex digest is sent as
+//! `X-Semver-Signature: sha256=<digest>`. Failed deliveries are retried with exponential
+//! backoff and finally appended to the dead-letter log as JSON lines.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use log::{debug, error, warn};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use thiserror::Error;
+
+use crate::events::{Change, Event};
+
+pub const SIGNATURE_HEADER: &str = "X-Semver-Signature";
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Webhook {
+    /// plain `http://host[:port]/path` URL
+    pub url: String,
+    pub secret: String,
+}
+
+impl Webhook {
+    pub fn new(url: impl Into<String>, secret: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            secret: secret.into(),
+        }
+    }
+
+    /// fails if the url isn't supported
+    pub fn validate(&self) -> Result<(), WebhookError> {
+        split_url(&self.url).map(drop)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    pub hooks: Vec<Webhook>,
+    /// attempts per delivery, including the first one
+    pub max_attempts: u32,
+    /// wait before the first retry, doubled after every failure
+    pub initial_backoff: Duration,
+    pub timeout: Duration,
+    /// deliveries that exhausted all attempts end up here
+    pub dead_letter: Option<PathBuf>,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            hooks: vec![],
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            dead_letter: None,
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum WebhookError {
+    #[error("unsupported url '{0}' (expected: http://host[:port]/path)")]
+    UnsupportedUrl(String),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("unexpected response: {0}")]
+    Status(String),
+}
+
+/// hex encoded HMAC-SHA256 of `body`
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Delivers changes on a background thread so publishing never waits for slow receivers.
+/// Dropping the dispatcher delivers everything still queued, then stops the thread.
+pub struct Dispatcher {
+    sender: Option<Sender<Change>>,
+    /// the hooks of `config`, replaceable while running
+    hooks: Arc<Mutex<Arc<[Webhook]>>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Dispatcher {
+    pub fn start(config: WebhookConfig) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let hooks = Arc::new(Mutex::new(config.hooks.clone().into()));
+        let thread = {
+            let hooks = hooks.clone();
+            thread::spawn(move || deliver_all(&config, &hooks, receiver))
+        };
+        Self {
+            sender: Some(sender),
+            hooks,
+            thread: Some(thread),
+        }
+    }
+
+    /// delivers changes to `hooks` from now on, deliveries in progress finish as they were
+    pub fn set_hooks(&self, hooks: Vec<Webhook>) {
+        *self.hooks.lock().unwrap() = hooks.into();
+    }
+
+    /// queues `change` for delivery if hooks care about it, i.e. crates and releases being added
+    pub fn notify(&self, change: &Change) {
+        if !matches!(
+            change.event,
+            Event::CrateAdded { .. } | Event::ReleaseAdded { .. }
+        ) {
+            return;
+        }
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(change.clone());
+        }
+    }
+}
+
+impl Drop for Dispatcher {
+    fn drop(&mut self) {
+        // closing the channel ends the delivery loop
+        self.sender.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DeadLetter<'a> {
+    url: &'a str,
+    error: String,
+    change: &'a Change,
+}
+
+fn deliver_all(config: &WebhookConfig, hooks: &Mutex<Arc<[Webhook]>>, receiver: Receiver<Change>) {
+    for change in receiver {
+        let body = match serde_json::to_vec(&change) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("could not serialize change #{}: {}", change.seq, e);
+                continue;
+            }
+        };
+
+        let hooks = hooks.lock().unwrap().clone();
+        for hook in hooks.iter() {
+            if let Err(e) = deliver_with_retries(config, hook, &body) {
+                error!(
+                    "giving up on webhook {} for change #{}: {}",
+                    hook.url, change.seq, e
+                );
+                dead_letter(config, hook, &change, e);
+            }
+        }
+    }
+}
+
+fn deliver_with_retries(
+    config: &WebhookConfig,
+    hook: &Webhook,
+    body: &[u8],
+) -> Result<(), WebhookError> {
+    let mut backoff = config.initial_backoff;
+    let mut attempt = 1;
+    loop {
+        match post(hook, body, config.timeout) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= config.max_attempts => return Err(e),
+            Err(e) => {
+                warn!("webhook {} failed (attempt {}): {}", hook.url, attempt, e);
+                thread::sleep(backoff);
+                backoff *= 2;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+fn dead_letter(config: &WebhookConfig, hook: &Webhook, change: &Change, e: WebhookError) {
+    let path = match &config.dead_letter {
+        Some(path) => path,
+        None => return,
+    };
+    let entry = DeadLetter {
+        url: &hook.url,
+        error: e.to_string(),
+        change,
+    };
+    let res = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut f| {
+            let line = serde_json::to_string(&entry)?;
+            writeln!(f, "{}", line)
+        });
+    if let Err(e) = res {
+        error!("could not write dead letter to {:?}: {}", path, e);
+    }
+}
+
+/// splits `http://host[:port]/path` into `host:port` and `/path`
+pub(crate) fn split_url(url: &str) -> Result<(String, String), WebhookError> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| WebhookError::UnsupportedUrl(url.to_string()))?;
+    let (authority, path) = match rest.find('/') {
+        Some(idx) => (&rest[..idx], &rest[idx..]),
+        None => (rest, "/"),
+    };
+    if authority.is_empty() {
+        return Err(WebhookError::UnsupportedUrl(url.to_string()));
+    }
+    let target = crate::net::parse_target(authority, 80)
+        .map_err(|_| WebhookError::UnsupportedUrl(url.to_string()))?;
+    Ok((target, path.to_string()))
+}
+
+fn post(hook: &Webhook, body: &[u8], timeout: Duration) -> Result<(), WebhookError> {
+    let signature = format!("{}: sha256={}", SIGNATURE_HEADER, sign(&hook.secret, body));
+    post_json(&hook.url, &[signature], body, timeout)
+}
+
+/// connects to the first address of `target` that answers within `timeout`
+fn connect(target: &str, timeout: Duration) -> std::io::Result<TcpStream> {
+    let mut last = None;
+    for addr in target.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last = Some(e),
+        }
+    }
+    Err(last.unwrap_or_else(|| std::io::Error::other(format!("{} has no address", target))))
+}
+
+/// POSTs `body` as JSON to a plain `http://` url, failing unless answered with a 2xx status
+pub(crate) fn post_json(
+    url: &str,
+    headers: &[String],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<(), WebhookError> {
+    let (target, path) = split_url(url)?;
+    debug!("POST {} → {}", url, target);
+    let mut stream = connect(&target, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let host = target.rsplit_once(':').map(|(h, _)| h).unwrap_or(&target);
+    let headers: String = headers.iter().map(|h| format!("{}\r\n", h)).collect();
+    write!(
+        stream,
+        "POST {path} HTTP/1.1\r\n\
+         Host: {host}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {len}\r\n\
+         {headers}\
+         Connection: close\r\n\r\n",
+        len = body.len(),
+    )?;
+    stream.write_all(body)?;
+
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status_line = response.lines().next().unwrap_or_default();
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(WebhookError::Status(status_line.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+    use crate::SemVer;
+
+    fn release_added() -> Change {
+        Change {
+            seq: 1,
+            at: Default::default(),
+            event: Event::ReleaseAdded {
+                name: "hello_bin".into(),
+                version: SemVer::new(1, 0, 1),
+                channel: Default::default(),
+            },
+        }
+    }
+
+    #[test]
+    fn delivers_signed_payload() -> Result<(), Box<dyn std::error::Error>> {
+        let receiver = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}/hook", receiver.local_addr()?);
+        let dispatcher = Dispatcher::start(WebhookConfig {
+            hooks: vec![Webhook::new(url, "s3cret")],
+            ..Default::default()
+        });
+        dispatcher.notify(&release_added());
+
+        let (mut stream, _) = receiver.accept()?;
+        let mut buf = vec![0; 4096];
+        let mut request = String::new();
+        let complete = |request: &str| match request.split_once("\r\n\r\n") {
+            Some((head, body)) => head
+                .lines()
+                .filter_map(|l| l.strip_prefix("Content-Length: "))
+                .any(|len| len.parse() == Ok(body.len())),
+            None => false,
+        };
+        while !complete(&request) {
+            let n = stream.read(&mut buf)?;
+            request.push_str(std::str::from_utf8(&buf[..n])?);
+        }
+        stream.write_all(b"HTTP/1.1 204 No Content\r\n\r\n")?;
+        drop(stream);
+        drop(dispatcher);
+
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /hook HTTP/1.1"));
+        let expected = format!(
+            "{}: sha256={}",
+            SIGNATURE_HEADER,
+            sign("s3cret", body.as_bytes())
+        );
+        assert!(head.lines().any(|l| l == expected));
+        let change: Change = serde_json::from_str(body)?;
+        assert_eq!(release_added(), change);
+        Ok(())
+    }
+
+    #[test]
+    fn dead_letters_after_retries() -> Result<(), Box<dyn std::error::Error>> {
+        // grab a free port and close it again, so nobody is listening
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let dir = tempfile::tempdir()?;
+        let dead_letter = dir.path().join("dead.jsonl");
+        let dispatcher = Dispatcher::start(WebhookConfig {
+            hooks: vec![Webhook::new(
+                format!("http://127.0.0.1:{}/", port),
+                "s3cret",
+            )],
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(1),
+            dead_letter: Some(dead_letter.clone()),
+            ..Default::default()
+        });
+        dispatcher.notify(&release_added());
+        drop(dispatcher);
+
+        let log = std::fs::read_to_string(dead_letter)?;
+        assert_eq!(1, log.lines().count());
+        assert!(log.contains("hello_bin"));
+        Ok(())
+    }
+
+    #[test]
+    fn ignores_yanks() {
+        let (sender, receiver) = mpsc::channel();
+        let dispatcher = Dispatcher {
+            sender: Some(sender),
+            hooks: Default::default(),
+            thread: None,
+        };
+        dispatcher.notify(&Change {
+            seq: 1,
+            at: Default::default(),
+            event: Event::Yanked {
+                name: "hello_bin".into(),
+                version: SemVer::new(1, 0, 0),
+            },
+        });
+        drop(dispatcher);
+        assert!(receiver.recv().is_err());
+    }
+}
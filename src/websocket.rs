@@ -0,0 +1,204 @@
+//! WebSocket transport, behind the `websocket` feature, e.g. for browser-based dashboards.
+//!
+//! Every text message is an [`ApiRequest`] in the same JSON as over TCP and is answered with one
+//! message holding its result, so one connection can carry any number of requests. A
+//! `Subscribe` request turns the connection into a feed instead: every batch of new changes is
+//! pushed as a [`crate::api::SubscribeResult`] until the client disconnects. Enable it with
+//! [`crate::server::ServerConfig::websocket_listen`].
+
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use log::{debug, error, warn};
+use tungstenite::protocol::WebSocketConfig;
+use tungstenite::{Message, WebSocket};
+
+use crate::api::{ApiError, ApiRequest, ApiResult, SubscribeResult};
+use crate::quota::Quota;
+use crate::server::{self, Shared, ShutdownHandle};
+
+fn error_message(e: ApiError) -> Message {
+    let res: ApiResult<()> = Err(e);
+    Message::text(serde_json::to_string(&res).unwrap_or_default())
+}
+
+/// Accepts WebSocket connections on `listener` until `shutdown`, one thread each.
+pub(crate) fn serve(listener: TcpListener, shared: &Arc<Shared>, shutdown: &ShutdownHandle) {
+    for connection in listener.incoming() {
+        if shutdown.is_shutdown() {
+            break;
+        }
+        let stream = match connection {
+            Ok(stream) => stream,
+            Err(e) => {
+                error!("Connection error: {:?}", e);
+                continue;
+            }
+        };
+        let shared = shared.clone();
+        let shutdown = shutdown.clone();
+        thread::spawn(move || {
+            let config = WebSocketConfig::default()
+                .max_message_size(shared.settings().quotas.max_request_size);
+            match tungstenite::accept_with_config(stream, Some(config)) {
+                Ok(socket) => handle_connection(socket, &shared, &shutdown),
+                Err(e) => warn!("WebSocket handshake failed: {}", e),
+            }
+        });
+    }
+}
+
+fn handle_connection(mut socket: WebSocket<TcpStream>, shared: &Shared, shutdown: &ShutdownHandle) {
+    let peer = socket
+        .get_ref()
+        .peer_addr()
+        .map(|addr| addr.ip().to_string())
+        .unwrap_or_else(|_| "unknown".to_string());
+    while !shutdown.is_shutdown() {
+        let message = match socket.read() {
+            Ok(Message::Text(text)) => text,
+            // pings are answered by tungstenite
+            Ok(Message::Ping(_) | Message::Pong(_) | Message::Frame(_)) => continue,
+            Ok(Message::Binary(_)) => {
+                let _ = socket.send(error_message(ApiError::Internal));
+                continue;
+            }
+            Ok(Message::Close(_)) => break,
+            Err(tungstenite::Error::Capacity(_)) => {
+                warn!("rejected WebSocket message exceeding the size limit");
+                let _ = socket.send(error_message(ApiError::QuotaExceeded(Quota::RequestSize)));
+                break;
+            }
+            Err(e) => {
+                debug!("WebSocket connection ended: {}", e);
+                break;
+            }
+        };
+        let request: ApiRequest = match serde_json::from_str(message.as_str()) {
+            Ok(request) => request,
+            Err(e) => {
+                warn!("could not parse request - {}", e);
+                let _ = socket.send(error_message(ApiError::Internal));
+                continue;
+            }
+        };
+        if let ApiRequest::Subscribe { since } = request {
+            push_changes(&mut socket, since, &peer, shared, shutdown);
+            break;
+        }
+        let response = server::dispatch(request, &peer, shared, false);
+        if socket.send(Message::text(response)).is_err() {
+            break;
+        }
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+/// pushes changes as they happen, until the client goes away
+fn push_changes(
+    socket: &mut WebSocket<TcpStream>,
+    mut since: u64,
+    peer: &str,
+    shared: &Shared,
+    shutdown: &ShutdownHandle,
+) {
+    while !shutdown.is_shutdown() {
+        // waits for changes until the subscribe timeout
+        let response = server::dispatch(ApiRequest::Subscribe { since }, peer, shared, false);
+        let changes: SubscribeResult = match serde_json::from_str(&response) {
+            Ok(changes) => changes,
+            Err(_) => Err(ApiError::Internal),
+        };
+        match changes {
+            Ok(changes) if changes.is_empty() => {
+                // a timeout, make sure the client is still around
+                if socket.send(Message::Ping(Default::default())).is_err() {
+                    return;
+                }
+            }
+            Ok(changes) => {
+                since = changes.last().map_or(since, |change| change.seq);
+                if socket.send(Message::text(response)).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = socket.send(error_message(e));
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::api::{AddResult, FindExactResult};
+    use crate::server::{Server, ServerConfig};
+    use crate::{CrateKind, Metadata, SemVer};
+
+    fn send<T: serde::de::DeserializeOwned>(
+        socket: &mut WebSocket<tungstenite::stream::MaybeTlsStream<TcpStream>>,
+        request: &ApiRequest,
+    ) -> T {
+        socket
+            .send(Message::text(serde_json::to_string(request).unwrap()))
+            .unwrap();
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(text.as_str()).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn websocket() -> Result<(), Box<dyn std::error::Error>> {
+        let store_dir = tempfile::tempdir()?;
+        let mut config = ServerConfig::new(store_dir.path().join("store.json"))
+            .with_listen(vec![SocketAddr::from(([127, 0, 0, 1], 0))]);
+        config.websocket_listen = Some(SocketAddr::from(([127, 0, 0, 1], 0)));
+        config.subscribe_timeout = Duration::from_millis(100);
+        let server = Server::bind(config)?;
+        let addr = server.websocket_addr().unwrap();
+        let shutdown = server.shutdown_handle();
+        let thread = thread::spawn(move || server.serve());
+
+        let (mut socket, _) = tungstenite::connect(format!("ws://{addr}"))?;
+        let res: AddResult = send(
+            &mut socket,
+            &ApiRequest::AddCrate(
+                Metadata::new("hello_bin", "Busy Person", CrateKind::Binary),
+                SemVer::new(1, 0, 0),
+            ),
+        );
+        assert_eq!(Ok(()), res.map_err(|e| e.to_string()));
+        // several requests on one connection
+        let res: FindExactResult = send(&mut socket, &ApiRequest::FindExact("hello_bin".into()));
+        assert!(res.unwrap().is_some());
+
+        let (mut feed, _) = tungstenite::connect(format!("ws://{addr}"))?;
+        let changes: SubscribeResult = send(&mut feed, &ApiRequest::Subscribe { since: 0 });
+        assert_eq!(1, changes.unwrap()[0].seq);
+        let _: AddResult = send(
+            &mut socket,
+            &ApiRequest::AddRelease("hello_bin".into(), SemVer::new(1, 1, 0)),
+        );
+        // pushed without asking again
+        let changes: SubscribeResult = loop {
+            if let Message::Text(text) = feed.read()? {
+                break serde_json::from_str(text.as_str())?;
+            }
+        };
+        assert_eq!(2, changes.unwrap()[0].seq);
+
+        drop((socket, feed));
+        shutdown.shutdown();
+        thread.join().unwrap()?;
+        Ok(())
+    }
+}